@@ -0,0 +1,940 @@
+//! `hashline apply`: run the edit pipeline for one file or a multi-file
+//! transaction, then write every file or none.
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Value};
+
+use crate::diff::{self, ChangeBlock, Hunk, LineKind};
+use crate::edit::{
+    self, ApplyOptions, EditOutcome, EditSpan, EditStatus, FileChangePolicy, HashlineEdit,
+    HashlineParams, NoopEdit, PatchOp,
+};
+use crate::error::{ConflictingEdit, EditConflictError, HashlineMismatchError};
+use crate::format;
+use crate::hash::{
+    compute_file_fingerprint, compute_line_hashes_in, file_fingerprint_matches, HashOptions,
+};
+use crate::heuristics::HeuristicSet;
+use crate::patch;
+use crate::usage::UsageResult;
+use crate::util::{read_normalized, read_text, write_all_or_rollback, TextFormat};
+
+/// Computed (not yet written) update for a single file.
+struct FileUpdate {
+    path: String,
+    original: String,
+    content: String,
+    format: TextFormat,
+    first_changed_line: Option<usize>,
+    warnings: Vec<String>,
+    edit_count: usize,
+    /// Hash options resolved for this file's path.
+    hash: HashOptions,
+    /// Caller guards with `expect_file_hash`, so hand back the new fingerprint.
+    report_fingerprint: bool,
+    /// Anchor edit spans in the original content (payload edit indices).
+    anchor_spans: Vec<EditSpan>,
+    /// Replace edit spans in the final content (payload edit indices).
+    replace_spans: Vec<EditSpan>,
+    /// Operation name of each payload edit, in payload order.
+    ops: Vec<&'static str>,
+    /// Edits that left their lines as they were (payload edit indices).
+    noop_edits: Vec<NoopEdit>,
+    /// Outcome of each payload edit, in payload order.
+    outcomes: Vec<EditOutcome>,
+}
+
+/// How `--dry-run` reports the would-be result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DryRun {
+    /// Unified diff on stdout.
+    Diff,
+    /// JSON object with per-file hunks attributed to edits.
+    Json,
+}
+
+/// How `apply` reports results and errors.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human-readable text; errors and warnings go to stderr.
+    #[default]
+    Text,
+    /// Exactly one JSON object on stdout, whatever the outcome.
+    Json,
+}
+
+/// Options for [`run`].
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    pub emit_updated: bool,
+    pub hash: HashOptions,
+    /// Validate and compute, print the result, but write nothing.
+    pub dry_run: Option<DryRun>,
+    pub format: OutputFormat,
+    /// Apply every edit verbatim: no heuristics, no anchor relocation.
+    pub strict: bool,
+    /// Heuristics allowed to rewrite edit text.
+    pub heuristics: HeuristicSet,
+    /// The input is a unified diff, not JSON edits.
+    pub patch: bool,
+    /// Also write the changes as a unified diff to this file (`-` for stdout).
+    pub emit_patch: Option<String>,
+}
+
+/// Why a file's edits could not be computed.
+enum FileFailure {
+    /// One or more anchors no longer match the file.
+    Mismatch(HashlineMismatchError),
+    /// Two edits in the payload touch overlapping lines.
+    Conflict(EditConflictError),
+    /// `expect_file_hash` no longer matches and the policy is `refuse`.
+    FileChanged {
+        expected: String,
+        current: String,
+        message: String,
+    },
+    Error(String),
+}
+
+impl FileFailure {
+    fn message(&self) -> String {
+        match self {
+            FileFailure::Mismatch(e) => e.to_string(),
+            FileFailure::Conflict(e) => format!("Error: {}", e),
+            FileFailure::FileChanged { message, .. } | FileFailure::Error(message) => {
+                message.clone()
+            }
+        }
+    }
+
+    fn exit(&self) -> (i32, UsageResult) {
+        match self {
+            FileFailure::Conflict(_) | FileFailure::Error(_) => (2, UsageResult::Error),
+            _ => (1, UsageResult::Mismatch),
+        }
+    }
+
+    fn to_json(&self, path: &str) -> Value {
+        match self {
+            FileFailure::Mismatch(e) => {
+                let mismatches: Vec<Value> = e
+                    .mismatches
+                    .iter()
+                    .map(|m| {
+                        json!({
+                            "line": m.line,
+                            "expected": m.expected,
+                            "actual": m.actual,
+                        })
+                    })
+                    .collect();
+                let remaps: BTreeMap<String, String> = e.remaps().into_iter().collect();
+                json!({
+                    "status": "mismatch",
+                    "kind": "anchors",
+                    "path": path,
+                    "message": self.message(),
+                    "mismatches": mismatches,
+                    "remaps": remaps,
+                })
+            }
+            FileFailure::FileChanged {
+                expected, current, ..
+            } => json!({
+                "status": "mismatch",
+                "kind": "file_changed",
+                "path": path,
+                "message": self.message(),
+                "expected_file_hash": expected,
+                "current_file_hash": current,
+            }),
+            FileFailure::Conflict(e) => {
+                let side = |c: &ConflictingEdit| {
+                    json!({
+                        "index": c.edit_index,
+                        "op": c.op,
+                        "start_line": c.start_line,
+                        "end_line": c.end_line,
+                    })
+                };
+                json!({
+                    "status": "error",
+                    "kind": "conflict",
+                    "path": path,
+                    "message": self.message(),
+                    "edits": [side(&e.first), side(&e.second)],
+                })
+            }
+            FileFailure::Error(_) => json!({
+                "status": "error",
+                "path": path,
+                "message": self.message(),
+            }),
+        }
+    }
+}
+
+/// Report an error that is not tied to one file's edits and return exit code 2.
+pub fn fail(format: OutputFormat, message: String) -> (i32, UsageResult) {
+    match format {
+        OutputFormat::Text => eprintln!("{}", message),
+        OutputFormat::Json => println!("{}", json!({"status": "error", "message": message})),
+    }
+    (2, UsageResult::Error)
+}
+
+/// Run `hashline apply`. Returns the process exit code and the usage result.
+pub fn run(input: Option<&str>, opts: RunOptions) -> (i32, UsageResult) {
+    if opts.emit_patch.as_deref() == Some("-") {
+        if opts.format == OutputFormat::Json {
+            return fail(
+                opts.format,
+                "Error: --emit-patch - cannot share stdout with --format json; give it a file"
+                    .into(),
+            );
+        }
+        if opts.dry_run == Some(DryRun::Json) {
+            return fail(
+                opts.format,
+                "Error: --emit-patch - cannot share stdout with --dry-run=json; give it a file"
+                    .into(),
+            );
+        }
+    }
+
+    let input_data = if let Some(path) = input {
+        match std::fs::read_to_string(path) {
+            Ok(c) => c,
+            Err(e) => {
+                return fail(
+                    opts.format,
+                    format!("Error reading input file {}: {}", path, e),
+                )
+            }
+        }
+    } else {
+        let mut buf = String::new();
+        if let Err(e) = std::io::stdin().read_to_string(&mut buf) {
+            return fail(opts.format, format!("Error reading stdin: {}", e));
+        }
+        buf
+    };
+
+    let parsed = if opts.patch {
+        patch_files(&input_data)
+    } else {
+        edit::parse_apply_input(&input_data).map_err(|e| format!("Invalid JSON input: {}", e))
+    };
+    let (files, is_transaction) = match parsed {
+        Ok(p) => p,
+        Err(message) => return fail(opts.format, message),
+    };
+
+    if is_transaction {
+        if files.is_empty() {
+            return fail(
+                opts.format,
+                "Error: transaction must list at least one file".to_string(),
+            );
+        }
+        if let Some(dup) = find_duplicate_path(&files) {
+            return fail(
+                opts.format,
+                format!(
+                    "Error: {} appears more than once in the transaction; combine its edits into one entry",
+                    dup
+                ),
+            );
+        }
+    }
+
+    // Phase 1: validate and compute every file before touching the disk.
+    let mut updates = Vec::with_capacity(files.len());
+    for params in &files {
+        match compute_update(params, &opts) {
+            Ok(u) => updates.push(u),
+            Err(failure) => {
+                match opts.format {
+                    OutputFormat::Text if is_transaction => {
+                        eprintln!("{}: {}", params.path, failure.message())
+                    }
+                    OutputFormat::Text => eprintln!("{}", failure.message()),
+                    OutputFormat::Json => println!("{}", failure.to_json(&params.path)),
+                }
+                return failure.exit();
+            }
+        }
+    }
+
+    if let Some(target) = &opts.emit_patch {
+        if let Err(message) = write_patch(target, &updates) {
+            return fail(opts.format, message);
+        }
+    }
+
+    if let Some(mode) = opts.dry_run {
+        match (mode, opts.format) {
+            (DryRun::Diff, OutputFormat::Text) => {
+                print_warnings(&updates, is_transaction);
+                // The patch on stdout already is the diff.
+                if opts.emit_patch.as_deref() != Some("-") {
+                    print_dry_run_diff(&updates);
+                }
+            }
+            _ => print_dry_run_json(&updates),
+        }
+        return (0, UsageResult::Success);
+    }
+
+    // Phase 2: write all changed files in their original layout, restoring
+    // earlier ones if a later write fails.
+    let writes: Vec<(PathBuf, Vec<u8>)> = updates
+        .iter()
+        .filter(|u| u.content != u.original)
+        .map(|u| {
+            (
+                PathBuf::from(&u.path),
+                u.format.render_edit(&u.original, &u.content).into_bytes(),
+            )
+        })
+        .collect();
+    if let Err(e) = write_all_or_rollback(&writes) {
+        // The emitted patch describes a change that did not land.
+        if let Some(target) = opts.emit_patch.as_deref().filter(|t| *t != "-") {
+            let _ = std::fs::remove_file(target);
+        }
+        let message = format!("Error writing {}: {}", e.path.display(), e.source);
+        match opts.format {
+            OutputFormat::Text => {
+                eprintln!("{}", message);
+                if e.rollback_failures.is_empty() {
+                    if writes.len() > 1 {
+                        eprintln!("All files were restored to their original content.");
+                    }
+                } else {
+                    for (path, err) in &e.rollback_failures {
+                        eprintln!("Error restoring {}: {}", path.display(), err);
+                    }
+                }
+            }
+            OutputFormat::Json => {
+                let rollback_failures: Vec<Value> = e
+                    .rollback_failures
+                    .iter()
+                    .map(|(path, err)| json!({"path": path, "message": err.to_string()}))
+                    .collect();
+                println!(
+                    "{}",
+                    json!({
+                        "status": "error",
+                        "path": e.path,
+                        "message": message,
+                        "rollback_failures": rollback_failures,
+                    })
+                );
+            }
+        }
+        return (2, UsageResult::Error);
+    }
+
+    if opts.format == OutputFormat::Json {
+        print_result_json(&updates);
+        return (0, UsageResult::Success);
+    }
+
+    print_warnings(&updates, is_transaction);
+
+    if opts.emit_updated {
+        for u in &updates {
+            if let Some(first_line) = u.first_changed_line {
+                print_updated_window(u, first_line, is_transaction);
+            }
+        }
+    }
+
+    if updates.iter().all(|u| u.first_changed_line.is_none()) {
+        println!("No changes applied.");
+    }
+    (0, UsageResult::Success)
+}
+
+/// Turn a unified diff into one `patch` edit per file. A diff touching
+/// several files becomes a transaction.
+fn patch_files(diff: &str) -> Result<(Vec<HashlineParams>, bool), String> {
+    let files = patch::parse_patch(diff).map_err(|e| format!("Invalid patch: {}", e))?;
+    let params = files
+        .into_iter()
+        .map(|f| {
+            Ok(HashlineParams {
+                path: f.path().map_err(|e| format!("Invalid patch: {}", e))?,
+                edits: vec![HashlineEdit::Patch {
+                    patch: PatchOp {
+                        diff: f.text,
+                        verbatim: false,
+                    },
+                }],
+                expect_file_hash: None,
+                on_file_change: FileChangePolicy::default(),
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    let is_transaction = params.len() > 1;
+    Ok((params, is_transaction))
+}
+
+fn print_warnings(updates: &[FileUpdate], is_transaction: bool) {
+    for u in updates {
+        let prefix = if is_transaction {
+            format!("{}: ", u.path)
+        } else {
+            String::new()
+        };
+        for w in &u.warnings {
+            eprintln!("Warning: {}{}", prefix, w);
+        }
+        for note in outcome_notes(u) {
+            eprintln!("Note: {}{}", prefix, note);
+        }
+    }
+}
+
+/// One line per edit that was skipped, relocated or rewritten by a heuristic.
+fn outcome_notes(update: &FileUpdate) -> Vec<String> {
+    let mut notes = Vec::new();
+    for o in &update.outcomes {
+        let edit = format!("edit {} ({})", o.edit_index, update.ops[o.edit_index]);
+        match o.status {
+            EditStatus::Noop => notes.push(format!("{} changed nothing", edit)),
+            EditStatus::Duplicate => {
+                notes.push(format!("{} repeats an earlier edit and was skipped", edit))
+            }
+            EditStatus::Applied => {}
+        }
+        for (start, end) in &o.fuzzy_matches {
+            notes.push(if start == end {
+                format!("{}: fuzzy match on line {}", edit, start)
+            } else {
+                format!("{}: fuzzy match on lines {}-{}", edit, start, end)
+            });
+        }
+        for (from, to) in &o.relocated {
+            notes.push(format!(
+                "{}: anchor line {} relocated to {}",
+                edit, from, to
+            ));
+        }
+        for h in &o.heuristics {
+            let lines: Vec<String> = h.lines.iter().map(|l| l.to_string()).collect();
+            let mut note = format!(
+                "{}: {} on text line{} {}",
+                edit,
+                h.heuristic.as_str(),
+                if h.lines.len() == 1 { "" } else { "s" },
+                lines.join(",")
+            );
+            if let Some(detail) = &h.detail {
+                note.push_str(&format!(" ({})", detail));
+            }
+            notes.push(note);
+        }
+    }
+    notes
+}
+
+/// Read one file and run anchor edits followed by replace edits.
+fn compute_update(params: &HashlineParams, opts: &RunOptions) -> Result<FileUpdate, FileFailure> {
+    let (content, format) = read_text(Path::new(&params.path))
+        .map_err(|e| FileFailure::Error(format!("Error reading {}: {}", params.path, e)))?;
+
+    let mut options = ApplyOptions {
+        relocate: !opts.strict,
+        hash: opts.hash.for_path(Path::new(&params.path)),
+        verbatim: opts.strict,
+        heuristics: opts.heuristics,
+    };
+    let mut warnings = Vec::new();
+    if let Some(expected) = &params.expect_file_hash {
+        if !file_fingerprint_matches(&content, expected) {
+            let current = compute_file_fingerprint(&content);
+            match params.on_file_change {
+                FileChangePolicy::Refuse => {
+                    let message = format!(
+                        "File changed since last read (expect_file_hash {}, current {}). \
+Re-run `hashline read --fingerprint {}` for fresh anchors, or set \"on_file_change\": \"strict\" \
+to apply only if every anchor still matches exactly.",
+                        expected, current, params.path
+                    );
+                    return Err(FileFailure::FileChanged {
+                        expected: expected.clone(),
+                        current,
+                        message,
+                    });
+                }
+                FileChangePolicy::Strict => {
+                    options.relocate = false;
+                    warnings.push(format!(
+                        "File changed since last read (current file_hash {}); anchors were validated without relocation.",
+                        current
+                    ));
+                }
+            }
+        }
+    }
+
+    // Anchor edits (including anchored replaces) run first, then unanchored
+    // replace edits on the result
+    let (replace_indices, anchor_indices): (Vec<usize>, Vec<usize>) = (0..params.edits.len())
+        .partition(|&i| {
+            matches!(&params.edits[i], HashlineEdit::Replace { replace } if !replace.is_anchored())
+        });
+    let pick = |indices: &[usize]| -> Vec<HashlineEdit> {
+        indices.iter().map(|&i| params.edits[i].clone()).collect()
+    };
+    let (anchor_edits, replace_edits) = (pick(&anchor_indices), pick(&replace_indices));
+    // Map spans from sub-slice positions back to payload edit indices.
+    let remap = |spans: Vec<EditSpan>, indices: &[usize]| -> Vec<EditSpan> {
+        spans
+            .into_iter()
+            .map(|s| EditSpan {
+                edit_index: indices[s.edit_index],
+                ..s
+            })
+            .collect()
+    };
+
+    let anchor_result = edit::apply_hashline_edits_with_options(&content, &anchor_edits, &options)
+        .map_err(|e| edit_failure(e, &anchor_indices))?;
+
+    warnings.extend(anchor_result.warnings);
+    let anchor_spans = remap(anchor_result.edit_spans, &anchor_indices);
+    let noop_edits = anchor_result
+        .noop_edits
+        .into_iter()
+        .map(|n| NoopEdit {
+            edit_index: anchor_indices[n.edit_index],
+            ..n
+        })
+        .collect();
+    let mut outcomes: Vec<EditOutcome> =
+        (0..params.edits.len()).map(EditOutcome::applied).collect();
+    for o in anchor_result.outcomes {
+        let edit_index = anchor_indices[o.edit_index];
+        outcomes[edit_index] = EditOutcome { edit_index, ..o };
+    }
+    let mut replace_spans = Vec::new();
+    let mut final_content = anchor_result.content;
+    let mut first_changed_line = anchor_result.first_changed_line;
+    if !replace_edits.is_empty() {
+        let r = edit::apply_replace_edits_with_options(&final_content, &replace_edits, &options)
+            .map_err(|e| edit_failure(e, &replace_indices))?;
+        if r.replacements > 0 {
+            first_changed_line = match (first_changed_line, r.first_changed_line) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+        }
+        replace_spans = remap(r.spans, &replace_indices);
+        for m in remap(r.fuzzy_matches, &replace_indices) {
+            outcomes[m.edit_index]
+                .fuzzy_matches
+                .push((m.start_line, m.end_line));
+        }
+        final_content = r.content;
+    }
+
+    Ok(FileUpdate {
+        path: params.path.clone(),
+        original: content,
+        content: final_content,
+        format,
+        first_changed_line,
+        warnings,
+        edit_count: params.edits.len(),
+        hash: options.hash,
+        report_fingerprint: params.expect_file_hash.is_some(),
+        anchor_spans,
+        replace_spans,
+        ops: params.edits.iter().map(HashlineEdit::op_name).collect(),
+        noop_edits,
+        outcomes,
+    })
+}
+
+/// Classify an error from applying a sub-slice of the payload's edits;
+/// `indices` maps sub-slice positions back to payload edit indices.
+fn edit_failure(e: Box<dyn std::error::Error>, indices: &[usize]) -> FileFailure {
+    match e.downcast::<HashlineMismatchError>() {
+        Ok(mismatch) => FileFailure::Mismatch(*mismatch),
+        Err(e) => match e.downcast::<EditConflictError>() {
+            Ok(conflict) => {
+                let mut conflict = *conflict;
+                conflict.first.edit_index = indices[conflict.first.edit_index];
+                conflict.second.edit_index = indices[conflict.second.edit_index];
+                FileFailure::Conflict(conflict)
+            }
+            Err(e) => FileFailure::Error(format!("Error: {}", e)),
+        },
+    }
+}
+
+/// Per-file fields shared by the `--format json` and `--dry-run=json` output.
+fn file_summary_json(update: &FileUpdate) -> serde_json::Map<String, Value> {
+    let edits: Vec<Value> = update
+        .outcomes
+        .iter()
+        .map(|o| {
+            let heuristics: Vec<Value> = o
+                .heuristics
+                .iter()
+                .map(|h| {
+                    let mut event = json!({
+                        "name": h.heuristic.as_str(),
+                        "lines": h.lines,
+                    });
+                    if let Some(detail) = &h.detail {
+                        event["detail"] = json!(detail);
+                    }
+                    event
+                })
+                .collect();
+            let relocated: Vec<Value> = o
+                .relocated
+                .iter()
+                .map(|(from, to)| json!({"from": from, "to": to}))
+                .collect();
+            let mut edit = json!({
+                "index": o.edit_index,
+                "op": update.ops[o.edit_index],
+                "status": o.status.as_str(),
+                "heuristics": heuristics,
+                "relocated": relocated,
+            });
+            if !o.fuzzy_matches.is_empty() {
+                let matched: Vec<Value> = o
+                    .fuzzy_matches
+                    .iter()
+                    .map(|(start, end)| json!({"start_line": start, "end_line": end}))
+                    .collect();
+                edit["fuzzy_matches"] = Value::Array(matched);
+            }
+            edit
+        })
+        .collect();
+    let noop_edits: Vec<Value> = update
+        .noop_edits
+        .iter()
+        .map(|n| {
+            json!({
+                "edit_index": n.edit_index,
+                "anchor": n.loc,
+                "current_content": n.current_content,
+            })
+        })
+        .collect();
+    let mut out = serde_json::Map::new();
+    out.insert("path".into(), json!(update.path));
+    out.insert("changed".into(), json!(update.content != update.original));
+    out.insert(
+        "first_changed_line".into(),
+        json!(update.first_changed_line),
+    );
+    out.insert("warnings".into(), json!(update.warnings));
+    out.insert("edits".into(), Value::Array(edits));
+    out.insert("noop_edits".into(), Value::Array(noop_edits));
+    out
+}
+
+fn any_changed(updates: &[FileUpdate]) -> bool {
+    updates.iter().any(|u| u.content != u.original)
+}
+
+fn print_result_json(updates: &[FileUpdate]) {
+    let files: Vec<Value> = updates
+        .iter()
+        .map(|u| {
+            let mut file = file_summary_json(u);
+            let window = u.first_changed_line.and_then(|l| updated_window(u, l));
+            if let Some(w) = window {
+                let lines: Vec<Value> = (w.start..w.end)
+                    .zip(&w.hashes)
+                    .map(|(i, hash)| {
+                        json!({
+                            "anchor": format!("{}:{}", i + 1, hash),
+                            "content": w.lines[i],
+                        })
+                    })
+                    .collect();
+                let mut updated = json!({
+                    "start_line": w.start + 1,
+                    "end_line": w.end,
+                    "lines": lines,
+                });
+                if let Some(file_hash) = w.file_hash {
+                    updated["file_hash"] = json!(file_hash);
+                }
+                file.insert("updated".into(), updated);
+            }
+            Value::Object(file)
+        })
+        .collect();
+    let out = json!({
+        "status": "ok",
+        "changed": any_changed(updates),
+        "files": files,
+    });
+    println!("{}", out);
+}
+
+/// Diff labels: `a/path` and `b/path` as git writes them, relative to the
+/// working directory, which is where `git apply` and `apply --patch` look.
+/// An absolute path outside it keeps its absolute label.
+fn diff_labels(path: &str) -> (String, String) {
+    match relative_to_cwd(path) {
+        Some(rel) => (format!("a/{}", rel), format!("b/{}", rel)),
+        None => (path.to_string(), path.to_string()),
+    }
+}
+
+/// `path` relative to the working directory, if it is inside it.
+fn relative_to_cwd(path: &str) -> Option<String> {
+    let absolute = Path::new(path);
+    if absolute.is_relative() {
+        return Some(path.to_string());
+    }
+    let cwd = std::env::current_dir().ok()?;
+    let rel = match absolute.strip_prefix(&cwd) {
+        Ok(rel) => rel.to_path_buf(),
+        // Either side may go through a symlink, e.g. a temp dir.
+        Err(_) => absolute
+            .canonicalize()
+            .ok()?
+            .strip_prefix(cwd.canonicalize().ok()?)
+            .ok()?
+            .to_path_buf(),
+    };
+    let rel = rel.to_str()?;
+    (!rel.is_empty()).then(|| rel.to_string())
+}
+
+/// Hunks between the file as it is on disk and as it would be written.
+fn update_hunks(update: &FileUpdate) -> Vec<Hunk> {
+    if update.content == update.original {
+        return Vec::new();
+    }
+    diff::diff_hunks(
+        &update
+            .format
+            .render_edit(&update.original, &update.original),
+        &update.format.render_edit(&update.original, &update.content),
+        diff::DEFAULT_CONTEXT,
+    )
+}
+
+/// Payload indices of the edits that produced a change block. Anchor edits
+/// are matched on the original side, replace edits on the new side; a block
+/// directly next to an edit's lines counts (insertions land after the anchor).
+fn edits_for_block(update: &FileUpdate, block: &ChangeBlock) -> Vec<usize> {
+    let near = |span: &EditSpan, start: usize, end: usize| {
+        start <= span.end_line + 1 && end + 1 >= span.start_line
+    };
+    let mut indices: Vec<usize> = update
+        .anchor_spans
+        .iter()
+        .filter(|s| near(s, block.old_start, block.old_end))
+        .chain(
+            update
+                .replace_spans
+                .iter()
+                .filter(|s| near(s, block.new_start, block.new_end)),
+        )
+        .map(|s| s.edit_index)
+        .collect();
+    indices.sort_unstable();
+    indices.dedup();
+    indices
+}
+
+/// Payload indices of the edits that produced a hunk.
+fn hunk_edits(update: &FileUpdate, hunk: &Hunk) -> Vec<usize> {
+    let mut edits: Vec<usize> = hunk
+        .change_blocks()
+        .iter()
+        .flat_map(|b| edits_for_block(update, b))
+        .collect();
+    edits.sort_unstable();
+    edits.dedup();
+    edits
+}
+
+/// Unified diff of every changed file, each hunk headed by the payload
+/// indices of the edits that produced it (`@@ -4 +4 @@ edits 0,2`).
+fn render_patch(updates: &[FileUpdate]) -> String {
+    let mut out = String::new();
+    for u in updates {
+        let hunks = update_hunks(u);
+        if hunks.is_empty() {
+            continue;
+        }
+        let (old_label, new_label) = diff_labels(&u.path);
+        out.push_str(&format!("--- {}\n+++ {}\n", old_label, new_label));
+        for hunk in &hunks {
+            let edits: Vec<String> = hunk_edits(u, hunk).iter().map(|i| i.to_string()).collect();
+            let heading = match edits.len() {
+                0 => String::new(),
+                1 => format!("edit {}", edits[0]),
+                _ => format!("edits {}", edits.join(",")),
+            };
+            out.push_str(&hunk.render_with_heading(&heading));
+        }
+    }
+    out
+}
+
+/// Write [`render_patch`] to `target`, or to stdout for `-`. Runs before any
+/// file is touched, so a patch that cannot be saved stops the apply.
+fn write_patch(target: &str, updates: &[FileUpdate]) -> Result<(), String> {
+    let patch = render_patch(updates);
+    if target == "-" {
+        print!("{}", patch);
+        return Ok(());
+    }
+    std::fs::write(target, patch).map_err(|e| format!("Error writing patch {}: {}", target, e))
+}
+
+fn print_dry_run_diff(updates: &[FileUpdate]) {
+    let mut any = false;
+    for u in updates {
+        let hunks = update_hunks(u);
+        if hunks.is_empty() {
+            continue;
+        }
+        any = true;
+        let (old_label, new_label) = diff_labels(&u.path);
+        print!("{}", diff::render_unified(&old_label, &new_label, &hunks));
+    }
+    if !any {
+        eprintln!("No changes.");
+    }
+}
+
+fn print_dry_run_json(updates: &[FileUpdate]) {
+    let files: Vec<Value> = updates
+        .iter()
+        .map(|u| {
+            let hunks = update_hunks(u);
+            let hunk_values: Vec<Value> = hunks
+                .iter()
+                .map(|h| {
+                    let edits = hunk_edits(u, h);
+                    let lines: Vec<String> = h
+                        .lines
+                        .iter()
+                        .map(|l| {
+                            let prefix = match l.kind {
+                                LineKind::Context => ' ',
+                                LineKind::Removed => '-',
+                                LineKind::Added => '+',
+                            };
+                            let text = l.text.strip_suffix('\n').unwrap_or(&l.text);
+                            let text = text.strip_suffix('\r').unwrap_or(text);
+                            format!("{}{}", prefix, text)
+                        })
+                        .collect();
+                    json!({
+                        "header": h.header(),
+                        "old_start": h.old_start,
+                        "old_lines": h.old_lines,
+                        "new_start": h.new_start,
+                        "new_lines": h.new_lines,
+                        "edits": edits,
+                        "lines": lines,
+                    })
+                })
+                .collect();
+            let (old_label, new_label) = diff_labels(&u.path);
+            let mut file = file_summary_json(u);
+            file.insert(
+                "diff".into(),
+                json!(diff::render_unified(&old_label, &new_label, &hunks)),
+            );
+            file.insert("hunks".into(), Value::Array(hunk_values));
+            Value::Object(file)
+        })
+        .collect();
+    let out = json!({
+        "status": "ok",
+        "dry_run": true,
+        "changed": any_changed(updates),
+        "files": files,
+    });
+    println!("{}", out);
+}
+
+/// Fresh anchors around the first changed line of a written file.
+struct UpdatedWindow {
+    lines: Vec<String>,
+    /// Hashes of `lines[start..end]`.
+    hashes: Vec<String>,
+    /// 0-based, end-exclusive line range to show.
+    start: usize,
+    end: usize,
+    file_hash: Option<String>,
+}
+
+fn updated_window(update: &FileUpdate, first_line: usize) -> Option<UpdatedWindow> {
+    let updated = read_normalized(Path::new(&update.path)).unwrap_or_default();
+    let lines: Vec<String> = updated.split('\n').map(str::to_string).collect();
+    let context = 2;
+    let start = first_line.saturating_sub(1 + context);
+    let end = lines
+        .len()
+        .min(start + (update.edit_count * 3).max(10) + context * 2);
+    if start >= end {
+        return None;
+    }
+    Some(UpdatedWindow {
+        hashes: compute_line_hashes_in(&lines, start..end, &update.hash),
+        lines,
+        start,
+        end,
+        file_hash: update
+            .report_fingerprint
+            .then(|| compute_file_fingerprint(&updated)),
+    })
+}
+
+/// Print fresh LINE:HASH anchors around the first changed line.
+fn print_updated_window(update: &FileUpdate, first_line: usize, with_path: bool) {
+    let Some(window) = updated_window(update, first_line) else {
+        return;
+    };
+    if with_path {
+        println!("--- {}", update.path);
+    } else {
+        println!("---");
+    }
+    if let Some(header) = format::format_read_header(&update.hash, window.file_hash.as_deref()) {
+        println!("{}", header);
+    }
+    let all_lines: Vec<&str> = window.lines.iter().map(String::as_str).collect();
+    println!(
+        "{}",
+        format::format_hashline_range(&all_lines, window.start, window.end, &update.hash)
+    );
+}
+
+/// Return the first path that is listed twice (after resolving symlinks and
+/// relative components where the file exists).
+fn find_duplicate_path(files: &[HashlineParams]) -> Option<String> {
+    let mut seen = std::collections::HashSet::new();
+    for params in files {
+        let key =
+            std::fs::canonicalize(&params.path).unwrap_or_else(|_| PathBuf::from(&params.path));
+        if !seen.insert(key) {
+            return Some(params.path.clone());
+        }
+    }
+    None
+}
@@ -183,6 +183,7 @@ fn run_benchmarks() -> Vec<BenchResult> {
                         set_line: SetLineOp {
                             anchor: format!("{}:{}", line_num, hash),
                             new_text: format!("    let var_{} = REPLACED;", line_idx),
+                            verbatim: false,
                         },
                     }
                 })
@@ -215,6 +216,7 @@ fn run_benchmarks() -> Vec<BenchResult> {
                     set_line: SetLineOp {
                         anchor: format!("{}:{}", line_num, hash),
                         new_text: format!("    let var_{} = REPLACED;", line_idx),
+                        verbatim: false,
                     },
                 }
             })
@@ -1,4 +1,7 @@
-use clap::{builder::RangedU64ValueParser, Parser, Subcommand, ValueEnum};
+use clap::{builder::RangedU64ValueParser, Args, Parser, Subcommand, ValueEnum};
+
+// Shared with the mangen binary, which includes this file by path.
+use hashline::hash::parse_hash_width;
 
 #[derive(Parser)]
 #[command(
@@ -10,7 +13,9 @@ allowing AI coding agents to reference lines by anchor rather than reproducing \
 exact text. Hash mismatches after file changes are detected before any edit is \
 applied, preventing silent corruption.\n\n\
 For JSON files, use JSONPath-based anchors (JSONPATH:VALUEHASH) for semantic editing.\n\n\
-Hash algorithm: xxHash32(whitespace_stripped_line, seed=0) % 256, formatted as 2 hex chars.\n\n\
+Hash algorithm: xxHash32(whitespace_stripped_line, seed=0) % 16^N, formatted as N hex chars. \
+N is 2 by default; set 3, 4 or 6 with --hash-width, HASHLINE_HASH_WIDTH or \
+{\"hash_width\": N} in .hashline.json (flag > env > file).\n\n\
 Exit codes: 0 = success, 1 = hash mismatch (stderr has updated anchors), 2 = other error.",
     after_long_help = include_str!("../cli_help.md"),
 )]
@@ -19,6 +24,29 @@ pub struct Cli {
     pub command: Commands,
 }
 
+/// Hash settings shared by every command that prints or checks anchors.
+#[derive(Args, Debug, Clone, Default)]
+pub struct HashArgs {
+    /// Anchor hash width in hex chars: 2, 3, 4 or 6 (overrides HASHLINE_HASH_WIDTH and .hashline.json)
+    #[arg(long, value_name = "N", value_parser = parse_hash_width)]
+    pub hash_width: Option<usize>,
+    /// Mix the nearest non-blank lines into each line hash (overrides HASHLINE_HASH_CONTEXT and .hashline.json)
+    #[arg(long, value_name = "MODE", value_enum)]
+    pub hash_context: Option<HashContextArg>,
+    /// Hash leading indentation: auto picks indent for .py, .yaml and Makefiles (overrides HASHLINE_HASH_WHITESPACE and .hashline.json)
+    #[arg(long, value_name = "POLICY", value_enum)]
+    pub hash_whitespace: Option<HashWhitespaceArg>,
+}
+
+/// Hash settings for the path-anchored JSON, YAML and TOML commands. Their
+/// anchors hash canonical values, not lines, so only the width applies.
+#[derive(Args, Debug, Clone, Default)]
+pub struct PathHashArgs {
+    /// Anchor hash width in hex chars: 2, 3, 4 or 6 (overrides HASHLINE_HASH_WIDTH and .hashline.json)
+    #[arg(long, value_name = "N", value_parser = parse_hash_width)]
+    pub hash_width: Option<usize>,
+}
+
 #[derive(Subcommand)]
 pub enum Commands {
     /// Read a file and output hashline-formatted content
@@ -26,16 +54,29 @@ pub enum Commands {
         long_about = "Read a file and output each line prefixed with its LINE:HASH anchor.\n\n\
 Each line of output has the form:\n\n\
     LINENUM:HASH|CONTENT\n\n\
-where HASH is a 2-char hex string derived from xxHash32 of the whitespace-stripped \
-line content. Use --start-line and --lines to read a specific range — useful for \
-verifying edits without re-reading an entire large file.",
+where HASH is a 2-char hex string (or --hash-width chars) derived from xxHash32 of the \
+whitespace-stripped line content. A non-default width is announced by a first line \
+`# hashline hash_width=N`; pass the same width to apply.\n\n\
+With --hash-context neighbors, each hash also covers the nearest non-blank lines \
+above and below, so repeated lines such as `}` get distinct anchors. The header then \
+includes `context=neighbors`.\n\n\
+For indentation-significant files (.py, .pyi, .yaml, .yml, .mk, Makefile) the hash keeps \
+leading indentation, so re-indenting a line changes its anchor; the header then includes \
+`whitespace=indent`. Override with --hash-whitespace.\n\n\
+Use --start-line and --lines to read a specific range — useful for verifying edits \
+without re-reading an entire large file.\n\n\
+With --fingerprint, a first line `# hashline file_hash=HASH:LINES` carries a fingerprint \
+of the whole file (xxHash64 + line count). Pass it back as \"expect_file_hash\" in an \
+apply payload to reject the edit if anything in the file changed since the read.",
         after_long_help = "EXAMPLES\n\
     Read the whole file:\n\
         hashline read src/main.rs\n\n\
     Read lines 50-74:\n\
         hashline read --start-line 50 --lines 25 src/main.rs\n\n\
     Read from line 100 to end of file:\n\
-        hashline read --start-line 100 src/main.rs"
+        hashline read --start-line 100 src/main.rs\n\n\
+    Read with a whole-file fingerprint for expect_file_hash:\n\
+        hashline read --fingerprint src/main.rs"
     )]
     Read {
         /// File path to read
@@ -46,6 +87,11 @@ verifying edits without re-reading an entire large file.",
         /// Maximum number of lines to output (u32::MAX limit: practical for files)
         #[arg(long, value_parser = RangedU64ValueParser::<usize>::new().range(1..=(u32::MAX as u64)))]
         lines: Option<usize>,
+        /// Print a `# hashline file_hash=HASH:LINES` header with the whole-file fingerprint
+        #[arg(long)]
+        fingerprint: bool,
+        #[command(flatten)]
+        hash: HashArgs,
     },
     /// Apply hashline edits to a file (reads JSON from stdin or --input file)
     #[command(
@@ -55,12 +101,44 @@ All anchors are validated against the current file state before any changes are
 correct updated LINE:HASH refs are printed to stderr.\n\n\
 Input format:\n\
     {\"path\": \"<file>\", \"edits\": [<edit>, ...]}\n\n\
-Supported edit operations: set_line, replace_lines, insert_after, replace.\n\
+Multi-file transaction (all files are validated first, then all are written or none):\n\
+    {\"files\": [{\"path\": \"<file>\", \"edits\": [...]}, ...]}\n\n\
+Optional concurrency guard (fingerprint from `hashline read --fingerprint`):\n\
+    {\"path\": ..., \"expect_file_hash\": \"<HASH:LINES>\", \"on_file_change\": \"refuse\"|\"strict\", \"edits\": [...]}\n\
+\"refuse\" (default) rejects the payload if the file changed at all; \"strict\" applies only \
+if every anchor still matches at its exact line, with no relocation.\n\n\
+Unified diffs (diff -u, git diff) are accepted with --patch FILE instead of JSON. \
+Each hunk's context and removed lines are matched like anchor hashes (ignoring \
+whitespace) and a hunk that moved is relocated to the only place it matches; if a \
+hunk cannot be placed, nothing is written and the stale lines are reported like \
+any other hash mismatch. A diff touching several files is applied as a transaction.\n\n\
+Supported edit operations: set_line, replace_lines, insert_after, insert_before, \
+delete_lines, insert_at_start, append_to_end, move_lines, copy_lines, patch, replace.\n\
 See hashline(1) for the full edit operation reference.\n\n\
 Exit codes:\n\
     0  All edits applied successfully\n\
     1  Hash mismatch — stderr contains updated LINE:HASH anchors, retry with those\n\
-    2  Other error (bad JSON, file not found, ambiguous replace match, etc.)",
+    2  Other error (bad JSON, file not found, overlapping edits, ambiguous replace match, etc.)\n\n\
+With --dry-run, everything is validated and computed but nothing is written; \
+the result is printed as a unified diff (default) or, with --dry-run=json, as \
+JSON listing each hunk and the indices of the edits that produced it. \
+Exit codes are the same.\n\n\
+--emit-patch FILE also saves the changes as a unified diff that `git apply` and \
+`hashline apply --patch` accept; each hunk header ends with the edits that produced \
+it, e.g. `@@ -4,3 +4,3 @@ edits 0,2`. It is written before any file is (and removed \
+again if the writes roll back), and with --dry-run as well; `--dry-run --emit-patch -` \
+prints just the patch. Paths are labelled a/ and b/ relative to the working directory; \
+a file outside it keeps its absolute path.\n\n\
+With --format json, the result or error is printed to stdout as a single JSON object \
+with \"status\" ok, mismatch or error. Success lists each file's warnings, per-edit status \
+(applied or noop) and updated anchors; a mismatch lists the stale anchors and their \
+remapped LINE:HASH values.\n\n\
+Edit text normally passes through recovery heuristics (copied LINE:HASH prefixes \
+are stripped, indentation is restored, echoed boundary lines are dropped, ...) and \
+stale anchors are relocated to the unique line with the same hash. --strict turns \
+all of that off for the whole call; \"verbatim\": true does the same for one edit. \
+To turn off single heuristics, use --disable-heuristic or a \"heuristics\" object in \
+.hashline.json, e.g. {\"heuristics\": {\"strip_diff_plus\": false}}.",
         after_long_help = "EXAMPLES\n\
     Recommended (writes payload to disk, emits fresh anchors):\n\
         hashline apply --emit-updated --input edits.json\n\
@@ -87,16 +165,63 @@ Exit codes:\n\
         EOF\n\n\
     Delete a range of lines:\n\
         hashline apply << 'EOF'\n\
-        {\"path\":\"src/main.rs\",\"edits\":[{\"replace_lines\":{\"start_anchor\":\"3:7f\",\"end_anchor\":\"5:0e\",\"new_text\":\"\"}}]}\n\
-        EOF"
+        {\"path\":\"src/main.rs\",\"edits\":[{\"delete_lines\":{\"start_anchor\":\"3:7f\",\"end_anchor\":\"5:0e\"}}]}\n\
+        EOF\n\n\
+    Edit two files atomically:\n\
+        hashline apply << 'EOF'\n\
+        {\"files\":[\n\
+          {\"path\":\"src/a.rs\",\"edits\":[{\"set_line\":{\"anchor\":\"4:01\",\"new_text\":\"use b::run;\"}}]},\n\
+          {\"path\":\"src/b.rs\",\"edits\":[{\"set_line\":{\"anchor\":\"9:3c\",\"new_text\":\"pub fn run() {}\"}}]}\n\
+        ]}\n\
+        EOF\n\n\
+    Keep a reviewable record of the change:\n\
+        hashline apply --emit-patch change.diff --input edits.json\n\
+        git apply --check -R change.diff\n\n\
+    Apply a unified diff:\n\
+        git diff > fix.diff && git stash && hashline apply --patch fix.diff\n\
+        hashline apply --patch - < fix.diff\n\n\
+    Preview without writing:\n\
+        hashline apply --dry-run --input edits.json\n\
+        hashline apply --dry-run=json --input edits.json"
     )]
     Apply {
         /// Read JSON input from a file instead of stdin
         #[arg(short, long, value_name = "FILE")]
         input: Option<String>,
+        /// Apply a unified diff (`diff -u`, `git diff`) instead of JSON edits; `-` reads stdin
+        #[arg(long, value_name = "FILE", conflicts_with = "input")]
+        patch: Option<String>,
         /// After successful apply, emit updated LINE:HASH anchors for changed region
         #[arg(long)]
         emit_updated: bool,
+        /// Validate and print the resulting changes without writing: `diff` (default) or `json`
+        #[arg(
+            long,
+            value_name = "FORMAT",
+            value_enum,
+            num_args = 0..=1,
+            require_equals = true,
+            default_missing_value = "diff",
+            conflicts_with = "emit_updated"
+        )]
+        dry_run: Option<DryRunFormat>,
+        /// Also write the changes as a unified diff (hunks name their edits); `-` for stdout
+        #[arg(long, value_name = "FILE")]
+        emit_patch: Option<String>,
+        /// Write every edit's text exactly as given: no heuristics, no anchor relocation
+        #[arg(long)]
+        strict: bool,
+        /// Turn off a recovery heuristic for this call (repeatable or comma-separated)
+        #[arg(long, value_name = "NAME", value_enum, value_delimiter = ',')]
+        disable_heuristic: Vec<HeuristicArg>,
+        /// Turn on a heuristic that .hashline.json disables (repeatable or comma-separated)
+        #[arg(long, value_name = "NAME", value_enum, value_delimiter = ',')]
+        enable_heuristic: Vec<HeuristicArg>,
+        /// Output format: `text` (default) or `json` (one object on stdout, also for errors)
+        #[arg(long, value_name = "FORMAT", value_enum, default_value = "text")]
+        format: OutputFormat,
+        #[command(flatten)]
+        hash: HashArgs,
     },
     /// Output line hashes for a file
     #[command(
@@ -108,23 +233,39 @@ Exit codes:\n\
     Hash {
         /// File path to hash
         file: String,
+        #[command(flatten)]
+        hash: HashArgs,
     },
     /// Read a JSON file and output JSONPath-anchored content
     #[command(
         long_about = "Read a JSON file and output with JSONPath-based anchors.\n\n\
 Each value gets a comment with its JSONPATH:HASH anchor before it. \
-Use this to collect anchors for JSON-aware edits.",
-        after_long_help = "EXAMPLES\n\n    Basic:\n        hashline json-read package.json\n\n    Output example with array indices:\n        {\n          // $.items[0]:ab\n          \"foo\",\n          // $.items[1]:cd\n          \"bar\"\n        }"
+Use this to collect anchors for JSON-aware edits.\n\n\
+JSONC and JSON5 files (comments, trailing commas, unquoted keys, ...) are \
+accepted; the anchored output is plain JSON.\n\n\
+--select prints only the values matching a JSONPath. Besides $.key, \
+$[\"quoted\"] and [N], paths may use negative indices ([-1]), wildcards \
+([*], .*), recursive descent (..name) and filters ([?(@.name==\"foo\")]). \
+A selector that can match several values is printed with its own anchor \
+first; that anchor validates every match together in json-apply.",
+        after_long_help = "EXAMPLES\n\n    Basic:\n        hashline json-read package.json\n\n    Only the version of every package:\n        hashline json-read --select '$.packages[*].version' manifest.json\n\n    Output example with array indices:\n        {\n          // $.items[0]:ab\n          \"foo\",\n          // $.items[1]:cd\n          \"bar\"\n        }"
     )]
     JsonRead {
         /// JSON file path to read
         file: String,
+        /// Print only the values matched by this JSONPath (e.g. '$.packages[*].version')
+        #[arg(long, value_name = "PATH")]
+        select: Option<String>,
+        #[command(flatten)]
+        hash: PathHashArgs,
     },
     /// Apply JSON-aware edits to a JSON file (reads JSON from stdin or --input file)
     #[command(
         long_about = "Read a JSON edit specification from stdin and apply it to a JSON file.\n\n\
 Uses JSONPath-based anchors instead of line numbers. All edits are atomic — \
-anchors are validated before any changes are made.\n\n\
+anchors are validated before any changes are made. Only the edited values \
+change on disk; formatting and comments elsewhere are kept, and JSONC / JSON5 \
+files stay in their dialect.\n\n\
 Input format:\n\
     {\"path\": \"<file>\", \"edits\": [<json_edit>, ...]}\n\n\
 Supported operations: set_path, insert_at_path, delete_path.\n\n\
@@ -152,6 +293,98 @@ Exit codes: 0 = success, 1 = hash mismatch, 2 = other error",
         /// After successful apply, emit updated JSONPATH:HASH anchors
         #[arg(long)]
         emit_updated: bool,
+        #[command(flatten)]
+        hash: PathHashArgs,
+    },
+    /// Read a YAML file and output path-anchored content
+    #[command(
+        long_about = "Read a YAML file and output with path-based anchors.\n\n\
+Each value gets a `# PATH:HASH` comment before it, using the same paths and \
+hashes as json-read. A stream of several documents is printed one document \
+per `---`, addressed as $[0], $[1], ...",
+        after_long_help = "EXAMPLES\n\n    Basic:\n        hashline yaml-read .github/workflows/ci.yml\n\n    Output example:\n        # $:3f\n        # $.name:ab\n        name: CI\n        # $.on:cd\n        on:\n          # $.on[0]:12\n          - push"
+    )]
+    YamlRead {
+        /// YAML file path to read
+        file: String,
+        #[command(flatten)]
+        hash: PathHashArgs,
+    },
+    /// Apply path-anchored edits to a YAML file (reads JSON from stdin or --input file)
+    #[command(
+        long_about = "Read a JSON edit specification from stdin and apply it to a YAML file.\n\n\
+Takes the same payload and operations as json-apply, with anchors from \
+yaml-read. All edits are atomic. Only the edited values change on disk: \
+comments, anchors and aliases, quoting and block or flow style elsewhere \
+are kept.\n\n\
+Input format:\n\
+    {\"path\": \"<file>\", \"edits\": [<json_edit>, ...]}\n\n\
+Supported operations: set_path, insert_at_path, delete_path.\n\n\
+Exit codes: 0 = success, 1 = hash mismatch, 2 = other error",
+        after_long_help = "EXAMPLES\n\
+    hashline yaml-apply --emit-updated --input yaml-edits.json\n\
+    yaml-edits.json:\n\
+        {\n\
+          \"path\": \"deploy.yaml\",\n\
+          \"edits\": [\n\
+            {\"set_path\": {\"anchor\": \"$[1].spec.replicas:4e\", \"value\": 3}}\n\
+          ]\n\
+        }"
+    )]
+    YamlApply {
+        /// Read JSON input from a file instead of stdin
+        #[arg(short, long, value_name = "FILE")]
+        input: Option<String>,
+        /// After successful apply, emit updated PATH:HASH anchors
+        #[arg(long)]
+        emit_updated: bool,
+        #[command(flatten)]
+        hash: PathHashArgs,
+    },
+    /// Read a TOML file and output path-anchored content
+    #[command(
+        long_about = "Read a TOML file and output with path-based anchors.\n\n\
+Each value gets a `# PATH:HASH` comment before it, using the same paths and \
+hashes as json-read: `$.dependencies.serde` is the `serde` key of \
+[dependencies], whether written as a key, an inline table or a table.",
+        after_long_help = "EXAMPLES\n\n    Basic:\n        hashline toml-read Cargo.toml\n\n    Output example:\n        # $.package:3f\n        [package]\n        # $.package.name:ab\n        name = \"demo\""
+    )]
+    TomlRead {
+        /// TOML file path to read
+        file: String,
+        #[command(flatten)]
+        hash: PathHashArgs,
+    },
+    /// Apply path-anchored edits to a TOML file (reads JSON from stdin or --input file)
+    #[command(
+        long_about = "Read a JSON edit specification from stdin and apply it to a TOML file.\n\n\
+Takes the same payload and operations as json-apply, with anchors from \
+toml-read. All edits are atomic. Only the edited values change on disk: \
+comments, key order and table layout elsewhere are kept. New keys are \
+appended to their table; a new top-level table gets its own [header].\n\n\
+Input format:\n\
+    {\"path\": \"<file>\", \"edits\": [<json_edit>, ...]}\n\n\
+Supported operations: set_path, insert_at_path, delete_path.\n\n\
+Exit codes: 0 = success, 1 = hash mismatch, 2 = other error",
+        after_long_help = "EXAMPLES\n\
+    hashline toml-apply --emit-updated --input toml-edits.json\n\
+    toml-edits.json:\n\
+        {\n\
+          \"path\": \"Cargo.toml\",\n\
+          \"edits\": [\n\
+            {\"insert_at_path\": {\"anchor\": \"$.dependencies:27\", \"key\": \"anyhow\", \"value\": \"1\"}}\n\
+          ]\n\
+        }"
+    )]
+    TomlApply {
+        /// Read JSON input from a file instead of stdin
+        #[arg(short, long, value_name = "FILE")]
+        input: Option<String>,
+        /// After successful apply, emit updated PATH:HASH anchors
+        #[arg(long)]
+        emit_updated: bool,
+        #[command(flatten)]
+        hash: PathHashArgs,
     },
     /// Claude Code hook handlers (read-before-apply enforcement)
     #[command(
@@ -200,6 +433,44 @@ pub enum HookAction {
     Post,
 }
 
+#[derive(Clone, Copy, Debug, ValueEnum)]
+#[value(rename_all = "snake_case")]
+pub enum HeuristicArg {
+    StripHashlinePrefix,
+    StripDiffPlus,
+    StripBoundaryEcho,
+    StripAnchorEcho,
+    RestoreWrappedLines,
+    RestoreIndent,
+    ExpandMerge,
+    NormalizeHyphens,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum DryRunFormat {
+    Diff,
+    Json,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum HashContextArg {
+    None,
+    Neighbors,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum HashWhitespaceArg {
+    Auto,
+    Ignore,
+    Indent,
+}
+
 #[derive(Clone, Copy, Debug, ValueEnum)]
 pub enum SetupAgent {
     Claude,
@@ -0,0 +1,321 @@
+//! Project configuration.
+//!
+//! Settings come from, in order of precedence: CLI flags, `HASHLINE_*`
+//! environment variables, a `.hashline.json` file, then built-in defaults.
+//! The file is found by walking up from the working directory; set
+//! `HASHLINE_CONFIG` to use a specific file instead.
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::hash::{
+    parse_hash_context, parse_hash_whitespace, parse_hash_width, HashContext, HashOptions,
+    HashWhitespace,
+};
+use crate::heuristics::{parse_heuristic, Heuristic, HeuristicSet};
+
+/// Config file name searched for in the working directory and its ancestors.
+pub const CONFIG_FILE_NAME: &str = ".hashline.json";
+/// Env var naming an explicit config file (skips the upward search).
+pub const CONFIG_PATH_ENV: &str = "HASHLINE_CONFIG";
+/// Env var overriding the anchor hash width.
+pub const HASH_WIDTH_ENV: &str = "HASHLINE_HASH_WIDTH";
+/// Env var overriding the hash context mode.
+pub const HASH_CONTEXT_ENV: &str = "HASHLINE_HASH_CONTEXT";
+/// Env var overriding the hash whitespace policy.
+pub const HASH_WHITESPACE_ENV: &str = "HASHLINE_HASH_WHITESPACE";
+
+/// Hash settings given as CLI flags. `None` defers to env, then config file.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HashOverrides {
+    pub width: Option<usize>,
+    pub context: Option<HashContext>,
+    pub whitespace: Option<HashWhitespace>,
+}
+
+/// Heuristic switches given as CLI flags; they override the config file.
+#[derive(Debug, Clone, Default)]
+pub struct HeuristicOverrides {
+    pub enable: Vec<Heuristic>,
+    pub disable: Vec<Heuristic>,
+}
+
+/// Contents of `.hashline.json`. Unknown keys are ignored.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Config {
+    /// Anchor hash width in hex chars (2, 3, 4 or 6).
+    #[serde(default)]
+    pub hash_width: Option<usize>,
+    /// Hash context mode: `"none"` or `"neighbors"`.
+    #[serde(default)]
+    pub hash_context: Option<HashContext>,
+    /// Whitespace policy: `"auto"` (default), `"ignore"` or `"indent"`.
+    #[serde(default)]
+    pub hash_whitespace: Option<HashWhitespace>,
+    /// Per-heuristic switches, e.g. `{"restore_indent": false}`. Unlisted
+    /// heuristics stay enabled.
+    #[serde(default)]
+    pub heuristics: BTreeMap<String, bool>,
+}
+
+impl Config {
+    /// Load the config named by `HASHLINE_CONFIG`, or the nearest
+    /// `.hashline.json` above the working directory. Missing means default.
+    pub fn load() -> Result<Config, String> {
+        if let Some(path) = std::env::var_os(CONFIG_PATH_ENV) {
+            return Config::load_from(Path::new(&path));
+        }
+        let cwd = std::env::current_dir().map_err(|e| e.to_string())?;
+        match find_config_file(&cwd) {
+            Some(path) => Config::load_from(&path),
+            None => Ok(Config::default()),
+        }
+    }
+
+    /// Load and validate a specific config file.
+    pub fn load_from(path: &Path) -> Result<Config, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Error reading config {}: {}", path.display(), e))?;
+        let config: Config = serde_json::from_str(&text)
+            .map_err(|e| format!("Invalid config {}: {}", path.display(), e))?;
+        if let Some(width) = config.hash_width {
+            parse_hash_width(&width.to_string())
+                .map_err(|e| format!("Invalid config {}: {}", path.display(), e))?;
+        }
+        for name in config.heuristics.keys() {
+            parse_heuristic(name)
+                .map_err(|e| format!("Invalid config {}: heuristics: {}", path.display(), e))?;
+        }
+        Ok(config)
+    }
+
+    /// Resolve which heuristics run: CLI flags > config file > all enabled.
+    /// A heuristic both enabled and disabled on the command line stays off.
+    pub fn heuristics(&self, cli: &HeuristicOverrides) -> Result<HeuristicSet, String> {
+        let mut set = HeuristicSet::all();
+        for (name, &enabled) in &self.heuristics {
+            set.set(parse_heuristic(name)?, enabled);
+        }
+        for &h in &cli.enable {
+            set.set(h, true);
+        }
+        for &h in &cli.disable {
+            set.set(h, false);
+        }
+        Ok(set)
+    }
+
+    /// Resolve hash options: CLI flag > `HASHLINE_*` env var > config file.
+    ///
+    /// The whitespace policy defaults to `auto`; call
+    /// [`HashOptions::for_path`] before hashing a particular file.
+    pub fn hash_options(&self, cli: &HashOverrides) -> Result<HashOptions, String> {
+        self.hash_options_with_env(cli, |key| std::env::var(key).ok())
+    }
+
+    fn hash_options_with_env(
+        &self,
+        cli: &HashOverrides,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<HashOptions, String> {
+        let env_value = |key: &str| env(key).filter(|s| !s.trim().is_empty());
+        let mut options = HashOptions::default();
+        if let Some(width) = cli.width {
+            options.width = width;
+        } else if let Some(raw) = env_value(HASH_WIDTH_ENV) {
+            options.width =
+                parse_hash_width(&raw).map_err(|e| format!("{}: {}", HASH_WIDTH_ENV, e))?;
+        } else if let Some(width) = self.hash_width {
+            options.width = width;
+        }
+        if let Some(context) = cli.context {
+            options.context = context;
+        } else if let Some(raw) = env_value(HASH_CONTEXT_ENV) {
+            options.context =
+                parse_hash_context(&raw).map_err(|e| format!("{}: {}", HASH_CONTEXT_ENV, e))?;
+        } else if let Some(context) = self.hash_context {
+            options.context = context;
+        }
+        options.whitespace = if let Some(whitespace) = cli.whitespace {
+            whitespace
+        } else if let Some(raw) = env_value(HASH_WHITESPACE_ENV) {
+            parse_hash_whitespace(&raw).map_err(|e| format!("{}: {}", HASH_WHITESPACE_ENV, e))?
+        } else {
+            self.hash_whitespace.unwrap_or(HashWhitespace::Auto)
+        };
+        Ok(options)
+    }
+}
+
+/// Find the nearest `.hashline.json` in `start` or one of its ancestors.
+pub fn find_config_file(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .map(|dir| dir.join(CONFIG_FILE_NAME))
+        .find(|p| p.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn no_env(_: &str) -> Option<String> {
+        None
+    }
+
+    fn env_with(key: &'static str, value: &'static str) -> impl Fn(&str) -> Option<String> {
+        move |k| (k == key).then(|| value.to_string())
+    }
+
+    #[test]
+    fn default_when_nothing_set() {
+        let options = Config::default()
+            .hash_options_with_env(&HashOverrides::default(), no_env)
+            .unwrap();
+        assert_eq!(
+            options,
+            HashOptions {
+                whitespace: HashWhitespace::Auto,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn whitespace_policy_precedence() {
+        let config: Config = serde_json::from_str(r#"{"hash_whitespace": "indent"}"#).unwrap();
+        let none = HashOverrides::default();
+        assert_eq!(
+            config
+                .hash_options_with_env(&none, no_env)
+                .unwrap()
+                .whitespace,
+            HashWhitespace::Indent
+        );
+        let env = env_with(HASH_WHITESPACE_ENV, "ignore");
+        assert_eq!(
+            config
+                .hash_options_with_env(&none, &env)
+                .unwrap()
+                .whitespace,
+            HashWhitespace::Ignore
+        );
+        let cli = HashOverrides {
+            whitespace: Some(HashWhitespace::Auto),
+            ..Default::default()
+        };
+        assert_eq!(
+            config.hash_options_with_env(&cli, &env).unwrap().whitespace,
+            HashWhitespace::Auto
+        );
+    }
+
+    #[test]
+    fn precedence_cli_env_file() {
+        let config = Config {
+            hash_width: Some(3),
+            ..Default::default()
+        };
+        let none = HashOverrides::default();
+        let cli = HashOverrides {
+            width: Some(6),
+            ..Default::default()
+        };
+        let env = env_with(HASH_WIDTH_ENV, "4");
+        assert_eq!(
+            config.hash_options_with_env(&none, no_env).unwrap().width,
+            3
+        );
+        assert_eq!(config.hash_options_with_env(&none, &env).unwrap().width, 4);
+        assert_eq!(config.hash_options_with_env(&cli, &env).unwrap().width, 6);
+    }
+
+    #[test]
+    fn context_from_config_env_and_cli() {
+        let config: Config = serde_json::from_str(r#"{"hash_context": "neighbors"}"#).unwrap();
+        let none = HashOverrides::default();
+        assert_eq!(
+            config.hash_options_with_env(&none, no_env).unwrap().context,
+            HashContext::Neighbors
+        );
+        let env = env_with(HASH_CONTEXT_ENV, "none");
+        assert_eq!(
+            config.hash_options_with_env(&none, &env).unwrap().context,
+            HashContext::None
+        );
+        let cli = HashOverrides {
+            context: Some(HashContext::Neighbors),
+            ..Default::default()
+        };
+        assert_eq!(
+            Config::default()
+                .hash_options_with_env(&cli, &env)
+                .unwrap()
+                .context,
+            HashContext::Neighbors
+        );
+    }
+
+    #[test]
+    fn rejects_bad_env_width() {
+        let err = Config::default()
+            .hash_options_with_env(&HashOverrides::default(), env_with(HASH_WIDTH_ENV, "5"))
+            .unwrap_err();
+        assert!(err.contains(HASH_WIDTH_ENV));
+    }
+
+    #[test]
+    fn heuristics_from_config_and_cli() {
+        let config: Config = serde_json::from_str(
+            r#"{"heuristics": {"restore_indent": false, "strip_diff_plus": false}}"#,
+        )
+        .unwrap();
+        let set = config.heuristics(&HeuristicOverrides::default()).unwrap();
+        assert!(!set.contains(Heuristic::RestoreIndent));
+        assert!(!set.contains(Heuristic::StripDiffPlus));
+        assert!(set.contains(Heuristic::ExpandMerge));
+
+        let cli = HeuristicOverrides {
+            enable: vec![Heuristic::RestoreIndent],
+            disable: vec![Heuristic::ExpandMerge],
+        };
+        let set = config.heuristics(&cli).unwrap();
+        assert!(set.contains(Heuristic::RestoreIndent));
+        assert!(!set.contains(Heuristic::ExpandMerge));
+        assert!(!set.contains(Heuristic::StripDiffPlus));
+    }
+
+    #[test]
+    fn load_from_rejects_unknown_heuristic() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(CONFIG_FILE_NAME);
+        std::fs::write(&path, r#"{"heuristics": {"restore_indnet": false}}"#).unwrap();
+        let err = Config::load_from(&path).unwrap_err();
+        assert!(
+            err.contains("unknown heuristic 'restore_indnet'"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn finds_config_in_ancestor() {
+        let dir = TempDir::new().unwrap();
+        let nested = dir.path().join("a").join("b");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::write(dir.path().join(CONFIG_FILE_NAME), r#"{"hash_width": 4}"#).unwrap();
+        let found = find_config_file(&nested).unwrap();
+        assert_eq!(found, dir.path().join(CONFIG_FILE_NAME));
+        assert_eq!(Config::load_from(&found).unwrap().hash_width, Some(4));
+    }
+
+    #[test]
+    fn load_from_rejects_unsupported_width() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(CONFIG_FILE_NAME);
+        std::fs::write(&path, r#"{"hash_width": 8}"#).unwrap();
+        let err = Config::load_from(&path).unwrap_err();
+        assert!(err.contains("unsupported hash width 8"));
+    }
+}
@@ -0,0 +1,519 @@
+//! Line diffs (Myers) and unified-diff rendering.
+//!
+//! Lines keep their terminator, so a missing final newline shows up as
+//! `\ No newline at end of file` exactly as `diff -u` and `git diff` print it.
+
+/// Default number of context lines around each hunk.
+pub const DEFAULT_CONTEXT: usize = 3;
+
+/// One step of an edit script from `old` to `new` (0-based indices).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffOp {
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/// Kind of a line inside a hunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineKind {
+    Context,
+    Removed,
+    Added,
+}
+
+impl LineKind {
+    fn prefix(&self) -> char {
+        match self {
+            LineKind::Context => ' ',
+            LineKind::Removed => '-',
+            LineKind::Added => '+',
+        }
+    }
+}
+
+/// A line in a hunk. `text` includes its `\n` (or `\r\n`) unless it is the
+/// last line of a file without a final newline.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HunkLine {
+    pub kind: LineKind,
+    pub text: String,
+}
+
+/// A unified-diff hunk. Line numbers are 1-based; an empty side starts at the
+/// line *before* the hunk, as in `@@ -0,0 +1,2 @@`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    pub old_start: usize,
+    pub old_lines: usize,
+    pub new_start: usize,
+    pub new_lines: usize,
+    pub lines: Vec<HunkLine>,
+}
+
+/// A run of removed and/or added lines inside a hunk.
+///
+/// Ranges are 1-based and inclusive; an empty side has `end == start - 1`,
+/// with `start` the line the other side's lines go in front of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChangeBlock {
+    pub old_start: usize,
+    pub old_end: usize,
+    pub new_start: usize,
+    pub new_end: usize,
+}
+
+impl Hunk {
+    /// `@@ -a,b +c,d @@` (counts of 1 are omitted, like `diff -u`).
+    pub fn header(&self) -> String {
+        fn range(start: usize, len: usize) -> String {
+            if len == 1 {
+                start.to_string()
+            } else {
+                format!("{},{}", start, len)
+            }
+        }
+        format!(
+            "@@ -{} +{} @@",
+            range(self.old_start, self.old_lines),
+            range(self.new_start, self.new_lines)
+        )
+    }
+
+    /// Contiguous runs of changed lines, with their old and new positions.
+    pub fn change_blocks(&self) -> Vec<ChangeBlock> {
+        // Position of the next line on each side.
+        let mut old = if self.old_lines == 0 {
+            self.old_start + 1
+        } else {
+            self.old_start
+        };
+        let mut new = if self.new_lines == 0 {
+            self.new_start + 1
+        } else {
+            self.new_start
+        };
+        let mut blocks = Vec::new();
+        let mut current: Option<ChangeBlock> = None;
+        for line in &self.lines {
+            match line.kind {
+                LineKind::Context => {
+                    blocks.extend(current.take());
+                    old += 1;
+                    new += 1;
+                }
+                LineKind::Removed => {
+                    let block = current.get_or_insert(ChangeBlock {
+                        old_start: old,
+                        old_end: old - 1,
+                        new_start: new,
+                        new_end: new - 1,
+                    });
+                    block.old_end = old;
+                    old += 1;
+                }
+                LineKind::Added => {
+                    let block = current.get_or_insert(ChangeBlock {
+                        old_start: old,
+                        old_end: old - 1,
+                        new_start: new,
+                        new_end: new - 1,
+                    });
+                    block.new_end = new;
+                    new += 1;
+                }
+            }
+        }
+        blocks.extend(current);
+        blocks
+    }
+
+    /// Render the header and lines, including no-newline markers.
+    pub fn render(&self) -> String {
+        self.render_with_heading("")
+    }
+
+    /// Like [`Hunk::render`], with `heading` after the header the way
+    /// `diff -p` shows the enclosing function. Patch tools ignore it.
+    pub fn render_with_heading(&self, heading: &str) -> String {
+        let mut out = self.header();
+        if !heading.is_empty() {
+            out.push(' ');
+            out.push_str(heading);
+        }
+        out.push('\n');
+        for line in &self.lines {
+            out.push(line.kind.prefix());
+            out.push_str(&line.text);
+            if !line.text.ends_with('\n') {
+                out.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+        out
+    }
+}
+
+/// Compute a shortest edit script from `old` to `new` (Myers' O(ND) algorithm,
+/// in its linear-space form: memory stays O(N + M) however many lines change).
+pub fn diff_ops<T: PartialEq>(old: &[T], new: &[T]) -> Vec<DiffOp> {
+    let mut ops = Vec::with_capacity(old.len().max(new.len()));
+    let mut vf = vec![0isize; 2 * (old.len() + new.len()) + 3];
+    let mut vb = vf.clone();
+    diff_between(old, new, 0, 0, &mut vf, &mut vb, &mut ops);
+    ops
+}
+
+/// Append the edit script from `a` to `b`, which start at `x_base` and
+/// `y_base` in the full sequences: split at the middle snake and solve each
+/// half the same way. `vf`/`vb` are scratch rows shared by every call.
+fn diff_between<T: PartialEq>(
+    a: &[T],
+    b: &[T],
+    x_base: usize,
+    y_base: usize,
+    vf: &mut [isize],
+    vb: &mut [isize],
+    ops: &mut Vec<DiffOp>,
+) {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    ops.extend((0..prefix).map(|i| DiffOp::Equal(x_base + i, y_base + i)));
+    let (a, b) = (&a[prefix..], &b[prefix..]);
+    let (x_base, y_base) = (x_base + prefix, y_base + prefix);
+    let suffix = a
+        .iter()
+        .rev()
+        .zip(b.iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (a, b) = (&a[..a.len() - suffix], &b[..b.len() - suffix]);
+
+    if a.is_empty() {
+        ops.extend((0..b.len()).map(|j| DiffOp::Insert(y_base + j)));
+    } else if b.is_empty() {
+        ops.extend((0..a.len()).map(|i| DiffOp::Delete(x_base + i)));
+    } else {
+        let (x0, y0, x1, y1) = middle_snake(a, b, vf, vb);
+        diff_between(&a[..x0], &b[..y0], x_base, y_base, vf, vb, ops);
+        ops.extend((0..x1 - x0).map(|i| DiffOp::Equal(x_base + x0 + i, y_base + y0 + i)));
+        diff_between(&a[x1..], &b[y1..], x_base + x1, y_base + y1, vf, vb, ops);
+    }
+
+    let (x_tail, y_tail) = (x_base + a.len(), y_base + b.len());
+    ops.extend((0..suffix).map(|i| DiffOp::Equal(x_tail + i, y_tail + i)));
+}
+
+/// Run the greedy search forward from the start and backward from the end
+/// until they overlap, and return the snake `(x0, y0) → (x1, y1)` where they
+/// meet. It splits a shortest script into two with about half the edits each.
+/// `a` and `b` must both be non-empty.
+fn middle_snake<T: PartialEq>(
+    a: &[T],
+    b: &[T],
+    vf: &mut [isize],
+    vb: &mut [isize],
+) -> (usize, usize, usize, usize) {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    // Diagonal k forward is diagonal `delta - k` backward.
+    let delta = n - m;
+    let odd = delta % 2 != 0;
+    let offset = (vf.len() / 2) as isize;
+    let at = |k: isize| (k + offset) as usize;
+    vf[at(1)] = 0;
+    vb[at(1)] = 0;
+
+    for d in 0..=(n + m + 1) / 2 {
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && vf[at(k - 1)] < vf[at(k + 1)]) {
+                vf[at(k + 1)]
+            } else {
+                vf[at(k - 1)] + 1
+            };
+            let mut y = x - k;
+            let (x0, y0) = (x, y);
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            vf[at(k)] = x;
+            if odd && (delta - k).abs() < d && x + vb[at(delta - k)] >= n {
+                return (x0 as usize, y0 as usize, x as usize, y as usize);
+            }
+        }
+        // Backward, in coordinates measured from the end of both sequences.
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && vb[at(k - 1)] < vb[at(k + 1)]) {
+                vb[at(k + 1)]
+            } else {
+                vb[at(k - 1)] + 1
+            };
+            let mut y = x - k;
+            let (x0, y0) = (x, y);
+            while x < n && y < m && a[(n - x - 1) as usize] == b[(m - y - 1) as usize] {
+                x += 1;
+                y += 1;
+            }
+            vb[at(k)] = x;
+            if !odd && (delta - k).abs() <= d && x + vf[at(delta - k)] >= n {
+                return (
+                    (n - x) as usize,
+                    (m - y) as usize,
+                    (n - x0) as usize,
+                    (m - y0) as usize,
+                );
+            }
+        }
+    }
+    unreachable!("forward and backward searches always meet")
+}
+
+/// Split text into lines that keep their terminators.
+fn split_lines(text: &str) -> Vec<&str> {
+    text.split_inclusive('\n').collect()
+}
+
+/// Group a diff of `old` → `new` into hunks with `context` lines around changes.
+pub fn diff_hunks(old: &str, new: &str, context: usize) -> Vec<Hunk> {
+    let old_lines = split_lines(old);
+    let new_lines = split_lines(new);
+    let ops = diff_ops(&old_lines, &new_lines);
+
+    let changes: Vec<usize> = ops
+        .iter()
+        .enumerate()
+        .filter(|(_, op)| !matches!(op, DiffOp::Equal(..)))
+        .map(|(i, _)| i)
+        .collect();
+    if changes.is_empty() {
+        return Vec::new();
+    }
+
+    // Lines consumed on each side before op i.
+    let mut before = Vec::with_capacity(ops.len() + 1);
+    let (mut o, mut n) = (0, 0);
+    for op in &ops {
+        before.push((o, n));
+        match op {
+            DiffOp::Equal(..) => {
+                o += 1;
+                n += 1;
+            }
+            DiffOp::Delete(_) => o += 1,
+            DiffOp::Insert(_) => n += 1,
+        }
+    }
+    before.push((o, n));
+
+    // Merge changes whose context windows touch.
+    let mut groups: Vec<(usize, usize)> = Vec::new();
+    for &i in &changes {
+        let start = i.saturating_sub(context);
+        let end = (i + context + 1).min(ops.len());
+        match groups.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => groups.push((start, end)),
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|(start, end)| {
+            let lines: Vec<HunkLine> = ops[start..end]
+                .iter()
+                .map(|op| match *op {
+                    DiffOp::Equal(x, _) => HunkLine {
+                        kind: LineKind::Context,
+                        text: old_lines[x].to_string(),
+                    },
+                    DiffOp::Delete(x) => HunkLine {
+                        kind: LineKind::Removed,
+                        text: old_lines[x].to_string(),
+                    },
+                    DiffOp::Insert(y) => HunkLine {
+                        kind: LineKind::Added,
+                        text: new_lines[y].to_string(),
+                    },
+                })
+                .collect();
+            let (old_before, new_before) = before[start];
+            let old_count = before[end].0 - old_before;
+            let new_count = before[end].1 - new_before;
+            Hunk {
+                old_start: if old_count == 0 {
+                    old_before
+                } else {
+                    old_before + 1
+                },
+                old_lines: old_count,
+                new_start: if new_count == 0 {
+                    new_before
+                } else {
+                    new_before + 1
+                },
+                new_lines: new_count,
+                lines,
+            }
+        })
+        .collect()
+}
+
+/// Render `---`/`+++` headers followed by hunks. Empty when nothing changed.
+pub fn render_unified(old_label: &str, new_label: &str, hunks: &[Hunk]) -> String {
+    if hunks.is_empty() {
+        return String::new();
+    }
+    let mut out = format!("--- {}\n+++ {}\n", old_label, new_label);
+    for hunk in hunks {
+        out.push_str(&hunk.render());
+    }
+    out
+}
+
+/// Unified diff of `old` → `new` with [`DEFAULT_CONTEXT`] lines of context.
+#[allow(dead_code)]
+pub fn unified_diff(old_label: &str, new_label: &str, old: &str, new: &str) -> String {
+    render_unified(old_label, new_label, &diff_hunks(old, new, DEFAULT_CONTEXT))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply_ops(old: &[&str], new: &[&str], ops: &[DiffOp]) -> Vec<String> {
+        let mut out = Vec::new();
+        let mut seen_old = 0;
+        for op in ops {
+            match *op {
+                DiffOp::Equal(x, y) => {
+                    assert_eq!(old[x], new[y]);
+                    assert_eq!(x, seen_old);
+                    seen_old += 1;
+                    out.push(old[x].to_string());
+                }
+                DiffOp::Delete(x) => {
+                    assert_eq!(x, seen_old);
+                    seen_old += 1;
+                }
+                DiffOp::Insert(y) => out.push(new[y].to_string()),
+            }
+        }
+        assert_eq!(seen_old, old.len());
+        out
+    }
+
+    #[test]
+    fn ops_reproduce_new_sequence() {
+        let cases: &[(&[&str], &[&str])] = &[
+            (&[], &[]),
+            (&["a"], &[]),
+            (&[], &["a"]),
+            (&["a", "b", "c"], &["a", "x", "c"]),
+            (
+                &["a", "b", "c", "a", "b", "b", "a"],
+                &["c", "b", "a", "b", "a", "c"],
+            ),
+            (&["x", "y"], &["y", "x"]),
+        ];
+        for (old, new) in cases {
+            let ops = diff_ops(old, new);
+            assert_eq!(apply_ops(old, new, &ops), new.to_vec());
+        }
+    }
+
+    #[test]
+    fn myers_is_minimal() {
+        // Classic example from the paper: D = 5.
+        let old = ["a", "b", "c", "a", "b", "b", "a"];
+        let new = ["c", "b", "a", "b", "a", "c"];
+        let edits = diff_ops(&old, &new)
+            .iter()
+            .filter(|op| !matches!(op, DiffOp::Equal(..)))
+            .count();
+        assert_eq!(edits, 5);
+    }
+
+    #[test]
+    fn unified_diff_single_change() {
+        let diff = unified_diff("a/f", "b/f", "1\n2\n3\n", "1\ntwo\n3\n");
+        assert_eq!(
+            diff,
+            "--- a/f\n+++ b/f\n@@ -1,3 +1,3 @@\n 1\n-2\n+two\n 3\n"
+        );
+    }
+
+    #[test]
+    fn unified_diff_empty_when_equal() {
+        assert_eq!(unified_diff("a", "b", "x\n", "x\n"), "");
+    }
+
+    #[test]
+    fn unified_diff_marks_missing_final_newline() {
+        let diff = unified_diff("a/f", "b/f", "x\ny", "x\nz");
+        assert_eq!(
+            diff,
+            "--- a/f\n+++ b/f\n@@ -1,2 +1,2 @@\n x\n-y\n\\ No newline at end of file\n+z\n\\ No newline at end of file\n"
+        );
+    }
+
+    #[test]
+    fn heading_follows_hunk_header() {
+        let hunks = diff_hunks("a\n", "b\n", 3);
+        assert_eq!(
+            hunks[0].render_with_heading("edit 0"),
+            "@@ -1 +1 @@ edit 0\n-a\n+b\n"
+        );
+        assert_eq!(hunks[0].render_with_heading(""), hunks[0].render());
+    }
+
+    #[test]
+    fn hunks_split_on_distant_changes() {
+        let line = |i: usize| match i {
+            2 => "two\n".to_string(),
+            19 => "nineteen\n".to_string(),
+            _ => format!("{}\n", i),
+        };
+        let old: String = (1..=20).map(|i| format!("{}\n", i)).collect();
+        let new: String = (1..=20).map(line).collect();
+        let hunks = diff_hunks(&old, &new, 3);
+        assert_eq!(hunks.len(), 2);
+        assert_eq!(hunks[0].header(), "@@ -1,5 +1,5 @@");
+        assert_eq!(hunks[1].header(), "@@ -16,5 +16,5 @@");
+    }
+
+    #[test]
+    fn pure_insertion_header_and_blocks() {
+        let hunks = diff_hunks("", "a\nb\n", 3);
+        assert_eq!(hunks[0].header(), "@@ -0,0 +1,2 @@");
+        assert_eq!(
+            hunks[0].change_blocks(),
+            vec![ChangeBlock {
+                old_start: 1,
+                old_end: 0,
+                new_start: 1,
+                new_end: 2
+            }]
+        );
+    }
+
+    #[test]
+    fn change_blocks_track_positions() {
+        let hunks = diff_hunks("a\nb\nc\nd\n", "a\nB\nc\nd\ne\n", 1);
+        let blocks: Vec<ChangeBlock> = hunks.iter().flat_map(|h| h.change_blocks()).collect();
+        assert_eq!(
+            blocks,
+            vec![
+                ChangeBlock {
+                    old_start: 2,
+                    old_end: 2,
+                    new_start: 2,
+                    new_end: 2
+                },
+                ChangeBlock {
+                    old_start: 5,
+                    old_end: 4,
+                    new_start: 5,
+                    new_end: 5
+                },
+            ]
+        );
+    }
+}
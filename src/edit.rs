@@ -1,7 +1,8 @@
-use crate::error::{HashMismatch, HashlineMismatchError};
-use crate::hash::compute_line_hash;
-use crate::heuristics;
-use crate::parse::parse_line_ref;
+use crate::error::{ConflictingEdit, EditConflictError, HashMismatch, HashlineMismatchError};
+use crate::hash::{compute_line_hashes, normalize_line, HashOptions};
+use crate::heuristics::{self, Heuristic, HeuristicEvent, HeuristicSet};
+use crate::parse::parse_line_ref_with_width;
+use crate::patch;
 use serde::Deserialize;
 use std::collections::{HashMap, HashSet};
 
@@ -18,16 +19,76 @@ pub enum HashlineEdit {
     InsertAfter {
         insert_after: InsertAfterOp,
     },
+    DeleteLines {
+        delete_lines: DeleteLinesOp,
+    },
+    InsertBefore {
+        insert_before: InsertBeforeOp,
+    },
+    InsertAtStart {
+        insert_at_start: InsertTextOp,
+    },
+    AppendToEnd {
+        append_to_end: InsertTextOp,
+    },
+    MoveLines {
+        move_lines: TransferLinesOp,
+    },
+    CopyLines {
+        copy_lines: TransferLinesOp,
+    },
+    Patch {
+        patch: PatchOp,
+    },
     Replace {
         #[allow(dead_code)]
         replace: ReplaceOp,
     },
 }
 
+impl HashlineEdit {
+    /// Operation name as it appears in the JSON payload.
+    pub fn op_name(&self) -> &'static str {
+        match self {
+            HashlineEdit::SetLine { .. } => "set_line",
+            HashlineEdit::ReplaceLines { .. } => "replace_lines",
+            HashlineEdit::InsertAfter { .. } => "insert_after",
+            HashlineEdit::DeleteLines { .. } => "delete_lines",
+            HashlineEdit::InsertBefore { .. } => "insert_before",
+            HashlineEdit::InsertAtStart { .. } => "insert_at_start",
+            HashlineEdit::AppendToEnd { .. } => "append_to_end",
+            HashlineEdit::MoveLines { .. } => "move_lines",
+            HashlineEdit::CopyLines { .. } => "copy_lines",
+            HashlineEdit::Patch { .. } => "patch",
+            HashlineEdit::Replace { .. } => "replace",
+        }
+    }
+
+    /// Whether the edit asked for its text to be used exactly as given.
+    pub fn is_verbatim(&self) -> bool {
+        match self {
+            HashlineEdit::SetLine { set_line } => set_line.verbatim,
+            HashlineEdit::ReplaceLines { replace_lines } => replace_lines.verbatim,
+            HashlineEdit::InsertAfter { insert_after } => insert_after.verbatim,
+            HashlineEdit::DeleteLines { delete_lines } => delete_lines.verbatim,
+            HashlineEdit::InsertBefore { insert_before } => insert_before.verbatim,
+            HashlineEdit::InsertAtStart { insert_at_start } => insert_at_start.verbatim,
+            HashlineEdit::AppendToEnd { append_to_end } => append_to_end.verbatim,
+            HashlineEdit::MoveLines { move_lines } => move_lines.verbatim,
+            HashlineEdit::CopyLines { copy_lines } => copy_lines.verbatim,
+            HashlineEdit::Patch { patch } => patch.verbatim,
+            HashlineEdit::Replace { .. } => true,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct SetLineOp {
     pub anchor: String,
     pub new_text: String,
+    /// Write `new_text` exactly as given: no heuristics, no anchor relocation.
+    #[serde(default)]
+    pub verbatim: bool,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -35,6 +96,9 @@ pub struct ReplaceLinesOp {
     pub start_anchor: String,
     pub end_anchor: Option<String>,
     pub new_text: Option<String>,
+    /// Write `new_text` exactly as given: no heuristics, no anchor relocation.
+    #[serde(default)]
+    pub verbatim: bool,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -42,13 +106,117 @@ pub struct InsertAfterOp {
     pub anchor: String,
     pub text: Option<String>,
     pub content: Option<String>,
+    /// Insert the text exactly as given: no heuristics, no anchor relocation.
+    #[serde(default)]
+    pub verbatim: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DeleteLinesOp {
+    pub start_anchor: String,
+    /// Last line to delete; omit to delete only `start_anchor`.
+    pub end_anchor: Option<String>,
+    /// Delete exactly the anchored lines: no anchor relocation.
+    #[serde(default)]
+    pub verbatim: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct InsertBeforeOp {
+    pub anchor: String,
+    pub text: Option<String>,
+    /// Insert the text exactly as given: no heuristics, no anchor relocation.
+    #[serde(default)]
+    pub verbatim: bool,
+}
+
+/// Text for the anchorless `insert_at_start` and `append_to_end` operations.
+#[derive(Debug, Clone, Deserialize)]
+pub struct InsertTextOp {
+    pub text: Option<String>,
+    /// Insert the text exactly as given: no heuristics.
+    #[serde(default)]
+    pub verbatim: bool,
+}
+
+/// Source lines and destination for `move_lines` and `copy_lines`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TransferLinesOp {
+    pub start_anchor: String,
+    /// Last source line; omit to move or copy only `start_anchor`.
+    pub end_anchor: Option<String>,
+    /// The lines are placed after this line, which must not be inside the source.
+    pub after_anchor: String,
+    /// Use exactly the anchored lines: no anchor relocation.
+    #[serde(default)]
+    pub verbatim: bool,
 }
 
+/// A unified diff for the payload's file (see [`crate::patch`]).
 #[derive(Debug, Clone, Deserialize)]
+pub struct PatchOp {
+    /// `diff -u` or `git diff` output for this one file, or bare `@@` hunks.
+    pub diff: String,
+    /// Apply each hunk only at the lines its header names: no relocation.
+    #[serde(default)]
+    pub verbatim: bool,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
 #[allow(dead_code)]
 pub struct ReplaceOp {
     pub old_text: String,
     pub new_text: String,
+    /// Replace every match instead of requiring exactly one.
+    #[serde(default)]
+    pub all: bool,
+    /// Replace only the Nth match (1-based).
+    #[serde(default)]
+    pub occurrence: Option<usize>,
+    /// Treat `old_text` as a regular expression; `new_text` may use `$1` or
+    /// `${name}` to refer to capture groups.
+    #[serde(default)]
+    pub regex: bool,
+    /// Match `old_text` as whole lines, comparing each line the way anchor
+    /// hashes do (ignoring whitespace, or all but indentation).
+    #[serde(default)]
+    pub fuzzy: bool,
+    /// Only match on this `LINE:HASH` line.
+    #[serde(default)]
+    pub anchor: Option<String>,
+    /// Only match inside these lines (also accepted as `range`).
+    #[serde(default, alias = "range")]
+    pub within: Option<AnchorRange>,
+}
+
+impl ReplaceOp {
+    /// The lines `anchor` or `within` limit the search to, if any.
+    pub fn window(&self) -> Result<Option<AnchorRange>, String> {
+        match (&self.anchor, &self.within) {
+            (Some(_), Some(_)) => {
+                Err("replace edit: use either anchor or within/range, not both".into())
+            }
+            (Some(anchor), None) => Ok(Some(AnchorRange {
+                start_anchor: anchor.clone(),
+                end_anchor: None,
+            })),
+            (None, within) => Ok(within.clone()),
+        }
+    }
+
+    /// Whether the edit is pinned to anchored lines. Such edits are validated
+    /// and applied with the other anchor edits.
+    pub fn is_anchored(&self) -> bool {
+        self.anchor.is_some() || self.within.is_some()
+    }
+}
+
+/// A `start_anchor` / optional `end_anchor` pair of `LINE:HASH` refs.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AnchorRange {
+    pub start_anchor: String,
+    /// Last line of the range; omit for a single line.
+    pub end_anchor: Option<String>,
 }
 
 /// JSON input format for the CLI.
@@ -56,6 +224,71 @@ pub struct ReplaceOp {
 pub struct HashlineParams {
     pub path: String,
     pub edits: Vec<HashlineEdit>,
+    /// Whole-file fingerprint from `hashline read --fingerprint`. When set and
+    /// the file has changed at all, `on_file_change` decides what happens.
+    #[serde(default)]
+    pub expect_file_hash: Option<String>,
+    #[serde(default)]
+    pub on_file_change: FileChangePolicy,
+}
+
+/// What to do when `expect_file_hash` no longer matches the file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileChangePolicy {
+    /// Reject the whole payload.
+    #[default]
+    Refuse,
+    /// Apply only if every anchor still matches at its exact line (no relocation).
+    Strict,
+}
+
+/// Options for [`apply_hashline_edits_with_options`].
+#[derive(Debug, Clone)]
+pub struct ApplyOptions {
+    /// Move a stale anchor to the line whose hash is unique in the file.
+    pub relocate: bool,
+    /// Hash scheme the anchors were produced with (see `hashline read`).
+    pub hash: HashOptions,
+    /// Treat every edit as `verbatim`: no heuristics and no relocation.
+    pub verbatim: bool,
+    /// Heuristics allowed to rewrite edit text (ignored for verbatim edits).
+    pub heuristics: HeuristicSet,
+}
+
+impl Default for ApplyOptions {
+    fn default() -> Self {
+        ApplyOptions {
+            relocate: true,
+            hash: HashOptions::default(),
+            verbatim: false,
+            heuristics: HeuristicSet::all(),
+        }
+    }
+}
+
+/// JSON input format for a multi-file transaction.
+///
+/// Every file's anchors are validated before any file is written; either all
+/// files are updated or none are.
+#[derive(Debug, Clone, Deserialize)]
+pub struct HashlineTransaction {
+    pub files: Vec<HashlineParams>,
+}
+
+/// Parse `apply` input: either a single `{path, edits}` object or a
+/// `{files: [{path, edits}, ...]}` transaction.
+///
+/// Returns the per-file params and whether the transaction form was used.
+pub fn parse_apply_input(input: &str) -> Result<(Vec<HashlineParams>, bool), serde_json::Error> {
+    let value: serde_json::Value = serde_json::from_str(input)?;
+    if value.get("files").is_some() {
+        let tx: HashlineTransaction = serde_json::from_value(value)?;
+        Ok((tx.files, true))
+    } else {
+        let params: HashlineParams = serde_json::from_value(value)?;
+        Ok((vec![params], false))
+    }
 }
 
 /// Result of applying edits.
@@ -66,6 +299,67 @@ pub struct ApplyResult {
     pub first_changed_line: Option<usize>,
     pub warnings: Vec<String>,
     pub noop_edits: Vec<NoopEdit>,
+    /// Lines each edit addressed in the original content, after relocation.
+    pub edit_spans: Vec<EditSpan>,
+    /// What happened to each edit, indexed like the input edits.
+    pub outcomes: Vec<EditOutcome>,
+}
+
+/// Whether an edit changed the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditStatus {
+    Applied,
+    /// The lines already had the requested content.
+    Noop,
+    /// Identical to an earlier edit in the same payload, so skipped.
+    Duplicate,
+}
+
+impl EditStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EditStatus::Applied => "applied",
+            EditStatus::Noop => "noop",
+            EditStatus::Duplicate => "duplicate",
+        }
+    }
+}
+
+/// What happened to one edit, including any rewriting on its way in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EditOutcome {
+    pub edit_index: usize,
+    pub status: EditStatus,
+    /// Heuristics that rewrote the edit's text, in the order they ran.
+    pub heuristics: Vec<HeuristicEvent>,
+    /// Stale anchors moved to the unique line with their hash: `(given, used)`.
+    pub relocated: Vec<(usize, usize)>,
+    /// Lines a fuzzy `replace` matched, `(start, end)` in the content it searched.
+    pub fuzzy_matches: Vec<(usize, usize)>,
+}
+
+impl EditOutcome {
+    pub fn applied(edit_index: usize) -> Self {
+        EditOutcome {
+            edit_index,
+            status: EditStatus::Applied,
+            heuristics: Vec::new(),
+            relocated: Vec::new(),
+            fuzzy_matches: Vec::new(),
+        }
+    }
+}
+
+/// Lines addressed by one edit (1-based, inclusive). For `insert_after` and
+/// `insert_before` this is the anchor line, for `insert_at_start` the first
+/// line and for `append_to_end` the last; for `replace` it is where
+/// `new_text` ended up. `move_lines` has two spans, the source lines and the
+/// `after_anchor` line; `copy_lines` only the latter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EditSpan {
+    pub edit_index: usize,
+    pub start_line: usize,
+    pub end_line: usize,
 }
 
 #[derive(Debug)]
@@ -92,14 +386,313 @@ enum ParsedRefs {
         line: usize,
         hash: String,
     },
+    InsertBefore {
+        line: usize,
+        hash: String,
+    },
+    /// `insert_at_start`: no anchor.
+    InsertAtStart,
+    /// `append_to_end`: no anchor.
+    AppendToEnd,
+}
+
+/// Where `move_lines` and `copy_lines` put the source lines.
+struct Destination {
+    line: usize,
+    hash: String,
+    keep_source: bool,
 }
 
 struct ParsedEdit {
+    /// The lines replaced; for `move_lines` and `copy_lines`, the source lines.
     spec: ParsedRefs,
     dst_lines: Vec<String>,
+    /// 1-based line of the supplied text each `dst_lines` entry came from.
+    origin: Vec<usize>,
+    /// Skip relocation for this edit.
+    verbatim: bool,
+    /// Heuristics that may rewrite this edit's text (none when verbatim).
+    heuristics: HeuristicSet,
+    /// Set for `move_lines` and `copy_lines` until they are split into a
+    /// deletion and an insert.
+    dest: Option<Destination>,
+    /// Set for an anchored `replace`; its text is worked out once the
+    /// anchors are validated.
+    replace: Option<ReplaceOp>,
 }
 
-fn parse_hashline_edit(edit: &HashlineEdit) -> Result<(ParsedRefs, String), String> {
+/// What an edit does to the original lines, for conflict detection.
+#[derive(Debug, Clone, Copy)]
+enum Footprint {
+    /// Replaces or deletes lines `start..=end`.
+    Lines(usize, usize),
+    /// Inserts between line `n` and line `n + 1` (0 is the top of the file).
+    Gap(usize),
+    /// Reads lines `start..=end` without changing them (a `copy_lines`
+    /// source).
+    Read(usize, usize),
+}
+
+impl Footprint {
+    /// Whether applying both in either order could give different results.
+    /// Inserts right before or after replaced lines are ordered by
+    /// [`ParsedRefs::sort_key`], so only inserts strictly inside a range and
+    /// overlapping ranges conflict. A copied range conflicts with any edit of
+    /// its lines, which would otherwise be copied in their old form.
+    fn overlaps(self, other: Footprint) -> bool {
+        match (self, other) {
+            (Footprint::Lines(s1, e1), Footprint::Lines(s2, e2))
+            | (Footprint::Lines(s1, e1), Footprint::Read(s2, e2))
+            | (Footprint::Read(s1, e1), Footprint::Lines(s2, e2)) => s1 <= e2 && s2 <= e1,
+            (Footprint::Lines(s, e), Footprint::Gap(g))
+            | (Footprint::Gap(g), Footprint::Lines(s, e))
+            | (Footprint::Read(s, e), Footprint::Gap(g))
+            | (Footprint::Gap(g), Footprint::Read(s, e)) => s <= g && g < e,
+            (Footprint::Gap(_), Footprint::Gap(_)) | (Footprint::Read(..), Footprint::Read(..)) => {
+                false
+            }
+        }
+    }
+}
+
+impl ParsedEdit {
+    /// Anchor lines in the order they appear in the edit.
+    fn anchor_lines(&self) -> Vec<usize> {
+        let mut lines = self.spec.lines();
+        lines.extend(self.dest.as_ref().map(|d| d.line));
+        lines
+    }
+
+    /// Footprints of the edit with the lines to report for each.
+    fn footprints(&self, file_len: usize) -> Vec<(Footprint, (usize, usize))> {
+        let mut out = Vec::new();
+        let span = self.spec.span(file_len);
+        match &self.dest {
+            Some(dest) => {
+                let source = if dest.keep_source {
+                    Footprint::Read(span.0, span.1)
+                } else {
+                    Footprint::Lines(span.0, span.1)
+                };
+                out.push((source, span));
+                out.push((Footprint::Gap(dest.line), (dest.line, dest.line)));
+            }
+            None => {
+                let footprint = match &self.spec {
+                    ParsedRefs::Single { .. } | ParsedRefs::Range { .. } => {
+                        Footprint::Lines(span.0, span.1)
+                    }
+                    ParsedRefs::InsertAfter { line, .. } => Footprint::Gap(*line),
+                    ParsedRefs::InsertBefore { line, .. } => Footprint::Gap(line - 1),
+                    ParsedRefs::InsertAtStart => Footprint::Gap(0),
+                    ParsedRefs::AppendToEnd => Footprint::Gap(file_len),
+                };
+                out.push((footprint, span));
+            }
+        }
+        out
+    }
+}
+
+/// Find the first pair of edits (in payload order) whose footprints overlap.
+fn find_conflict(
+    parsed: &[(usize, ParsedEdit)],
+    edits: &[HashlineEdit],
+    file_len: usize,
+) -> Option<EditConflictError> {
+    let mut footprints: Vec<(usize, Footprint, (usize, usize))> = parsed
+        .iter()
+        .flat_map(|(idx, p)| {
+            p.footprints(file_len)
+                .into_iter()
+                .map(move |(f, lines)| (*idx, f, lines))
+        })
+        .collect();
+    footprints.sort_by_key(|(idx, _, _)| *idx);
+    let side = |idx: usize, (start_line, end_line): (usize, usize)| ConflictingEdit {
+        edit_index: idx,
+        op: edits[idx].op_name(),
+        start_line,
+        end_line,
+    };
+    for (i, &(idx_a, a, lines_a)) in footprints.iter().enumerate() {
+        for &(idx_b, b, lines_b) in &footprints[i + 1..] {
+            if idx_a != idx_b && a.overlaps(b) {
+                return Some(EditConflictError {
+                    first: side(idx_a, lines_a),
+                    second: side(idx_b, lines_b),
+                });
+            }
+        }
+    }
+    None
+}
+
+impl ParsedRefs {
+    /// Anchor lines in the order they appear in the edit.
+    fn lines(&self) -> Vec<usize> {
+        match self {
+            ParsedRefs::Single { line, .. }
+            | ParsedRefs::InsertAfter { line, .. }
+            | ParsedRefs::InsertBefore { line, .. } => vec![*line],
+            ParsedRefs::Range {
+                start_line,
+                end_line,
+                ..
+            } => vec![*start_line, *end_line],
+            ParsedRefs::InsertAtStart | ParsedRefs::AppendToEnd => vec![],
+        }
+    }
+
+    /// First and last line the edit addresses in a file of `file_len` lines.
+    fn span(&self, file_len: usize) -> (usize, usize) {
+        match self {
+            ParsedRefs::Single { line, .. }
+            | ParsedRefs::InsertAfter { line, .. }
+            | ParsedRefs::InsertBefore { line, .. } => (*line, *line),
+            ParsedRefs::Range {
+                start_line,
+                end_line,
+                ..
+            } => (*start_line, *end_line),
+            ParsedRefs::InsertAtStart => (1, 1),
+            ParsedRefs::AppendToEnd => (file_len, file_len),
+        }
+    }
+
+    /// Bottom-up application order: descending line, then ascending rank.
+    /// At the same line, `insert_after` runs before the line is replaced so it
+    /// stays below the new text, and `insert_before` runs last so it lands
+    /// above it.
+    fn sort_key(&self) -> (usize, u8) {
+        match self {
+            ParsedRefs::InsertAfter { line, .. } => (*line, 0),
+            ParsedRefs::Single { line, .. } => (*line, 1),
+            ParsedRefs::Range { end_line, .. } => (*end_line, 1),
+            ParsedRefs::InsertBefore { line, .. } => (*line, 2),
+            ParsedRefs::InsertAtStart => (0, 2),
+            ParsedRefs::AppendToEnd => (usize::MAX, 0),
+        }
+    }
+
+    /// Inserts that share a position keep payload order only if later edits
+    /// are applied first.
+    fn inserts_in_reverse(&self) -> bool {
+        matches!(
+            self,
+            ParsedRefs::InsertBefore { .. } | ParsedRefs::InsertAtStart
+        )
+    }
+}
+
+/// Record a heuristic on `outcome` when it touched at least one line.
+fn record_heuristic(
+    outcome: &mut EditOutcome,
+    heuristic: Heuristic,
+    lines: Vec<usize>,
+    detail: Option<String>,
+) {
+    if !lines.is_empty() {
+        outcome.heuristics.push(HeuristicEvent {
+            heuristic,
+            lines,
+            detail,
+        });
+    }
+}
+
+/// Origins of the lines that differ between two same-length line lists.
+fn changed_origins(before: &[String], after: &[String], origin: &[usize]) -> Vec<usize> {
+    before
+        .iter()
+        .zip(after)
+        .zip(origin)
+        .filter(|((b, a), _)| b != a)
+        .map(|(_, &o)| o)
+        .collect()
+}
+
+/// Run the replacement heuristics on the text for lines `start..=end`,
+/// recording what each one changed on `outcome`. Only the heuristics enabled
+/// for the edit run.
+fn rewrite_replacement(
+    original_file_lines: &[String],
+    start: usize,
+    end: usize,
+    edit: &ParsedEdit,
+    outcome: &mut EditOutcome,
+) -> Vec<String> {
+    let enabled = edit.heuristics;
+    let orig_lines = &original_file_lines[start - 1..end];
+    let mut origin = edit.origin.clone();
+    let (stripped, dropped_first, dropped_last) = if enabled.contains(Heuristic::StripBoundaryEcho)
+    {
+        heuristics::strip_range_boundary_echo_traced(
+            original_file_lines,
+            start,
+            end,
+            &edit.dst_lines,
+        )
+    } else {
+        (edit.dst_lines.clone(), false, false)
+    };
+    let mut dropped = Vec::new();
+    if dropped_last {
+        dropped.extend(origin.pop());
+    }
+    if dropped_first {
+        dropped.insert(0, origin.remove(0));
+    }
+    record_heuristic(outcome, Heuristic::StripBoundaryEcho, dropped, None);
+
+    let (unwrapped, spans) = if enabled.contains(Heuristic::RestoreWrappedLines) {
+        heuristics::restore_old_wrapped_lines_traced(orig_lines, &stripped)
+    } else {
+        (stripped, Vec::new())
+    };
+    let rejoined: Vec<usize> = spans
+        .iter()
+        .flat_map(|&(s, len)| origin[s..s + len].to_vec())
+        .collect();
+    for &(s, len) in spans.iter().rev() {
+        origin.drain(s + 1..s + len);
+    }
+    record_heuristic(outcome, Heuristic::RestoreWrappedLines, rejoined, None);
+
+    let mut new_lines = if enabled.contains(Heuristic::RestoreIndent) {
+        heuristics::restore_indent_for_paired_replacement(orig_lines, &unwrapped)
+    } else {
+        unwrapped.clone()
+    };
+    let changed = changed_origins(&unwrapped, &new_lines, &origin);
+    record_heuristic(outcome, Heuristic::RestoreIndent, changed, None);
+    if enabled.contains(Heuristic::NormalizeHyphens) {
+        normalize_hyphens_if_noop(orig_lines, &mut new_lines, &origin, outcome);
+    }
+    new_lines
+}
+
+/// Normalize look-alike hyphens when that is the only difference from `orig_lines`.
+fn normalize_hyphens_if_noop(
+    orig_lines: &[String],
+    new_lines: &mut Vec<String>,
+    origin: &[usize],
+    outcome: &mut EditOutcome,
+) {
+    if orig_lines.join("\n") == new_lines.join("\n")
+        && orig_lines
+            .iter()
+            .any(|l| heuristics::has_confusable_hyphens(l))
+    {
+        let normalized = heuristics::normalize_confusable_hyphens_in_lines(new_lines);
+        let changed = changed_origins(new_lines, &normalized, origin);
+        record_heuristic(outcome, Heuristic::NormalizeHyphens, changed, None);
+        *new_lines = normalized;
+    }
+}
+
+fn parse_hashline_edit(edit: &HashlineEdit, width: usize) -> Result<(ParsedRefs, String), String> {
+    let parse_line_ref = |s: &str| parse_line_ref_with_width(s, width);
     match edit {
         HashlineEdit::SetLine { set_line } => {
             let r = parse_line_ref(&set_line.anchor)?;
@@ -161,10 +754,197 @@ fn parse_hashline_edit(edit: &HashlineEdit) -> Result<(ParsedRefs, String), Stri
                 text,
             ))
         }
-        HashlineEdit::Replace { .. } => Err(
-            "replace edits are applied separately; do not pass them to applyHashlineEdits".into(),
-        ),
+        HashlineEdit::DeleteLines { delete_lines } => Ok((
+            parse_line_span(
+                &delete_lines.start_anchor,
+                delete_lines.end_anchor.as_deref(),
+                width,
+            )?,
+            String::new(),
+        )),
+        HashlineEdit::MoveLines { move_lines: op } | HashlineEdit::CopyLines { copy_lines: op } => {
+            Ok((
+                parse_line_span(&op.start_anchor, op.end_anchor.as_deref(), width)?,
+                String::new(),
+            ))
+        }
+        HashlineEdit::InsertBefore { insert_before } => {
+            let r = parse_line_ref(&insert_before.anchor)?;
+            Ok((
+                ParsedRefs::InsertBefore {
+                    line: r.line,
+                    hash: r.hash,
+                },
+                insert_before.text.clone().unwrap_or_default(),
+            ))
+        }
+        HashlineEdit::InsertAtStart { insert_at_start } => Ok((
+            ParsedRefs::InsertAtStart,
+            insert_at_start.text.clone().unwrap_or_default(),
+        )),
+        HashlineEdit::AppendToEnd { append_to_end } => Ok((
+            ParsedRefs::AppendToEnd,
+            append_to_end.text.clone().unwrap_or_default(),
+        )),
+        HashlineEdit::Replace { replace } => match replace.window()? {
+            Some(window) => Ok((
+                parse_line_span(
+                    &window.start_anchor,
+                    window.end_anchor.as_deref(),
+                    width,
+                )?,
+                String::new(),
+            )),
+            None => Err(
+                "replace edits are applied separately unless they set anchor or within; do not pass them to applyHashlineEdits"
+                    .into(),
+            ),
+        },
+        HashlineEdit::Patch { .. } => Err("patch edits are expanded by expand_patch".into()),
+    }
+}
+
+/// Parse a `start_anchor` / optional `end_anchor` pair.
+fn parse_line_span(start: &str, end: Option<&str>, width: usize) -> Result<ParsedRefs, String> {
+    let start = parse_line_ref_with_width(start, width)?;
+    let end = match end {
+        Some(end_str) => parse_line_ref_with_width(end_str, width)?,
+        None => start.clone(),
+    };
+    if start.line == end.line {
+        Ok(ParsedRefs::Single {
+            line: start.line,
+            hash: start.hash,
+        })
+    } else {
+        Ok(ParsedRefs::Range {
+            start_line: start.line,
+            start_hash: start.hash,
+            end_line: end.line,
+            end_hash: end.hash,
+        })
+    }
+}
+
+/// The `after_anchor` of a `move_lines` or `copy_lines` edit.
+fn parse_destination(edit: &HashlineEdit, width: usize) -> Result<Option<Destination>, String> {
+    let (op, keep_source) = match edit {
+        HashlineEdit::MoveLines { move_lines } => (move_lines, false),
+        HashlineEdit::CopyLines { copy_lines } => (copy_lines, true),
+        _ => return Ok(None),
+    };
+    let r = parse_line_ref_with_width(&op.after_anchor, width)?;
+    Ok(Some(Destination {
+        line: r.line,
+        hash: r.hash,
+        keep_source,
+    }))
+}
+
+/// Split a `patch` edit into one line edit per run of changed lines, anchored
+/// where each hunk's old lines are found. Hunks that cannot be placed add
+/// their differing lines to `mismatches`.
+///
+/// A hunk states exactly which lines it replaces, so only the configured
+/// heuristics that repair the added text run on it: copied `LINE:HASH|`
+/// prefixes, lost indentation, re-wrapped lines and look-alike hyphens.
+fn expand_patch(
+    op: &PatchOp,
+    file_lines: &[String],
+    line_hashes: &[String],
+    options: &ApplyOptions,
+    relocate: bool,
+    outcome: &mut EditOutcome,
+    mismatches: &mut Vec<HashMismatch>,
+) -> Result<Vec<ParsedEdit>, String> {
+    let files = patch::parse_patch(&op.diff)?;
+    if files.len() > 1 {
+        return Err(format!(
+            "patch edit: the diff covers {} files; give each file its own patch edit",
+            files.len()
+        ));
+    }
+    let mut enabled = if options.verbatim || op.verbatim {
+        HeuristicSet::none()
+    } else {
+        options.heuristics
+    };
+    for heuristic in [
+        Heuristic::StripDiffPlus,
+        Heuristic::StripBoundaryEcho,
+        Heuristic::StripAnchorEcho,
+        Heuristic::ExpandMerge,
+    ] {
+        enabled.set(heuristic, false);
+    }
+    let mut parsed = Vec::new();
+    // Later hunks are first looked for where the previous one was found.
+    let mut shift: isize = 0;
+    for hunk in &files[0].hunks {
+        let stated = patch::stated_start(hunk);
+        let expected = stated.saturating_add_signed(shift);
+        let Some(start) = patch::locate_hunk(
+            file_lines,
+            hunk,
+            expected,
+            options.hash.whitespace,
+            relocate,
+        ) else {
+            mismatches.extend(patch::hunk_mismatches(
+                file_lines,
+                line_hashes,
+                hunk,
+                expected,
+                &options.hash,
+            )?);
+            continue;
+        };
+        if start != stated {
+            outcome.relocated.push((stated, start));
+        }
+        shift = start as isize - stated as isize;
+        for change in patch::hunk_changes(hunk) {
+            let line = start + change.offset;
+            let end = line + change.removed.saturating_sub(1);
+            let spec = if change.removed == 0 && line == 1 {
+                ParsedRefs::InsertAtStart
+            } else if change.removed == 0 {
+                ParsedRefs::InsertAfter {
+                    line: line - 1,
+                    hash: line_hashes[line - 2].clone(),
+                }
+            } else if end == line {
+                ParsedRefs::Single {
+                    line,
+                    hash: line_hashes[line - 1].clone(),
+                }
+            } else {
+                ParsedRefs::Range {
+                    start_line: line,
+                    start_hash: line_hashes[line - 1].clone(),
+                    end_line: end,
+                    end_hash: line_hashes[end - 1].clone(),
+                }
+            };
+            let (dst_lines, prefix) =
+                heuristics::strip_new_line_prefixes_traced(&change.added, enabled);
+            let origin: Vec<usize> = (1..=dst_lines.len()).collect();
+            if let Some(heuristic) = prefix {
+                let changed = changed_origins(&change.added, &dst_lines, &origin);
+                record_heuristic(outcome, heuristic, changed, None);
+            }
+            parsed.push(ParsedEdit {
+                spec,
+                origin,
+                dst_lines,
+                verbatim: true,
+                heuristics: enabled,
+                dest: None,
+                replace: None,
+            });
+        }
     }
+    Ok(parsed)
 }
 
 fn split_dst_lines(dst: &str) -> Vec<String> {
@@ -176,9 +956,19 @@ fn split_dst_lines(dst: &str) -> Vec<String> {
 }
 
 /// Apply an array of hashline edits to file content.
+#[allow(dead_code)]
 pub fn apply_hashline_edits(
     content: &str,
     edits: &[HashlineEdit],
+) -> Result<ApplyResult, Box<dyn std::error::Error>> {
+    apply_hashline_edits_with_options(content, edits, &ApplyOptions::default())
+}
+
+/// Apply an array of hashline edits to file content with explicit options.
+pub fn apply_hashline_edits_with_options(
+    content: &str,
+    edits: &[HashlineEdit],
+    options: &ApplyOptions,
 ) -> Result<ApplyResult, Box<dyn std::error::Error>> {
     if edits.is_empty() {
         return Ok(ApplyResult {
@@ -186,6 +976,8 @@ pub fn apply_hashline_edits(
             first_changed_line: None,
             warnings: vec![],
             noop_edits: vec![],
+            edit_spans: vec![],
+            outcomes: vec![],
         });
     }
 
@@ -195,12 +987,58 @@ pub fn apply_hashline_edits(
     let mut first_changed_line: Option<usize> = None;
     let mut noop_edits: Vec<NoopEdit> = Vec::new();
 
+    let mut outcomes: Vec<EditOutcome> = (0..edits.len()).map(EditOutcome::applied).collect();
+    let line_hashes = compute_line_hashes(&file_lines, &options.hash);
+    let mut mismatches: Vec<HashMismatch> = Vec::new();
+
     // Parse all edits up front
     let mut parsed: Vec<(usize, ParsedEdit)> = Vec::new();
     for (i, edit) in edits.iter().enumerate() {
-        let (spec, dst) = parse_hashline_edit(edit)?;
-        let dst_lines = heuristics::strip_new_line_prefixes(&split_dst_lines(&dst));
-        parsed.push((i, ParsedEdit { spec, dst_lines }));
+        if let HashlineEdit::Patch { patch } = edit {
+            let relocate = options.relocate && !options.verbatim && !patch.verbatim;
+            let expanded = expand_patch(
+                patch,
+                &file_lines,
+                &line_hashes,
+                options,
+                relocate,
+                &mut outcomes[i],
+                &mut mismatches,
+            )?;
+            parsed.extend(expanded.into_iter().map(|p| (i, p)));
+            continue;
+        }
+        let (spec, dst) = parse_hashline_edit(edit, options.hash.width)?;
+        let dest = parse_destination(edit, options.hash.width)?;
+        let verbatim = options.verbatim || edit.is_verbatim();
+        // Moved and copied text comes from the file, so there is nothing to repair.
+        let enabled = if verbatim || dest.is_some() {
+            HeuristicSet::none()
+        } else {
+            options.heuristics
+        };
+        let supplied = split_dst_lines(&dst);
+        let (dst_lines, prefix) = heuristics::strip_new_line_prefixes_traced(&supplied, enabled);
+        let origin: Vec<usize> = (1..=dst_lines.len()).collect();
+        if let Some(heuristic) = prefix {
+            let changed = changed_origins(&supplied, &dst_lines, &origin);
+            record_heuristic(&mut outcomes[i], heuristic, changed, None);
+        }
+        parsed.push((
+            i,
+            ParsedEdit {
+                spec,
+                dst_lines,
+                origin,
+                verbatim,
+                heuristics: enabled,
+                dest,
+                replace: match edit {
+                    HashlineEdit::Replace { replace } => Some(replace.clone()),
+                    _ => None,
+                },
+            },
+        ));
     }
 
     // Collect explicitly touched lines
@@ -220,9 +1058,13 @@ pub fn apply_hashline_edits(
                         touched.insert(ln);
                     }
                 }
-                ParsedRefs::InsertAfter { line, .. } => {
+                ParsedRefs::InsertAfter { line, .. } | ParsedRefs::InsertBefore { line, .. } => {
                     touched.insert(*line);
                 }
+                ParsedRefs::InsertAtStart | ParsedRefs::AppendToEnd => {}
+            }
+            if let Some(dest) = &p.dest {
+                touched.insert(dest.line);
             }
         }
         touched
@@ -230,12 +1072,12 @@ pub fn apply_hashline_edits(
 
     let mut _explicitly_touched = collect_touched(&parsed);
 
-    // Build unique hash map for relocation
+    // Build unique hash map for relocation. In context mode the hashes cover
+    // each line's neighbours too, so relocation matches structure, not just text.
     let mut unique_line_by_hash: HashMap<String, usize> = HashMap::new();
     let mut seen_duplicate_hashes: HashSet<String> = HashSet::new();
-    for (i, line) in file_lines.iter().enumerate() {
+    for (i, hash) in line_hashes.iter().cloned().enumerate() {
         let line_no = i + 1;
-        let hash = compute_line_hash(line_no, line);
         if seen_duplicate_hashes.contains(&hash) {
             continue;
         }
@@ -248,25 +1090,27 @@ pub fn apply_hashline_edits(
     }
 
     // Pre-validate all hashes
-    let mut mismatches: Vec<HashMismatch> = Vec::new();
 
     let validate_or_relocate = |line: &mut usize,
                                 hash: &str,
                                 file_lines: &[String],
                                 unique_line_by_hash: &HashMap<String, usize>,
-                                mismatches: &mut Vec<HashMismatch>|
+                                mismatches: &mut Vec<HashMismatch>,
+                                relocate: bool|
      -> bool {
         if *line < 1 || *line > file_lines.len() {
             return false; // will be caught as out-of-range error
         }
         let expected = hash.to_lowercase();
-        let actual = compute_line_hash(*line, &file_lines[*line - 1]);
+        let actual = line_hashes[*line - 1].clone();
         if actual == expected {
             return true;
         }
-        if let Some(&relocated) = unique_line_by_hash.get(&expected) {
-            *line = relocated;
-            return true;
+        if relocate {
+            if let Some(&relocated) = unique_line_by_hash.get(&expected) {
+                *line = relocated;
+                return true;
+            }
         }
         mismatches.push(HashMismatch {
             line: *line,
@@ -276,7 +1120,9 @@ pub fn apply_hashline_edits(
         false
     };
 
-    for (_, p) in parsed.iter_mut() {
+    for (idx, p) in parsed.iter_mut() {
+        let given_lines = p.anchor_lines();
+        let relocate = options.relocate && !p.verbatim;
         match &mut p.spec {
             ParsedRefs::Single { line, hash } => {
                 if *line < 1 || *line > file_lines.len() {
@@ -293,9 +1139,10 @@ pub fn apply_hashline_edits(
                     &file_lines,
                     &unique_line_by_hash,
                     &mut mismatches,
+                    relocate,
                 );
             }
-            ParsedRefs::InsertAfter { line, hash } => {
+            ParsedRefs::InsertAfter { line, hash } | ParsedRefs::InsertBefore { line, hash } => {
                 if *line < 1 || *line > file_lines.len() {
                     return Err(format!(
                         "Line {} does not exist (file has {} lines)",
@@ -307,6 +1154,7 @@ pub fn apply_hashline_edits(
                 if p.dst_lines.is_empty() {
                     // Empty text means "insert a blank line"
                     p.dst_lines = vec![String::new()];
+                    p.origin = vec![1];
                 }
                 validate_or_relocate(
                     line,
@@ -314,8 +1162,15 @@ pub fn apply_hashline_edits(
                     &file_lines,
                     &unique_line_by_hash,
                     &mut mismatches,
+                    relocate,
                 );
             }
+            ParsedRefs::InsertAtStart | ParsedRefs::AppendToEnd => {
+                if p.dst_lines.is_empty() {
+                    p.dst_lines = vec![String::new()];
+                    p.origin = vec![1];
+                }
+            }
             ParsedRefs::Range {
                 start_line,
                 start_hash,
@@ -356,6 +1211,7 @@ pub fn apply_hashline_edits(
                     &file_lines,
                     &unique_line_by_hash,
                     &mut mismatches,
+                    relocate,
                 );
                 let end_ok = validate_or_relocate(
                     end_line,
@@ -363,6 +1219,7 @@ pub fn apply_hashline_edits(
                     &file_lines,
                     &unique_line_by_hash,
                     &mut mismatches,
+                    relocate,
                 );
 
                 if start_ok && end_ok {
@@ -379,28 +1236,93 @@ pub fn apply_hashline_edits(
                         mismatches.push(HashMismatch {
                             line: original_start,
                             expected: start_hash.clone(),
-                            actual: compute_line_hash(
-                                original_start,
-                                &file_lines[original_start - 1],
-                            ),
+                            actual: line_hashes[original_start - 1].clone(),
                         });
                         mismatches.push(HashMismatch {
                             line: original_end,
                             expected: end_hash.clone(),
-                            actual: compute_line_hash(original_end, &file_lines[original_end - 1]),
+                            actual: line_hashes[original_end - 1].clone(),
                         });
                     }
                 }
             }
         }
+        if let Some(dest) = &mut p.dest {
+            if dest.line < 1 || dest.line > file_lines.len() {
+                return Err(format!(
+                    "Line {} does not exist (file has {} lines)",
+                    dest.line,
+                    file_lines.len()
+                )
+                .into());
+            }
+            validate_or_relocate(
+                &mut dest.line,
+                &dest.hash,
+                &file_lines,
+                &unique_line_by_hash,
+                &mut mismatches,
+                relocate,
+            );
+        }
+        for (given, used) in given_lines.into_iter().zip(p.anchor_lines()) {
+            if given != used {
+                outcomes[*idx].relocated.push((given, used));
+            }
+        }
     }
 
     if !mismatches.is_empty() {
-        return Err(Box::new(HashlineMismatchError::new(mismatches, file_lines)));
+        return Err(Box::new(
+            HashlineMismatchError::new(mismatches, file_lines).with_hash_options(options.hash),
+        ));
+    }
+
+    // Work out the new text of anchored replace edits from the validated lines
+    for (idx, p) in parsed.iter_mut() {
+        let Some(op) = &p.replace else {
+            continue;
+        };
+        let (start, end) = p.spec.span(file_lines.len());
+        let mut text = original_file_lines[start - 1..end].join("\n");
+        let place = if start == end {
+            format!("line {}", start)
+        } else {
+            format!("lines {}-{}", start, end)
+        };
+        let chosen = select_replace_matches(&text, op, options, &place)?;
+        for (m_start, m_end, replacement) in chosen.into_iter().rev() {
+            if op.fuzzy {
+                let first = start + text[..m_start].matches('\n').count();
+                let matched = &text[m_start..m_end];
+                let matched = matched.strip_suffix('\n').unwrap_or(matched);
+                outcomes[*idx]
+                    .fuzzy_matches
+                    .insert(0, (first, first + matched.matches('\n').count()));
+            }
+            text.replace_range(m_start..m_end, &replacement);
+        }
+        p.dst_lines = split_dst_lines(&text);
+        p.origin = (1..=p.dst_lines.len()).collect();
     }
 
     // Recompute touched lines after relocation
     let explicitly_touched_lines = collect_touched(&parsed);
+    let edit_spans: Vec<EditSpan> = parsed
+        .iter()
+        .flat_map(|(idx, p)| {
+            let mut spans = Vec::new();
+            if !p.dest.as_ref().is_some_and(|d| d.keep_source) {
+                spans.push(p.spec.span(file_lines.len()));
+            }
+            spans.extend(p.dest.as_ref().map(|d| (d.line, d.line)));
+            spans.into_iter().map(|(start_line, end_line)| EditSpan {
+                edit_index: *idx,
+                start_line,
+                end_line,
+            })
+        })
+        .collect();
 
     // Deduplicate identical edits
     let mut seen_edit_keys: HashMap<String, usize> = HashMap::new();
@@ -414,8 +1336,16 @@ pub fn apply_hashline_edits(
                 ..
             } => format!("r:{}:{}", start_line, end_line),
             ParsedRefs::InsertAfter { line, .. } => format!("i:{}", line),
+            ParsedRefs::InsertBefore { line, .. } => format!("b:{}", line),
+            ParsedRefs::InsertAtStart => "start".to_string(),
+            ParsedRefs::AppendToEnd => "end".to_string(),
+        };
+        let dest_key = match &p.dest {
+            Some(d) if d.keep_source => format!("c:{}", d.line),
+            Some(d) => format!("m:{}", d.line),
+            None => String::new(),
         };
-        let dst_key = format!("{}|{}", line_key, p.dst_lines.join("\n"));
+        let dst_key = format!("{}{}|{}", line_key, dest_key, p.dst_lines.join("\n"));
         if let std::collections::hash_map::Entry::Vacant(e) = seen_edit_keys.entry(dst_key) {
             e.insert(i);
         } else {
@@ -427,104 +1357,148 @@ pub fn apply_hashline_edits(
         while i > 0 {
             i -= 1;
             if dedup_indices.contains(&i) {
-                parsed.remove(i);
+                let (idx, _) = parsed.remove(i);
+                outcomes[idx].status = EditStatus::Duplicate;
             }
         }
     }
 
+    // Reject edits that overlap each other, now that anchors are final
+    if let Some(conflict) = find_conflict(&parsed, edits, file_lines.len()) {
+        return Err(Box::new(conflict));
+    }
+
+    // Split move_lines / copy_lines into an insert of the source text after
+    // the destination and, for a move, a deletion of the source. The text is
+    // the original source lines: find_conflict has rejected any other edit
+    // of them, so the order edits run in below cannot change what is copied.
+    let mut expanded: Vec<(usize, ParsedEdit)> = Vec::with_capacity(parsed.len());
+    for (idx, p) in parsed {
+        let Some(dest) = p.dest else {
+            expanded.push((idx, p));
+            continue;
+        };
+        let (start, end) = p.spec.span(file_lines.len());
+        if dest.line >= start && dest.line < end {
+            return Err(format!(
+                "{}: after_anchor line {} is inside the source lines {}-{}",
+                edits[idx].op_name(),
+                dest.line,
+                start,
+                end
+            )
+            .into());
+        }
+        let text = original_file_lines[start - 1..end].to_vec();
+        if !dest.keep_source && (dest.line + 1 == start || dest.line == end) {
+            let start_hash = match &p.spec {
+                ParsedRefs::Single { hash, .. } => hash,
+                ParsedRefs::Range { start_hash, .. } => start_hash,
+                _ => unreachable!("move source is a line span"),
+            };
+            outcomes[idx].status = EditStatus::Noop;
+            noop_edits.push(NoopEdit {
+                edit_index: idx,
+                loc: format!("{}:{}", start, start_hash),
+                current_content: text.join("\n"),
+            });
+            continue;
+        }
+        expanded.push((
+            idx,
+            ParsedEdit {
+                spec: ParsedRefs::InsertAfter {
+                    line: dest.line,
+                    hash: dest.hash,
+                },
+                origin: (1..=text.len()).collect(),
+                dst_lines: text,
+                verbatim: true,
+                heuristics: HeuristicSet::none(),
+                dest: None,
+                replace: None,
+            },
+        ));
+        if !dest.keep_source {
+            expanded.push((idx, ParsedEdit { dest: None, ..p }));
+        }
+    }
+    let mut parsed = expanded;
+
     // Sort bottom-up (descending line number)
     parsed.sort_by(|a, b| {
-        let sort_line_a = match &a.1.spec {
-            ParsedRefs::Single { line, .. } => *line,
-            ParsedRefs::Range { end_line, .. } => *end_line,
-            ParsedRefs::InsertAfter { line, .. } => *line,
-        };
-        let sort_line_b = match &b.1.spec {
-            ParsedRefs::Single { line, .. } => *line,
-            ParsedRefs::Range { end_line, .. } => *end_line,
-            ParsedRefs::InsertAfter { line, .. } => *line,
-        };
-        let prec_a = match &a.1.spec {
-            ParsedRefs::InsertAfter { .. } => 1,
-            _ => 0,
-        };
-        let prec_b = match &b.1.spec {
-            ParsedRefs::InsertAfter { .. } => 1,
-            _ => 0,
+        let (sort_line_a, prec_a) = a.1.spec.sort_key();
+        let (sort_line_b, prec_b) = b.1.spec.sort_key();
+        let order = if a.1.spec.inserts_in_reverse() {
+            b.0.cmp(&a.0)
+        } else {
+            a.0.cmp(&b.0)
         };
         sort_line_b
             .cmp(&sort_line_a)
             .then(prec_a.cmp(&prec_b))
-            .then(a.0.cmp(&b.0))
+            .then(order)
     });
 
     // Apply edits bottom-up
+    let mut changed_edits: HashSet<usize> = HashSet::new();
+    // Lines changed, counted per splice for the reformatting warning.
+    let mut changed_line_count = 0;
     for (idx, edit) in &parsed {
-        match &edit.spec {
+        let outcome = &mut outcomes[*idx];
+        let (loc, orig_lines, new_lines, splice) = match &edit.spec {
             ParsedRefs::Single { line, hash } => {
                 let line = *line;
                 // Try merge expansion
-                if let Some((start, delete_count, new_lines)) =
+                let merge = if edit.heuristics.contains(Heuristic::ExpandMerge) {
                     heuristics::maybe_expand_single_line_merge(
                         line,
                         &edit.dst_lines,
                         &file_lines,
                         &explicitly_touched_lines,
                     )
-                {
-                    let orig_lines: Vec<String> =
-                        original_file_lines[start - 1..start - 1 + delete_count].to_vec();
-                    let mut next_lines = heuristics::restore_indent_for_paired_replacement(
-                        &[orig_lines.first().cloned().unwrap_or_default()],
-                        &new_lines,
+                } else {
+                    None
+                };
+                if let Some((start, delete_count, merged)) = merge {
+                    let end = start + delete_count - 1;
+                    record_heuristic(
+                        outcome,
+                        Heuristic::ExpandMerge,
+                        edit.origin.clone(),
+                        Some(format!("replaced lines {}-{}", start, end)),
                     );
-                    if orig_lines.join("\n") == next_lines.join("\n")
-                        && orig_lines
-                            .iter()
-                            .any(|l| heuristics::has_confusable_hyphens(l))
-                    {
-                        next_lines = heuristics::normalize_confusable_hyphens_in_lines(&next_lines);
-                    }
-                    if orig_lines.join("\n") == next_lines.join("\n") {
-                        noop_edits.push(NoopEdit {
-                            edit_index: *idx,
-                            loc: format!("{}:{}", line, hash),
-                            current_content: orig_lines.join("\n"),
-                        });
-                        continue;
+                    let orig_lines: Vec<String> = original_file_lines[start - 1..end].to_vec();
+                    let origin = vec![edit.origin.first().copied().unwrap_or(1); merged.len()];
+                    let mut next_lines = if edit.heuristics.contains(Heuristic::RestoreIndent) {
+                        heuristics::restore_indent_for_paired_replacement(
+                            &[orig_lines.first().cloned().unwrap_or_default()],
+                            &merged,
+                        )
+                    } else {
+                        merged.clone()
+                    };
+                    let changed = changed_origins(&merged, &next_lines, &origin);
+                    record_heuristic(outcome, Heuristic::RestoreIndent, changed, None);
+                    if edit.heuristics.contains(Heuristic::NormalizeHyphens) {
+                        normalize_hyphens_if_noop(&orig_lines, &mut next_lines, &origin, outcome);
                     }
-                    file_lines.splice(start - 1..start - 1 + delete_count, next_lines);
-                    track_first_changed(&mut first_changed_line, start);
-                    continue;
-                }
-
-                let orig_lines: Vec<String> = original_file_lines[line - 1..line].to_vec();
-                let stripped = heuristics::strip_range_boundary_echo(
-                    &original_file_lines,
-                    line,
-                    line,
-                    &edit.dst_lines,
-                );
-                let stripped = heuristics::restore_old_wrapped_lines(&orig_lines, &stripped);
-                let mut new_lines =
-                    heuristics::restore_indent_for_paired_replacement(&orig_lines, &stripped);
-                if orig_lines.join("\n") == new_lines.join("\n")
-                    && orig_lines
-                        .iter()
-                        .any(|l| heuristics::has_confusable_hyphens(l))
-                {
-                    new_lines = heuristics::normalize_confusable_hyphens_in_lines(&new_lines);
-                }
-                if orig_lines.join("\n") == new_lines.join("\n") {
-                    noop_edits.push(NoopEdit {
-                        edit_index: *idx,
-                        loc: format!("{}:{}", line, hash),
-                        current_content: orig_lines.join("\n"),
-                    });
-                    continue;
+                    (
+                        format!("{}:{}", line, hash),
+                        orig_lines,
+                        next_lines,
+                        start - 1..end,
+                    )
+                } else {
+                    let new_lines =
+                        rewrite_replacement(&original_file_lines, line, line, edit, outcome);
+                    (
+                        format!("{}:{}", line, hash),
+                        original_file_lines[line - 1..line].to_vec(),
+                        new_lines,
+                        line - 1..line,
+                    )
                 }
-                file_lines.splice(line - 1..line, new_lines);
-                track_first_changed(&mut first_changed_line, line);
             }
             ParsedRefs::Range {
                 start_line,
@@ -532,70 +1506,140 @@ pub fn apply_hashline_edits(
                 end_line,
                 ..
             } => {
-                let start = *start_line;
-                let end = *end_line;
-                let count = end - start + 1;
-                let orig_lines: Vec<String> =
-                    original_file_lines[start - 1..start - 1 + count].to_vec();
-                let stripped = heuristics::strip_range_boundary_echo(
-                    &original_file_lines,
-                    start,
-                    end,
-                    &edit.dst_lines,
-                );
-                let stripped = heuristics::restore_old_wrapped_lines(&orig_lines, &stripped);
-                let mut new_lines =
-                    heuristics::restore_indent_for_paired_replacement(&orig_lines, &stripped);
-                if orig_lines.join("\n") == new_lines.join("\n")
-                    && orig_lines
-                        .iter()
-                        .any(|l| heuristics::has_confusable_hyphens(l))
-                {
-                    new_lines = heuristics::normalize_confusable_hyphens_in_lines(&new_lines);
+                let (start, end) = (*start_line, *end_line);
+                let new_lines =
+                    rewrite_replacement(&original_file_lines, start, end, edit, outcome);
+                (
+                    format!("{}:{}", start, start_hash),
+                    original_file_lines[start - 1..end].to_vec(),
+                    new_lines,
+                    start - 1..end,
+                )
+            }
+            ParsedRefs::InsertAfter { line, hash } => {
+                let line = *line;
+                let anchor_line = &original_file_lines[line - 1];
+                let inserted = if edit.heuristics.contains(Heuristic::StripAnchorEcho) {
+                    heuristics::strip_insert_anchor_echo_after(anchor_line, &edit.dst_lines)
+                } else {
+                    edit.dst_lines.clone()
+                };
+                if inserted.len() < edit.dst_lines.len() {
+                    record_heuristic(
+                        outcome,
+                        Heuristic::StripAnchorEcho,
+                        edit.origin[..1].to_vec(),
+                        None,
+                    );
                 }
-                if orig_lines.join("\n") == new_lines.join("\n") {
+                if inserted.is_empty() {
+                    outcome.status = EditStatus::Noop;
                     noop_edits.push(NoopEdit {
                         edit_index: *idx,
-                        loc: format!("{}:{}", start, start_hash),
-                        current_content: orig_lines.join("\n"),
+                        loc: format!("{}:{}", line, hash),
+                        current_content: anchor_line.clone(),
                     });
                     continue;
                 }
-                file_lines.splice(start - 1..start - 1 + count, new_lines);
-                track_first_changed(&mut first_changed_line, start);
+                changed_line_count += inserted.len();
+                file_lines.splice(line..line, inserted);
+                track_first_changed(&mut first_changed_line, line + 1);
+                changed_edits.insert(*idx);
+                continue;
             }
-            ParsedRefs::InsertAfter { line, hash } => {
+            ParsedRefs::InsertBefore { line, hash } => {
                 let line = *line;
                 let anchor_line = &original_file_lines[line - 1];
-                let inserted =
-                    heuristics::strip_insert_anchor_echo_after(anchor_line, &edit.dst_lines);
+                let inserted = if edit.heuristics.contains(Heuristic::StripAnchorEcho) {
+                    heuristics::strip_insert_anchor_echo_before(anchor_line, &edit.dst_lines)
+                } else {
+                    edit.dst_lines.clone()
+                };
+                if inserted.len() < edit.dst_lines.len() {
+                    record_heuristic(
+                        outcome,
+                        Heuristic::StripAnchorEcho,
+                        edit.origin[edit.origin.len() - 1..].to_vec(),
+                        None,
+                    );
+                }
                 if inserted.is_empty() {
+                    outcome.status = EditStatus::Noop;
                     noop_edits.push(NoopEdit {
                         edit_index: *idx,
                         loc: format!("{}:{}", line, hash),
-                        current_content: original_file_lines[line - 1].clone(),
+                        current_content: anchor_line.clone(),
                     });
                     continue;
                 }
-                file_lines.splice(line..line, inserted);
-                track_first_changed(&mut first_changed_line, line + 1);
+                changed_line_count += inserted.len();
+                file_lines.splice(line - 1..line - 1, inserted);
+                track_first_changed(&mut first_changed_line, line);
+                changed_edits.insert(*idx);
+                continue;
+            }
+            ParsedRefs::InsertAtStart | ParsedRefs::AppendToEnd => {
+                // An empty file is a single empty line; fill it instead of
+                // keeping it next to the new text.
+                let pos = if matches!(edit.spec, ParsedRefs::InsertAtStart) {
+                    0
+                } else {
+                    file_lines.len()
+                };
+                let splice = if file_lines.len() == 1 && file_lines[0].is_empty() {
+                    0..1
+                } else {
+                    pos..pos
+                };
+                track_first_changed(&mut first_changed_line, splice.start + 1);
+                changed_edits.insert(*idx);
+                changed_line_count += edit.dst_lines.len();
+                file_lines.splice(splice, edit.dst_lines.clone());
+                continue;
             }
+        };
+        if orig_lines == new_lines {
+            outcome.status = EditStatus::Noop;
+            noop_edits.push(NoopEdit {
+                edit_index: *idx,
+                loc,
+                current_content: orig_lines.join("\n"),
+            });
+            continue;
+        }
+        track_first_changed(&mut first_changed_line, splice.start + 1);
+        changed_edits.insert(*idx);
+        changed_line_count += changed_lines_between(&orig_lines, &new_lines);
+        file_lines.splice(splice, new_lines);
+    }
+
+    // An edit applied in parts (a patch's hunks) only changed nothing if no
+    // part did.
+    for idx in changed_edits {
+        if outcomes[idx].status == EditStatus::Noop {
+            outcomes[idx].status = EditStatus::Applied;
+            noop_edits.retain(|n| n.edit_index != idx);
         }
     }
 
     // Warnings
     let mut warnings = Vec::new();
-    let mut diff_line_count =
-        (file_lines.len() as isize - original_file_lines.len() as isize).unsigned_abs();
-    for i in 0..std::cmp::min(file_lines.len(), original_file_lines.len()) {
-        if file_lines[i] != original_file_lines[i] {
-            diff_line_count += 1;
+    // A patch spells out every line it changes, a move or copy carries a
+    // whole range, and a bulk replace rewrites every match, so large diffs are
+    // expected.
+    let expects_large_diff = edits.iter().any(|e| match e {
+        HashlineEdit::Patch { .. }
+        | HashlineEdit::MoveLines { .. }
+        | HashlineEdit::CopyLines { .. } => true,
+        HashlineEdit::Replace { replace } => {
+            replace.all || replace.regex || replace.within.is_some()
         }
-    }
-    if diff_line_count > edits.len() * 4 {
+        _ => false,
+    });
+    if changed_line_count > edits.len() * 4 && !expects_large_diff {
         warnings.push(format!(
             "Edit changed {} lines across {} operations — verify no unintended reformatting.",
-            diff_line_count,
+            changed_line_count,
             edits.len()
         ));
     }
@@ -605,9 +1649,24 @@ pub fn apply_hashline_edits(
         first_changed_line,
         warnings,
         noop_edits,
+        edit_spans,
+        outcomes,
     })
 }
 
+/// Removed plus added lines when `new` replaces `old`, not counting the lines
+/// they start and end with in common.
+fn changed_lines_between(old: &[String], new: &[String]) -> usize {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    old.len() + new.len() - 2 * (prefix + suffix)
+}
+
 fn track_first_changed(first: &mut Option<usize>, line: usize) {
     if first.is_none() || line < first.unwrap() {
         *first = Some(line);
@@ -621,54 +1680,296 @@ pub struct ReplaceResult {
     pub content: String,
     pub replacements: usize,
     pub first_changed_line: Option<usize>,
+    /// Lines holding each replacement's `new_text` in the returned content.
+    pub spans: Vec<EditSpan>,
+    /// Lines each fuzzy replacement matched, in the content as that edit saw it.
+    pub fuzzy_matches: Vec<EditSpan>,
 }
 
-/// Apply `replace` edits (exact substring replacement) to file content.
+/// Apply `replace` edits (substring or regex replacement) to file content.
 ///
 /// Runs after anchor-based edits. Each op searches for `old_text` and
-/// replaces with `new_text`. Errors on ambiguity (multiple matches) when
-/// `all` is false. Returns an error if `old_text` is not found.
+/// replaces with `new_text`. Errors on ambiguity (multiple matches) unless
+/// `all` or `occurrence` says which matches to replace. Returns an error if
+/// `old_text` is not found.
+#[allow(dead_code)]
 pub fn apply_replace_edits(
     content: &str,
     edits: &[HashlineEdit],
+) -> Result<ReplaceResult, Box<dyn std::error::Error>> {
+    apply_replace_edits_with_options(content, edits, &ApplyOptions::default())
+}
+
+/// Byte range of the lines a `within` range covers in `content`, excluding
+/// the final newline. The anchors are checked against `content`.
+fn resolve_within(
+    content: &str,
+    within: &AnchorRange,
+    options: &ApplyOptions,
+) -> Result<std::ops::Range<usize>, Box<dyn std::error::Error>> {
+    let lines: Vec<String> = content.split('\n').map(|s| s.to_string()).collect();
+    let spec = parse_line_span(
+        &within.start_anchor,
+        within.end_anchor.as_deref(),
+        options.hash.width,
+    )?;
+    let anchors = match &spec {
+        ParsedRefs::Single { line, hash } => vec![(*line, hash)],
+        ParsedRefs::Range {
+            start_line,
+            start_hash,
+            end_line,
+            end_hash,
+        } => vec![(*start_line, start_hash), (*end_line, end_hash)],
+        _ => unreachable!("parse_line_span returns a line span"),
+    };
+    let hashes = compute_line_hashes(&lines, &options.hash);
+    let mut mismatches = Vec::new();
+    for &(line, hash) in &anchors {
+        if line < 1 || line > lines.len() {
+            return Err(format!(
+                "Line {} does not exist (file has {} lines)",
+                line,
+                lines.len()
+            )
+            .into());
+        }
+        if hashes[line - 1] != hash.to_lowercase() {
+            mismatches.push(HashMismatch {
+                line,
+                expected: hash.clone(),
+                actual: hashes[line - 1].clone(),
+            });
+        }
+    }
+    if !mismatches.is_empty() {
+        return Err(Box::new(
+            HashlineMismatchError::new(mismatches, lines).with_hash_options(options.hash),
+        ));
+    }
+    let (start, end) = spec.span(lines.len());
+    if start > end {
+        return Err(format!("Range start line {} must be <= end line {}", start, end).into());
+    }
+    let offset = |line: usize| -> usize { lines[..line - 1].iter().map(|l| l.len() + 1).sum() };
+    Ok(offset(start)..offset(end) + lines[end - 1].len())
+}
+
+/// Non-overlapping runs of whole lines in `haystack` that equal the lines of
+/// `old_text` after [`normalize_line`], as byte ranges. A trailing newline in
+/// `old_text` also takes the newline after the last matched line.
+fn find_fuzzy_matches(
+    haystack: &str,
+    old_text: &str,
+    options: &HashOptions,
+) -> Vec<std::ops::Range<usize>> {
+    let take_newline = old_text.ends_with('\n');
+    let wanted: Vec<String> = old_text
+        .strip_suffix('\n')
+        .unwrap_or(old_text)
+        .split('\n')
+        .map(|l| normalize_line(l, options.whitespace))
+        .collect();
+    let mut starts = Vec::new();
+    let mut pos = 0;
+    let lines: Vec<&str> = haystack
+        .split('\n')
+        .inspect(|l| {
+            starts.push(pos);
+            pos += l.len() + 1;
+        })
+        .collect();
+    let normalized: Vec<String> = lines
+        .iter()
+        .map(|l| normalize_line(l, options.whitespace))
+        .collect();
+    let mut matches = Vec::new();
+    let mut i = 0;
+    while i + wanted.len() <= lines.len() {
+        if normalized[i..i + wanted.len()] != wanted[..] {
+            i += 1;
+            continue;
+        }
+        let last = i + wanted.len() - 1;
+        let mut end = starts[last] + lines[last].len();
+        if take_newline && end < haystack.len() {
+            end += 1;
+        }
+        matches.push(starts[i]..end);
+        i += wanted.len();
+    }
+    matches
+}
+
+/// A replace match as `(start, end, replacement)` byte offsets.
+type ReplaceMatch = (usize, usize, String);
+
+/// Matches of `op.old_text` in `haystack`.
+fn find_replace_matches(
+    haystack: &str,
+    op: &ReplaceOp,
+    options: &ApplyOptions,
+) -> Result<Vec<ReplaceMatch>, String> {
+    if op.fuzzy {
+        return Ok(find_fuzzy_matches(haystack, &op.old_text, &options.hash)
+            .into_iter()
+            .map(|m| (m.start, m.end, op.new_text.clone()))
+            .collect());
+    }
+    if !op.regex {
+        return Ok(haystack
+            .match_indices(op.old_text.as_str())
+            .map(|(pos, m)| (pos, pos + m.len(), op.new_text.clone()))
+            .collect());
+    }
+    let re = regex::Regex::new(&op.old_text)
+        .map_err(|e| format!("replace edit: invalid regex: {}", e))?;
+    Ok(re
+        .captures_iter(haystack)
+        .map(|caps| {
+            let m = caps.get(0).expect("group 0 always matches");
+            let mut replacement = String::new();
+            caps.expand(&op.new_text, &mut replacement);
+            (m.start(), m.end(), replacement)
+        })
+        .collect())
+}
+
+/// The matches of `op` in `haystack` that it should replace. `place` names
+/// the searched text in errors.
+fn select_replace_matches(
+    haystack: &str,
+    op: &ReplaceOp,
+    options: &ApplyOptions,
+    place: &str,
+) -> Result<Vec<ReplaceMatch>, Box<dyn std::error::Error>> {
+    if op.old_text.is_empty() {
+        return Err("replace edit: old_text must not be empty".into());
+    }
+    if op.all && op.occurrence.is_some() {
+        return Err("replace edit: use either all or occurrence, not both".into());
+    }
+    if op.occurrence == Some(0) {
+        return Err("replace edit: occurrence is 1-based".into());
+    }
+    if op.fuzzy && op.regex {
+        return Err("replace edit: use either fuzzy or regex, not both".into());
+    }
+    let matches = find_replace_matches(haystack, op, options)?;
+    if matches.is_empty() {
+        return Err(format!(
+            "replace edit: old_text not found in {}{}:\n{}",
+            place,
+            if op.fuzzy { " (fuzzy)" } else { "" },
+            op.old_text
+        )
+        .into());
+    }
+    if op.all {
+        return Ok(matches);
+    }
+    if let Some(n) = op.occurrence {
+        if n > matches.len() {
+            return Err(format!(
+                "replace edit: occurrence {} requested but old_text matches {} location{}:\n{}",
+                n,
+                matches.len(),
+                if matches.len() == 1 { "" } else { "s" },
+                op.old_text
+            )
+            .into());
+        }
+        return Ok(vec![matches[n - 1].clone()]);
+    }
+    if matches.len() > 1 {
+        return Err(format!(
+            "replace edit: old_text matches {} locations — add more context to make it unique, or set all or occurrence:\n{}",
+            matches.len(),
+            op.old_text
+        )
+        .into());
+    }
+    Ok(matches)
+}
+
+/// Apply `replace` edits with explicit options (hash scheme for `within`).
+pub fn apply_replace_edits_with_options(
+    content: &str,
+    edits: &[HashlineEdit],
+    options: &ApplyOptions,
 ) -> Result<ReplaceResult, Box<dyn std::error::Error>> {
     let mut current = content.to_string();
     let mut total_replacements = 0;
     let mut first_changed_line: Option<usize> = None;
-    for edit in edits {
+    // Byte ranges of each replacement in `current`, shifted as later ops apply.
+    let mut byte_spans: Vec<(usize, usize, usize)> = Vec::new();
+    let mut fuzzy_matches: Vec<EditSpan> = Vec::new();
+    for (edit_index, edit) in edits.iter().enumerate() {
         let op = match edit {
             HashlineEdit::Replace { replace } => replace,
             _ => continue,
         };
-        if op.old_text.is_empty() {
-            return Err("replace edit: old_text must not be empty".into());
-        }
-        let mut match_iter = current.match_indices(op.old_text.as_str());
-        let (match_pos, _) = match_iter
-            .next()
-            .ok_or_else(|| format!("replace edit: old_text not found in file:\n{}", op.old_text))?;
-        let duplicate_count = match_iter.count();
-
-        if duplicate_count > 0 {
-            let total = duplicate_count + 1;
-            return Err(format!(
-                    "replace edit: old_text matches {} locations — add more context to make it unique:\n{}",
-                    total, op.old_text
-                )
-                .into(),
-            );
-        }
+        let (window, place) = match op.window()? {
+            Some(within) => {
+                let window = resolve_within(&current, &within, options)?;
+                let first = current[..window.start].matches('\n').count() + 1;
+                let last = first + current[window.clone()].matches('\n').count();
+                (window, format!("lines {}-{}", first, last))
+            }
+            None => (0..current.len(), "file".to_string()),
+        };
+        let chosen: Vec<ReplaceMatch> =
+            select_replace_matches(&current[window.clone()], op, options, &place)?
+                .into_iter()
+                .map(|(start, end, text)| (window.start + start, window.start + end, text))
+                .collect();
 
-        let line = current[..match_pos].bytes().filter(|b| *b == b'\n').count() + 1;
-        if first_changed_line.is_none_or(|existing| line < existing) {
-            first_changed_line = Some(line);
+        // Last match first, so earlier byte offsets stay valid.
+        for (start, end, replacement) in chosen.into_iter().rev() {
+            let line = current[..start].bytes().filter(|b| *b == b'\n').count() + 1;
+            if op.fuzzy {
+                let matched = current[start..end].strip_suffix('\n');
+                let matched = matched.unwrap_or(&current[start..end]);
+                fuzzy_matches.push(EditSpan {
+                    edit_index,
+                    start_line: line,
+                    end_line: line + matched.matches('\n').count(),
+                });
+            }
+            if first_changed_line.is_none_or(|existing| line < existing) {
+                first_changed_line = Some(line);
+            }
+            current.replace_range(start..end, &replacement);
+            for span in byte_spans.iter_mut() {
+                if span.1 >= end {
+                    span.1 = span.1 + replacement.len() - (end - start);
+                    span.2 = span.2 + replacement.len() - (end - start);
+                }
+            }
+            byte_spans.push((edit_index, start, start + replacement.len()));
+            total_replacements += 1;
         }
-        current = current.replacen(op.old_text.as_str(), op.new_text.as_str(), 1);
-        total_replacements += 1;
     }
+    let line_at = |pos: usize| current[..pos].bytes().filter(|b| *b == b'\n').count() + 1;
+    let spans = byte_spans
+        .iter()
+        .map(|&(edit_index, start, end)| {
+            let start_line = line_at(start);
+            // Text ending in a newline stops on the line before the next one.
+            let end_line = line_at(end.saturating_sub(1).max(start)).max(start_line);
+            EditSpan {
+                edit_index,
+                start_line,
+                end_line,
+            }
+        })
+        .collect();
+    fuzzy_matches.sort_by_key(|s| (s.edit_index, s.start_line));
     Ok(ReplaceResult {
         content: current,
         replacements: total_replacements,
         first_changed_line,
+        spans,
+        fuzzy_matches,
     })
 }
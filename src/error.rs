@@ -1,4 +1,4 @@
-use crate::hash::compute_line_hash;
+use crate::hash::{compute_line_hashes, HashOptions};
 use std::fmt;
 
 /// A single hash mismatch found during validation.
@@ -18,6 +18,8 @@ const MISMATCH_CONTEXT: usize = 2;
 pub struct HashlineMismatchError {
     pub mismatches: Vec<HashMismatch>,
     pub file_lines: Vec<String>,
+    /// Options used to render the updated anchors (must match the caller's).
+    pub hash_options: HashOptions,
 }
 
 impl HashlineMismatchError {
@@ -25,9 +27,16 @@ impl HashlineMismatchError {
         Self {
             mismatches,
             file_lines,
+            hash_options: HashOptions::default(),
         }
     }
 
+    /// Render updated anchors with the given hash options instead of the default.
+    pub fn with_hash_options(mut self, hash_options: HashOptions) -> Self {
+        self.hash_options = hash_options;
+        self
+    }
+
     pub fn format_message(&self) -> String {
         let mut mismatch_set = std::collections::HashMap::new();
         for m in &self.mismatches {
@@ -56,6 +65,7 @@ impl HashlineMismatchError {
         ));
         lines.push(String::new());
 
+        let hashes = compute_line_hashes(&self.file_lines, &self.hash_options);
         let sorted: Vec<usize> = display_lines.into_iter().collect();
         let mut prev_line: Option<usize> = None;
 
@@ -68,8 +78,7 @@ impl HashlineMismatchError {
             prev_line = Some(line_num);
 
             let content = &self.file_lines[line_num - 1];
-            let hash = compute_line_hash(line_num, content);
-            let prefix = format!("{}:{}", line_num, hash);
+            let prefix = format!("{}:{}", line_num, hashes[line_num - 1]);
 
             if mismatch_set.contains_key(&line_num) {
                 lines.push(format!(">>> {}|{}", prefix, content));
@@ -78,24 +87,43 @@ impl HashlineMismatchError {
             }
         }
 
+        if let Some(m) = self
+            .mismatches
+            .iter()
+            .find(|m| m.expected.len() != self.hash_options.width)
+        {
+            lines.push(String::new());
+            lines.push(hash_width_hint(m.expected.len(), self.hash_options.width));
+        }
+
         lines.join("\n")
     }
 
     /// Build a map from old "LINE:HASH" → new "LINE:HASH" for each mismatch.
     #[allow(dead_code)]
     pub fn remaps(&self) -> std::collections::HashMap<String, String> {
+        let hashes = compute_line_hashes(&self.file_lines, &self.hash_options);
         let mut map = std::collections::HashMap::new();
         for m in &self.mismatches {
-            let actual = compute_line_hash(m.line, &self.file_lines[m.line - 1]);
             map.insert(
                 format!("{}:{}", m.line, m.expected),
-                format!("{}:{}", m.line, actual),
+                format!("{}:{}", m.line, hashes[m.line - 1]),
             );
         }
         map
     }
 }
 
+/// Hint for an anchor whose hash is `found` chars long while `hash_width`
+/// is `width`: the anchors most likely come from a read with another width.
+pub fn hash_width_hint(found: usize, width: usize) -> String {
+    format!(
+        "Note: the anchor hash is {} chars but hash_width is {}. If the file was read with \
+`--hash-width {}`, pass `--hash-width {}` here too (or set \"hash_width\": {} in .hashline.json).",
+        found, width, found, found, found
+    )
+}
+
 impl fmt::Display for HashlineMismatchError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.format_message())
@@ -103,3 +131,50 @@ impl fmt::Display for HashlineMismatchError {
 }
 
 impl std::error::Error for HashlineMismatchError {}
+
+/// One of the two edits named by an [`EditConflictError`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConflictingEdit {
+    pub edit_index: usize,
+    pub op: &'static str,
+    /// Lines the edit replaces, or the anchor line of an insert (1-based, inclusive).
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+impl ConflictingEdit {
+    fn describe(&self) -> String {
+        if self.start_line == self.end_line {
+            format!(
+                "edit {} ({}, line {})",
+                self.edit_index, self.op, self.start_line
+            )
+        } else {
+            format!(
+                "edit {} ({}, lines {}-{})",
+                self.edit_index, self.op, self.start_line, self.end_line
+            )
+        }
+    }
+}
+
+/// Error when two edits in one payload touch overlapping lines, so the result
+/// would depend on the order they are applied in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EditConflictError {
+    pub first: ConflictingEdit,
+    pub second: ConflictingEdit,
+}
+
+impl fmt::Display for EditConflictError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} and {} overlap. Combine them into one edit or target separate lines.",
+            self.first.describe(),
+            self.second.describe()
+        )
+    }
+}
+
+impl std::error::Error for EditConflictError {}
@@ -1,26 +1,70 @@
-use crate::hash::compute_line_hash;
+use crate::hash::{compute_line_hashes, compute_line_hashes_in, HashOptions};
 
 /// Format file content with hashline prefixes for display.
 ///
 /// Each line becomes `LINENUM:HASH|CONTENT` where LINENUM is 1-indexed.
+#[allow(dead_code)]
 pub fn format_hashlines(content: &str, start_line: usize) -> String {
+    format_hashlines_with(content, start_line, &HashOptions::default())
+}
+
+/// Format file content with hashline prefixes using explicit hash options.
+///
+/// `content` is treated as the whole file: in context mode the first and last
+/// lines only see neighbours inside it. Use [`format_hashline_range`] for a
+/// slice of a larger file.
+pub fn format_hashlines_with(content: &str, start_line: usize, options: &HashOptions) -> String {
     let lines: Vec<&str> = content.split('\n').collect();
+    let hashes = compute_line_hashes(&lines, options);
     lines
         .iter()
+        .zip(hashes)
+        .enumerate()
+        .map(|(i, (line, hash))| format!("{}:{}|{}", start_line + i, hash, line))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Format lines `start..end` (0-based) of a file, numbered from `start + 1`.
+///
+/// Hashes are computed against the whole file, so anchors in a partial read
+/// match a full read in every hash mode.
+pub fn format_hashline_range(
+    all_lines: &[&str],
+    start: usize,
+    end: usize,
+    options: &HashOptions,
+) -> String {
+    let hashes = compute_line_hashes_in(all_lines, start..end, options);
+    all_lines[start..end]
+        .iter()
+        .zip(hashes)
         .enumerate()
-        .map(|(i, line)| {
-            let num = start_line + i;
-            let hash = compute_line_hash(num, line);
-            format!("{}:{}|{}", num, hash, line)
-        })
+        .map(|(i, (line, hash))| format!("{}:{}|{}", start + i + 1, hash, line))
         .collect::<Vec<_>>()
         .join("\n")
 }
 
+/// Build the `# hashline key=value ...` header printed before read output.
+///
+/// Lists non-default hash settings plus the whole-file fingerprint when given;
+/// `None` when there is nothing to report, so default output is unchanged.
+pub fn format_read_header(options: &HashOptions, file_hash: Option<&str>) -> Option<String> {
+    let mut fields = options.header_fields();
+    if let Some(fp) = file_hash {
+        fields.push(format!("file_hash={}", fp));
+    }
+    if fields.is_empty() {
+        None
+    } else {
+        Some(format!("# hashline {}", fields.join(" ")))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::hash::compute_line_hash;
+    use crate::hash::{compute_line_hash, compute_line_hash_with, HashContext};
 
     #[test]
     fn formats_single_line() {
@@ -57,6 +101,49 @@ mod tests {
         assert!(lines[1].ends_with('|'));
     }
 
+    #[test]
+    fn uses_configured_width() {
+        let options = HashOptions {
+            width: 4,
+            ..Default::default()
+        };
+        let result = format_hashlines_with("hello", 7, &options);
+        let hash = compute_line_hash_with(7, "hello", &options);
+        assert_eq!(hash.len(), 4);
+        assert_eq!(result, format!("7:{}|hello", hash));
+    }
+
+    #[test]
+    fn range_uses_context_outside_slice() {
+        let options = HashOptions {
+            context: HashContext::Neighbors,
+            ..Default::default()
+        };
+        let lines = ["a", "}", "b", "}"];
+        let full = format_hashlines_with(&lines.join("\n"), 1, &options);
+        let full_lines: Vec<&str> = full.split('\n').collect();
+        assert_eq!(
+            format_hashline_range(&lines, 1, 3, &options),
+            full_lines[1..3].join("\n")
+        );
+    }
+
+    #[test]
+    fn read_header_only_when_needed() {
+        assert_eq!(format_read_header(&HashOptions::default(), None), None);
+        assert_eq!(
+            format_read_header(
+                &HashOptions {
+                    width: 4,
+                    ..Default::default()
+                },
+                Some("abc:3")
+            )
+            .unwrap(),
+            "# hashline hash_width=4 file_hash=abc:3"
+        );
+    }
+
     #[test]
     fn round_trips_with_compute_line_hash() {
         let content = "function hello() {\n  return 42;\n}";
@@ -1,24 +1,301 @@
+use serde::Deserialize;
+use std::ops::Range;
+use std::path::Path;
 use xxhash_rust::xxh32::xxh32;
+use xxhash_rust::xxh64::xxh64;
 
-const HASH_LEN: u32 = 2;
 const RADIX: u32 = 16;
-const HASH_MOD: u32 = RADIX.pow(HASH_LEN);
+
+/// Default anchor hash width in hex chars (256 buckets).
+pub const DEFAULT_HASH_WIDTH: usize = 2;
+
+/// Anchor hash widths accepted by `--hash-width`, `HASHLINE_HASH_WIDTH` and
+/// the `hash_width` config key.
+pub const SUPPORTED_HASH_WIDTHS: [usize; 4] = [2, 3, 4, 6];
+
+/// What goes into a line's anchor hash besides the line itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashContext {
+    /// The line's own text only.
+    #[default]
+    None,
+    /// Also the nearest non-blank line above and below, so repeated lines
+    /// such as `}` or blank lines get distinct anchors.
+    Neighbors,
+}
+
+impl HashContext {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HashContext::None => "none",
+            HashContext::Neighbors => "neighbors",
+        }
+    }
+}
+
+/// How whitespace inside a line affects its hash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashWhitespace {
+    /// Pick per file: `indent` for indentation-significant formats (see
+    /// [`is_indent_sensitive`]), `ignore` otherwise. Resolve with
+    /// [`HashOptions::for_path`]; unresolved it hashes like `ignore`.
+    Auto,
+    /// Strip all whitespace, so re-indenting a line keeps its anchor.
+    #[default]
+    Ignore,
+    /// Keep leading indentation, so re-indented lines get new anchors.
+    Indent,
+}
+
+impl HashWhitespace {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HashWhitespace::Auto => "auto",
+            HashWhitespace::Ignore => "ignore",
+            HashWhitespace::Indent => "indent",
+        }
+    }
+}
+
+/// Extensions whose meaning depends on indentation.
+const INDENT_SENSITIVE_EXTENSIONS: &[&str] = &["py", "pyi", "pyw", "yaml", "yml", "mk"];
+/// File names (without extension) whose meaning depends on indentation.
+const INDENT_SENSITIVE_NAMES: &[&str] = &["Makefile", "makefile", "GNUmakefile"];
+
+/// Whether `path` is a format where indentation changes meaning (Python,
+/// YAML, Makefiles), used by [`HashWhitespace::Auto`].
+pub fn is_indent_sensitive(path: &Path) -> bool {
+    let name_match = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|n| INDENT_SENSITIVE_NAMES.contains(&n));
+    let ext_match = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| INDENT_SENSITIVE_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()));
+    name_match || ext_match
+}
+
+/// How per-line anchor hashes are computed.
+///
+/// Only `HashOptions::default()`, which ignores indentation, reproduces the
+/// original 2-char scheme. The CLI's default whitespace mode is `auto`, so
+/// without any configuration its anchors for indentation-sensitive files
+/// (`.py`, `.yaml`, `Makefile`, ...) differ from that scheme.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HashOptions {
+    /// Number of lowercase hex chars per anchor hash.
+    pub width: usize,
+    /// Neighbourhood mixed into each hash.
+    pub context: HashContext,
+    /// Whether leading indentation is part of the hash.
+    pub whitespace: HashWhitespace,
+}
+
+impl Default for HashOptions {
+    fn default() -> Self {
+        HashOptions {
+            width: DEFAULT_HASH_WIDTH,
+            context: HashContext::None,
+            whitespace: HashWhitespace::Ignore,
+        }
+    }
+}
+
+impl HashOptions {
+    /// Resolve `HashWhitespace::Auto` for a specific file.
+    pub fn for_path(&self, path: &Path) -> HashOptions {
+        let mut options = *self;
+        if options.whitespace == HashWhitespace::Auto {
+            options.whitespace = if is_indent_sensitive(path) {
+                HashWhitespace::Indent
+            } else {
+                HashWhitespace::Ignore
+            };
+        }
+        options
+    }
+
+    /// Settings that differ from the default, as `key=value` pairs for the
+    /// `# hashline ...` header printed by `read`. Empty for the default scheme.
+    pub fn header_fields(&self) -> Vec<String> {
+        let mut fields = Vec::new();
+        if self.width != DEFAULT_HASH_WIDTH {
+            fields.push(format!("hash_width={}", self.width));
+        }
+        if self.context != HashContext::None {
+            fields.push(format!("context={}", self.context.as_str()));
+        }
+        if self.whitespace == HashWhitespace::Indent {
+            fields.push(format!("whitespace={}", self.whitespace.as_str()));
+        }
+        fields
+    }
+}
+
+/// Parse and validate a hash width (`"2"`, `"3"`, `"4"` or `"6"`).
+pub fn parse_hash_width(s: &str) -> Result<usize, String> {
+    let width: usize = s
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid hash width {:?}: expected one of 2, 3, 4, 6", s))?;
+    if SUPPORTED_HASH_WIDTHS.contains(&width) {
+        Ok(width)
+    } else {
+        Err(format!(
+            "unsupported hash width {}: expected one of 2, 3, 4, 6",
+            width
+        ))
+    }
+}
+
+/// Parse a hash context mode (`"none"` or `"neighbors"`).
+pub fn parse_hash_context(s: &str) -> Result<HashContext, String> {
+    match s.trim().to_ascii_lowercase().as_str() {
+        "none" => Ok(HashContext::None),
+        "neighbors" | "neighbours" => Ok(HashContext::Neighbors),
+        _ => Err(format!(
+            "invalid hash context {:?}: expected \"none\" or \"neighbors\"",
+            s
+        )),
+    }
+}
+
+/// Parse a whitespace policy (`"auto"`, `"ignore"` or `"indent"`).
+pub fn parse_hash_whitespace(s: &str) -> Result<HashWhitespace, String> {
+    match s.trim().to_ascii_lowercase().as_str() {
+        "auto" => Ok(HashWhitespace::Auto),
+        "ignore" => Ok(HashWhitespace::Ignore),
+        "indent" => Ok(HashWhitespace::Indent),
+        _ => Err(format!(
+            "invalid hash whitespace policy {:?}: expected \"auto\", \"ignore\" or \"indent\"",
+            s
+        )),
+    }
+}
+
+/// Reduce a 32-bit hash to `width` lowercase hex chars.
+pub fn format_hash(h: u32, width: usize) -> String {
+    let modulus = RADIX.pow(width as u32);
+    format!("{:0width$x}", h % modulus, width = width)
+}
 
 /// Compute a short hex hash of a single line.
 ///
 /// Normalizes whitespace (strips all `\s` chars), computes xxHash32 with seed 0,
 /// then returns `hash % 256` as a 2-char lowercase hex string.
-/// The `_idx` parameter is accepted for compatibility but unused.
-pub fn compute_line_hash(_idx: usize, line: &str) -> String {
-    let mut line = line;
-    // Strip trailing \r
-    if line.ends_with('\r') {
-        line = &line[..line.len() - 1];
+/// The `idx` parameter is accepted for compatibility but unused.
+#[allow(dead_code)]
+pub fn compute_line_hash(idx: usize, line: &str) -> String {
+    compute_line_hash_with(idx, line, &HashOptions::default())
+}
+
+/// Compute a line hash with explicit options (see [`HashOptions`]).
+///
+/// With the default options this is identical to [`compute_line_hash`]; wider
+/// settings keep more bits of the same xxHash32 value. This hashes the line on
+/// its own and ignores `options.context`; use [`compute_line_hashes`] when the
+/// whole file is available.
+pub fn compute_line_hash_with(_idx: usize, line: &str, options: &HashOptions) -> String {
+    format_hash(
+        xxh32(normalize_line(line, options.whitespace).as_bytes(), 0),
+        options.width,
+    )
+}
+
+/// Hash every line of a file, honouring `options.context`.
+pub fn compute_line_hashes<S: AsRef<str>>(lines: &[S], options: &HashOptions) -> Vec<String> {
+    compute_line_hashes_in(lines, 0..lines.len(), options)
+}
+
+/// Hash the lines in `range` (0-based indices into `lines`).
+///
+/// In `Neighbors` mode the nearest non-blank lines are looked up in the whole
+/// of `lines`, so hashes of a partial read match those of a full read.
+pub fn compute_line_hashes_in<S: AsRef<str>>(
+    lines: &[S],
+    range: Range<usize>,
+    options: &HashOptions,
+) -> Vec<String> {
+    if options.context == HashContext::None {
+        return range
+            .map(|i| compute_line_hash_with(i + 1, lines[i].as_ref(), options))
+            .collect();
+    }
+
+    let normalized: Vec<String> = lines
+        .iter()
+        .map(|l| normalize_line(l.as_ref(), options.whitespace))
+        .collect();
+    // Nearest non-blank line strictly before / after each index.
+    let mut prev: Vec<Option<usize>> = Vec::with_capacity(normalized.len());
+    let mut last = None;
+    for (i, n) in normalized.iter().enumerate() {
+        prev.push(last);
+        if !n.is_empty() {
+            last = Some(i);
+        }
+    }
+    let mut next: Vec<Option<usize>> = vec![None; normalized.len()];
+    let mut following = None;
+    for (i, n) in normalized.iter().enumerate().rev() {
+        next[i] = following;
+        if !n.is_empty() {
+            following = Some(i);
+        }
+    }
+
+    range
+        .map(|i| {
+            let neighbour = |j: Option<usize>| j.map_or("", |j| normalized[j].as_str());
+            let mut buf = Vec::new();
+            buf.extend_from_slice(neighbour(prev[i]).as_bytes());
+            buf.push(0x1f);
+            buf.extend_from_slice(normalized[i].as_bytes());
+            buf.push(0x1f);
+            buf.extend_from_slice(neighbour(next[i]).as_bytes());
+            format_hash(xxh32(&buf, 0), options.width)
+        })
+        .collect()
+}
+
+/// Strip a trailing `\r` and whitespace; `Indent` keeps the leading run.
+///
+/// Two lines with the same normalized form get the same anchor hash.
+pub fn normalize_line(line: &str, whitespace: HashWhitespace) -> String {
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    let stripped: String = line.chars().filter(|c| !c.is_whitespace()).collect();
+    if whitespace != HashWhitespace::Indent || stripped.is_empty() {
+        return stripped;
+    }
+    let body = line.trim_start();
+    let indent = &line[..line.len() - body.len()];
+    format!("{}{}", indent, stripped)
+}
+
+/// Compute a whole-file fingerprint: `HASH:LINES`.
+///
+/// HASH is the 16-char lowercase hex xxHash64 (seed 0) of the normalized
+/// content and LINES is its line count. Unlike per-line anchors, any change
+/// anywhere in the file — including whitespace — changes the fingerprint.
+pub fn compute_file_fingerprint(content: &str) -> String {
+    let lines = content.split('\n').count();
+    format!("{:016x}:{}", xxh64(content.as_bytes(), 0), lines)
+}
+
+/// Check a caller-supplied fingerprint against the current content.
+///
+/// Accepts either the full `HASH:LINES` form or just the `HASH` part.
+pub fn file_fingerprint_matches(content: &str, expected: &str) -> bool {
+    let actual = compute_file_fingerprint(content);
+    let expected = expected.trim().to_ascii_lowercase();
+    if expected.contains(':') {
+        actual == expected
+    } else {
+        actual.split(':').next() == Some(expected.as_str())
     }
-    // Strip all whitespace
-    let normalized: String = line.chars().filter(|c| !c.is_whitespace()).collect();
-    let h = xxh32(normalized.as_bytes(), 0) % HASH_MOD;
-    format!("{:02x}", h)
 }
 
 #[cfg(test)]
@@ -64,4 +341,216 @@ mod tests {
             compute_line_hash(1, "hello")
         );
     }
+
+    #[test]
+    fn width_controls_hash_length() {
+        for width in SUPPORTED_HASH_WIDTHS {
+            let hash = compute_line_hash_with(
+                1,
+                "hello",
+                &HashOptions {
+                    width,
+                    ..Default::default()
+                },
+            );
+            assert_eq!(hash.len(), width);
+            assert!(hash.chars().all(|c| c.is_ascii_hexdigit()));
+        }
+    }
+
+    #[test]
+    fn default_width_matches_compute_line_hash() {
+        assert_eq!(
+            compute_line_hash_with(1, "hello", &HashOptions::default()),
+            compute_line_hash(1, "hello")
+        );
+    }
+
+    #[test]
+    fn wider_hash_extends_narrower_bits() {
+        // Same xxHash32 value, more low-order nibbles kept.
+        let narrow = compute_line_hash_with(
+            1,
+            "hello",
+            &HashOptions {
+                width: 2,
+                ..Default::default()
+            },
+        );
+        let wide = compute_line_hash_with(
+            1,
+            "hello",
+            &HashOptions {
+                width: 6,
+                ..Default::default()
+            },
+        );
+        assert!(wide.ends_with(&narrow));
+    }
+
+    #[test]
+    fn parse_hash_width_accepts_supported_only() {
+        assert_eq!(parse_hash_width("4"), Ok(4));
+        assert_eq!(parse_hash_width(" 6 "), Ok(6));
+        assert!(parse_hash_width("5").is_err());
+        assert!(parse_hash_width("8").is_err());
+        assert!(parse_hash_width("four").is_err());
+    }
+
+    #[test]
+    fn header_fields_empty_for_default() {
+        assert!(HashOptions::default().header_fields().is_empty());
+        assert_eq!(
+            HashOptions {
+                width: 4,
+                ..Default::default()
+            }
+            .header_fields(),
+            vec!["hash_width=4".to_string()]
+        );
+    }
+
+    fn neighbors() -> HashOptions {
+        HashOptions {
+            context: HashContext::Neighbors,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn context_none_matches_per_line_hash() {
+        let lines = ["fn a() {", "}", "", "fn b() {", "}"];
+        let hashes = compute_line_hashes(&lines, &HashOptions::default());
+        for (i, line) in lines.iter().enumerate() {
+            assert_eq!(hashes[i], compute_line_hash(i + 1, line));
+        }
+    }
+
+    #[test]
+    fn context_distinguishes_repeated_lines() {
+        let lines = ["fn a() {", "}", "", "fn b() {", "}"];
+        let plain = compute_line_hashes(&lines, &HashOptions::default());
+        assert_eq!(plain[1], plain[4]);
+        let ctx = compute_line_hashes(&lines, &neighbors());
+        assert_ne!(ctx[1], ctx[4]);
+    }
+
+    #[test]
+    fn context_skips_blank_neighbours() {
+        // Extra blank lines between neighbours do not change the hash.
+        let a = compute_line_hashes(&["x", "y", "z"], &neighbors());
+        let b = compute_line_hashes(&["x", "", "", "y", "", "z"], &neighbors());
+        assert_eq!(a[1], b[3]);
+    }
+
+    #[test]
+    fn context_range_matches_full_hashes() {
+        let lines = ["a", "}", "b", "}", "c"];
+        let full = compute_line_hashes(&lines, &neighbors());
+        let part = compute_line_hashes_in(&lines, 1..4, &neighbors());
+        assert_eq!(part, full[1..4].to_vec());
+    }
+
+    #[test]
+    fn parse_hash_context_values() {
+        assert_eq!(parse_hash_context("neighbors"), Ok(HashContext::Neighbors));
+        assert_eq!(parse_hash_context("none"), Ok(HashContext::None));
+        assert!(parse_hash_context("lines").is_err());
+    }
+
+    fn indent() -> HashOptions {
+        HashOptions {
+            whitespace: HashWhitespace::Indent,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn indent_mode_sees_reindent() {
+        assert_eq!(
+            compute_line_hash(1, "    x = 1"),
+            compute_line_hash(1, "x = 1")
+        );
+        assert_ne!(
+            compute_line_hash_with(1, "    x = 1", &indent()),
+            compute_line_hash_with(1, "x = 1", &indent())
+        );
+        assert_ne!(
+            compute_line_hash_with(1, "\tx = 1", &indent()),
+            compute_line_hash_with(1, "    x = 1", &indent())
+        );
+    }
+
+    #[test]
+    fn indent_mode_ignores_inner_and_trailing_whitespace() {
+        assert_eq!(
+            compute_line_hash_with(1, "    x  =  1   \r", &indent()),
+            compute_line_hash_with(1, "    x=1", &indent())
+        );
+        // Whitespace-only lines all hash as blank.
+        assert_eq!(
+            compute_line_hash_with(1, "    ", &indent()),
+            compute_line_hash_with(1, "", &indent())
+        );
+    }
+
+    #[test]
+    fn auto_resolves_by_path() {
+        let auto = HashOptions {
+            whitespace: HashWhitespace::Auto,
+            ..Default::default()
+        };
+        for path in ["a.py", "conf/app.YAML", "x.yml", "Makefile", "rules.mk"] {
+            assert_eq!(
+                auto.for_path(Path::new(path)).whitespace,
+                HashWhitespace::Indent,
+                "{}",
+                path
+            );
+        }
+        for path in ["main.rs", "README.md", "pyproject.toml"] {
+            assert_eq!(
+                auto.for_path(Path::new(path)).whitespace,
+                HashWhitespace::Ignore,
+                "{}",
+                path
+            );
+        }
+        // An explicit policy is never overridden by the extension.
+        assert_eq!(
+            HashOptions::default()
+                .for_path(Path::new("a.py"))
+                .whitespace,
+            HashWhitespace::Ignore
+        );
+    }
+
+    #[test]
+    fn fingerprint_has_hash_and_line_count() {
+        let fp = compute_file_fingerprint("a\nb\nc");
+        let (hash, lines) = fp.split_once(':').unwrap();
+        assert_eq!(hash.len(), 16);
+        assert!(hash.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_eq!(lines, "3");
+    }
+
+    #[test]
+    fn fingerprint_is_whitespace_sensitive() {
+        assert_ne!(
+            compute_file_fingerprint("a\n  b"),
+            compute_file_fingerprint("a\nb")
+        );
+    }
+
+    #[test]
+    fn fingerprint_matches_full_or_hash_only() {
+        let content = "x\ny";
+        let fp = compute_file_fingerprint(content);
+        assert!(file_fingerprint_matches(content, &fp));
+        assert!(file_fingerprint_matches(
+            content,
+            fp.split(':').next().unwrap()
+        ));
+        assert!(!file_fingerprint_matches("x\nz", &fp));
+    }
 }
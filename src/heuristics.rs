@@ -2,6 +2,117 @@ use regex::Regex;
 use std::collections::HashMap;
 use std::sync::LazyLock;
 
+/// A rewrite the edit pipeline may apply to the text an edit supplies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Heuristic {
+    /// Drop `LINE:HASH|` prefixes copied from `hashline read` output.
+    StripHashlinePrefix,
+    /// Drop unified-diff `+` markers.
+    StripDiffPlus,
+    /// Drop a first or last line that repeats the line just outside the range.
+    StripBoundaryEcho,
+    /// Drop an inserted line next to the anchor that repeats the anchor line.
+    StripAnchorEcho,
+    /// Re-join lines that were only re-wrapped, keeping the original line.
+    RestoreWrappedLines,
+    /// Copy the replaced line's leading indentation onto the new line.
+    RestoreIndent,
+    /// Widen a single-line edit that swallowed a neighbouring continuation line.
+    ExpandMerge,
+    /// Replace Unicode look-alike hyphens with ASCII `-`.
+    NormalizeHyphens,
+}
+
+impl Heuristic {
+    /// Every heuristic, in pipeline order.
+    pub const ALL: [Heuristic; 8] = [
+        Heuristic::StripHashlinePrefix,
+        Heuristic::StripDiffPlus,
+        Heuristic::StripBoundaryEcho,
+        Heuristic::StripAnchorEcho,
+        Heuristic::RestoreWrappedLines,
+        Heuristic::RestoreIndent,
+        Heuristic::ExpandMerge,
+        Heuristic::NormalizeHyphens,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Heuristic::StripHashlinePrefix => "strip_hashline_prefix",
+            Heuristic::StripDiffPlus => "strip_diff_plus",
+            Heuristic::StripBoundaryEcho => "strip_boundary_echo",
+            Heuristic::StripAnchorEcho => "strip_anchor_echo",
+            Heuristic::RestoreWrappedLines => "restore_wrapped_lines",
+            Heuristic::RestoreIndent => "restore_indent",
+            Heuristic::ExpandMerge => "expand_merge",
+            Heuristic::NormalizeHyphens => "normalize_hyphens",
+        }
+    }
+}
+
+/// Parse a heuristic name as used in `.hashline.json` and on the command line.
+pub fn parse_heuristic(s: &str) -> Result<Heuristic, String> {
+    Heuristic::ALL
+        .into_iter()
+        .find(|h| h.as_str() == s.trim())
+        .ok_or_else(|| {
+            let names: Vec<&str> = Heuristic::ALL.iter().map(|h| h.as_str()).collect();
+            format!(
+                "unknown heuristic '{}' (expected one of: {})",
+                s.trim(),
+                names.join(", ")
+            )
+        })
+}
+
+/// The set of heuristics allowed to run. All are enabled by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeuristicSet {
+    bits: u8,
+}
+
+impl HeuristicSet {
+    pub fn all() -> Self {
+        HeuristicSet { bits: u8::MAX }
+    }
+
+    pub fn none() -> Self {
+        HeuristicSet { bits: 0 }
+    }
+
+    pub fn contains(self, heuristic: Heuristic) -> bool {
+        self.bits & Self::bit(heuristic) != 0
+    }
+
+    pub fn set(&mut self, heuristic: Heuristic, enabled: bool) {
+        if enabled {
+            self.bits |= Self::bit(heuristic);
+        } else {
+            self.bits &= !Self::bit(heuristic);
+        }
+    }
+
+    fn bit(heuristic: Heuristic) -> u8 {
+        1 << heuristic as u8
+    }
+}
+
+impl Default for HeuristicSet {
+    fn default() -> Self {
+        HeuristicSet::all()
+    }
+}
+
+/// One heuristic's effect on one edit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeuristicEvent {
+    pub heuristic: Heuristic,
+    /// 1-based lines of the edit's supplied text that were rewritten or dropped.
+    pub lines: Vec<usize>,
+    /// Extra context, e.g. which file lines a merge expansion took over.
+    pub detail: Option<String>,
+}
+
 /// Pattern matching hashline display format: `LINE:HASH|CONTENT`
 static HASHLINE_PREFIX_RE: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"^\s*(?:>>>|>>)?\s*\d+:[0-9a-zA-Z]{1,16}\|").unwrap());
@@ -87,7 +198,17 @@ fn strip_merge_operator_chars(s: &str) -> String {
 }
 
 /// Strip hashline display prefixes and diff `+` markers from replacement lines.
+#[allow(dead_code)]
 pub fn strip_new_line_prefixes(lines: &[String]) -> Vec<String> {
+    strip_new_line_prefixes_traced(lines, HeuristicSet::all()).0
+}
+
+/// [`strip_new_line_prefixes`] limited to the prefix kinds in `enabled`, also
+/// returning which kind was stripped.
+pub fn strip_new_line_prefixes_traced(
+    lines: &[String],
+    enabled: HeuristicSet,
+) -> (Vec<String>, Option<Heuristic>) {
     let mut hash_prefix_count = 0;
     let mut diff_plus_count = 0;
     let mut non_empty = 0;
@@ -106,17 +227,27 @@ pub fn strip_new_line_prefixes(lines: &[String]) -> Vec<String> {
     }
 
     if non_empty == 0 {
-        return lines.to_vec();
+        return (lines.to_vec(), None);
     }
 
-    let strip_hash = hash_prefix_count > 0 && hash_prefix_count * 2 >= non_empty;
-    let strip_plus = !strip_hash && diff_plus_count > 0 && diff_plus_count * 2 >= non_empty;
+    let strip_hash = enabled.contains(Heuristic::StripHashlinePrefix)
+        && hash_prefix_count > 0
+        && hash_prefix_count * 2 >= non_empty;
+    let strip_plus = !strip_hash
+        && enabled.contains(Heuristic::StripDiffPlus)
+        && diff_plus_count > 0
+        && diff_plus_count * 2 >= non_empty;
 
     if !strip_hash && !strip_plus {
-        return lines.to_vec();
+        return (lines.to_vec(), None);
     }
 
-    lines
+    let heuristic = if strip_hash {
+        Heuristic::StripHashlinePrefix
+    } else {
+        Heuristic::StripDiffPlus
+    };
+    let out = lines
         .iter()
         .map(|l| {
             if strip_hash {
@@ -127,7 +258,8 @@ pub fn strip_new_line_prefixes(lines: &[String]) -> Vec<String> {
                 l.clone()
             }
         })
-        .collect()
+        .collect();
+    (out, Some(heuristic))
 }
 
 /// Restore indentation for paired old/new replacement lines.
@@ -156,9 +288,19 @@ pub fn restore_indent_for_paired_replacement(
 
 /// Undo pure formatting rewrites where the model reflows a single logical line
 /// into multiple lines (or similar), but the token stream is identical.
+#[allow(dead_code)]
 pub fn restore_old_wrapped_lines(old_lines: &[String], new_lines: &[String]) -> Vec<String> {
+    restore_old_wrapped_lines_traced(old_lines, new_lines).0
+}
+
+/// [`restore_old_wrapped_lines`], also returning each collapsed
+/// `(start, len)` span of `new_lines`, in ascending order.
+pub fn restore_old_wrapped_lines_traced(
+    old_lines: &[String],
+    new_lines: &[String],
+) -> (Vec<String>, Vec<(usize, usize)>) {
     if old_lines.is_empty() || new_lines.len() < 2 {
-        return new_lines.to_vec();
+        return (new_lines.to_vec(), Vec::new());
     }
 
     let mut canon_to_old: HashMap<String, (String, usize)> = HashMap::new();
@@ -196,7 +338,7 @@ pub fn restore_old_wrapped_lines(old_lines: &[String], new_lines: &[String]) ->
     }
 
     if candidates.is_empty() {
-        return new_lines.to_vec();
+        return (new_lines.to_vec(), Vec::new());
     }
 
     // Keep only spans whose canonical match is unique in the new output
@@ -210,7 +352,7 @@ pub fn restore_old_wrapped_lines(old_lines: &[String], new_lines: &[String]) ->
         .collect();
 
     if unique_candidates.is_empty() {
-        return new_lines.to_vec();
+        return (new_lines.to_vec(), Vec::new());
     }
 
     // Sort by start descending for back-to-front application
@@ -218,13 +360,16 @@ pub fn restore_old_wrapped_lines(old_lines: &[String], new_lines: &[String]) ->
     sorted.sort_by(|a, b| b.start.cmp(&a.start));
 
     let mut out: Vec<String> = new_lines.to_vec();
+    let mut spans = Vec::with_capacity(sorted.len());
     for c in sorted {
         out.splice(
             c.start..c.start + c.len,
             std::iter::once(c.replacement.clone()),
         );
+        spans.push((c.start, c.len));
     }
-    out
+    spans.reverse();
+    (out, spans)
 }
 
 /// Strip echoed anchor line from insert-after content.
@@ -239,25 +384,52 @@ pub fn strip_insert_anchor_echo_after(anchor_line: &str, dst_lines: &[String]) -
     }
 }
 
+/// Drop a trailing copy of the anchor line from `insert_before` text.
+pub fn strip_insert_anchor_echo_before(anchor_line: &str, dst_lines: &[String]) -> Vec<String> {
+    if dst_lines.len() <= 1 {
+        return dst_lines.to_vec();
+    }
+    if equals_ignoring_whitespace(&dst_lines[dst_lines.len() - 1], anchor_line) {
+        dst_lines[..dst_lines.len() - 1].to_vec()
+    } else {
+        dst_lines.to_vec()
+    }
+}
+
 /// Strip echoed boundary lines from range replacement content.
+#[allow(dead_code)]
 pub fn strip_range_boundary_echo(
     file_lines: &[String],
     start_line: usize,
     end_line: usize,
     dst_lines: &[String],
 ) -> Vec<String> {
+    strip_range_boundary_echo_traced(file_lines, start_line, end_line, dst_lines).0
+}
+
+/// [`strip_range_boundary_echo`], also returning whether the first and the
+/// last line were dropped.
+pub fn strip_range_boundary_echo_traced(
+    file_lines: &[String],
+    start_line: usize,
+    end_line: usize,
+    dst_lines: &[String],
+) -> (Vec<String>, bool, bool) {
     let count = end_line - start_line + 1;
     if dst_lines.len() <= 1 || dst_lines.len() <= count {
-        return dst_lines.to_vec();
+        return (dst_lines.to_vec(), false, false);
     }
 
     let mut out = dst_lines.to_vec();
+    let mut dropped_first = false;
+    let mut dropped_last = false;
 
     // Check if first dst line echoes line before the range
     if start_line >= 2 {
         let before_idx = start_line - 2;
         if equals_ignoring_whitespace(&out[0], &file_lines[before_idx]) {
             out = out[1..].to_vec();
+            dropped_first = true;
         }
     }
 
@@ -268,9 +440,10 @@ pub fn strip_range_boundary_echo(
         && equals_ignoring_whitespace(out.last().unwrap(), &file_lines[after_idx])
     {
         out.pop();
+        dropped_last = true;
     }
 
-    out
+    (out, dropped_first, dropped_last)
 }
 
 /// Detect when model merges a single-line edit with adjacent continuation lines.
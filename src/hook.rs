@@ -8,8 +8,12 @@ use std::path::{Path, PathBuf};
 enum HashlineCmdKind {
     Read,
     JsonRead,
+    YamlRead,
+    TomlRead,
     Apply,
     JsonApply,
+    YamlApply,
+    TomlApply,
 }
 
 /// Session file path: `<tmp>/hashline_session_<ppid>` unless overridden.
@@ -94,28 +98,56 @@ fn parse_hashline_cmd(cmd: &str) -> Option<(HashlineCmdKind, Vec<String>, usize)
     let kind = match sub {
         "read" => HashlineCmdKind::Read,
         "json-read" => HashlineCmdKind::JsonRead,
+        "yaml-read" => HashlineCmdKind::YamlRead,
+        "toml-read" => HashlineCmdKind::TomlRead,
         "apply" => HashlineCmdKind::Apply,
         "json-apply" => HashlineCmdKind::JsonApply,
+        "yaml-apply" => HashlineCmdKind::YamlApply,
+        "toml-apply" => HashlineCmdKind::TomlApply,
         _ => return None,
     };
     Some((kind, tokens, idx + 2))
 }
 
+/// Whether the apply output carries fresh anchors: `--emit-updated`, or
+/// `--format json` (which always includes the updated window).
 fn has_emit_updated(tokens: &[String], args_start: usize) -> bool {
-    tokens[args_start..].iter().any(|t| t == "--emit-updated")
+    let args = &tokens[args_start..];
+    args.iter()
+        .any(|t| t == "--emit-updated" || t == "--format=json")
+        || args
+            .windows(2)
+            .any(|w| w[0] == "--format" && w[1] == "json")
+}
+
+/// `--dry-run` leaves files untouched, so session state does not change.
+fn is_dry_run(tokens: &[String], args_start: usize) -> bool {
+    tokens[args_start..]
+        .iter()
+        .any(|t| t == "--dry-run" || t.starts_with("--dry-run="))
 }
 
 fn extract_input_flag(tokens: &[String], args_start: usize) -> Option<String> {
+    extract_flag_value(tokens, args_start, "--input", Some("-i"))
+}
+
+/// The value of `--flag VALUE`, `--flag=VALUE` or `short VALUE`.
+fn extract_flag_value(
+    tokens: &[String],
+    args_start: usize,
+    flag: &str,
+    short: Option<&str>,
+) -> Option<String> {
     let mut i = args_start;
     while i < tokens.len() {
         let t = &tokens[i];
-        if t == "--input" || t == "-i" {
+        if t == flag || Some(t.as_str()) == short {
             if i + 1 < tokens.len() {
                 return Some(tokens[i + 1].clone());
             }
             return None;
         }
-        if let Some(rest) = t.strip_prefix("--input=") {
+        if let Some(rest) = t.strip_prefix(flag).and_then(|r| r.strip_prefix('=')) {
             if !rest.is_empty() {
                 return Some(rest.to_string());
             }
@@ -124,38 +156,86 @@ fn extract_input_flag(tokens: &[String], args_start: usize) -> Option<String> {
     }
     None
 }
-fn extract_path_from_json_text(text: &str) -> Option<String> {
-    let re = regex::Regex::new(r#""path"\s*:\s*"([^"]*)""#).ok()?;
-    re.captures(text)
-        .map(|c| c[1].to_string())
-        .filter(|s| !s.is_empty())
+/// Collect target paths from an inline payload (heredoc or `echo '...' |`):
+/// the first JSON object in the command with a top-level `path` or `files`.
+/// `path` keys nested in edits or edit text are not targets.
+fn extract_paths_from_json_text(text: &str) -> Vec<String> {
+    for (start, _) in text.match_indices('{') {
+        let mut stream =
+            serde_json::Deserializer::from_str(&text[start..]).into_iter::<serde_json::Value>();
+        if let Some(Ok(v)) = stream.next() {
+            if v.get("path").is_some() || v.get("files").is_some() {
+                return paths_from_payload(&v);
+            }
+        }
+    }
+    Vec::new()
 }
-/// Extract the target file path from a hashline apply/json-apply command string.
-fn extract_apply_file(cmd: &str) -> Option<String> {
-    let (kind, tokens, args_start) = parse_hashline_cmd(cmd)?;
-    if kind != HashlineCmdKind::Apply && kind != HashlineCmdKind::JsonApply {
-        return None;
+/// Collect target paths from a parsed apply payload: `path` for single-file
+/// input, or every `files[].path` for a transaction.
+fn paths_from_payload(v: &serde_json::Value) -> Vec<String> {
+    let entries: Vec<&serde_json::Value> = match v.get("files").and_then(|f| f.as_array()) {
+        Some(files) => files.iter().collect(),
+        None => vec![v],
+    };
+    entries
+        .into_iter()
+        .filter_map(|e| e.get("path").and_then(|p| p.as_str()))
+        .filter(|p| !p.is_empty())
+        .map(|p| p.to_string())
+        .collect()
+}
+/// Extract the target file paths from a hashline apply/json-apply/yaml-apply/toml-apply command string.
+fn extract_apply_files(cmd: &str) -> Vec<String> {
+    let Some((kind, tokens, args_start)) = parse_hashline_cmd(cmd) else {
+        return Vec::new();
+    };
+    if !matches!(
+        kind,
+        HashlineCmdKind::Apply
+            | HashlineCmdKind::JsonApply
+            | HashlineCmdKind::YamlApply
+            | HashlineCmdKind::TomlApply
+    ) {
+        return Vec::new();
+    }
+
+    // `apply --patch FILE` targets the files its ---/+++ headers name.
+    if let Some(pfile) = extract_flag_value(&tokens, args_start, "--patch", None) {
+        if let Ok(contents) = std::fs::read_to_string(&pfile) {
+            if let Ok(files) = crate::patch::parse_patch(&contents) {
+                let paths: Vec<String> = files.iter().filter_map(|f| f.path().ok()).collect();
+                if !paths.is_empty() {
+                    return paths;
+                }
+            }
+        }
     }
 
     if let Some(ifile) = extract_input_flag(&tokens, args_start) {
         if Path::new(&ifile).is_file() {
             if let Ok(contents) = std::fs::read_to_string(&ifile) {
                 if let Ok(v) = serde_json::from_str::<serde_json::Value>(&contents) {
-                    if let Some(p) = v.get("path").and_then(|v| v.as_str()) {
-                        if !p.is_empty() {
-                            return Some(p.to_string());
-                        }
+                    let paths = paths_from_payload(&v);
+                    if !paths.is_empty() {
+                        return paths;
                     }
                 }
             }
         }
     }
-    extract_path_from_json_text(cmd)
+    extract_paths_from_json_text(cmd)
 }
-/// Extract the file argument from a hashline read/json-read command.
+/// Extract the file argument from a hashline read/json-read/yaml-read/toml-read command.
 fn extract_read_file(cmd: &str) -> Option<String> {
     let (kind, tokens, args_start) = parse_hashline_cmd(cmd)?;
-    if kind != HashlineCmdKind::Read && kind != HashlineCmdKind::JsonRead {
+    if !matches!(
+        kind,
+        HashlineCmdKind::Read
+            | HashlineCmdKind::JsonRead
+            | HashlineCmdKind::YamlRead
+            | HashlineCmdKind::TomlRead
+    ) {
         return None;
     }
 
@@ -163,7 +243,15 @@ fn extract_read_file(cmd: &str) -> Option<String> {
     let mut i = args_start;
     while i < tokens.len() {
         let t = &tokens[i];
-        if t == "--start-line" || t == "--lines" {
+        if matches!(
+            t.as_str(),
+            "--start-line"
+                | "--lines"
+                | "--select"
+                | "--hash-width"
+                | "--hash-context"
+                | "--hash-whitespace"
+        ) {
             i += 2;
             continue;
         }
@@ -181,20 +269,30 @@ fn extract_read_file(cmd: &str) -> Option<String> {
 fn apply_kind(cmd: &str) -> Option<HashlineCmdKind> {
     let (kind, _, _) = parse_hashline_cmd(cmd)?;
     match kind {
-        HashlineCmdKind::Apply | HashlineCmdKind::JsonApply => Some(kind),
+        HashlineCmdKind::Apply
+        | HashlineCmdKind::JsonApply
+        | HashlineCmdKind::YamlApply
+        | HashlineCmdKind::TomlApply => Some(kind),
         _ => None,
     }
 }
 fn is_read_cmd(cmd: &str) -> bool {
     matches!(
         parse_hashline_cmd(cmd).map(|(k, _, _)| k),
-        Some(HashlineCmdKind::Read | HashlineCmdKind::JsonRead)
+        Some(
+            HashlineCmdKind::Read
+                | HashlineCmdKind::JsonRead
+                | HashlineCmdKind::YamlRead
+                | HashlineCmdKind::TomlRead
+        )
     )
 }
 
 fn expected_read_command(kind: HashlineCmdKind, file: &str) -> String {
     match kind {
         HashlineCmdKind::JsonApply => format!("hashline json-read {}", file),
+        HashlineCmdKind::YamlApply => format!("hashline yaml-read {}", file),
+        HashlineCmdKind::TomlApply => format!("hashline toml-read {}", file),
         _ => format!("hashline read {}", file),
     }
 }
@@ -248,39 +346,40 @@ fn pre_from_value(v: &serde_json::Value) -> i32 {
         Some(k) => k,
         None => return 0,
     };
-    let file = match extract_apply_file(cmd) {
-        Some(f) => resolve_path(&f),
-        None => {
-            if strict_mode() {
-                eprintln!(
-                    "BLOCKED: Could not determine apply target path in strict mode.\nUse --input with JSON containing \"path\", or an inline payload with \"path\"."
-                );
-                return 2;
-            }
-            return 0;
+    let files = extract_apply_files(cmd);
+    if files.is_empty() {
+        if strict_mode() {
+            eprintln!(
+                "BLOCKED: Could not determine apply target path in strict mode.\nUse --input with JSON containing \"path\", or an inline payload with \"path\"."
+            );
+            return 2;
         }
-    };
-
-    let session = session_path();
-    let read_entry = format!("read:{}", file);
-    let stale_entry = format!("stale:{}", file);
-    let read_cmd = expected_read_command(kind, &file);
-    if session_has(&session, &read_entry) {
         return 0;
     }
-    if session_has(&session, &stale_entry) {
+
+    let session = session_path();
+    for file in files.iter().map(|f| resolve_path(f)) {
+        let read_entry = format!("read:{}", file);
+        let stale_entry = format!("stale:{}", file);
+        let read_cmd = expected_read_command(kind, &file);
+        if session_has(&session, &read_entry) {
+            continue;
+        }
+        if session_has(&session, &stale_entry) {
+            eprintln!(
+                "BLOCKED: \"{}\" was modified by hashline apply but not re-read.\nAnchors are stale. Run:\n  {}\nbefore applying edits.",
+                file, read_cmd
+            );
+            return 2;
+        }
+
         eprintln!(
-            "BLOCKED: \"{}\" was modified by hashline apply but not re-read.\nAnchors are stale. Run:\n  {}\nbefore applying edits.",
+            "BLOCKED: \"{}\" has not been read in this session.\nRun:\n  {}\nbefore applying edits.",
             file, read_cmd
         );
         return 2;
     }
-
-    eprintln!(
-        "BLOCKED: \"{}\" has not been read in this session.\nRun:\n  {}\nbefore applying edits.",
-        file, read_cmd
-    );
-    2
+    0
 }
 /// PreToolUse hook handler. Exit 0 = allow, exit 2 = block.
 pub fn pre() -> i32 {
@@ -314,14 +413,21 @@ pub fn post() -> i32 {
             mark_session(&session, &file, "read");
         }
     } else if let Some((kind, tokens, args_start)) = parse_hashline_cmd(&cmd) {
-        if matches!(kind, HashlineCmdKind::Apply | HashlineCmdKind::JsonApply) {
-            if let Some(file) = extract_apply_file(&cmd) {
-                let file = resolve_path(&file);
-                if has_emit_updated(&tokens, args_start) {
-                    mark_session(&session, &file, "read");
-                } else {
-                    mark_session(&session, &file, "stale");
-                }
+        if matches!(
+            kind,
+            HashlineCmdKind::Apply
+                | HashlineCmdKind::JsonApply
+                | HashlineCmdKind::YamlApply
+                | HashlineCmdKind::TomlApply
+        ) && !is_dry_run(&tokens, args_start)
+        {
+            let state = if has_emit_updated(&tokens, args_start) {
+                "read"
+            } else {
+                "stale"
+            };
+            for file in extract_apply_files(&cmd) {
+                mark_session(&session, &resolve_path(&file), state);
             }
         }
     }
@@ -339,25 +445,88 @@ mod tests {
         assert_eq!(kind, Some(HashlineCmdKind::Apply));
     }
 
+    #[test]
+    fn structured_commands_pair_read_and_apply() {
+        for (format, kind, file) in [
+            ("yaml", HashlineCmdKind::YamlApply, "ci.yml"),
+            ("toml", HashlineCmdKind::TomlApply, "Cargo.toml"),
+        ] {
+            let apply = format!("hashline {}-apply --input e.json", format);
+            assert_eq!(apply_kind(&apply), Some(kind));
+            let read = format!("hashline {}-read --hash-width 4 {}", format, file);
+            assert!(is_read_cmd(&read));
+            assert_eq!(extract_read_file(&read).as_deref(), Some(file));
+            assert_eq!(
+                expected_read_command(kind, file),
+                format!("hashline {}-read {}", format, file)
+            );
+        }
+    }
+
     #[test]
     fn extract_input_supports_short_flag() {
-        let missing = std::env::temp_dir().join(format!("hashline-missing-input-{}-{}.json", std::process::id(), std::thread::current().name().unwrap_or("t")));
+        let missing = std::env::temp_dir().join(format!(
+            "hashline-missing-input-{}-{}.json",
+            std::process::id(),
+            std::thread::current().name().unwrap_or("t")
+        ));
         let _ = std::fs::remove_file(&missing);
         let cmd = format!("hashline apply -i {}", missing.display());
 
-        let f = extract_apply_file(&cmd);
-        assert!(f.is_none());
+        let f = extract_apply_files(&cmd);
+        assert!(f.is_empty());
         let tokens = tokenize_shell_line(&cmd);
         let got = extract_input_flag(&tokens, 2);
         assert_eq!(got.as_deref(), Some(missing.to_string_lossy().as_ref()));
     }
 
+    #[test]
+    fn extract_apply_files_reads_patch_headers() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let diff = dir.path().join("fix.diff");
+        std::fs::write(
+            &diff,
+            "--- a/src/a.rs\n+++ b/src/a.rs\n@@ -1 +1 @@\n-a\n+b\n--- src/b.rs\n+++ src/b.rs\n@@ -1 +1 @@\n-a\n+b\n",
+        )
+        .unwrap();
+        let cmd = format!("hashline apply --patch {}", diff.display());
+        assert_eq!(extract_apply_files(&cmd), vec!["src/a.rs", "src/b.rs"]);
+        let cmd = format!("hashline apply --dry-run --patch={}", diff.display());
+        assert_eq!(extract_apply_files(&cmd), vec!["src/a.rs", "src/b.rs"]);
+    }
+
     #[test]
     fn extract_read_file_handles_quotes() {
         let got = extract_read_file("hashline read --start-line 2 --lines 5 \"dir/a b.rs\"");
         assert_eq!(got.as_deref(), Some("dir/a b.rs"));
     }
 
+    #[test]
+    fn extract_read_file_skips_hash_width_value() {
+        let got = extract_read_file("hashline read src/a.rs --hash-width 4");
+        assert_eq!(got.as_deref(), Some("src/a.rs"));
+    }
+
+    #[test]
+    fn extract_read_file_skips_select_value() {
+        let got = extract_read_file("hashline json-read package.json --select '$.scripts.*'");
+        assert_eq!(got.as_deref(), Some("package.json"));
+    }
+
+    #[test]
+    fn format_json_counts_as_emit_updated() {
+        for cmd in [
+            "hashline apply --format json -i e.json",
+            "hashline apply --format=json -i e.json",
+            "hashline apply --emit-updated -i e.json",
+        ] {
+            let (_, tokens, args_start) = parse_hashline_cmd(cmd).unwrap();
+            assert!(has_emit_updated(&tokens, args_start), "{}", cmd);
+        }
+        let (_, tokens, args_start) = parse_hashline_cmd("hashline apply -i e.json").unwrap();
+        assert!(!has_emit_updated(&tokens, args_start));
+    }
+
     #[test]
     fn strict_mode_blocks_unresolvable_apply() {
         let v: serde_json::Value = serde_json::json!({
@@ -368,4 +537,28 @@ mod tests {
         std::env::remove_var("HASHLINE_HOOK_STRICT");
         assert_eq!(code, 2);
     }
+
+    #[test]
+    fn extract_apply_files_lists_transaction_paths() {
+        let cmd = r#"hashline apply << 'EOF'
+{"files":[{"path":"a.rs","edits":[]},{"path":"b.rs","edits":[]}]}
+EOF"#;
+        assert_eq!(extract_apply_files(cmd), vec!["a.rs", "b.rs"]);
+    }
+
+    #[test]
+    fn extract_apply_files_ignores_nested_path_keys() {
+        let cmd = r#"hashline yaml-apply << 'EOF'
+{"path":"ci.yml","edits":[{"set_path":{"anchor":"$.path:ab","value":{"path":"other.rs"}}},{"set_line":{"anchor":"1:ab","new_text":"\"path\": \"x.rs\""}}]}
+EOF"#;
+        assert_eq!(extract_apply_files(cmd), vec!["ci.yml"]);
+    }
+
+    #[test]
+    fn paths_from_payload_reads_transaction_files() {
+        let v = serde_json::json!({"files": [{"path": "x.rs"}, {"path": "y.rs"}]});
+        assert_eq!(paths_from_payload(&v), vec!["x.rs", "y.rs"]);
+        let v = serde_json::json!({"path": "z.rs", "edits": []});
+        assert_eq!(paths_from_payload(&v), vec!["z.rs"]);
+    }
 }
@@ -3,11 +3,18 @@ use std::fmt;
 use std::fs;
 use std::path::Path;
 
+use crate::hash::{format_hash, HashOptions};
+
+mod cst;
+
+pub use cst::JsonDocument;
+
 // ---------------------------------------------------------------------------
 // Error handling primitives
 // ---------------------------------------------------------------------------
 
-/// Typed error returned by `apply_json_edits`.
+/// Typed error returned by `apply_json_edits` and the other path-anchored
+/// edit APIs (YAML).
 pub enum JsonError {
     HashMismatch {
         path: String,
@@ -81,13 +88,60 @@ pub struct JsonApplyParams {
 // JSONPath segment parser
 // ---------------------------------------------------------------------------
 
-#[derive(Debug, PartialEq)]
-enum PathSegment {
+/// One step of a JSONPath: an object key or an array index.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathSegment {
     Key(String),
     Index(usize),
 }
 
-fn parse_path_segments(path: &str) -> Result<Vec<PathSegment>, JsonError> {
+/// One step of a JSONPath selector. Besides keys and indices, a selector can
+/// count back from the end of an array (`[-1]`), match every child (`[*]`,
+/// `.*`), search all descendants (`..name`) or filter children
+/// (`[?(@.name=="foo")]`).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Selector {
+    Key(String),
+    Index(i64),
+    Wildcard,
+    Descendant(Box<Selector>),
+    Filter(FilterExpr),
+}
+
+/// A filter such as `@.version` (the key exists) or `@.name == "foo"`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterExpr {
+    /// Path below the filtered child (`@`).
+    pub path: Vec<PathSegment>,
+    pub comparison: Option<(FilterOp, Value)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// Split a JSONPath such as `$.a["b.c"][0]` into segments. Selector paths
+/// (wildcards, filters, negative indices, ...) are rejected; resolve them
+/// against a document with `select_path` first.
+pub fn parse_path_segments(path: &str) -> Result<Vec<PathSegment>, JsonError> {
+    parse_selectors(path)?
+        .into_iter()
+        .map(|selector| match selector {
+            Selector::Key(key) => Ok(PathSegment::Key(key)),
+            Selector::Index(idx) if idx >= 0 => Ok(PathSegment::Index(idx as usize)),
+            _ => Err(format!("Path selects by pattern, not a single location: {}", path).into()),
+        })
+        .collect()
+}
+
+/// Split a JSONPath selector such as `$.packages[*].version` into steps.
+pub fn parse_selectors(path: &str) -> Result<Vec<Selector>, JsonError> {
     if path == "$" {
         return Ok(vec![]);
     }
@@ -99,81 +153,47 @@ fn parse_path_segments(path: &str) -> Result<Vec<PathSegment>, JsonError> {
     let bytes = tail.as_bytes();
     let len = bytes.len();
     let mut i = 0;
-    let mut segments = Vec::new();
+    let mut selectors = Vec::new();
     while i < len {
         match bytes[i] {
             b'.' => {
-                i += 1;
+                let descend = tail[i..].starts_with("..");
+                i += if descend { 2 } else { 1 };
                 if i >= len {
                     return Err(format!("Path cannot end with '.': {}", path).into());
                 }
-                if bytes[i] == b'[' {
-                    continue;
-                }
-                let start = i;
-                while i < len && bytes[i] != b'.' && bytes[i] != b'[' {
-                    i += 1;
-                }
-                let key = &tail[start..i];
-                if key.is_empty() {
-                    return Err(format!("Empty key segment in path: {}", path).into());
-                }
-                segments.push(PathSegment::Key(key.to_string()));
-            }
-            b'[' => {
-                if i + 1 >= len {
-                    return Err(format!("Unterminated bracket segment in path: {}", path).into());
-                }
-                if bytes[i + 1] == b'"' {
-                    let start_quote = i + 1;
-                    let mut j = start_quote + 1;
-                    while j < len {
-                        match bytes[j] {
-                            b'\\' => {
-                                j += 1;
-                                if j >= len {
-                                    return Err(
-                                        format!("Unterminated escape in path: {}", path).into()
-                                    );
-                                }
-                                j += 1;
-                            }
-                            b'"' => break,
-                            _ => j += 1,
-                        }
-                    }
-                    if j >= len {
-                        return Err(format!("Unterminated quoted key in path: {}", path).into());
+                let selector = if bytes[i] == b'[' {
+                    if !descend {
+                        continue;
                     }
-                    let str_slice = &tail[start_quote..=j];
-                    let key: String = serde_json::from_str(str_slice)
-                        .map_err(|_| format!("Invalid quoted key in path: {}", path))?;
-                    let closing_bracket = j + 1;
-                    if closing_bracket >= len || bytes[closing_bracket] != b']' {
-                        return Err(format!("Missing closing ']' in path: {}", path).into());
-                    }
-                    segments.push(PathSegment::Key(key));
-                    i = closing_bracket + 1;
-                } else {
+                    let (selector, next) = parse_bracket(tail, i, path)?;
+                    i = next;
+                    selector
+                } else if bytes[i] == b'*' {
                     i += 1;
+                    Selector::Wildcard
+                } else {
                     let start = i;
-                    while i < len && bytes[i].is_ascii_digit() {
+                    while i < len && bytes[i] != b'.' && bytes[i] != b'[' {
                         i += 1;
                     }
-                    if start == i {
-                        return Err(format!("Expected array index in path: {}", path).into());
+                    let key = &tail[start..i];
+                    if key.is_empty() {
+                        return Err(format!("Empty key segment in path: {}", path).into());
                     }
-                    let idx_str = &tail[start..i];
-                    let idx: usize = idx_str.parse().map_err(|_| {
-                        format!("Invalid array index '{}' in path: {}", idx_str, path)
-                    })?;
-                    if i >= len || bytes[i] != b']' {
-                        return Err(format!("Missing closing ']' in path: {}", path).into());
-                    }
-                    i += 1;
-                    segments.push(PathSegment::Index(idx));
+                    Selector::Key(key.to_string())
+                };
+                if descend {
+                    selectors.push(Selector::Descendant(Box::new(selector)));
+                } else {
+                    selectors.push(selector);
                 }
             }
+            b'[' => {
+                let (selector, next) = parse_bracket(tail, i, path)?;
+                selectors.push(selector);
+                i = next;
+            }
             other => {
                 return Err(
                     format!("Unexpected character '{}' in path: {}", other as char, path).into(),
@@ -182,8 +202,285 @@ fn parse_path_segments(path: &str) -> Result<Vec<PathSegment>, JsonError> {
         }
     }
 
-    Ok(segments)
+    Ok(selectors)
 }
+
+/// Parse the bracket step starting at `tail[i] == '['`, returning it and the
+/// offset just past its closing `]`.
+fn parse_bracket(tail: &str, i: usize, path: &str) -> Result<(Selector, usize), JsonError> {
+    let bytes = tail.as_bytes();
+    let len = bytes.len();
+    if i + 1 >= len {
+        return Err(format!("Unterminated bracket segment in path: {}", path).into());
+    }
+    match bytes[i + 1] {
+        b'"' => {
+            let end = skip_quoted(bytes, i + 1)
+                .ok_or_else(|| format!("Unterminated quoted key in path: {}", path))?;
+            let key: String = serde_json::from_str(&tail[i + 1..end])
+                .map_err(|_| format!("Invalid quoted key in path: {}", path))?;
+            if end >= len || bytes[end] != b']' {
+                return Err(format!("Missing closing ']' in path: {}", path).into());
+            }
+            Ok((Selector::Key(key), end + 1))
+        }
+        b'*' => {
+            if i + 2 >= len || bytes[i + 2] != b']' {
+                return Err(format!("Missing closing ']' in path: {}", path).into());
+            }
+            Ok((Selector::Wildcard, i + 3))
+        }
+        b'?' => {
+            if i + 2 >= len || bytes[i + 2] != b'(' {
+                return Err(format!("Expected '(' after '?' in path: {}", path).into());
+            }
+            let start = i + 3;
+            let mut j = start;
+            while j < len && bytes[j] != b')' {
+                j = match bytes[j] {
+                    b'"' | b'\'' => skip_quoted(bytes, j)
+                        .ok_or_else(|| format!("Unterminated string in filter: {}", path))?,
+                    _ => j + 1,
+                };
+            }
+            if j + 1 >= len || bytes[j + 1] != b']' {
+                return Err(format!("Unterminated filter in path: {}", path).into());
+            }
+            let filter = parse_filter(&tail[start..j], path)?;
+            Ok((Selector::Filter(filter), j + 2))
+        }
+        _ => {
+            let start = i + 1;
+            let mut j = start;
+            if j < len && bytes[j] == b'-' {
+                j += 1;
+            }
+            while j < len && bytes[j].is_ascii_digit() {
+                j += 1;
+            }
+            if j == start || (j == start + 1 && bytes[start] == b'-') {
+                return Err(format!("Expected array index in path: {}", path).into());
+            }
+            let idx_str = &tail[start..j];
+            let idx: i64 = idx_str
+                .parse()
+                .map_err(|_| format!("Invalid array index '{}' in path: {}", idx_str, path))?;
+            if j >= len || bytes[j] != b']' {
+                return Err(format!("Missing closing ']' in path: {}", path).into());
+            }
+            Ok((Selector::Index(idx), j + 1))
+        }
+    }
+}
+
+/// Return the offset just past the string literal opening at `bytes[start]`.
+fn skip_quoted(bytes: &[u8], start: usize) -> Option<usize> {
+    let quote = bytes[start];
+    let mut j = start + 1;
+    while j < bytes.len() {
+        match bytes[j] {
+            b'\\' => j += 2,
+            b if b == quote => return Some(j + 1),
+            _ => j += 1,
+        }
+    }
+    None
+}
+
+/// Parse the inside of `[?( ... )]`: `@`, a relative path and an optional
+/// comparison against a JSON literal.
+fn parse_filter(expr: &str, path: &str) -> Result<FilterExpr, JsonError> {
+    let expr = expr.trim();
+    let rest = expr
+        .strip_prefix('@')
+        .ok_or_else(|| format!("Filter must start with '@' in path: {}", path))?;
+    // The relative path runs up to the first space or operator outside quotes.
+    let bytes = rest.as_bytes();
+    let mut j = 0;
+    while j < bytes.len() && !matches!(bytes[j], b'=' | b'!' | b'<' | b'>') {
+        if bytes[j].is_ascii_whitespace() {
+            break;
+        }
+        j = match bytes[j] {
+            b'"' => skip_quoted(bytes, j)
+                .ok_or_else(|| format!("Unterminated string in filter: {}", path))?,
+            _ => j + 1,
+        };
+    }
+    let lhs = &rest[..j];
+    let tail = rest[j..].trim_start();
+
+    let comparison = if tail.is_empty() {
+        None
+    } else {
+        let (op, literal) = [
+            ("==", FilterOp::Eq),
+            ("!=", FilterOp::Ne),
+            ("<=", FilterOp::Le),
+            (">=", FilterOp::Ge),
+            ("<", FilterOp::Lt),
+            (">", FilterOp::Gt),
+        ]
+        .iter()
+        .find_map(|(token, op)| tail.strip_prefix(token).map(|lit| (*op, lit.trim())))
+        .ok_or_else(|| format!("Unknown filter operator in path: {}", path))?;
+        let value = match literal
+            .strip_prefix('\'')
+            .and_then(|s| s.strip_suffix('\''))
+        {
+            Some(s) => Value::String(s.to_string()),
+            None => serde_json::from_str(literal)
+                .map_err(|_| format!("Invalid value '{}' in filter: {}", literal, path))?,
+        };
+        Some((op, value))
+    };
+    let path = parse_path_segments(&format!("${}", lhs))
+        .map_err(|_| format!("Invalid filter path '@{}' in path: {}", lhs, path))?;
+    Ok(FilterExpr { path, comparison })
+}
+
+impl FilterExpr {
+    fn matches(&self, item: &Value) -> bool {
+        let Ok(actual) = query_path_segments(item, &self.path) else {
+            return false;
+        };
+        let Some((op, expected)) = &self.comparison else {
+            return true;
+        };
+        let ordering = match (actual, expected) {
+            (Value::Number(a), Value::Number(b)) => {
+                a.as_f64().and_then(|a| a.partial_cmp(&b.as_f64()?))
+            }
+            (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+            _ => None,
+        };
+        let equal = match ordering {
+            Some(ordering) => ordering.is_eq(),
+            None => actual == expected,
+        };
+        match op {
+            FilterOp::Eq => equal,
+            FilterOp::Ne => !equal,
+            FilterOp::Lt => ordering.is_some_and(|o| o.is_lt()),
+            FilterOp::Le => ordering.is_some_and(|o| o.is_le()),
+            FilterOp::Gt => ordering.is_some_and(|o| o.is_gt()),
+            FilterOp::Ge => ordering.is_some_and(|o| o.is_ge()),
+        }
+    }
+}
+
+/// Resolve a JSONPath selector against `ast`, returning every matched value
+/// with its concrete path, in document order.
+pub fn select_path<'a>(ast: &'a Value, path: &str) -> Result<Vec<(String, &'a Value)>, JsonError> {
+    let mut current = vec![("$".to_string(), ast)];
+    for selector in parse_selectors(path)? {
+        let mut next = Vec::new();
+        for (base, value) in &current {
+            apply_selector(&selector, base, value, &mut next);
+        }
+        current = next;
+    }
+    // Nested descents (`$..a..b`) can reach one value twice.
+    let mut seen = std::collections::HashSet::new();
+    current.retain(|(p, _)| seen.insert(p.clone()));
+    Ok(current)
+}
+
+fn apply_selector<'a>(
+    selector: &Selector,
+    base: &str,
+    value: &'a Value,
+    out: &mut Vec<(String, &'a Value)>,
+) {
+    match selector {
+        Selector::Key(key) => {
+            if let Some(child) = value.as_object().and_then(|map| map.get(key)) {
+                out.push((append_key_path(base, key), child));
+            }
+        }
+        Selector::Index(idx) => {
+            if let Some(items) = value.as_array() {
+                let idx = if *idx < 0 {
+                    items.len() as i64 + idx
+                } else {
+                    *idx
+                };
+                if let Some(item) = usize::try_from(idx).ok().and_then(|i| items.get(i)) {
+                    out.push((format!("{}[{}]", base, idx), item));
+                }
+            }
+        }
+        Selector::Wildcard => out.extend(children(base, value)),
+        Selector::Filter(filter) => out.extend(
+            children(base, value)
+                .into_iter()
+                .filter(|(_, child)| filter.matches(child)),
+        ),
+        Selector::Descendant(inner) => {
+            apply_selector(inner, base, value, out);
+            for (child_path, child) in children(base, value) {
+                apply_selector(selector, &child_path, child, out);
+            }
+        }
+    }
+}
+
+fn children<'a>(base: &str, value: &'a Value) -> Vec<(String, &'a Value)> {
+    match value {
+        Value::Object(map) => map
+            .iter()
+            .map(|(key, child)| (append_key_path(base, key), child))
+            .collect(),
+        Value::Array(items) => items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| (format!("{}[{}]", base, i), item))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// The values an anchor path points at. A plain path names exactly one value;
+/// a selector path may match several, and its anchor hashes them together.
+struct Selection<'a> {
+    matches: Vec<(String, &'a Value)>,
+    single: bool,
+}
+
+impl<'a> Selection<'a> {
+    fn resolve(ast: &'a Value, path: &str) -> Result<Self, JsonError> {
+        let selectors = parse_selectors(path)?;
+        if selectors
+            .iter()
+            .all(|s| matches!(s, Selector::Key(_) | Selector::Index(_)))
+        {
+            return Ok(Selection {
+                matches: vec![query_location(ast, path, &selectors)?],
+                single: true,
+            });
+        }
+        let matches = select_path(ast, path)?;
+        if matches.is_empty() {
+            return Err(format!("No values match path: {}", path).into());
+        }
+        Ok(Selection {
+            matches,
+            single: false,
+        })
+    }
+
+    /// The anchor hash: the value's own hash for a plain path, otherwise the
+    /// hash of the array of all matches in document order.
+    fn hash(&self, options: &HashOptions) -> String {
+        if self.single {
+            compute_canonical_hash_with(self.matches[0].1, options)
+        } else {
+            let all = Value::Array(self.matches.iter().map(|(_, v)| (*v).clone()).collect());
+            compute_canonical_hash_with(&all, options)
+        }
+    }
+}
+
 fn is_dot_compatible_key(key: &str) -> bool {
     let mut chars = key.chars();
     match chars.next() {
@@ -192,7 +489,9 @@ fn is_dot_compatible_key(key: &str) -> bool {
     }
     chars.all(|c| c == '_' || c.is_ascii_alphanumeric())
 }
-fn append_key_path(base: &str, key: &str) -> String {
+/// Extend `base` with `key`, using bracket notation when dot notation cannot
+/// express it.
+pub fn append_key_path(base: &str, key: &str) -> String {
     if is_dot_compatible_key(key) {
         if base == "$" {
             format!("$.{}", key)
@@ -210,6 +509,69 @@ fn append_key_path(base: &str, key: &str) -> String {
     }
 }
 
+/// Navigate to the one value a key/index path names. Negative indices count
+/// from the end, and an index out of range is an error at either end. The
+/// returned path is `path` itself, or its concrete form when it counts from
+/// the end.
+fn query_location<'a>(
+    ast: &'a Value,
+    path: &str,
+    selectors: &[Selector],
+) -> Result<(String, &'a Value), JsonError> {
+    let mut segments = Vec::with_capacity(selectors.len());
+    let mut current = ast;
+    for (i, selector) in selectors.iter().enumerate() {
+        match selector {
+            Selector::Key(key) => {
+                current = current
+                    .as_object()
+                    .ok_or_else(|| {
+                        JsonError::Other(format!(
+                            "Expected object at segment {} but got non-object",
+                            i
+                        ))
+                    })?
+                    .get(key)
+                    .ok_or_else(|| JsonError::Other(format!("Key not found: {}", key)))?;
+                segments.push(PathSegment::Key(key.clone()));
+            }
+            Selector::Index(idx) => {
+                let items = current.as_array().ok_or_else(|| {
+                    JsonError::Other(format!("Expected array at segment {} but got non-array", i))
+                })?;
+                let resolved = if *idx < 0 {
+                    items.len() as i64 + idx
+                } else {
+                    *idx
+                };
+                let position = usize::try_from(resolved)
+                    .ok()
+                    .filter(|&p| p < items.len())
+                    .ok_or_else(|| {
+                        JsonError::Other(format!("Array index {} out of bounds", idx))
+                    })?;
+                current = &items[position];
+                segments.push(PathSegment::Index(position));
+            }
+            _ => unreachable!("query_location takes key and index selectors only"),
+        }
+    }
+    let negative = selectors
+        .iter()
+        .any(|s| matches!(s, Selector::Index(idx) if *idx < 0));
+    let concrete = if negative {
+        segments
+            .iter()
+            .fold("$".to_string(), |base, seg| match seg {
+                PathSegment::Key(key) => append_key_path(&base, key),
+                PathSegment::Index(i) => format!("{}[{}]", base, i),
+            })
+    } else {
+        path.to_string()
+    };
+    Ok((concrete, current))
+}
+
 /// Navigate immutably to the node identified by `segments`.
 fn query_path_segments<'a>(
     ast: &'a Value,
@@ -279,11 +641,16 @@ fn query_path_segments_mut<'a>(
 
 // ---------------------------------------------------------------------------
 // Canonical hash (optimized, direct xxh32, zero string allocs)
+#[allow(dead_code)]
 pub fn compute_canonical_hash(value: &Value) -> String {
+    compute_canonical_hash_with(value, &HashOptions::default())
+}
+
+/// Canonical hash reduced to `options.width` hex chars.
+pub fn compute_canonical_hash_with(value: &Value, options: &HashOptions) -> String {
     let mut buf = Vec::new();
     hash_canonical(&mut buf, value).expect("hash_canonical failed");
-    let h = xxhash_rust::xxh32::xxh32(&buf, 0) % 256u32;
-    format!("{:02x}", h as u8)
+    format_hash(xxhash_rust::xxh32::xxh32(&buf, 0), options.width)
 }
 
 fn hash_canonical<W: std::io::Write>(w: &mut W, value: &Value) -> std::io::Result<()> {
@@ -364,27 +731,69 @@ fn hash_canonical<W: std::io::Write>(w: &mut W, value: &Value) -> std::io::Resul
 // Public API
 // ---------------------------------------------------------------------------
 
-/// Parse a JSON file into a serde_json Value AST.
+/// Parse a JSON, JSONC or JSON5 file into a serde_json Value AST.
 pub fn parse_json_ast(file_path: &Path) -> Result<Value, JsonError> {
+    Ok(parse_json_document(file_path)?.value())
+}
+
+/// Parse a JSON, JSONC or JSON5 file into a `JsonDocument` that keeps its
+/// original text, comments included.
+pub fn parse_json_document(file_path: &Path) -> Result<JsonDocument, JsonError> {
     let content = fs::read_to_string(file_path)
         .map_err(|e| JsonError::from(Box::new(e) as Box<dyn std::error::Error>))?;
-    let value = serde_json::from_str(&content)?;
-    Ok(value)
+    JsonDocument::parse(&content)
 }
 
 /// Compute a hash anchor for a JSON value at a given path.
 /// (stable canonical hash with sorted keys).
+#[allow(dead_code)]
 pub fn compute_json_anchor(path: &str, value: &Value) -> String {
-    format!("{}:{}", path, compute_canonical_hash(value))
+    compute_json_anchor_with(path, value, &HashOptions::default())
+}
+
+/// Compute a JSON anchor with explicit hash options.
+pub fn compute_json_anchor_with(path: &str, value: &Value, options: &HashOptions) -> String {
+    format!("{}:{}", path, compute_canonical_hash_with(value, options))
 }
 
 /// Format JSON AST with inline anchor comments.
+#[allow(dead_code)]
 pub fn format_json_anchors(ast: &Value) -> String {
+    format_json_anchors_with(ast, &HashOptions::default())
+}
+
+/// Format JSON AST with inline anchor comments using explicit hash options.
+pub fn format_json_anchors_with(ast: &Value, options: &HashOptions) -> String {
     let mut buf = String::new();
-    let _ = format_json_with_anchors_inner(&mut buf, ast, "$", 0);
+    let _ = format_json_with_anchors_inner(&mut buf, ast, "$", 0, options);
     buf
 }
 
+/// Format only the values matched by `path`, each under its own anchors. A
+/// selector that can match several values is headed by its own anchor, which
+/// covers all matches at once for bulk edits.
+#[allow(dead_code)]
+pub fn format_json_selection(ast: &Value, path: &str) -> Result<String, JsonError> {
+    format_json_selection_with(ast, path, &HashOptions::default())
+}
+
+/// Format the values matched by `path` using explicit hash options.
+pub fn format_json_selection_with(
+    ast: &Value,
+    path: &str,
+    options: &HashOptions,
+) -> Result<String, JsonError> {
+    let selection = Selection::resolve(ast, path)?;
+    let mut buf = String::new();
+    if !selection.single {
+        buf.push_str(&format!("// {}:{}\n", path, selection.hash(options)));
+    }
+    for (match_path, value) in &selection.matches {
+        let _ = format_json_with_anchors_inner(&mut buf, value, match_path, 0, options);
+    }
+    Ok(buf)
+}
+
 /// JSON-specific edit operations.
 #[allow(clippy::enum_variant_names)]
 #[derive(Debug, Clone, serde::Deserialize)]
@@ -418,48 +827,40 @@ pub struct DeletePathOp {
 
 /// Apply JSON edits to AST atomically.
 /// Returns `JsonError::HashMismatch` if any anchor hash does not match the current value.
+#[allow(dead_code)]
 pub fn apply_json_edits(ast: &mut Value, edits: &[JsonEdit]) -> Result<(), JsonError> {
-    // First pass: validate all anchors
-    for edit in edits {
-        let (path, expected_hash) = match edit {
-            JsonEdit::SetPath { set_path: op } => parse_anchor(&op.anchor)?,
-            JsonEdit::InsertAtPath { insert_at_path: op } => parse_anchor(&op.anchor)?,
-            JsonEdit::DeletePath { delete_path: op } => parse_anchor(&op.anchor)?,
-        };
-        let segments = parse_path_segments(&path)?;
-        let current_value = query_path_segments(ast, &segments)?;
-        let current_hash = compute_canonical_hash(current_value);
-        if current_hash != expected_hash {
-            return Err(JsonError::HashMismatch {
-                path,
-                expected: expected_hash,
-                actual: current_hash,
-            });
-        }
-    }
+    apply_json_edits_with(ast, edits, &HashOptions::default())
+}
+
+/// Apply JSON edits, validating anchors with explicit hash options.
+pub fn apply_json_edits_with(
+    ast: &mut Value,
+    edits: &[JsonEdit],
+    options: &HashOptions,
+) -> Result<(), JsonError> {
+    let resolved = resolve_json_anchors(ast, edits, options)?;
 
     let mut cloned_ast = ast.clone();
 
     // Apply edits to clone atomically
-    for edit in edits {
-        match edit {
-            JsonEdit::SetPath { set_path: op } => {
-                let (path, _) = parse_anchor(&op.anchor)?;
-                set_path(&mut cloned_ast, &path, op.value.clone())?;
-            }
-            JsonEdit::InsertAtPath { insert_at_path: op } => {
-                let (path, _) = parse_anchor(&op.anchor)?;
-                insert_at_path(
-                    &mut cloned_ast,
-                    &path,
-                    op.key.as_deref(),
-                    op.index,
-                    op.value.clone(),
-                )?;
-            }
-            JsonEdit::DeletePath { delete_path: op } => {
-                let (path, _) = parse_anchor(&op.anchor)?;
-                delete_path(&mut cloned_ast, &path)?;
+    for (edit, paths) in edits.iter().zip(&resolved) {
+        for path in paths {
+            match edit {
+                JsonEdit::SetPath { set_path: op } => {
+                    set_path(&mut cloned_ast, path, op.value.clone())?;
+                }
+                JsonEdit::InsertAtPath { insert_at_path: op } => {
+                    insert_at_path(
+                        &mut cloned_ast,
+                        path,
+                        op.key.as_deref(),
+                        op.index,
+                        op.value.clone(),
+                    )?;
+                }
+                JsonEdit::DeletePath { .. } => {
+                    delete_path(&mut cloned_ast, path)?;
+                }
             }
         }
     }
@@ -469,10 +870,139 @@ pub fn apply_json_edits(ast: &mut Value, edits: &[JsonEdit]) -> Result<(), JsonE
     Ok(())
 }
 
+/// Apply JSON edits to a document atomically, rewriting only the edited
+/// values. Whitespace, key order and number literals elsewhere are kept.
+#[allow(dead_code)]
+pub fn apply_json_document_edits(
+    doc: &mut JsonDocument,
+    edits: &[JsonEdit],
+) -> Result<(), JsonError> {
+    apply_json_document_edits_with(doc, edits, &HashOptions::default())
+}
+
+/// Apply JSON edits to a document, validating anchors with explicit hash options.
+pub fn apply_json_document_edits_with(
+    doc: &mut JsonDocument,
+    edits: &[JsonEdit],
+    options: &HashOptions,
+) -> Result<(), JsonError> {
+    apply_document_edits_with(doc, edits, options)
+}
+
+/// A parsed document that keeps its source text and accepts path-anchored
+/// edits. Implemented for JSON and YAML documents.
+pub trait PathDocument: Clone {
+    /// The document's data, which anchors are hashed against.
+    fn value(&self) -> Value;
+    /// The source text, including every edit so far.
+    fn text(&self) -> &str;
+    fn set_path(&mut self, path: &str, value: &Value) -> Result<(), JsonError>;
+    fn insert_at_path(
+        &mut self,
+        path: &str,
+        key: Option<&str>,
+        index: Option<usize>,
+        value: &Value,
+    ) -> Result<(), JsonError>;
+    fn delete_path(&mut self, path: &str) -> Result<(), JsonError>;
+}
+
+impl PathDocument for JsonDocument {
+    fn value(&self) -> Value {
+        JsonDocument::value(self)
+    }
+
+    fn text(&self) -> &str {
+        JsonDocument::text(self)
+    }
+
+    fn set_path(&mut self, path: &str, value: &Value) -> Result<(), JsonError> {
+        JsonDocument::set_path(self, path, value)
+    }
+
+    fn insert_at_path(
+        &mut self,
+        path: &str,
+        key: Option<&str>,
+        index: Option<usize>,
+        value: &Value,
+    ) -> Result<(), JsonError> {
+        JsonDocument::insert_at_path(self, path, key, index, value)
+    }
+
+    fn delete_path(&mut self, path: &str) -> Result<(), JsonError> {
+        JsonDocument::delete_path(self, path)
+    }
+}
+
+/// Apply edits to any `PathDocument` atomically: every anchor is validated
+/// against the current value first, then the edits run on a copy.
+pub fn apply_document_edits_with<D: PathDocument>(
+    doc: &mut D,
+    edits: &[JsonEdit],
+    options: &HashOptions,
+) -> Result<(), JsonError> {
+    let resolved = resolve_json_anchors(&doc.value(), edits, options)?;
+
+    let mut edited = doc.clone();
+    for (edit, paths) in edits.iter().zip(&resolved) {
+        for path in paths {
+            match edit {
+                JsonEdit::SetPath { set_path: op } => edited.set_path(path, &op.value)?,
+                JsonEdit::InsertAtPath { insert_at_path: op } => {
+                    edited.insert_at_path(path, op.key.as_deref(), op.index, &op.value)?
+                }
+                JsonEdit::DeletePath { .. } => edited.delete_path(path)?,
+            }
+        }
+    }
+
+    *doc = edited;
+
+    Ok(())
+}
+
 // ---------------------------------------------------------------------------
 // Internal helpers
 // ---------------------------------------------------------------------------
 
+/// Check every edit's anchor against the current values before anything is
+/// applied, and expand each anchor path into the concrete paths it matches.
+/// All values matched by a selector are validated together. Paths come back
+/// in reverse document order, so removing one match cannot shift another.
+fn resolve_json_anchors(
+    ast: &Value,
+    edits: &[JsonEdit],
+    options: &HashOptions,
+) -> Result<Vec<Vec<String>>, JsonError> {
+    let mut resolved = Vec::with_capacity(edits.len());
+    for edit in edits {
+        let (path, expected_hash) = match edit {
+            JsonEdit::SetPath { set_path: op } => parse_anchor(&op.anchor)?,
+            JsonEdit::InsertAtPath { insert_at_path: op } => parse_anchor(&op.anchor)?,
+            JsonEdit::DeletePath { delete_path: op } => parse_anchor(&op.anchor)?,
+        };
+        let selection = Selection::resolve(ast, &path)?;
+        let current_hash = selection.hash(options);
+        if current_hash != expected_hash {
+            return Err(JsonError::HashMismatch {
+                path,
+                expected: expected_hash,
+                actual: current_hash,
+            });
+        }
+        resolved.push(
+            selection
+                .matches
+                .into_iter()
+                .rev()
+                .map(|(p, _)| p)
+                .collect(),
+        );
+    }
+    Ok(resolved)
+}
+
 fn parse_anchor(anchor: &str) -> Result<(String, String), JsonError> {
     if let Some(colon_pos) = anchor.rfind(':') {
         let path = &anchor[..colon_pos];
@@ -600,6 +1130,7 @@ fn format_json_with_anchors_inner<W: std::fmt::Write>(
     value: &Value,
     current_path: &str,
     indent: usize,
+    options: &HashOptions,
 ) -> std::fmt::Result {
     fn write_indent<W: std::fmt::Write>(w: &mut W, depth: usize) -> std::fmt::Result {
         for _ in 0..depth {
@@ -613,6 +1144,7 @@ fn format_json_with_anchors_inner<W: std::fmt::Write>(
         current_path: &str,
         indent: usize,
         needs_comma: bool,
+        options: &HashOptions,
     ) -> std::fmt::Result {
         match value {
             Value::Object(map) => {
@@ -635,7 +1167,11 @@ fn format_json_with_anchors_inner<W: std::fmt::Write>(
                     let child_path = append_key_path(current_path, key);
 
                     write_indent(w, indent + 1)?;
-                    writeln!(w, "// {}", compute_json_anchor(&child_path, child))?;
+                    writeln!(
+                        w,
+                        "// {}",
+                        compute_json_anchor_with(&child_path, child, options)
+                    )?;
                     write_indent(w, indent + 1)?;
                     let key_repr = serde_json::to_string(key).map_err(|_| std::fmt::Error)?;
                     write!(w, "{}: ", key_repr)?;
@@ -643,7 +1179,7 @@ fn format_json_with_anchors_inner<W: std::fmt::Write>(
                     match child {
                         Value::Object(_) | Value::Array(_) => {
                             writeln!(w)?;
-                            render_value(w, child, &child_path, indent + 1, !is_last)?;
+                            render_value(w, child, &child_path, indent + 1, !is_last, options)?;
                         }
                         _ => {
                             let value_repr =
@@ -680,11 +1216,15 @@ fn format_json_with_anchors_inner<W: std::fmt::Write>(
                 for (index, item) in items.iter().enumerate() {
                     let child_path = format!("{}[{}]", current_path, index);
                     write_indent(w, indent + 1)?;
-                    writeln!(w, "// {}", compute_json_anchor(&child_path, item))?;
+                    writeln!(
+                        w,
+                        "// {}",
+                        compute_json_anchor_with(&child_path, item, options)
+                    )?;
                     let is_last = index + 1 == items.len();
                     match item {
                         Value::Object(_) | Value::Array(_) => {
-                            render_value(w, item, &child_path, indent + 1, !is_last)?;
+                            render_value(w, item, &child_path, indent + 1, !is_last, options)?;
                         }
                         _ => {
                             write_indent(w, indent + 1)?;
@@ -719,8 +1259,12 @@ fn format_json_with_anchors_inner<W: std::fmt::Write>(
     }
 
     write_indent(w, indent)?;
-    writeln!(w, "// {}", compute_json_anchor(current_path, value))?;
-    render_value(w, value, current_path, indent, false)
+    writeln!(
+        w,
+        "// {}",
+        compute_json_anchor_with(current_path, value, options)
+    )?;
+    render_value(w, value, current_path, indent, false, options)
 }
 
 #[cfg(test)]
@@ -762,6 +1306,35 @@ mod tests {
         assert_eq!(anchor, anchor2);
     }
 
+    #[test]
+    fn test_json_anchor_width() {
+        let value = serde_json::json!({"name": "test"});
+        let options = HashOptions {
+            width: 6,
+            ..Default::default()
+        };
+        let anchor = compute_json_anchor_with("$.test", &value, &options);
+        assert_eq!(anchor.len(), "$.test:".len() + 6);
+        let wide = compute_canonical_hash_with(&value, &options);
+        assert!(wide.ends_with(&compute_canonical_hash(&value)));
+    }
+
+    #[test]
+    fn test_apply_json_edits_with_width() {
+        let mut ast = serde_json::json!({"a": 1});
+        let options = HashOptions {
+            width: 4,
+            ..Default::default()
+        };
+        let hash = compute_canonical_hash_with(&ast["a"], &options);
+        let edits: Vec<JsonEdit> = serde_json::from_value(serde_json::json!([
+            {"set_path": {"anchor": format!("$.a:{}", hash), "value": 2}}
+        ]))
+        .unwrap();
+        apply_json_edits_with(&mut ast, &edits, &options).unwrap();
+        assert_eq!(ast["a"], 2);
+    }
+
     #[test]
     fn test_format_json_anchors() {
         let value = serde_json::json!({"name": "test", "value": 42});
@@ -912,4 +1485,136 @@ mod tests {
         let result = apply_json_edits(&mut ast, &edits);
         assert!(matches!(result, Err(JsonError::HashMismatch { .. })));
     }
+
+    #[test]
+    fn test_parse_selectors() {
+        let selectors = parse_selectors(r#"$.a[-1][*].*..name[?(@.id == "x")]"#).unwrap();
+        assert_eq!(
+            selectors,
+            vec![
+                Selector::Key("a".to_string()),
+                Selector::Index(-1),
+                Selector::Wildcard,
+                Selector::Wildcard,
+                Selector::Descendant(Box::new(Selector::Key("name".to_string()))),
+                Selector::Filter(FilterExpr {
+                    path: vec![PathSegment::Key("id".to_string())],
+                    comparison: Some((FilterOp::Eq, serde_json::json!("x"))),
+                }),
+            ]
+        );
+        assert!(parse_path_segments("$.a[*]").is_err());
+        assert!(parse_path_segments("$.a[-1]").is_err());
+        assert!(parse_selectors("$.a[?(@.id ~ 1)]").is_err());
+        assert!(parse_selectors("$.a[?(@.id == 1]").is_err());
+    }
+
+    #[test]
+    fn test_select_path_selectors() {
+        let ast = serde_json::json!({
+            "packages": [
+                {"name": "core", "version": "1.0", "deps": {"util": {"version": "0.3"}}},
+                {"name": "cli", "version": "2.0", "private": true}
+            ]
+        });
+        let paths = |path: &str| -> Vec<String> {
+            select_path(&ast, path)
+                .unwrap()
+                .into_iter()
+                .map(|(p, _)| p)
+                .collect()
+        };
+        assert_eq!(paths("$.packages[-1].name"), vec!["$.packages[1].name"]);
+        assert_eq!(
+            paths("$.packages[*].version"),
+            vec!["$.packages[0].version", "$.packages[1].version"]
+        );
+        assert_eq!(
+            paths("$..version"),
+            vec![
+                "$.packages[0].version",
+                "$.packages[0].deps.util.version",
+                "$.packages[1].version"
+            ]
+        );
+        assert_eq!(paths("$.packages[?(@.name=='cli')]"), vec!["$.packages[1]"]);
+        assert_eq!(
+            paths("$.packages[?(@.version < \"2\")]"),
+            vec!["$.packages[0]"]
+        );
+        assert_eq!(
+            paths("$.packages[?(@.private)].name"),
+            vec!["$.packages[1].name"]
+        );
+        assert!(paths("$.packages[5]").is_empty());
+    }
+
+    #[test]
+    fn test_apply_json_edits_selector_validates_all_matches() {
+        let mut ast = serde_json::json!({"packages": [{"version": "1.0"}, {"version": "2.0"}]});
+        let stale = compute_canonical_hash(&serde_json::json!(["1.0"]));
+        let edits: Vec<JsonEdit> = serde_json::from_value(serde_json::json!([
+            {"set_path": {"anchor": format!("$.packages[*].version:{}", stale), "value": "3.0"}}
+        ]))
+        .unwrap();
+        let result = apply_json_edits(&mut ast, &edits);
+        assert!(matches!(result, Err(JsonError::HashMismatch { .. })));
+
+        let hash = compute_canonical_hash(&serde_json::json!(["1.0", "2.0"]));
+        let edits: Vec<JsonEdit> = serde_json::from_value(serde_json::json!([
+            {"set_path": {"anchor": format!("$.packages[*].version:{}", hash), "value": "3.0"}}
+        ]))
+        .unwrap();
+        apply_json_edits(&mut ast, &edits).unwrap();
+        assert_eq!(
+            ast,
+            serde_json::json!({"packages": [{"version": "3.0"}, {"version": "3.0"}]})
+        );
+    }
+
+    #[test]
+    fn test_apply_json_edits_deletes_every_filter_match() {
+        let mut ast = serde_json::json!({"items": [1, 5, 2, 7, 3]});
+        let hash = compute_canonical_hash(&serde_json::json!([5, 7]));
+        let last = compute_canonical_hash(&serde_json::json!(3));
+        let edits: Vec<JsonEdit> = serde_json::from_value(serde_json::json!([
+            {"set_path": {"anchor": format!("$.items[-1]:{}", last), "value": 4}},
+            {"delete_path": {"anchor": format!("$.items[?(@ > 4)]:{}", hash)}}
+        ]))
+        .unwrap();
+        apply_json_edits(&mut ast, &edits).unwrap();
+        assert_eq!(ast, serde_json::json!({"items": [1, 2, 4]}));
+    }
+
+    #[test]
+    fn test_format_json_selection() {
+        let ast = serde_json::json!({"a": [{"v": 1}, {"v": 2}]});
+        let output = format_json_selection(&ast, "$.a[*].v").unwrap();
+        let hash = compute_canonical_hash(&serde_json::json!([1, 2]));
+        assert!(output.starts_with(&format!("// $.a[*].v:{}\n", hash)));
+        assert!(output.contains(&format!(
+            "// {}\n1\n",
+            compute_json_anchor("$.a[0].v", &ast["a"][0]["v"])
+        )));
+        let single = format_json_selection(&ast, "$.a[-1]").unwrap();
+        assert!(single.starts_with(&format!(
+            "// {}\n",
+            compute_json_anchor("$.a[1]", &ast["a"][1])
+        )));
+        assert!(format_json_selection(&ast, "$.b[*]").is_err());
+    }
+
+    #[test]
+    fn test_format_json_selection_index_out_of_range_at_either_end() {
+        let ast = serde_json::json!({"a": [1, 2, 3]});
+        for (path, message) in [
+            ("$.a[5]", "Array index 5 out of bounds"),
+            ("$.a[-5]", "Array index -5 out of bounds"),
+        ] {
+            match format_json_selection(&ast, path) {
+                Err(JsonError::Other(msg)) => assert_eq!(msg, message),
+                other => panic!("{}: {:?}", path, other),
+            }
+        }
+    }
 }
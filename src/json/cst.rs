@@ -0,0 +1,1100 @@
+//! Lossless JSON syntax tree.
+//!
+//! Every value keeps its byte span in the source text, so an edit splices new
+//! text over exactly the span it changes. Key order, whitespace, indentation
+//! style, comments and number spelling everywhere else stay as they were.
+//!
+//! The parser also accepts JSONC and JSON5: `//` and `/* */` comments,
+//! trailing commas, unquoted keys, single-quoted strings, hexadecimal and
+//! signed numbers, `Infinity` and `NaN`. Since edits only splice, a file
+//! keeps its dialect; inserted values are plain JSON, which every dialect
+//! accepts.
+
+use std::ops::Range;
+
+use serde_json::{Number, Value};
+
+use super::{parse_path_segments, JsonError, PathSegment};
+
+/// Nesting deeper than this is rejected, like `serde_json` does.
+const MAX_DEPTH: usize = 128;
+
+/// A JSON document: the source text plus a tree of spans into it.
+#[derive(Debug, Clone)]
+pub struct JsonDocument {
+    text: String,
+    root: Node,
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    span: Range<usize>,
+    kind: NodeKind,
+}
+
+#[derive(Debug, Clone)]
+enum NodeKind {
+    /// String, number or keyword literal, already decoded. JSON5 `Infinity`
+    /// and `NaN` have no JSON value and decode to `null`.
+    Scalar(Value),
+    Object(Vec<Entry>),
+    Array(Vec<Entry>),
+}
+
+/// A member of an object or an item of an array.
+#[derive(Debug, Clone)]
+struct Entry {
+    /// Object members only: the decoded key and the span of its token.
+    key: Option<(String, Range<usize>)>,
+    value: Node,
+    /// Position of the comma after this entry, if there is one.
+    comma: Option<usize>,
+}
+
+impl Entry {
+    fn name(&self) -> Option<&str> {
+        self.key.as_ref().map(|(name, _)| name.as_str())
+    }
+
+    fn start(&self) -> usize {
+        self.key
+            .as_ref()
+            .map_or(self.value.span.start, |(_, span)| span.start)
+    }
+
+    fn end(&self) -> usize {
+        self.value.span.end
+    }
+
+    /// The end of the entry including its comma.
+    fn end_with_comma(&self) -> usize {
+        self.comma.map_or(self.end(), |c| c + 1)
+    }
+}
+
+impl JsonDocument {
+    /// Parse `text`, keeping it verbatim.
+    pub fn parse(text: &str) -> Result<Self, JsonError> {
+        let mut parser = Parser { text, pos: 0 };
+        parser.skip_trivia()?;
+        let root = parser.parse_value(0)?;
+        parser.skip_trivia()?;
+        if parser.pos < text.len() {
+            return Err(parser.error("trailing characters"));
+        }
+        Ok(JsonDocument {
+            text: text.to_string(),
+            root,
+        })
+    }
+
+    /// The document text, including every edit so far.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The document's value, as `serde_json` would parse it.
+    pub fn value(&self) -> Value {
+        node_value(&self.root)
+    }
+
+    /// Replace the value at `path`, adding the key if its object lacks it.
+    pub fn set_path(&mut self, path: &str, value: &Value) -> Result<(), JsonError> {
+        let segments = parse_path_segments(path)?;
+        let Some((last, parent_segs)) = segments.split_last() else {
+            let span = self.root.span.clone();
+            let rendered = self.render(value, span.start);
+            return self.splice(vec![(span, rendered)]);
+        };
+        let parent = self.find(parent_segs)?.clone();
+        match (last, &parent.kind) {
+            (PathSegment::Key(key), NodeKind::Object(members)) => {
+                match members.iter().rev().find(|m| m.name() == Some(key)) {
+                    Some(member) => {
+                        let rendered = self.render(value, member.start());
+                        self.splice(vec![(member.value.span.clone(), rendered)])
+                    }
+                    None => self.add_member(&parent, members, key, value),
+                }
+            }
+            (PathSegment::Key(_), _) => Err("Expected object for set_path".into()),
+            (PathSegment::Index(idx), NodeKind::Array(items)) => match items.get(*idx) {
+                Some(item) => {
+                    let rendered = self.render(value, item.start());
+                    self.splice(vec![(item.value.span.clone(), rendered)])
+                }
+                None => Err(format!("Array index {} out of bounds in set_path", idx).into()),
+            },
+            (PathSegment::Index(_), _) => Err("Expected array for set_path".into()),
+        }
+    }
+
+    /// Add `key` to the object at `path` (replacing an existing value), or
+    /// insert into the array there at `index` (appending when `None`).
+    pub fn insert_at_path(
+        &mut self,
+        path: &str,
+        key: Option<&str>,
+        index: Option<usize>,
+        value: &Value,
+    ) -> Result<(), JsonError> {
+        if key.is_some() && index.is_some() {
+            return Err("insert_at_path requires either `key` or `index`, not both".into());
+        }
+        let target = self.find(&parse_path_segments(path)?)?.clone();
+        if let Some(key) = key {
+            let NodeKind::Object(members) = &target.kind else {
+                return Err("Cannot insert key into non-object".into());
+            };
+            return match members.iter().rev().find(|m| m.name() == Some(key)) {
+                Some(member) => {
+                    let rendered = self.render(value, member.start());
+                    self.splice(vec![(member.value.span.clone(), rendered)])
+                }
+                None => self.add_member(&target, members, key, value),
+            };
+        }
+        let NodeKind::Array(items) = &target.kind else {
+            return Err("Cannot insert into non-array".into());
+        };
+        match index {
+            Some(idx) if idx > items.len() => Err(format!(
+                "Array insert index {} out of bounds (len {})",
+                idx,
+                items.len()
+            )
+            .into()),
+            Some(idx) if idx < items.len() => self.insert_item(&target, items, idx, value),
+            _ => {
+                let rendered = self.render_entry(&target, items, value);
+                self.append_entry(&target, items, rendered)
+            }
+        }
+    }
+
+    /// Remove the member or item at `path`, with the comments attached to it.
+    pub fn delete_path(&mut self, path: &str) -> Result<(), JsonError> {
+        let segments = parse_path_segments(path)?;
+        let Some((last, parent_segs)) = segments.split_last() else {
+            return Err("Cannot delete root".into());
+        };
+        let parent = self.find(parent_segs)?.clone();
+        match (last, &parent.kind) {
+            (PathSegment::Key(key), NodeKind::Object(members)) => {
+                match members.iter().rposition(|m| m.name() == Some(key)) {
+                    Some(i) => self.remove_entry(&parent, members, i),
+                    // Deleting a missing key is not an error, as with the value API.
+                    None => Ok(()),
+                }
+            }
+            (PathSegment::Key(_), _) => Err("Expected object for delete_path".into()),
+            (PathSegment::Index(idx), NodeKind::Array(items)) => {
+                if *idx < items.len() {
+                    self.remove_entry(&parent, items, *idx)
+                } else {
+                    Err(format!("Array index {} out of bounds in delete_path", idx).into())
+                }
+            }
+            (PathSegment::Index(_), _) => Err("Expected array for delete_path".into()),
+        }
+    }
+
+    /// The node at `segments`, with the same errors as the value API.
+    fn find(&self, segments: &[PathSegment]) -> Result<&Node, JsonError> {
+        let mut current = &self.root;
+        for (i, seg) in segments.iter().enumerate() {
+            current = match (seg, &current.kind) {
+                (PathSegment::Key(key), NodeKind::Object(members)) => members
+                    .iter()
+                    .rev()
+                    .find(|m| m.name() == Some(key))
+                    .map(|m| &m.value)
+                    .ok_or_else(|| JsonError::Other(format!("Key not found: {}", key)))?,
+                (PathSegment::Key(_), _) => {
+                    return Err(
+                        format!("Expected object at segment {} but got non-object", i).into(),
+                    )
+                }
+                (PathSegment::Index(idx), NodeKind::Array(items)) => {
+                    items.get(*idx).map(|item| &item.value).ok_or_else(|| {
+                        JsonError::Other(format!("Array index {} out of bounds", idx))
+                    })?
+                }
+                (PathSegment::Index(_), _) => {
+                    return Err(format!("Expected array at segment {} but got non-array", i).into())
+                }
+            };
+        }
+        Ok(current)
+    }
+
+    /// Apply non-overlapping replacements and re-parse.
+    fn splice(&mut self, mut edits: Vec<(Range<usize>, String)>) -> Result<(), JsonError> {
+        // Stable sort, so of two inserts at one position the first listed
+        // ends up first.
+        edits.sort_by_key(|(span, _)| span.start);
+        let mut text = self.text.clone();
+        for (span, replacement) in edits.into_iter().rev() {
+            text.replace_range(span, &replacement);
+        }
+        *self = JsonDocument::parse(&text)?;
+        Ok(())
+    }
+
+    fn add_member(
+        &mut self,
+        object: &Node,
+        members: &[Entry],
+        key: &str,
+        value: &Value,
+    ) -> Result<(), JsonError> {
+        let last_key = members.last().and_then(|m| m.key.clone());
+        // Keep JSON5 unquoted keys unquoted where the new key allows it.
+        let quoted = match &last_key {
+            Some((_, span))
+                if !self.text[span.clone()].starts_with(['"', '\'']) && is_identifier(key) =>
+            {
+                key.to_string()
+            }
+            _ => serde_json::to_string(key)?,
+        };
+        let colon = match (&last_key, members.last()) {
+            (Some((_, span)), Some(m))
+                if !self.text[span.end..m.value.span.start].contains('/') =>
+            {
+                self.text[span.end..m.value.span.start].to_string()
+            }
+            _ if self.indent_unit().is_some() => ": ".to_string(),
+            _ => ":".to_string(),
+        };
+        let rendered = self.render_entry(object, members, value);
+        self.append_entry(object, members, format!("{}{}{}", quoted, colon, rendered))
+    }
+
+    /// Render `value` as a new entry of `container`, indented like its
+    /// existing entries.
+    fn render_entry(&self, container: &Node, entries: &[Entry], value: &Value) -> String {
+        let indent = match entries.last() {
+            Some(entry) => self.line_indent(entry.start()).to_string(),
+            None => format!(
+                "{}{}",
+                self.line_indent(container.span.start),
+                self.indent_unit().unwrap_or_default()
+            ),
+        };
+        self.render_at(value, &indent)
+    }
+
+    /// Add rendered `entry` after the last of `entries` in `container`.
+    fn append_entry(
+        &mut self,
+        container: &Node,
+        entries: &[Entry],
+        entry: String,
+    ) -> Result<(), JsonError> {
+        if let Some(last) = entries.last() {
+            let separator = self.item_separator(entries, entries.len() - 1);
+            return match last.comma {
+                // Trailing-comma style: the new entry gets one too.
+                Some(comma) => {
+                    let at = self.line_tail(comma + 1);
+                    self.splice(vec![(at..at, format!("{}{},", separator, entry))])
+                }
+                // A comment after the last entry stays on its line.
+                None => {
+                    let at = self.line_tail(last.end());
+                    self.splice(vec![
+                        (last.end()..last.end(), ",".to_string()),
+                        (at..at, format!("{}{}", separator, entry)),
+                    ])
+                }
+            };
+        }
+        let interior = container.span.start + 1..container.span.end - 1;
+        let indent = self.line_indent(container.span.start).to_string();
+        let newline = self.newline();
+        if !self.text[interior.clone()].trim().is_empty() {
+            // Only comments inside: add the entry after them.
+            let close = interior.end;
+            return if self.is_line_start(close) {
+                let at = self.line_start(close);
+                let unit = self.indent_unit().unwrap_or_default();
+                self.splice(vec![(
+                    at..at,
+                    format!("{}{}{}{}", indent, unit, entry, newline),
+                )])
+            } else {
+                self.splice(vec![(close..close, entry)])
+            };
+        }
+        // Empty container: lay it out over several lines in an indented
+        // document, inline in a minified one.
+        let inner = match self.indent_unit() {
+            Some(unit) => format!(
+                "{}{}{}{}{}{}",
+                newline, indent, unit, entry, newline, indent
+            ),
+            None => entry,
+        };
+        self.splice(vec![(interior, inner)])
+    }
+
+    /// Insert `value` into `array` in front of item `idx`, above any comment
+    /// lines attached to that item.
+    fn insert_item(
+        &mut self,
+        array: &Node,
+        items: &[Entry],
+        idx: usize,
+        value: &Value,
+    ) -> Result<(), JsonError> {
+        let start = items[idx].start();
+        let rendered = self.render(value, start);
+        if self.is_line_start(start) {
+            let at = self.leading_start(array, items, idx);
+            let line = format!("{}{},{}", self.line_indent(start), rendered, self.newline());
+            return self.splice(vec![(at..at, line)]);
+        }
+        let separator = self.item_separator(items, idx.max(1).min(items.len() - 1));
+        self.splice(vec![(start..start, format!("{},{}", rendered, separator))])
+    }
+
+    /// Remove entry `i` of `container` with its comma, the comment lines
+    /// directly above it and a comment after it on the same line.
+    fn remove_entry(
+        &mut self,
+        container: &Node,
+        entries: &[Entry],
+        i: usize,
+    ) -> Result<(), JsonError> {
+        let entry = &entries[i];
+        let is_last = i + 1 == entries.len();
+        let mut removals = Vec::new();
+        match self.line_end(entry.end_with_comma()) {
+            Some(end) if self.is_line_start(entry.start()) => {
+                removals.push(self.leading_start(container, entries, i)..end);
+                // Without a trailing comma, the previous entry's comma goes too.
+                if let (true, None, Some(prev)) = (is_last, entry.comma, i.checked_sub(1)) {
+                    if let Some(comma) = entries[prev].comma {
+                        removals.push(comma..comma + 1);
+                    }
+                }
+            }
+            _ if !is_last => removals.push(entry.start()..entries[i + 1].start()),
+            _ if i > 0 && entry.comma.is_some() => {
+                removals.push(entries[i - 1].end_with_comma()..entry.end_with_comma())
+            }
+            _ if i > 0 => removals.push(entries[i - 1].end()..entry.end()),
+            _ => removals.push(entry.start()..entry.end_with_comma()),
+        }
+
+        // Collapse a container left with nothing but whitespace to `{}` / `[]`.
+        let interior = container.span.start + 1..container.span.end - 1;
+        let mut rest = self.text[interior.clone()].to_string();
+        removals.sort_by_key(|r| r.start);
+        for r in removals.iter().rev() {
+            rest.replace_range(r.start - interior.start..r.end - interior.start, "");
+        }
+        if rest.trim().is_empty() {
+            removals = vec![interior];
+        }
+        self.splice(removals.into_iter().map(|r| (r, String::new())).collect())
+    }
+
+    /// Where the comment lines directly above entry `i` begin (a line start),
+    /// or the start of the entry's own line. A blank line ends the block.
+    fn leading_start(&self, container: &Node, entries: &[Entry], i: usize) -> usize {
+        let prev_end = match i {
+            0 => container.span.start + 1,
+            _ => entries[i - 1].end_with_comma(),
+        };
+        let mut start = self.line_start(entries[i].start());
+        while start > 0 {
+            let above = self.line_start(start - 1);
+            if above < prev_end {
+                break;
+            }
+            let line = self.text[above..start].trim();
+            let is_comment =
+                line.starts_with("//") || (line.starts_with("/*") && line.ends_with("*/"));
+            if !is_comment {
+                break;
+            }
+            start = above;
+        }
+        start
+    }
+
+    /// The whitespace in front of entry `i`, used between entries.
+    fn item_separator(&self, entries: &[Entry], i: usize) -> String {
+        let start = entries[i].start();
+        let before = &self.text[..start];
+        let trimmed = before.trim_end_matches([' ', '\t', '\n', '\r']);
+        before[trimmed.len()..].to_string()
+    }
+
+    fn line_start(&self, pos: usize) -> usize {
+        self.text[..pos].rfind('\n').map_or(0, |i| i + 1)
+    }
+
+    /// Whether only indentation precedes `pos` on its line.
+    fn is_line_start(&self, pos: usize) -> bool {
+        self.text[self.line_start(pos)..pos]
+            .chars()
+            .all(|c| c == ' ' || c == '\t')
+    }
+
+    /// If the rest of the line from `pos` holds only whitespace and comments,
+    /// the position of its line break; otherwise `pos`.
+    fn line_tail(&self, pos: usize) -> usize {
+        let bytes = self.text.as_bytes();
+        let mut i = pos;
+        loop {
+            match bytes.get(i) {
+                Some(b' ' | b'\t') => i += 1,
+                Some(b'/') if bytes.get(i + 1) == Some(&b'/') => {
+                    let end = self.text[i..].find('\n').map_or(self.text.len(), |n| i + n);
+                    return if self.text[..end].ends_with('\r') {
+                        end - 1
+                    } else {
+                        end
+                    };
+                }
+                Some(b'/') if bytes.get(i + 1) == Some(&b'*') => {
+                    match self.text[i + 2..].find("*/") {
+                        Some(n) if !self.text[i..i + n + 2].contains('\n') => i += n + 4,
+                        _ => return pos,
+                    }
+                }
+                Some(b'\r' | b'\n') | None => return i,
+                Some(_) => return pos,
+            }
+        }
+    }
+
+    /// The start of the next line, if the rest of the line from `pos` holds
+    /// only whitespace and comments.
+    fn line_end(&self, pos: usize) -> Option<usize> {
+        let tail = self.line_tail(pos);
+        let rest = &self.text[tail..];
+        if rest.starts_with("\r\n") {
+            Some(tail + 2)
+        } else if rest.starts_with('\n') {
+            Some(tail + 1)
+        } else {
+            None
+        }
+    }
+
+    /// Leading whitespace of the line containing `pos`.
+    fn line_indent(&self, pos: usize) -> &str {
+        let line = &self.text[self.line_start(pos)..];
+        &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+    }
+
+    /// One level of indentation, taken from the first indented line. `None`
+    /// for a document on a single line.
+    fn indent_unit(&self) -> Option<String> {
+        if !self.text.trim_end().contains('\n') {
+            return None;
+        }
+        let unit = self
+            .text
+            .split('\n')
+            .skip(1)
+            .map(|line| &line[..line.len() - line.trim_start_matches([' ', '\t']).len()])
+            .find(|indent| !indent.is_empty())
+            .unwrap_or("  ");
+        Some(unit.to_string())
+    }
+
+    fn newline(&self) -> &'static str {
+        if self.text.contains("\r\n") {
+            "\r\n"
+        } else {
+            "\n"
+        }
+    }
+
+    /// Render `value` for the position `pos`, indented like that line.
+    fn render(&self, value: &Value, pos: usize) -> String {
+        let indent = self.line_indent(pos).to_string();
+        self.render_at(value, &indent)
+    }
+
+    /// Render `value` whose first line is already indented by `indent`.
+    /// Containers follow the document's indentation unit, or stay compact in
+    /// a minified document.
+    fn render_at(&self, value: &Value, indent: &str) -> String {
+        let Some(unit) = self.indent_unit() else {
+            return value.to_string();
+        };
+        let mut out = String::new();
+        write_pretty(&mut out, value, indent, &unit, self.newline());
+        out
+    }
+}
+
+fn node_value(node: &Node) -> Value {
+    match &node.kind {
+        NodeKind::Scalar(value) => value.clone(),
+        NodeKind::Object(members) => Value::Object(
+            members
+                .iter()
+                .map(|m| {
+                    (
+                        m.name().unwrap_or_default().to_string(),
+                        node_value(&m.value),
+                    )
+                })
+                .collect(),
+        ),
+        NodeKind::Array(items) => {
+            Value::Array(items.iter().map(|i| node_value(&i.value)).collect())
+        }
+    }
+}
+
+/// Pretty-print like `serde_json::to_string_pretty`, with a custom
+/// indentation unit and base indentation.
+fn write_pretty(out: &mut String, value: &Value, indent: &str, unit: &str, newline: &str) {
+    let inner = format!("{}{}", indent, unit);
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            out.push('{');
+            for (i, (key, child)) in map.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(newline);
+                out.push_str(&inner);
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push_str(": ");
+                write_pretty(out, child, &inner, unit, newline);
+            }
+            out.push_str(newline);
+            out.push_str(indent);
+            out.push('}');
+        }
+        Value::Array(items) if !items.is_empty() => {
+            out.push('[');
+            for (i, child) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(newline);
+                out.push_str(&inner);
+                write_pretty(out, child, &inner, unit, newline);
+            }
+            out.push_str(newline);
+            out.push_str(indent);
+            out.push(']');
+        }
+        _ => out.push_str(&value.to_string()),
+    }
+}
+
+/// A JSON5 identifier that can be written as an unquoted key.
+fn is_identifier(key: &str) -> bool {
+    let mut chars = key.chars();
+    matches!(chars.next(), Some(c) if is_identifier_start(c))
+        && chars.all(|c| is_identifier_start(c) || c.is_alphanumeric())
+}
+
+fn is_identifier_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_' || c == '$'
+}
+
+/// Decode a JSON or JSON5 number literal.
+fn number_value(literal: &str) -> Option<Value> {
+    let (negative, body) = match literal.as_bytes().first() {
+        Some(b'-') => (true, &literal[1..]),
+        Some(b'+') => (false, &literal[1..]),
+        _ => (false, literal),
+    };
+    if body == "Infinity" || body == "NaN" {
+        return Some(Value::Null);
+    }
+    if let Some(hex) = body.strip_prefix("0x").or_else(|| body.strip_prefix("0X")) {
+        let n = u64::from_str_radix(hex, 16).ok()?;
+        let number = match (negative, i64::try_from(n)) {
+            (false, _) => Number::from(n),
+            (true, Ok(n)) => Number::from(-n),
+            (true, Err(_)) => Number::from_f64(-(n as f64))?,
+        };
+        return Some(Value::Number(number));
+    }
+    if !body.starts_with(|c: char| c.is_ascii_digit() || c == '.')
+        || !body.contains(|c: char| c.is_ascii_digit())
+    {
+        return None;
+    }
+    // JSON5 allows `.5` and `5.`; JSON wants a digit on both sides.
+    let mut normalized = String::from(if negative { "-" } else { "" });
+    if body.starts_with('.') {
+        normalized.push('0');
+    }
+    let mut chars = body.chars().peekable();
+    while let Some(c) = chars.next() {
+        normalized.push(c);
+        if c == '.' && !chars.peek().is_some_and(|c| c.is_ascii_digit()) {
+            normalized.push('0');
+        }
+    }
+    serde_json::from_str::<Number>(&normalized)
+        .ok()
+        .map(Value::Number)
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, what: &str) -> JsonError {
+        let before = &self.text[..self.pos];
+        let line = before.matches('\n').count() + 1;
+        let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+        JsonError::Other(format!(
+            "Invalid JSON: {} at line {} column {}",
+            what, line, column
+        ))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    /// Skip whitespace (including a BOM and JSON5's Unicode spaces) and comments.
+    fn skip_trivia(&mut self) -> Result<(), JsonError> {
+        loop {
+            let rest = &self.text[self.pos..];
+            if rest.starts_with("//") {
+                self.pos += rest.find('\n').unwrap_or(rest.len());
+            } else if let Some(comment) = rest.strip_prefix("/*") {
+                match comment.find("*/") {
+                    Some(n) => self.pos += n + 4,
+                    None => return Err(self.error("unterminated comment")),
+                }
+            } else if let Some(c) = self
+                .peek()
+                .filter(|&c| c.is_whitespace() || c == '\u{feff}')
+            {
+                self.pos += c.len_utf8();
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn expect(&mut self, c: char, what: &str) -> Result<(), JsonError> {
+        if self.peek() != Some(c) {
+            return Err(self.error(&format!("expected {}", what)));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn parse_value(&mut self, depth: usize) -> Result<Node, JsonError> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("recursion limit exceeded"));
+        }
+        let start = self.pos;
+        let kind = match self.peek() {
+            Some('{') => NodeKind::Object(self.parse_entries(depth, '}')?),
+            Some('[') => NodeKind::Array(self.parse_entries(depth, ']')?),
+            Some('"' | '\'') => NodeKind::Scalar(Value::String(self.parse_string()?)),
+            Some('-' | '+' | '.' | '0'..='9') => NodeKind::Scalar(self.parse_number()?),
+            Some(c) if is_identifier_start(c) => {
+                let word = self.parse_identifier();
+                NodeKind::Scalar(match word {
+                    "true" => Value::Bool(true),
+                    "false" => Value::Bool(false),
+                    "null" | "Infinity" | "NaN" => Value::Null,
+                    _ => {
+                        self.pos = start;
+                        return Err(self.error("expected value"));
+                    }
+                })
+            }
+            Some(_) => return Err(self.error("expected value")),
+            None => return Err(self.error("unexpected end of input")),
+        };
+        Ok(Node {
+            span: start..self.pos,
+            kind,
+        })
+    }
+
+    /// The entries of an object (`close` is `}`) or array (`]`).
+    fn parse_entries(&mut self, depth: usize, close: char) -> Result<Vec<Entry>, JsonError> {
+        let expected = if close == '}' {
+            "',' or '}'"
+        } else {
+            "',' or ']'"
+        };
+        self.pos += 1;
+        let mut entries = Vec::new();
+        loop {
+            self.skip_trivia()?;
+            // Also reached after a trailing comma.
+            if self.peek() == Some(close) {
+                self.pos += 1;
+                return Ok(entries);
+            }
+            let key = if close == '}' {
+                let start = self.pos;
+                let name = match self.peek() {
+                    Some('"' | '\'') => self.parse_string()?,
+                    Some(c) if is_identifier_start(c) => self.parse_identifier().to_string(),
+                    _ => return Err(self.error("expected string key")),
+                };
+                let span = start..self.pos;
+                self.skip_trivia()?;
+                self.expect(':', "':'")?;
+                self.skip_trivia()?;
+                Some((name, span))
+            } else {
+                None
+            };
+            let value = self.parse_value(depth + 1)?;
+            self.skip_trivia()?;
+            let comma = match self.peek() {
+                Some(',') => {
+                    self.pos += 1;
+                    Some(self.pos - 1)
+                }
+                Some(c) if c == close => None,
+                _ => return Err(self.error(&format!("expected {}", expected))),
+            };
+            entries.push(Entry { key, value, comma });
+        }
+    }
+
+    fn parse_identifier(&mut self) -> &'a str {
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| is_identifier_start(c) || c.is_alphanumeric())
+        {
+            self.bump();
+        }
+        &self.text[start..self.pos]
+    }
+
+    /// Decode a double- or single-quoted string literal.
+    fn parse_string(&mut self) -> Result<String, JsonError> {
+        let quote = self.bump();
+        let mut out = String::new();
+        loop {
+            let at = self.pos;
+            let c = match self.bump() {
+                Some('\n' | '\r') | None => {
+                    self.pos = at;
+                    return Err(self.error("unterminated string"));
+                }
+                Some(c) => c,
+            };
+            if Some(c) == quote {
+                return Ok(out);
+            }
+            if c != '\\' {
+                out.push(c);
+                continue;
+            }
+            let decoded = match self.bump() {
+                Some('b') => Some('\u{8}'),
+                Some('f') => Some('\u{c}'),
+                Some('n') => Some('\n'),
+                Some('r') => Some('\r'),
+                Some('t') => Some('\t'),
+                Some('v') => Some('\u{b}'),
+                Some('0') if !self.peek().is_some_and(|c| c.is_ascii_digit()) => Some('\0'),
+                Some('x') => self.parse_hex(2).and_then(char::from_u32),
+                Some('u') => self.parse_unicode_escape(),
+                // JSON5 line continuation.
+                Some('\r') => {
+                    if self.peek() == Some('\n') {
+                        self.pos += 1;
+                    }
+                    continue;
+                }
+                Some('\n' | '\u{2028}' | '\u{2029}') => continue,
+                Some('0'..='9') | None => None,
+                Some(other) => Some(other),
+            };
+            match decoded {
+                Some(c) => out.push(c),
+                None => {
+                    self.pos = at;
+                    return Err(self.error("invalid string escape"));
+                }
+            }
+        }
+    }
+
+    fn parse_hex(&mut self, digits: usize) -> Option<u32> {
+        let hex = self.text.get(self.pos..self.pos + digits)?;
+        let n = u32::from_str_radix(hex, 16).ok()?;
+        self.pos += digits;
+        Some(n)
+    }
+
+    /// `\uXXXX`, combining a surrogate pair into one character.
+    fn parse_unicode_escape(&mut self) -> Option<char> {
+        let high = self.parse_hex(4)?;
+        if !(0xD800..0xDC00).contains(&high) {
+            return char::from_u32(high);
+        }
+        if !self.text[self.pos..].starts_with("\\u") {
+            return None;
+        }
+        self.pos += 2;
+        let low = self.parse_hex(4)?;
+        if !(0xDC00..0xE000).contains(&low) {
+            return None;
+        }
+        char::from_u32(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00))
+    }
+
+    fn parse_number(&mut self) -> Result<Value, JsonError> {
+        let start = self.pos;
+        let mut prev = None;
+        while let Some(c) = self.peek() {
+            let exponent_sign =
+                matches!(c, '+' | '-') && (self.pos == start || matches!(prev, Some('e' | 'E')));
+            if !(c.is_ascii_alphanumeric() || c == '.' || exponent_sign) {
+                break;
+            }
+            // In hex literals `e` is a digit, not an exponent.
+            prev = Some(c).filter(|_| !self.text[start..self.pos].contains(['x', 'X']));
+            self.pos += 1;
+        }
+        number_value(&self.text[start..self.pos]).ok_or_else(|| {
+            self.pos = start;
+            self.error("invalid number")
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const PACKAGE: &str = "{\n    \"name\": \"demo\",\n    \"version\": 1.50,\n    \"deps\": {\n        \"b\": \"1\",\n        \"a\": \"2\"\n    },\n    \"files\": [\"x\", \"y\"]\n}\n";
+
+    const TSCONFIG: &str = "{\n  // Compiler settings\n  \"compilerOptions\": {\n    \"target\": \"es2020\", // keep in sync with node\n    /* strict mode */\n    \"strict\": true,\n    \"paths\": {},\n  },\n  \"include\": [\n    \"src\",\n    // generated\n    \"gen\",\n  ],\n}\n";
+
+    #[test]
+    fn parse_keeps_text_and_matches_serde() {
+        let doc = JsonDocument::parse(PACKAGE).unwrap();
+        assert_eq!(doc.text(), PACKAGE);
+        assert_eq!(doc.value(), serde_json::from_str::<Value>(PACKAGE).unwrap());
+    }
+
+    #[test]
+    fn parse_rejects_invalid_json_with_position() {
+        for bad in [
+            "{,}",
+            "[1,,2]",
+            "[1 2]",
+            "{\"a\" 1}",
+            "\"\\x\"",
+            "01",
+            "{} x",
+            "tru",
+            "/* open",
+            "'a\nb'",
+            "0x",
+            "{a b: 1}",
+            ".",
+            "\"\\01\"",
+        ] {
+            let err = JsonDocument::parse(bad).unwrap_err().to_string();
+            assert!(err.starts_with("Invalid JSON"), "{}: {}", bad, err);
+        }
+        let err = JsonDocument::parse("{\n  \"a\": ?\n}")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("line 2 column 8"), "{}", err);
+    }
+
+    #[test]
+    fn parse_jsonc_comments_and_trailing_commas() {
+        let doc = JsonDocument::parse(TSCONFIG).unwrap();
+        assert_eq!(
+            doc.value(),
+            json!({
+                "compilerOptions": {"target": "es2020", "strict": true, "paths": {}},
+                "include": ["src", "gen"]
+            })
+        );
+    }
+
+    #[test]
+    fn parse_json5_syntax() {
+        let doc = JsonDocument::parse(
+            "// config\n{unquoted: 'single \"q\"', $id: 0xFF, neg: -0x10, lead: .5, trail: 5., plus: +1e2,\n inf: -Infinity, nan: NaN, cont: 'a\\\nb', esc: '\\x41\\u00e9\\uD83D\\uDE00\\v', }",
+        )
+        .unwrap();
+        assert_eq!(
+            doc.value(),
+            json!({
+                "unquoted": "single \"q\"", "$id": 255, "neg": -16, "lead": 0.5, "trail": 5.0,
+                "plus": 100.0, "inf": null, "nan": null, "cont": "ab",
+                "esc": "A\u{e9}\u{1F600}\u{b}"
+            })
+        );
+    }
+
+    #[test]
+    fn set_replaces_only_the_value_span() {
+        let mut doc = JsonDocument::parse(PACKAGE).unwrap();
+        doc.set_path("$.name", &json!("renamed")).unwrap();
+        assert_eq!(doc.text(), PACKAGE.replace("\"demo\"", "\"renamed\""));
+    }
+
+    #[test]
+    fn set_missing_key_appends_member_in_document_style() {
+        let mut doc = JsonDocument::parse(PACKAGE).unwrap();
+        doc.set_path("$.deps.c", &json!({"x": [1]})).unwrap();
+        assert!(
+            doc.text().contains(
+                "        \"a\": \"2\",\n        \"c\": {\n            \"x\": [\n                1\n            ]\n        }\n    },"
+            ),
+            "{}",
+            doc.text()
+        );
+    }
+
+    #[test]
+    fn insert_into_inline_array_keeps_spacing() {
+        let mut doc = JsonDocument::parse(PACKAGE).unwrap();
+        doc.insert_at_path("$.files", None, Some(0), &json!("w"))
+            .unwrap();
+        doc.insert_at_path("$.files", None, None, &json!("z"))
+            .unwrap();
+        assert!(
+            doc.text().contains("[\"w\", \"x\", \"y\", \"z\"]"),
+            "{}",
+            doc.text()
+        );
+    }
+
+    #[test]
+    fn insert_into_empty_containers() {
+        let mut doc = JsonDocument::parse("{\n  \"a\": {},\n  \"b\": []\n}").unwrap();
+        doc.insert_at_path("$.a", Some("k"), None, &json!(1))
+            .unwrap();
+        doc.insert_at_path("$.b", None, None, &json!(2)).unwrap();
+        assert_eq!(
+            doc.text(),
+            "{\n  \"a\": {\n    \"k\": 1\n  },\n  \"b\": [\n    2\n  ]\n}"
+        );
+        let mut doc = JsonDocument::parse("{\"a\":{}}").unwrap();
+        doc.insert_at_path("$.a", Some("k"), None, &json!(1))
+            .unwrap();
+        assert_eq!(doc.text(), "{\"a\":{\"k\":1}}");
+    }
+
+    #[test]
+    fn delete_removes_member_and_separator() {
+        let mut doc = JsonDocument::parse(PACKAGE).unwrap();
+        doc.delete_path("$.deps.b").unwrap();
+        doc.delete_path("$.files").unwrap();
+        assert_eq!(
+            doc.text(),
+            "{\n    \"name\": \"demo\",\n    \"version\": 1.50,\n    \"deps\": {\n        \"a\": \"2\"\n    }\n}\n"
+        );
+        doc.delete_path("$.deps.a").unwrap();
+        assert!(doc.text().contains("\"deps\": {}"), "{}", doc.text());
+    }
+
+    #[test]
+    fn delete_inline_items() {
+        let mut doc = JsonDocument::parse("[1, 2, 3]").unwrap();
+        doc.delete_path("$[1]").unwrap();
+        assert_eq!(doc.text(), "[1, 3]");
+        doc.delete_path("$[1]").unwrap();
+        assert_eq!(doc.text(), "[1]");
+        doc.delete_path("$[0]").unwrap();
+        assert_eq!(doc.text(), "[]");
+        let mut doc = JsonDocument::parse("[1, 2, 3,]").unwrap();
+        doc.delete_path("$[2]").unwrap();
+        assert_eq!(doc.text(), "[1, 2,]");
+    }
+
+    #[test]
+    fn delete_takes_attached_comments_and_keeps_neighbours() {
+        let mut doc = JsonDocument::parse(TSCONFIG).unwrap();
+        doc.delete_path("$.compilerOptions.strict").unwrap();
+        doc.delete_path("$.include[1]").unwrap();
+        assert_eq!(
+            doc.text(),
+            "{\n  // Compiler settings\n  \"compilerOptions\": {\n    \"target\": \"es2020\", // keep in sync with node\n    \"paths\": {},\n  },\n  \"include\": [\n    \"src\",\n  ],\n}\n"
+        );
+        doc.delete_path("$.compilerOptions").unwrap();
+        assert!(doc.text().starts_with("{\n  \"include\""), "{}", doc.text());
+    }
+
+    #[test]
+    fn delete_last_member_drops_dangling_comma() {
+        let mut doc = JsonDocument::parse("{\n  \"a\": 1, // one\n  \"b\": 2\n}").unwrap();
+        doc.delete_path("$.b").unwrap();
+        assert_eq!(doc.text(), "{\n  \"a\": 1 // one\n}");
+    }
+
+    #[test]
+    fn append_follows_trailing_commas_and_comments() {
+        let mut doc = JsonDocument::parse(TSCONFIG).unwrap();
+        doc.insert_at_path("$.include", None, None, &json!("test"))
+            .unwrap();
+        assert!(
+            doc.text().contains("    \"gen\",\n    \"test\",\n  ],"),
+            "{}",
+            doc.text()
+        );
+        let mut doc = JsonDocument::parse("{\n  \"a\": 1 // one\n}").unwrap();
+        doc.set_path("$.b", &json!(2)).unwrap();
+        assert_eq!(doc.text(), "{\n  \"a\": 1, // one\n  \"b\": 2\n}");
+    }
+
+    #[test]
+    fn insert_item_goes_above_attached_comment() {
+        let mut doc = JsonDocument::parse(TSCONFIG).unwrap();
+        doc.insert_at_path("$.include", None, Some(1), &json!("lib"))
+            .unwrap();
+        assert!(
+            doc.text()
+                .contains("    \"src\",\n    \"lib\",\n    // generated\n    \"gen\","),
+            "{}",
+            doc.text()
+        );
+    }
+
+    #[test]
+    fn json5_new_keys_follow_unquoted_style() {
+        let mut doc = JsonDocument::parse("{\n  name: 'x',\n}\n").unwrap();
+        doc.set_path("$.version", &json!("1.0")).unwrap();
+        doc.set_path("$[\"a-b\"]", &json!(1)).unwrap();
+        assert_eq!(
+            doc.text(),
+            "{\n  name: 'x',\n  version: \"1.0\",\n  \"a-b\": 1,\n}\n"
+        );
+    }
+
+    #[test]
+    fn crlf_documents_get_crlf_insertions() {
+        let mut doc = JsonDocument::parse("{\r\n  \"a\": []\r\n}\r\n").unwrap();
+        doc.insert_at_path("$.a", None, None, &json!(1)).unwrap();
+        assert_eq!(doc.text(), "{\r\n  \"a\": [\r\n    1\r\n  ]\r\n}\r\n");
+    }
+}
@@ -1,3 +1,5 @@
+pub mod config;
+pub mod diff;
 pub mod edit;
 pub mod error;
 pub mod format;
@@ -5,13 +7,26 @@ pub mod hash;
 pub mod heuristics;
 pub mod json;
 pub mod parse;
+pub mod patch;
+pub mod toml;
+pub mod yaml;
 
+pub use config::{Config, HeuristicOverrides};
 pub use edit::{
-    apply_hashline_edits, apply_replace_edits, ApplyResult, HashlineEdit, HashlineParams,
-    ReplaceResult,
+    apply_hashline_edits, apply_hashline_edits_with_options, apply_replace_edits,
+    apply_replace_edits_with_options, parse_apply_input, ApplyOptions, ApplyResult, EditOutcome,
+    EditStatus, FileChangePolicy, HashlineEdit, HashlineParams, HashlineTransaction, ReplaceResult,
 };
-pub use error::{HashMismatch, HashlineMismatchError};
-pub use format::format_hashlines;
-pub use hash::compute_line_hash;
+pub use error::{ConflictingEdit, EditConflictError, HashMismatch, HashlineMismatchError};
+pub use format::{
+    format_hashline_range, format_hashlines, format_hashlines_with, format_read_header,
+};
+pub use hash::{
+    compute_file_fingerprint, compute_line_hash, compute_line_hash_with, compute_line_hashes,
+    HashContext, HashOptions, HashWhitespace,
+};
+pub use heuristics::{Heuristic, HeuristicEvent, HeuristicSet};
 pub use json::*;
-pub use parse::{parse_line_ref, LineRef};
+pub use parse::{parse_line_ref, parse_line_ref_with_width, LineRef};
+pub use toml::*;
+pub use yaml::*;
@@ -4,7 +4,10 @@ use std::path::Path;
 
 use std::process;
 
+mod apply;
 mod cli;
+mod config;
+mod diff;
 mod doctor;
 mod edit;
 mod error;
@@ -14,9 +17,12 @@ mod heuristics;
 mod hook;
 mod json;
 mod parse;
+mod patch;
 mod setup;
+mod toml;
 mod usage;
 mod util;
+mod yaml;
 
 use cli::{Cli, Commands, HookAction};
 use usage::{log_event, UsageEvent, UsageResult};
@@ -47,6 +53,247 @@ fn exit_with(
     process::exit(code);
 }
 
+/// Resolve hash options from CLI flags, `HASHLINE_*` env vars and
+/// `.hashline.json`. A broken config is reported in `format` and exits with
+/// code 2.
+fn resolve_hash_options(
+    cli: config::HashOverrides,
+    command: &'static str,
+    format: apply::OutputFormat,
+    emit_updated: bool,
+    used_input_file: bool,
+) -> hash::HashOptions {
+    match config::Config::load().and_then(|c| c.hash_options(&cli)) {
+        Ok(options) => options,
+        Err(e) => {
+            apply::fail(format, format!("Error: {}", e));
+            exit_with(
+                2,
+                command,
+                UsageResult::Error,
+                emit_updated,
+                used_input_file,
+            );
+        }
+    }
+}
+
+impl From<cli::HashArgs> for config::HashOverrides {
+    fn from(args: cli::HashArgs) -> Self {
+        config::HashOverrides {
+            width: args.hash_width,
+            context: args.hash_context.map(|c| match c {
+                cli::HashContextArg::None => hash::HashContext::None,
+                cli::HashContextArg::Neighbors => hash::HashContext::Neighbors,
+            }),
+            whitespace: args.hash_whitespace.map(|w| match w {
+                cli::HashWhitespaceArg::Auto => hash::HashWhitespace::Auto,
+                cli::HashWhitespaceArg::Ignore => hash::HashWhitespace::Ignore,
+                cli::HashWhitespaceArg::Indent => hash::HashWhitespace::Indent,
+            }),
+        }
+    }
+}
+
+/// Resolve enabled heuristics from CLI flags and `.hashline.json`.
+fn resolve_heuristics(
+    cli: config::HeuristicOverrides,
+    format: apply::OutputFormat,
+    emit_updated: bool,
+    used_input_file: bool,
+) -> heuristics::HeuristicSet {
+    match config::Config::load().and_then(|c| c.heuristics(&cli)) {
+        Ok(set) => set,
+        Err(e) => {
+            apply::fail(format, format!("Error: {}", e));
+            exit_with(
+                2,
+                "apply",
+                UsageResult::Error,
+                emit_updated,
+                used_input_file,
+            );
+        }
+    }
+}
+
+impl From<cli::HeuristicArg> for heuristics::Heuristic {
+    fn from(arg: cli::HeuristicArg) -> Self {
+        match arg {
+            cli::HeuristicArg::StripHashlinePrefix => heuristics::Heuristic::StripHashlinePrefix,
+            cli::HeuristicArg::StripDiffPlus => heuristics::Heuristic::StripDiffPlus,
+            cli::HeuristicArg::StripBoundaryEcho => heuristics::Heuristic::StripBoundaryEcho,
+            cli::HeuristicArg::StripAnchorEcho => heuristics::Heuristic::StripAnchorEcho,
+            cli::HeuristicArg::RestoreWrappedLines => heuristics::Heuristic::RestoreWrappedLines,
+            cli::HeuristicArg::RestoreIndent => heuristics::Heuristic::RestoreIndent,
+            cli::HeuristicArg::ExpandMerge => heuristics::Heuristic::ExpandMerge,
+            cli::HeuristicArg::NormalizeHyphens => heuristics::Heuristic::NormalizeHyphens,
+        }
+    }
+}
+
+impl From<cli::PathHashArgs> for config::HashOverrides {
+    fn from(args: cli::PathHashArgs) -> Self {
+        config::HashOverrides {
+            width: args.hash_width,
+            ..Default::default()
+        }
+    }
+}
+
+/// `json-apply` / `yaml-apply` / `toml-apply`: read the edit payload, validate every anchor
+/// against the parsed document, then write its edited text.
+fn run_document_apply<D: json::PathDocument>(
+    command: &'static str,
+    read_command: &str,
+    input: Option<String>,
+    emit_updated: bool,
+    hash: cli::PathHashArgs,
+    parse: fn(&Path) -> Result<D, json::JsonError>,
+    format_anchors: fn(&D, &hash::HashOptions) -> String,
+) {
+    let used_input_file = input.is_some();
+    let hash_options = resolve_hash_options(
+        hash.into(),
+        command,
+        apply::OutputFormat::Text,
+        emit_updated,
+        used_input_file,
+    );
+
+    let input_data = if let Some(ref path) = input {
+        match std::fs::read_to_string(path) {
+            Ok(c) => c,
+            Err(e) => {
+                eprintln!("Error reading input file {}: {}", path, e);
+                exit_with(
+                    2,
+                    command,
+                    UsageResult::Error,
+                    emit_updated,
+                    used_input_file,
+                );
+            }
+        }
+    } else {
+        let mut buf = String::new();
+        if let Err(e) = std::io::stdin().read_to_string(&mut buf) {
+            eprintln!("Error reading stdin: {}", e);
+            exit_with(
+                2,
+                command,
+                UsageResult::Error,
+                emit_updated,
+                used_input_file,
+            );
+        }
+        buf
+    };
+
+    let params: json::JsonApplyParams = match serde_json::from_str(&input_data) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("Invalid JSON input: {}", e);
+            exit_with(
+                2,
+                command,
+                UsageResult::Error,
+                emit_updated,
+                used_input_file,
+            );
+        }
+    };
+
+    let mut doc = match parse(Path::new(&params.path)) {
+        Ok(d) => d,
+        Err(e) => {
+            eprintln!(
+                "Error parsing {} {}: {}",
+                read_command.trim_end_matches("-read").to_uppercase(),
+                params.path,
+                e
+            );
+            exit_with(
+                2,
+                command,
+                UsageResult::Error,
+                emit_updated,
+                used_input_file,
+            );
+        }
+    };
+
+    if let Err(e) = json::apply_document_edits_with(&mut doc, &params.edits, &hash_options) {
+        match e {
+            json::JsonError::HashMismatch {
+                ref path,
+                ref expected,
+                ref actual,
+            } => {
+                eprintln!("Hash mismatch for {}.", path);
+                eprintln!("  expected hash: {}", expected);
+                eprintln!("  current hash:  {}", actual);
+                eprintln!("  updated anchor: {}:{}", path, actual);
+                // A plain read prints concrete paths only; a selector's own
+                // anchor comes from re-reading with the same selector.
+                let select =
+                    if read_command == "json-read" && json::parse_path_segments(path).is_err() {
+                        format!(" --select '{}'", path.replace('\'', "'\\''"))
+                    } else {
+                        String::new()
+                    };
+                eprintln!(
+                    "Re-run `hashline {}{} {}` to refresh anchors.",
+                    read_command, select, params.path
+                );
+                if expected.len() != hash_options.width {
+                    eprintln!(
+                        "{}",
+                        error::hash_width_hint(expected.len(), hash_options.width)
+                    );
+                }
+                exit_with(
+                    1,
+                    command,
+                    UsageResult::Mismatch,
+                    emit_updated,
+                    used_input_file,
+                );
+            }
+            json::JsonError::Other(msg) => {
+                eprintln!("Error: {}", msg);
+                exit_with(
+                    2,
+                    command,
+                    UsageResult::Error,
+                    emit_updated,
+                    used_input_file,
+                );
+            }
+        }
+    }
+
+    // Write back the edited text; everything outside the edits is unchanged
+    let output = doc.text();
+    if let Err(e) = util::write_atomic(Path::new(&params.path), output.as_bytes()) {
+        eprintln!("Error writing {}: {}", params.path, e);
+        exit_with(
+            2,
+            command,
+            UsageResult::Error,
+            emit_updated,
+            used_input_file,
+        );
+    }
+
+    if emit_updated {
+        // Re-format with updated anchors
+        println!("---");
+        println!("{}", format_anchors(&doc, &hash_options));
+    }
+    record_usage(command, UsageResult::Success, emit_updated, used_input_file);
+}
+
 fn main() {
     let cli = Cli::parse();
 
@@ -55,7 +302,12 @@ fn main() {
             file,
             start_line,
             lines,
+            fingerprint,
+            hash,
         } => {
+            let hash_options =
+                resolve_hash_options(hash.into(), "read", apply::OutputFormat::Text, false, false)
+                    .for_path(Path::new(&file));
             let content = match read_normalized(Path::new(&file)) {
                 Ok(c) => c,
                 Err(e) => {
@@ -63,6 +315,10 @@ fn main() {
                     exit_with(2, "read", UsageResult::Error, false, false);
                 }
             };
+            let file_hash = fingerprint.then(|| hash::compute_file_fingerprint(&content));
+            if let Some(header) = format::format_read_header(&hash_options, file_hash.as_deref()) {
+                println!("{}", header);
+            }
             let all_lines: Vec<&str> = content.split('\n').collect();
             let start_idx = start_line.saturating_sub(1).min(all_lines.len());
             let end_idx = if let Some(n) = lines {
@@ -70,189 +326,73 @@ fn main() {
             } else {
                 all_lines.len()
             };
-            let slice = &all_lines[start_idx..end_idx];
-            if !slice.is_empty() {
-                let sliced_content = slice.join("\n");
-                println!("{}", format::format_hashlines(&sliced_content, start_line));
+            if start_idx < end_idx {
+                println!(
+                    "{}",
+                    format::format_hashline_range(&all_lines, start_idx, end_idx, &hash_options)
+                );
             }
             record_usage("read", UsageResult::Success, false, false);
         }
         Commands::Apply {
             input,
+            patch,
             emit_updated,
+            dry_run,
+            emit_patch,
+            strict,
+            disable_heuristic,
+            enable_heuristic,
+            format,
+            hash,
         } => {
-            let used_input_file = input.is_some();
-
-            let input_data = if let Some(ref path) = input {
-                match std::fs::read_to_string(path) {
-                    Ok(c) => c,
-                    Err(e) => {
-                        eprintln!("Error reading input file {}: {}", path, e);
-                        exit_with(
-                            2,
-                            "apply",
-                            UsageResult::Error,
-                            emit_updated,
-                            used_input_file,
-                        );
-                    }
-                }
-            } else {
-                let mut buf = String::new();
-                if let Err(e) = std::io::stdin().read_to_string(&mut buf) {
-                    eprintln!("Error reading stdin: {}", e);
-                    exit_with(
-                        2,
-                        "apply",
-                        UsageResult::Error,
-                        emit_updated,
-                        used_input_file,
-                    );
-                }
-                buf
+            // `--patch -` reads the diff from stdin like a missing --input.
+            let patch_file = patch.as_deref().filter(|p| *p != "-");
+            let used_input_file = input.is_some() || patch_file.is_some();
+            let output_format = match format {
+                cli::OutputFormat::Text => apply::OutputFormat::Text,
+                cli::OutputFormat::Json => apply::OutputFormat::Json,
             };
-
-            let params: edit::HashlineParams = match serde_json::from_str(&input_data) {
-                Ok(p) => p,
-                Err(e) => {
-                    eprintln!("Invalid JSON input: {}", e);
-                    exit_with(
-                        2,
-                        "apply",
-                        UsageResult::Error,
-                        emit_updated,
-                        used_input_file,
-                    );
-                }
-            };
-
-            let content = match read_normalized(Path::new(&params.path)) {
-                Ok(c) => c,
-                Err(e) => {
-                    eprintln!("Error reading {}: {}", params.path, e);
-                    exit_with(
-                        2,
-                        "apply",
-                        UsageResult::Error,
-                        emit_updated,
-                        used_input_file,
-                    );
-                }
+            let hash_options = resolve_hash_options(
+                hash.into(),
+                "apply",
+                output_format,
+                emit_updated,
+                used_input_file,
+            );
+            let heuristic_overrides = config::HeuristicOverrides {
+                enable: enable_heuristic.into_iter().map(Into::into).collect(),
+                disable: disable_heuristic.into_iter().map(Into::into).collect(),
             };
-
-            // Anchor edits run first, then replace edits on the result
-            let anchor_edits: Vec<_> = params
-                .edits
-                .iter()
-                .filter(|e| !matches!(e, edit::HashlineEdit::Replace { .. }))
-                .cloned()
-                .collect();
-            let replace_edits: Vec<_> = params
-                .edits
-                .iter()
-                .filter(|e| matches!(e, edit::HashlineEdit::Replace { .. }))
-                .cloned()
-                .collect();
-
-            let anchor_result = match edit::apply_hashline_edits(&content, &anchor_edits) {
-                Ok(r) => r,
-                Err(e) => {
-                    if e.downcast_ref::<error::HashlineMismatchError>().is_some() {
-                        eprintln!("{}", e);
-                        exit_with(
-                            1,
-                            "apply",
-                            UsageResult::Mismatch,
-                            emit_updated,
-                            used_input_file,
-                        );
-                    } else {
-                        eprintln!("Error: {}", e);
-                        exit_with(
-                            2,
-                            "apply",
-                            UsageResult::Error,
-                            emit_updated,
-                            used_input_file,
-                        );
-                    }
-                }
+            let heuristics = resolve_heuristics(
+                heuristic_overrides,
+                output_format,
+                emit_updated,
+                used_input_file,
+            );
+            let options = apply::RunOptions {
+                emit_updated,
+                hash: hash_options,
+                dry_run: dry_run.map(|d| match d {
+                    cli::DryRunFormat::Diff => apply::DryRun::Diff,
+                    cli::DryRunFormat::Json => apply::DryRun::Json,
+                }),
+                format: output_format,
+                strict,
+                heuristics,
+                patch: patch.is_some(),
+                emit_patch,
             };
-
-            let mut final_content = anchor_result.content;
-            let mut replace_first_changed = None;
-            let mut replace_replacements = 0usize;
-            if !replace_edits.is_empty() {
-                match edit::apply_replace_edits(&final_content, &replace_edits) {
-                    Ok(r) => {
-                        replace_first_changed = r.first_changed_line;
-                        replace_replacements = r.replacements;
-                        final_content = r.content;
-                    }
-                    Err(e) => {
-                        eprintln!("Error: {}", e);
-                        exit_with(
-                            2,
-                            "apply",
-                            UsageResult::Error,
-                            emit_updated,
-                            used_input_file,
-                        );
-                    }
-                }
-            }
-
-            let mut output = final_content;
-            output.push('\n');
-            if let Err(e) = std::fs::write(&params.path, &output) {
-                eprintln!("Error writing {}: {}", params.path, e);
-                exit_with(
-                    2,
-                    "apply",
-                    UsageResult::Error,
-                    emit_updated,
-                    used_input_file,
-                );
-            }
-            if !anchor_result.warnings.is_empty() {
-                for w in &anchor_result.warnings {
-                    eprintln!("Warning: {}", w);
-                }
+            let (code, result) = apply::run(patch_file.or(input.as_deref()), options);
+            if code != 0 {
+                exit_with(code, "apply", result, emit_updated, used_input_file);
             }
-
-            let had_anchor_changes = anchor_result.first_changed_line.is_some();
-            let had_replace_changes = replace_replacements > 0;
-            if emit_updated {
-                let first_line = match (anchor_result.first_changed_line, replace_first_changed) {
-                    (Some(a), Some(b)) => Some(a.min(b)),
-                    (Some(a), None) => Some(a),
-                    (None, Some(b)) => Some(b),
-                    (None, None) => None,
-                };
-                if let Some(first_line) = first_line {
-                    let updated = read_normalized(Path::new(&params.path)).unwrap_or_default();
-                    let all_lines: Vec<&str> = updated.split('\n').collect();
-                    let context = 2;
-                    let start = first_line.saturating_sub(1 + context);
-                    let edits_count = params.edits.len();
-                    let end = all_lines
-                        .len()
-                        .min(start + (edits_count * 3).max(10) + context * 2);
-                    let slice = &all_lines[start..end];
-                    if !slice.is_empty() {
-                        let sliced_content = slice.join("\n");
-                        println!("---");
-                        println!("{}", format::format_hashlines(&sliced_content, start + 1));
-                    }
-                }
-            }
-
-            if !had_anchor_changes && !had_replace_changes {
-                println!("No changes applied.");
-            }
-            record_usage("apply", UsageResult::Success, emit_updated, used_input_file);
+            record_usage("apply", result, emit_updated, used_input_file);
         }
-        Commands::Hash { file } => {
+        Commands::Hash { file, hash } => {
+            let hash_options =
+                resolve_hash_options(hash.into(), "hash", apply::OutputFormat::Text, false, false)
+                    .for_path(Path::new(&file));
             let content = match read_normalized(Path::new(&file)) {
                 Ok(c) => c,
                 Err(e) => {
@@ -260,14 +400,24 @@ fn main() {
                     exit_with(2, "hash", UsageResult::Error, false, false);
                 }
             };
-            for (i, line) in content.split('\n').enumerate() {
-                let num = i + 1;
-                println!("{}:{}", num, hash::compute_line_hash(num, line));
+            let lines: Vec<&str> = content.split('\n').collect();
+            for (i, h) in hash::compute_line_hashes(&lines, &hash_options)
+                .iter()
+                .enumerate()
+            {
+                println!("{}:{}", i + 1, h);
             }
 
             record_usage("hash", UsageResult::Success, false, false);
         }
-        Commands::JsonRead { file } => {
+        Commands::JsonRead { file, select, hash } => {
+            let hash_options = resolve_hash_options(
+                hash.into(),
+                "json-read",
+                apply::OutputFormat::Text,
+                false,
+                false,
+            );
             use std::path::Path;
             let ast = match json::parse_json_ast(Path::new(&file)) {
                 Ok(a) => a,
@@ -276,146 +426,94 @@ fn main() {
                     exit_with(2, "json-read", UsageResult::Error, false, false);
                 }
             };
-            println!("{}", json::format_json_anchors(&ast));
+            let output = match select {
+                Some(path) => match json::format_json_selection_with(&ast, &path, &hash_options) {
+                    Ok(output) => output,
+                    Err(e) => {
+                        eprintln!("Error: {}", e);
+                        exit_with(2, "json-read", UsageResult::Error, false, false);
+                    }
+                },
+                None => json::format_json_anchors_with(&ast, &hash_options),
+            };
+            println!("{}", output);
             record_usage("json-read", UsageResult::Success, false, false);
         }
         Commands::JsonApply {
             input,
             emit_updated,
-        } => {
-            let used_input_file = input.is_some();
-
-            let input_data = if let Some(ref path) = input {
-                match std::fs::read_to_string(path) {
-                    Ok(c) => c,
-                    Err(e) => {
-                        eprintln!("Error reading input file {}: {}", path, e);
-                        exit_with(
-                            2,
-                            "json-apply",
-                            UsageResult::Error,
-                            emit_updated,
-                            used_input_file,
-                        );
-                    }
-                }
-            } else {
-                let mut buf = String::new();
-                if let Err(e) = std::io::stdin().read_to_string(&mut buf) {
-                    eprintln!("Error reading stdin: {}", e);
-                    exit_with(
-                        2,
-                        "json-apply",
-                        UsageResult::Error,
-                        emit_updated,
-                        used_input_file,
-                    );
-                }
-                buf
-            };
-
-            let params: json::JsonApplyParams = match serde_json::from_str(&input_data) {
-                Ok(p) => p,
-                Err(e) => {
-                    eprintln!("Invalid JSON input: {}", e);
-                    exit_with(
-                        2,
-                        "json-apply",
-                        UsageResult::Error,
-                        emit_updated,
-                        used_input_file,
-                    );
-                }
-            };
-
-            use std::path::Path;
-            let mut ast = match json::parse_json_ast(Path::new(&params.path)) {
-                Ok(a) => a,
+            hash,
+        } => run_document_apply(
+            "json-apply",
+            "json-read",
+            input,
+            emit_updated,
+            hash,
+            json::parse_json_document,
+            |doc, options| json::format_json_anchors_with(&doc.value(), options),
+        ),
+        Commands::YamlRead { file, hash } => {
+            let hash_options = resolve_hash_options(
+                hash.into(),
+                "yaml-read",
+                apply::OutputFormat::Text,
+                false,
+                false,
+            );
+            let doc = match yaml::parse_yaml_document(Path::new(&file)) {
+                Ok(d) => d,
                 Err(e) => {
-                    eprintln!("Error parsing JSON {}: {}", params.path, e);
-                    exit_with(
-                        2,
-                        "json-apply",
-                        UsageResult::Error,
-                        emit_updated,
-                        used_input_file,
-                    );
+                    eprintln!("Error parsing YAML {}: {}", file, e);
+                    exit_with(2, "yaml-read", UsageResult::Error, false, false);
                 }
             };
-
-            if let Err(e) = json::apply_json_edits(&mut ast, &params.edits) {
-                match e {
-                    json::JsonError::HashMismatch {
-                        ref path,
-                        ref expected,
-                        ref actual,
-                    } => {
-                        eprintln!("Hash mismatch for {}.", path);
-                        eprintln!("  expected hash: {}", expected);
-                        eprintln!("  current hash:  {}", actual);
-                        eprintln!("  updated anchor: {}:{}", path, actual);
-                        eprintln!(
-                            "Re-run `hashline json-read {}` to refresh anchors.",
-                            params.path
-                        );
-                        exit_with(
-                            1,
-                            "json-apply",
-                            UsageResult::Mismatch,
-                            emit_updated,
-                            used_input_file,
-                        );
-                    }
-                    json::JsonError::Other(msg) => {
-                        eprintln!("Error: {}", msg);
-                        exit_with(
-                            2,
-                            "json-apply",
-                            UsageResult::Error,
-                            emit_updated,
-                            used_input_file,
-                        );
-                    }
-                }
-            }
-
-            // Write back the modified JSON
-            let output = match serde_json::to_string_pretty(&ast) {
-                Ok(s) => s,
+            println!("{}", yaml::format_yaml_anchors_with(&doc, &hash_options));
+            record_usage("yaml-read", UsageResult::Success, false, false);
+        }
+        Commands::YamlApply {
+            input,
+            emit_updated,
+            hash,
+        } => run_document_apply(
+            "yaml-apply",
+            "yaml-read",
+            input,
+            emit_updated,
+            hash,
+            yaml::parse_yaml_document,
+            yaml::format_yaml_anchors_with,
+        ),
+        Commands::TomlRead { file, hash } => {
+            let hash_options = resolve_hash_options(
+                hash.into(),
+                "toml-read",
+                apply::OutputFormat::Text,
+                false,
+                false,
+            );
+            let doc = match toml::parse_toml_document(Path::new(&file)) {
+                Ok(d) => d,
                 Err(e) => {
-                    eprintln!("Error serializing JSON: {}", e);
-                    exit_with(
-                        2,
-                        "json-apply",
-                        UsageResult::Error,
-                        emit_updated,
-                        used_input_file,
-                    );
+                    eprintln!("Error parsing TOML {}: {}", file, e);
+                    exit_with(2, "toml-read", UsageResult::Error, false, false);
                 }
             };
-            if let Err(e) = std::fs::write(&params.path, output + "\n") {
-                eprintln!("Error writing {}: {}", params.path, e);
-                exit_with(
-                    2,
-                    "json-apply",
-                    UsageResult::Error,
-                    emit_updated,
-                    used_input_file,
-                );
-            }
-
-            if emit_updated {
-                // Re-format with updated anchors
-                println!("---");
-                println!("{}", json::format_json_anchors(&ast));
-            }
-            record_usage(
-                "json-apply",
-                UsageResult::Success,
-                emit_updated,
-                used_input_file,
-            );
+            println!("{}", toml::format_toml_anchors_with(&doc, &hash_options));
+            record_usage("toml-read", UsageResult::Success, false, false);
         }
+        Commands::TomlApply {
+            input,
+            emit_updated,
+            hash,
+        } => run_document_apply(
+            "toml-apply",
+            "toml-read",
+            input,
+            emit_updated,
+            hash,
+            toml::parse_toml_document,
+            toml::format_toml_anchors_with,
+        ),
         Commands::Setup {
             agent,
             settings_file,
@@ -1,6 +1,8 @@
 use regex::Regex;
 use std::sync::LazyLock;
 
+use crate::hash::{DEFAULT_HASH_WIDTH, SUPPORTED_HASH_WIDTHS};
+
 /// A parsed line reference: 1-indexed line number + hash string.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct LineRef {
@@ -10,14 +12,31 @@ pub struct LineRef {
 
 static STRICT_RE: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"^(\d+):([0-9a-zA-Z]{1,16})$").unwrap());
-static PREFIX_RE: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r"^(\d+):([0-9a-zA-Z]{2})").unwrap());
+/// One prefix regex per supported hash width, e.g. `^(\d+):([0-9a-zA-Z]{4})`.
+static PREFIX_RES: LazyLock<Vec<(usize, Regex)>> = LazyLock::new(|| {
+    SUPPORTED_HASH_WIDTHS
+        .iter()
+        .map(|&w| {
+            let re = Regex::new(&format!(r"^(\d+):([0-9a-zA-Z]{{{}}})", w)).unwrap();
+            (w, re)
+        })
+        .collect()
+});
 
 /// Parse a line reference string like `"5:ab"` into structured form.
 ///
 /// Handles display-format suffixes (`5:ab|content`), legacy format (`5:ab  content`),
 /// and `>>>` prefixes from error output.
+#[allow(dead_code)]
 pub fn parse_line_ref(ref_str: &str) -> Result<LineRef, String> {
+    parse_line_ref_with_width(ref_str, DEFAULT_HASH_WIDTH)
+}
+
+/// Parse a line reference whose hash is `width` hex chars.
+///
+/// The width only matters when the hash is followed by junk (`2:abexport ...`):
+/// the prefix match then takes exactly `width` chars.
+pub fn parse_line_ref_with_width(ref_str: &str, width: usize) -> Result<LineRef, String> {
     // Strip display-format suffix, legacy suffix, leading >>> markers
     let cleaned = ref_str.split('|').next().unwrap_or(ref_str);
     // Strip legacy "  content" suffix
@@ -46,8 +65,13 @@ pub fn parse_line_ref(ref_str: &str) -> Result<LineRef, String> {
         });
     }
 
-    // Then try prefix match (HASH_LEN=2 chars)
-    if let Some(caps) = PREFIX_RE.captures(&normalized) {
+    // Then try prefix match (exactly `width` chars)
+    let prefix_re = PREFIX_RES
+        .iter()
+        .find(|(w, _)| *w == width)
+        .map(|(_, re)| re)
+        .ok_or_else(|| format!("Unsupported hash width {}", width))?;
+    if let Some(caps) = prefix_re.captures(&normalized) {
         let line: usize = caps[1].parse().unwrap();
         if line < 1 {
             return Err(format!(
@@ -177,4 +201,16 @@ mod tests {
         assert_eq!(result.line, 2);
         assert_eq!(result.hash, "ab");
     }
+
+    #[test]
+    fn prefix_match_uses_hash_width() {
+        let result = parse_line_ref_with_width("2:abexport function foo() {}", 4).unwrap();
+        assert_eq!(result.line, 2);
+        assert_eq!(result.hash, "abex");
+    }
+
+    #[test]
+    fn rejects_unsupported_width() {
+        assert!(parse_line_ref_with_width("2:ab;", 5).is_err());
+    }
 }
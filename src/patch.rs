@@ -0,0 +1,488 @@
+//! Unified-diff ingestion: parse `diff -u` / `git diff` output and find where
+//! each hunk applies in the current file.
+//!
+//! Hunk lines are compared the way anchor hashes compare lines (see
+//! [`normalize_line`]), so whitespace drift in the patch does not stop it
+//! from applying.
+
+use crate::diff::{Hunk, HunkLine, LineKind};
+use crate::error::HashMismatch;
+use crate::hash::{compute_line_hashes, normalize_line, HashOptions, HashWhitespace};
+
+/// The `---` / `+++` path naming a missing file.
+const DEV_NULL: &str = "/dev/null";
+
+/// The hunks for one file in a unified diff.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilePatch {
+    /// Path from the `---` line; `None` for bare hunks without headers.
+    pub old_path: Option<String>,
+    /// Path from the `+++` line; `None` for bare hunks without headers.
+    pub new_path: Option<String>,
+    pub hunks: Vec<Hunk>,
+    /// This file's part of the diff, headers included.
+    pub text: String,
+}
+
+impl FilePatch {
+    /// The file the patch edits, with git's `a/` / `b/` prefixes removed.
+    pub fn path(&self) -> Result<String, String> {
+        let (old, new) = match (&self.old_path, &self.new_path) {
+            (Some(old), Some(new)) => (old.as_str(), new.as_str()),
+            _ => return Err("patch has no ---/+++ file headers".into()),
+        };
+        if new == DEV_NULL {
+            return Err(format!(
+                "patch deletes {}; deleting files is not supported",
+                old
+            ));
+        }
+        if old == DEV_NULL {
+            return Err(format!(
+                "patch creates {}; creating files is not supported",
+                new
+            ));
+        }
+        match (old.strip_prefix("a/"), new.strip_prefix("b/")) {
+            (Some(_), Some(path)) => Ok(path.to_string()),
+            _ => Ok(new.to_string()),
+        }
+    }
+}
+
+/// One run of removed and/or added lines in a hunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HunkChange {
+    /// Position of the first removed line (or of the line the added lines go
+    /// before) among the hunk's old-side lines, 0-based.
+    pub offset: usize,
+    pub removed: usize,
+    pub added: Vec<String>,
+}
+
+/// Parse a unified diff into per-file hunks.
+///
+/// Lines outside hunks (`diff --git`, `index`, mode lines) are skipped. Hunks
+/// before any `---`/`+++` pair form a file patch without paths.
+pub fn parse_patch(text: &str) -> Result<Vec<FilePatch>, String> {
+    let lines: Vec<&str> = text.split_inclusive('\n').collect();
+    let mut files: Vec<FilePatch> = Vec::new();
+    // Byte offset where the current file's text starts.
+    let mut file_start = 0;
+    let mut pos = 0;
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        let bare = trim_eol(line);
+        if let (Some(old), Some(new)) = (
+            bare.strip_prefix("--- "),
+            lines
+                .get(i + 1)
+                .and_then(|l| trim_eol(l).strip_prefix("+++ ")),
+        ) {
+            // A `diff` line may already have ended the previous file.
+            if files.last().is_some_and(|f| f.text.is_empty()) {
+                finish_file(&mut files, text, file_start, pos);
+                file_start = pos;
+            }
+            files.push(FilePatch {
+                old_path: Some(header_path(old)),
+                new_path: Some(header_path(new)),
+                hunks: Vec::new(),
+                text: String::new(),
+            });
+            pos += line.len() + lines[i + 1].len();
+            i += 2;
+            continue;
+        }
+        if bare.starts_with("@@") {
+            let (hunk, used) = parse_hunk(&lines[i..])?;
+            if files.is_empty() {
+                files.push(FilePatch {
+                    old_path: None,
+                    new_path: None,
+                    hunks: Vec::new(),
+                    text: String::new(),
+                });
+            }
+            files.last_mut().expect("pushed above").hunks.push(hunk);
+            pos += lines[i..i + used].iter().map(|l| l.len()).sum::<usize>();
+            i += used;
+            continue;
+        }
+        if bare.starts_with("diff ") && files.last().is_some_and(|f| !f.hunks.is_empty()) {
+            finish_file(&mut files, text, file_start, pos);
+            file_start = pos;
+        }
+        pos += line.len();
+        i += 1;
+    }
+    finish_file(&mut files, text, file_start, pos);
+    if let Some(empty) = files.iter().find(|f| f.hunks.is_empty()) {
+        return Err(format!(
+            "patch has no hunks for {}",
+            empty.new_path.as_deref().unwrap_or("the file")
+        ));
+    }
+    if files.is_empty() {
+        return Err("patch contains no hunks".into());
+    }
+    Ok(files)
+}
+
+/// Store the text of the last file patch, if it does not have it yet.
+fn finish_file(files: &mut [FilePatch], text: &str, start: usize, end: usize) {
+    if let Some(last) = files.last_mut() {
+        if last.text.is_empty() {
+            last.text = text[start..end].to_string();
+        }
+    }
+}
+
+fn trim_eol(line: &str) -> &str {
+    let line = line.strip_suffix('\n').unwrap_or(line);
+    line.strip_suffix('\r').unwrap_or(line)
+}
+
+/// The path on a `---`/`+++` line, without a trailing tab and timestamp.
+fn header_path(rest: &str) -> String {
+    rest.split('\t')
+        .next()
+        .unwrap_or(rest)
+        .trim_end()
+        .to_string()
+}
+
+/// `a` or `a,b` from a hunk header, as `(start, count)`.
+fn parse_range(s: &str) -> Option<(usize, usize)> {
+    match s.split_once(',') {
+        Some((start, count)) => Some((start.parse().ok()?, count.parse().ok()?)),
+        None => Some((s.parse().ok()?, 1)),
+    }
+}
+
+/// Parse one hunk starting at its `@@` line. Returns the hunk and the number
+/// of input lines it used.
+fn parse_hunk(lines: &[&str]) -> Result<(Hunk, usize), String> {
+    let header = trim_eol(lines[0]);
+    let invalid = || format!("invalid hunk header: {}", header);
+    let ranges = header
+        .strip_prefix("@@ ")
+        .and_then(|rest| rest.split(" @@").next())
+        .ok_or_else(invalid)?;
+    let (old, new) = ranges.split_once(' ').ok_or_else(invalid)?;
+    let (old_start, old_lines) = old
+        .strip_prefix('-')
+        .and_then(parse_range)
+        .ok_or_else(invalid)?;
+    let (new_start, new_lines) = new
+        .strip_prefix('+')
+        .and_then(parse_range)
+        .ok_or_else(invalid)?;
+
+    let mut hunk = Hunk {
+        old_start,
+        old_lines,
+        new_start,
+        new_lines,
+        lines: Vec::new(),
+    };
+    let (mut old_left, mut new_left) = (old_lines, new_lines);
+    let mut used = 1;
+    while old_left > 0 || new_left > 0 {
+        let Some(line) = lines.get(used) else {
+            return Err(format!(
+                "hunk {} ends early: {} old and {} new lines missing",
+                header, old_left, new_left
+            ));
+        };
+        used += 1;
+        // Some tools drop the space in front of blank context lines.
+        let (kind, text) = match line.chars().next() {
+            Some(' ') => (LineKind::Context, &line[1..]),
+            Some('\n') | Some('\r') => (LineKind::Context, *line),
+            Some('-') => (LineKind::Removed, &line[1..]),
+            Some('+') => (LineKind::Added, &line[1..]),
+            Some('\\') => continue,
+            _ => {
+                return Err(format!(
+                    "hunk {} ends early: {} old and {} new lines missing",
+                    header, old_left, new_left
+                ))
+            }
+        };
+        let left = match kind {
+            LineKind::Context if old_left > 0 && new_left > 0 => {
+                new_left -= 1;
+                &mut old_left
+            }
+            LineKind::Removed if old_left > 0 => &mut old_left,
+            LineKind::Added if new_left > 0 => &mut new_left,
+            _ => {
+                return Err(format!(
+                    "hunk {} has more lines than its header says",
+                    header
+                ))
+            }
+        };
+        *left -= 1;
+        hunk.lines.push(HunkLine {
+            kind,
+            text: text.to_string(),
+        });
+    }
+    // A trailing "\ No newline at end of file" belongs to this hunk.
+    if lines.get(used).is_some_and(|l| l.starts_with('\\')) {
+        used += 1;
+    }
+    Ok((hunk, used))
+}
+
+/// First old-side line the header names (1-based). For a hunk without old
+/// lines, the line its added lines go in front of.
+pub fn stated_start(hunk: &Hunk) -> usize {
+    if hunk.old_lines == 0 {
+        hunk.old_start + 1
+    } else {
+        hunk.old_start
+    }
+}
+
+/// The hunk's context and removed lines, without line terminators.
+pub fn old_side(hunk: &Hunk) -> Vec<&str> {
+    hunk.lines
+        .iter()
+        .filter(|l| l.kind != LineKind::Added)
+        .map(|l| trim_eol(&l.text))
+        .collect()
+}
+
+/// Whether the hunk's old side matches `file_lines` starting at `start`.
+fn matches_at(
+    file_lines: &[String],
+    old: &[&str],
+    start: usize,
+    whitespace: HashWhitespace,
+) -> bool {
+    if start == 0 || start - 1 + old.len() > file_lines.len() {
+        return false;
+    }
+    old.iter()
+        .zip(&file_lines[start - 1..])
+        .all(|(a, b)| normalize_line(a, whitespace) == normalize_line(b, whitespace))
+}
+
+/// Where the hunk's old side is in `file_lines` (1-based): at `expected` if it
+/// matches there, otherwise, with `relocate`, the only place it matches.
+pub fn locate_hunk(
+    file_lines: &[String],
+    hunk: &Hunk,
+    expected: usize,
+    whitespace: HashWhitespace,
+    relocate: bool,
+) -> Option<usize> {
+    let old = old_side(hunk);
+    if old.is_empty() {
+        // Nothing to match; the text goes in front of `expected`.
+        return (expected >= 1 && expected <= file_lines.len() + 1).then_some(expected);
+    }
+    if matches_at(file_lines, &old, expected, whitespace) {
+        return Some(expected);
+    }
+    if !relocate {
+        return None;
+    }
+    let mut found = (1..=file_lines.len()).filter(|&s| matches_at(file_lines, &old, s, whitespace));
+    match (found.next(), found.next()) {
+        (Some(start), None) => Some(start),
+        _ => None,
+    }
+}
+
+/// Lines at `expected` that differ from the hunk's old side, as mismatches
+/// against the current `line_hashes`. Errors if the hunk runs past the end
+/// of the file.
+pub fn hunk_mismatches(
+    file_lines: &[String],
+    line_hashes: &[String],
+    hunk: &Hunk,
+    expected: usize,
+    options: &HashOptions,
+) -> Result<Vec<HashMismatch>, String> {
+    let old = old_side(hunk);
+    let end = expected + old.len().max(1) - 1;
+    if expected == 0 || end > file_lines.len() {
+        return Err(format!(
+            "hunk {} expects lines {}-{} but the file has {} lines",
+            hunk.header(),
+            expected,
+            end,
+            file_lines.len()
+        ));
+    }
+    let expected_hashes = compute_line_hashes(&old, options);
+    Ok(old
+        .iter()
+        .enumerate()
+        .filter(|(i, text)| {
+            normalize_line(text, options.whitespace)
+                != normalize_line(&file_lines[expected - 1 + i], options.whitespace)
+        })
+        .map(|(i, _)| HashMismatch {
+            line: expected + i,
+            expected: expected_hashes[i].clone(),
+            actual: line_hashes[expected - 1 + i].clone(),
+        })
+        .collect())
+}
+
+/// The runs of changed lines in a hunk, positioned on its old side.
+pub fn hunk_changes(hunk: &Hunk) -> Vec<HunkChange> {
+    let first_old = stated_start(hunk);
+    let first_new = if hunk.new_lines == 0 {
+        hunk.new_start + 1
+    } else {
+        hunk.new_start
+    };
+    let new_side: Vec<&str> = hunk
+        .lines
+        .iter()
+        .filter(|l| l.kind != LineKind::Removed)
+        .map(|l| trim_eol(&l.text))
+        .collect();
+    hunk.change_blocks()
+        .into_iter()
+        .map(|b| HunkChange {
+            offset: b.old_start - first_old,
+            removed: b.old_end + 1 - b.old_start,
+            added: new_side[b.new_start - first_new..b.new_end + 1 - first_new]
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(s: &[&str]) -> Vec<String> {
+        s.iter().map(|l| l.to_string()).collect()
+    }
+
+    const GIT_DIFF: &str = concat!(
+        "diff --git a/src/a.rs b/src/a.rs\n",
+        "index 1111111..2222222 100644\n",
+        "--- a/src/a.rs\n",
+        "+++ b/src/a.rs\n",
+        "@@ -1,3 +1,3 @@\n",
+        " one\n",
+        "-two\n",
+        "+TWO\n",
+        " three\n",
+        "diff --git a/b.txt b/b.txt\n",
+        "--- a/b.txt\n",
+        "+++ b/b.txt\n",
+        "@@ -2,0 +3,2 @@ fn section()\n",
+        "+x\n",
+        "+y\n",
+    );
+
+    #[test]
+    fn parses_git_diff_per_file() {
+        let files = parse_patch(GIT_DIFF).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].path().unwrap(), "src/a.rs");
+        assert_eq!(files[1].path().unwrap(), "b.txt");
+        assert_eq!(files[0].hunks[0].header(), "@@ -1,3 +1,3 @@");
+        assert!(files[0].text.starts_with("diff --git a/src/a.rs"));
+        assert!(files[0].text.ends_with(" three\n"));
+        assert!(files[1].text.starts_with("diff --git a/b.txt"));
+        assert_eq!(
+            parse_patch(&files[1].text).unwrap()[0].hunks,
+            files[1].hunks
+        );
+    }
+
+    #[test]
+    fn parses_bare_hunks_and_no_newline_marker() {
+        let files = parse_patch("@@ -1 +1 @@\n-a\n\\ No newline at end of file\n+b\n").unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].old_path, None);
+        assert_eq!(files[0].hunks[0].lines.len(), 2);
+        assert!(files[0].path().is_err());
+    }
+
+    #[test]
+    fn rejects_short_hunk_and_bad_header() {
+        assert!(parse_patch("@@ -1,3 +1,3 @@\n a\n")
+            .unwrap_err()
+            .contains("ends early"));
+        assert!(parse_patch("@@ -x +1 @@\n")
+            .unwrap_err()
+            .contains("invalid hunk header"));
+        assert!(parse_patch("just text\n").is_err());
+    }
+
+    #[test]
+    fn path_rejects_file_creation() {
+        let files = parse_patch("--- /dev/null\n+++ b/new.rs\n@@ -0,0 +1 @@\n+x\n").unwrap();
+        assert!(files[0].path().unwrap_err().contains("creating files"));
+    }
+
+    #[test]
+    fn locate_ignores_whitespace_and_relocates_unique_match() {
+        let hunk = &parse_patch("@@ -1,2 +1,2 @@\n   b\n-c\n+C\n").unwrap()[0].hunks[0];
+        let file = lines(&["a", "b", "c", "d"]);
+        assert_eq!(
+            locate_hunk(&file, hunk, 1, HashWhitespace::Ignore, true),
+            Some(2)
+        );
+        assert_eq!(
+            locate_hunk(&file, hunk, 1, HashWhitespace::Ignore, false),
+            None
+        );
+        assert_eq!(
+            locate_hunk(&file, hunk, 2, HashWhitespace::Indent, false),
+            None
+        );
+        let repeated = lines(&["b", "c", "b", "c"]);
+        assert_eq!(
+            locate_hunk(&repeated, hunk, 4, HashWhitespace::Ignore, true),
+            None
+        );
+    }
+
+    #[test]
+    fn changes_are_relative_to_old_side() {
+        let hunk = &parse_patch("@@ -5,4 +5,5 @@\n a\n-b\n+B\n c\n+d\n e\n").unwrap()[0].hunks[0];
+        assert_eq!(
+            hunk_changes(hunk),
+            vec![
+                HunkChange {
+                    offset: 1,
+                    removed: 1,
+                    added: vec!["B".to_string()],
+                },
+                HunkChange {
+                    offset: 3,
+                    removed: 0,
+                    added: vec!["d".to_string()],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn mismatches_report_differing_lines() {
+        let hunk = &parse_patch("@@ -1,2 +1,2 @@\n a\n-b\n+B\n").unwrap()[0].hunks[0];
+        let file = lines(&["a", "x"]);
+        let hashes = compute_line_hashes(&file, &HashOptions::default());
+        let found = hunk_mismatches(&file, &hashes, hunk, 1, &HashOptions::default()).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].line, 2);
+        assert_eq!(found[0].actual, hashes[1]);
+        assert!(hunk_mismatches(&file, &hashes, hunk, 2, &HashOptions::default()).is_err());
+    }
+}
@@ -0,0 +1,1001 @@
+//! TOML documents with path anchors.
+//!
+//! `toml-read` and `toml-apply` use the JSON subsystem's `PATH:HASH` anchors
+//! and its `set_path` / `insert_at_path` / `delete_path` edits, so
+//! `$.dependencies.serde` addresses the `serde` key of `[dependencies]`
+//! whether it is written as a key, an inline table or a `[dependencies.serde]`
+//! table. Editing goes through `toml_edit`, which keeps comments, key order,
+//! table layout and value spellings outside the edited values.
+
+use std::fs;
+use std::path::Path;
+
+use serde_json::{Map, Number, Value};
+use toml_edit::{
+    Array, ArrayOfTables, Datetime, DocumentMut, InlineTable, Item, Table, TableLike,
+    Value as TomlValue,
+};
+
+use crate::hash::HashOptions;
+use crate::json::{
+    append_key_path, apply_document_edits_with, compute_json_anchor_with, parse_path_segments,
+    JsonEdit, JsonError, PathDocument, PathSegment,
+};
+
+/// A TOML document: the editable tree and its current text.
+#[derive(Debug, Clone)]
+pub struct TomlDocument {
+    doc: DocumentMut,
+    text: String,
+}
+
+/// A node reached by a path: table entries are items, array elements are
+/// values and the elements of an array of tables are tables.
+enum NodeMut<'a> {
+    Item(&'a mut Item),
+    Value(&'a mut TomlValue),
+    Table(&'a mut Table),
+}
+
+impl<'a> NodeMut<'a> {
+    fn table_like(self) -> Option<&'a mut dyn TableLike> {
+        match self {
+            NodeMut::Item(item) => item.as_table_like_mut(),
+            NodeMut::Value(TomlValue::InlineTable(t)) => Some(t),
+            NodeMut::Table(t) => Some(t),
+            NodeMut::Value(_) => None,
+        }
+    }
+
+    fn is_inline_table(&self) -> bool {
+        match self {
+            NodeMut::Item(item) => item.is_inline_table(),
+            NodeMut::Value(value) => value.is_inline_table(),
+            NodeMut::Table(_) => false,
+        }
+    }
+
+    fn is_table_like(&self) -> bool {
+        match self {
+            NodeMut::Item(item) => item.is_table_like(),
+            NodeMut::Value(value) => value.is_inline_table(),
+            NodeMut::Table(_) => true,
+        }
+    }
+
+    fn child(self, seg: &PathSegment, i: usize) -> Result<NodeMut<'a>, JsonError> {
+        match seg {
+            PathSegment::Key(key) => {
+                let table = self.table_like().ok_or_else(|| {
+                    JsonError::Other(format!(
+                        "Expected object at segment {} but got non-object",
+                        i
+                    ))
+                })?;
+                match table.get_mut(key) {
+                    Some(item) if !item.is_none() => Ok(NodeMut::Item(item)),
+                    _ => Err(format!("Key not found: {}", key).into()),
+                }
+            }
+            PathSegment::Index(idx) => {
+                let out_of_bounds =
+                    || JsonError::Other(format!("Array index {} out of bounds", idx));
+                match self {
+                    NodeMut::Item(Item::ArrayOfTables(tables)) => tables
+                        .get_mut(*idx)
+                        .map(NodeMut::Table)
+                        .ok_or_else(out_of_bounds),
+                    NodeMut::Item(Item::Value(TomlValue::Array(array)))
+                    | NodeMut::Value(TomlValue::Array(array)) => array
+                        .get_mut(*idx)
+                        .map(NodeMut::Value)
+                        .ok_or_else(out_of_bounds),
+                    _ => Err(format!("Expected array at segment {} but got non-array", i).into()),
+                }
+            }
+        }
+    }
+
+    fn value(&self) -> Value {
+        match self {
+            NodeMut::Item(item) => item_value(item),
+            NodeMut::Value(value) => toml_value(value),
+            NodeMut::Table(table) => table_value(*table),
+        }
+    }
+}
+
+impl TomlDocument {
+    /// Parse a TOML document, keeping its text verbatim.
+    pub fn parse(text: &str) -> Result<Self, JsonError> {
+        let doc: DocumentMut = text.parse().map_err(|e: toml_edit::TomlError| {
+            JsonError::Other(format!("Invalid TOML: {}", e.to_string().trim_end()))
+        })?;
+        Ok(TomlDocument {
+            doc,
+            text: text.to_string(),
+        })
+    }
+
+    /// The document text, including every edit so far.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The document's data. Datetimes read as strings; JSON has no `inf`
+    /// or `nan`, so they read as `null`.
+    pub fn value(&self) -> Value {
+        table_value(self.doc.as_table())
+    }
+
+    /// Replace the value at `path`, adding the key if its table lacks it.
+    pub fn set_path(&mut self, path: &str, value: &Value) -> Result<(), JsonError> {
+        let segments = parse_path_segments(path)?;
+        let Some((last, parent_segs)) = segments.split_last() else {
+            return self.edit(|root| assign(NodeMut::Table(root), value));
+        };
+        self.edit(|root| {
+            let at_root = parent_segs.is_empty();
+            let parent = navigate(root, parent_segs)?;
+            match last {
+                PathSegment::Key(key) => {
+                    let inline = parent.is_inline_table();
+                    let table = parent
+                        .table_like()
+                        .ok_or_else(|| JsonError::from("Expected object for set_path"))?;
+                    set_key(table, key, value, at_root, inline)
+                }
+                PathSegment::Index(idx) => {
+                    let element = parent.child(last, parent_segs.len()).map_err(|e| match e {
+                        JsonError::Other(msg) if msg.starts_with("Expected array") => {
+                            JsonError::from("Expected array for set_path")
+                        }
+                        _ => JsonError::Other(format!(
+                            "Array index {} out of bounds in set_path",
+                            idx
+                        )),
+                    })?;
+                    assign(element, value)
+                }
+            }
+        })
+    }
+
+    /// Add `key` to the table at `path` (replacing an existing value), or
+    /// insert into the array there at `index` (appending when `None`).
+    pub fn insert_at_path(
+        &mut self,
+        path: &str,
+        key: Option<&str>,
+        index: Option<usize>,
+        value: &Value,
+    ) -> Result<(), JsonError> {
+        if key.is_some() && index.is_some() {
+            return Err("insert_at_path requires either `key` or `index`, not both".into());
+        }
+        let segments = parse_path_segments(path)?;
+        self.edit(|root| {
+            let at_root = segments.is_empty();
+            let target = navigate(root, &segments)?;
+            if let Some(key) = key {
+                let inline = target.is_inline_table();
+                let table = target
+                    .table_like()
+                    .ok_or_else(|| JsonError::from("Cannot insert key into non-object"))?;
+                return set_key(table, key, value, at_root, inline);
+            }
+            let len = match &target {
+                NodeMut::Item(Item::ArrayOfTables(tables)) => tables.len(),
+                NodeMut::Item(Item::Value(TomlValue::Array(array)))
+                | NodeMut::Value(TomlValue::Array(array)) => array.len(),
+                _ => return Err("Cannot insert into non-array".into()),
+            };
+            let idx = index.unwrap_or(len);
+            if idx > len {
+                return Err(
+                    format!("Array insert index {} out of bounds (len {})", idx, len).into(),
+                );
+            }
+            match target {
+                NodeMut::Item(Item::ArrayOfTables(tables)) => {
+                    let Value::Object(map) = value else {
+                        return Err("Array of tables items must be objects".into());
+                    };
+                    insert_table(tables, idx, new_table(map)?);
+                    Ok(())
+                }
+                NodeMut::Item(Item::Value(TomlValue::Array(array)))
+                | NodeMut::Value(TomlValue::Array(array)) => {
+                    insert_element(array, idx, new_value(value)?);
+                    Ok(())
+                }
+                _ => unreachable!("checked above"),
+            }
+        })
+    }
+
+    /// Remove the entry at `path` with its comments. Removing a table also
+    /// removes its header.
+    pub fn delete_path(&mut self, path: &str) -> Result<(), JsonError> {
+        let segments = parse_path_segments(path)?;
+        let Some((last, parent_segs)) = segments.split_last() else {
+            return Err("Cannot delete root".into());
+        };
+        self.edit(|root| {
+            let parent = navigate(root, parent_segs)?;
+            match last {
+                PathSegment::Key(key) => {
+                    let inline = parent.is_inline_table();
+                    let table = parent
+                        .table_like()
+                        .ok_or_else(|| JsonError::from("Expected object for delete_path"))?;
+                    // Deleting a missing key is not an error, as with JSON.
+                    if table.remove(key).is_some() && inline {
+                        table.fmt();
+                    }
+                    Ok(())
+                }
+                PathSegment::Index(idx) => {
+                    let out_of_bounds = || {
+                        JsonError::Other(format!(
+                            "Array index {} out of bounds in delete_path",
+                            idx
+                        ))
+                    };
+                    match parent {
+                        NodeMut::Item(Item::ArrayOfTables(tables)) => {
+                            if *idx >= tables.len() {
+                                return Err(out_of_bounds());
+                            }
+                            tables.remove(*idx);
+                            Ok(())
+                        }
+                        NodeMut::Item(Item::Value(TomlValue::Array(array)))
+                        | NodeMut::Value(TomlValue::Array(array)) => {
+                            if *idx >= array.len() {
+                                return Err(out_of_bounds());
+                            }
+                            remove_element(array, *idx);
+                            Ok(())
+                        }
+                        _ => Err("Expected array for delete_path".into()),
+                    }
+                }
+            }
+        })
+    }
+
+    /// Run `f` on the root table and refresh the text. A failed edit
+    /// leaves the document as it was.
+    fn edit(
+        &mut self,
+        f: impl FnOnce(&mut Table) -> Result<(), JsonError>,
+    ) -> Result<(), JsonError> {
+        let mut doc = self.doc.clone();
+        f(doc.as_table_mut())?;
+        self.text = doc.to_string();
+        self.doc = doc;
+        Ok(())
+    }
+}
+
+impl PathDocument for TomlDocument {
+    fn value(&self) -> Value {
+        TomlDocument::value(self)
+    }
+
+    fn text(&self) -> &str {
+        TomlDocument::text(self)
+    }
+
+    fn set_path(&mut self, path: &str, value: &Value) -> Result<(), JsonError> {
+        TomlDocument::set_path(self, path, value)
+    }
+
+    fn insert_at_path(
+        &mut self,
+        path: &str,
+        key: Option<&str>,
+        index: Option<usize>,
+        value: &Value,
+    ) -> Result<(), JsonError> {
+        TomlDocument::insert_at_path(self, path, key, index, value)
+    }
+
+    fn delete_path(&mut self, path: &str) -> Result<(), JsonError> {
+        TomlDocument::delete_path(self, path)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Public API
+// ---------------------------------------------------------------------------
+
+/// Parse a TOML file into a `TomlDocument`.
+pub fn parse_toml_document(file_path: &Path) -> Result<TomlDocument, JsonError> {
+    let content = fs::read_to_string(file_path)
+        .map_err(|e| JsonError::from(Box::new(e) as Box<dyn std::error::Error>))?;
+    TomlDocument::parse(&content)
+}
+
+/// Apply path-anchored edits to a TOML document atomically.
+/// Returns `JsonError::HashMismatch` if any anchor hash does not match the current value.
+#[allow(dead_code)]
+pub fn apply_toml_edits(doc: &mut TomlDocument, edits: &[JsonEdit]) -> Result<(), JsonError> {
+    apply_toml_edits_with(doc, edits, &HashOptions::default())
+}
+
+/// Apply TOML edits, validating anchors with explicit hash options.
+pub fn apply_toml_edits_with(
+    doc: &mut TomlDocument,
+    edits: &[JsonEdit],
+    options: &HashOptions,
+) -> Result<(), JsonError> {
+    apply_document_edits_with(doc, edits, options)
+}
+
+/// Format a TOML document as TOML with `# PATH:HASH` anchor comments.
+#[allow(dead_code)]
+pub fn format_toml_anchors(doc: &TomlDocument) -> String {
+    format_toml_anchors_with(doc, &HashOptions::default())
+}
+
+/// Format a TOML document with anchor comments using explicit hash options.
+/// Key/value pairs come before sub-tables, as TOML requires.
+pub fn format_toml_anchors_with(doc: &TomlDocument, options: &HashOptions) -> String {
+    let value = doc.value();
+    let mut out = format!("# {}\n", compute_json_anchor_with("$", &value, options));
+    if let Value::Object(map) = &value {
+        write_table(&mut out, map, "$", &[], options);
+    }
+    // Like `format_json_anchors_with`, without a final newline.
+    out.pop();
+    out
+}
+
+// ---------------------------------------------------------------------------
+// Internal helpers
+// ---------------------------------------------------------------------------
+
+fn navigate<'a>(root: &'a mut Table, segments: &[PathSegment]) -> Result<NodeMut<'a>, JsonError> {
+    let mut node = NodeMut::Table(root);
+    for (i, seg) in segments.iter().enumerate() {
+        node = node.child(seg, i)?;
+    }
+    Ok(node)
+}
+
+/// Set `key` in `table`: an existing value is updated in place, a new one is
+/// appended. New tables at the top level get a `[header]`; elsewhere they
+/// are inline tables. An `inline` table is re-spaced after a key is added.
+fn set_key(
+    table: &mut dyn TableLike,
+    key: &str,
+    value: &Value,
+    at_root: bool,
+    inline: bool,
+) -> Result<(), JsonError> {
+    match table.get_mut(key) {
+        Some(item) if !item.is_none() => assign(NodeMut::Item(item), value),
+        _ => {
+            let item = match value {
+                Value::Object(map) if at_root && !map.is_empty() => Item::Table(new_table(map)?),
+                _ => Item::Value(new_value(value)?),
+            };
+            table.insert(key, item);
+            if inline {
+                table.fmt();
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Make `node` hold `value`, changing as little of its text as possible:
+/// tables and arrays are updated entry by entry, so untouched entries keep
+/// their comments and formatting.
+fn assign(node: NodeMut<'_>, value: &Value) -> Result<(), JsonError> {
+    if node.value() == *value {
+        return Ok(());
+    }
+    match (node, value) {
+        (node, Value::Object(map)) if node.is_table_like() => {
+            let inline = node.is_inline_table();
+            merge_table(node.table_like().expect("checked above"), map, inline)
+        }
+        (NodeMut::Table(_), _) => Err("Array of tables items must be objects".into()),
+        (NodeMut::Item(item @ Item::ArrayOfTables(_)), Value::Array(items))
+            if is_array_of_objects(items) =>
+        {
+            let Item::ArrayOfTables(tables) = item else {
+                unreachable!("matched above")
+            };
+            for (i, element) in items.iter().enumerate() {
+                let Value::Object(map) = element else {
+                    unreachable!("checked by is_array_of_objects")
+                };
+                match tables.get_mut(i) {
+                    Some(table) => merge_table(table, map, false)?,
+                    None => tables.push(new_table(map)?),
+                }
+            }
+            while tables.len() > items.len() {
+                tables.remove(tables.len() - 1);
+            }
+            Ok(())
+        }
+        (NodeMut::Item(Item::Value(old)), value) | (NodeMut::Value(old), value) => {
+            assign_value(old, value)
+        }
+        (NodeMut::Item(item), value) => {
+            // A table or array of tables replaced by another kind of value.
+            *item = Item::Value(new_value(value)?);
+            Ok(())
+        }
+    }
+}
+
+fn assign_value(old: &mut TomlValue, value: &Value) -> Result<(), JsonError> {
+    if let (TomlValue::Array(array), Value::Array(items)) = (&mut *old, value) {
+        let common = array.len().min(items.len());
+        for (i, item) in items.iter().enumerate().take(common) {
+            assign(NodeMut::Value(array.get_mut(i).expect("in bounds")), item)?;
+        }
+        while array.len() > items.len() {
+            remove_element(array, array.len() - 1);
+        }
+        for item in &items[common..] {
+            insert_element(array, array.len(), new_value(item)?);
+        }
+        return Ok(());
+    }
+    let mut new = scalar_like(value, old)?;
+    *new.decor_mut() = old.decor().clone();
+    *old = new;
+    Ok(())
+}
+
+fn merge_table(
+    table: &mut dyn TableLike,
+    map: &Map<String, Value>,
+    inline: bool,
+) -> Result<(), JsonError> {
+    let stale: Vec<String> = table
+        .iter()
+        .map(|(k, _)| k.to_string())
+        .filter(|k| !map.contains_key(k))
+        .collect();
+    for key in &stale {
+        table.remove(key);
+    }
+    if inline && !stale.is_empty() {
+        table.fmt();
+    }
+    for (key, value) in map {
+        set_key(table, key, value, false, inline)?;
+    }
+    Ok(())
+}
+
+/// A new value for `value` that keeps the spelling of `old` where it can: a
+/// datetime stays a datetime and a literal string stays literal.
+fn scalar_like(value: &Value, old: &TomlValue) -> Result<TomlValue, JsonError> {
+    if let Value::String(s) = value {
+        match old {
+            TomlValue::Datetime(_) => {
+                if let Ok(datetime) = s.parse::<Datetime>() {
+                    return Ok(datetime.into());
+                }
+            }
+            TomlValue::String(formatted) => {
+                let literal = formatted
+                    .as_repr()
+                    .and_then(|repr| repr.as_raw().as_str())
+                    .is_some_and(|raw| raw.starts_with('\'') && !raw.starts_with("'''"));
+                if literal && !s.contains('\'') && !s.chars().any(char::is_control) {
+                    if let Ok(value) = format!("'{}'", s).parse::<TomlValue>() {
+                        return Ok(value);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    new_value(value)
+}
+
+/// `value` as a TOML value with default formatting.
+fn new_value(value: &Value) -> Result<TomlValue, JsonError> {
+    Ok(match value {
+        Value::Null => return Err("TOML has no null value".into()),
+        Value::Bool(b) => (*b).into(),
+        Value::Number(n) => match (n.as_i64(), n.as_f64()) {
+            (Some(i), _) => i.into(),
+            (None, Some(_)) if n.is_u64() => {
+                return Err(format!("{} is out of range for a TOML integer", n).into())
+            }
+            (None, Some(f)) => f.into(),
+            (None, None) => return Err(format!("Unsupported number {}", n).into()),
+        },
+        Value::String(s) => s.as_str().into(),
+        Value::Array(items) => {
+            let mut array = Array::new();
+            for item in items {
+                array.push(new_value(item)?);
+            }
+            TomlValue::Array(array)
+        }
+        Value::Object(map) => {
+            let mut table = InlineTable::new();
+            for (key, value) in map {
+                table.insert(key, new_value(value)?);
+            }
+            TomlValue::InlineTable(table)
+        }
+    })
+}
+
+fn new_table(map: &Map<String, Value>) -> Result<Table, JsonError> {
+    let mut table = Table::new();
+    for (key, value) in map {
+        table.insert(key, Item::Value(new_value(value)?));
+    }
+    Ok(table)
+}
+
+/// Insert into an array, spacing the new element like its neighbours.
+fn insert_element(array: &mut Array, idx: usize, mut value: TomlValue) {
+    let len = array.len();
+    if len == 0 {
+        array.push(value);
+        return;
+    }
+    // The element that will follow the new one, or the last one when appending.
+    let neighbour = array
+        .get(idx.min(len - 1))
+        .expect("in bounds")
+        .decor()
+        .clone();
+    let prefix = |v: &TomlValue| {
+        v.decor()
+            .prefix()
+            .and_then(|p| p.as_str())
+            .unwrap_or(" ")
+            .to_string()
+    };
+    let spacing = match array.get(1) {
+        // The first element has no space after `[`; use the second's.
+        Some(second) => prefix(second),
+        None => match neighbour.prefix().and_then(|p| p.as_str()) {
+            Some(p) if p.contains('\n') => p.to_string(),
+            _ => " ".to_string(),
+        },
+    };
+    if idx == len {
+        // The last element's suffix is the whitespace before `]`.
+        let last = array.get_mut(len - 1).expect("in bounds");
+        let suffix = last.decor().suffix().cloned();
+        last.decor_mut().set_suffix("");
+        value.decor_mut().set_prefix(spacing);
+        value.decor_mut().set_suffix(suffix.unwrap_or_default());
+    } else if idx == 0 {
+        value
+            .decor_mut()
+            .set_prefix(neighbour.prefix().cloned().unwrap_or_default());
+        value.decor_mut().set_suffix("");
+        array
+            .get_mut(0)
+            .expect("in bounds")
+            .decor_mut()
+            .set_prefix(spacing);
+    } else {
+        value.decor_mut().set_prefix(spacing);
+        value.decor_mut().set_suffix("");
+    }
+    array.insert_formatted(idx, value);
+}
+
+/// Remove from an array, keeping the spacing after `[` and before `]`.
+fn remove_element(array: &mut Array, idx: usize) {
+    let removed = array.remove(idx);
+    let len = array.len();
+    if len == 0 {
+        return;
+    }
+    if idx == 0 {
+        let prefix = removed.decor().prefix().cloned().unwrap_or_default();
+        array
+            .get_mut(0)
+            .expect("in bounds")
+            .decor_mut()
+            .set_prefix(prefix);
+    }
+    if idx == len {
+        let suffix = removed.decor().suffix().cloned().unwrap_or_default();
+        array
+            .get_mut(len - 1)
+            .expect("in bounds")
+            .decor_mut()
+            .set_suffix(suffix);
+    }
+}
+
+/// Insert into an array of tables. Tables print in document order, so the
+/// new table takes the position of the one it goes before.
+fn insert_table(tables: &mut ArrayOfTables, idx: usize, mut table: Table) {
+    if idx == tables.len() {
+        if let Some(position) = tables.get(idx.wrapping_sub(1)).and_then(Table::position) {
+            table.set_position(position);
+        }
+        tables.push(table);
+        return;
+    }
+    let mut rest: Vec<Table> = tables.iter().skip(idx).cloned().collect();
+    while tables.len() > idx {
+        tables.remove(tables.len() - 1);
+    }
+    if let Some(position) = rest[0].position() {
+        table.set_position(position);
+    }
+    tables.push(table);
+    for table in rest.drain(..) {
+        tables.push(table);
+    }
+}
+
+fn is_array_of_objects(items: &[Value]) -> bool {
+    !items.is_empty() && items.iter().all(Value::is_object)
+}
+
+fn item_value(item: &Item) -> Value {
+    match item {
+        Item::None => Value::Null,
+        Item::Value(value) => toml_value(value),
+        Item::Table(table) => table_value(table),
+        Item::ArrayOfTables(tables) => {
+            Value::Array(tables.iter().map(|t| table_value(t)).collect())
+        }
+    }
+}
+
+fn table_value(table: &dyn TableLike) -> Value {
+    Value::Object(
+        table
+            .iter()
+            .filter(|(_, item)| !item.is_none())
+            .map(|(key, item)| (key.to_string(), item_value(item)))
+            .collect(),
+    )
+}
+
+fn toml_value(value: &TomlValue) -> Value {
+    match value {
+        TomlValue::String(s) => Value::String(s.value().clone()),
+        TomlValue::Integer(i) => Value::Number((*i.value()).into()),
+        TomlValue::Float(f) => Number::from_f64(*f.value()).map_or(Value::Null, Value::Number),
+        TomlValue::Boolean(b) => Value::Bool(*b.value()),
+        TomlValue::Datetime(d) => Value::String(d.value().to_string()),
+        TomlValue::Array(array) => Value::Array(array.iter().map(toml_value).collect()),
+        TomlValue::InlineTable(table) => table_value(table),
+    }
+}
+
+/// A key as written in TOML: bare when it can be, otherwise quoted.
+fn key_text(key: &str) -> String {
+    let bare = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if bare {
+        key.to_string()
+    } else {
+        Value::String(key.to_string()).to_string()
+    }
+}
+
+/// Render `value` on one line. JSON strings and numbers are valid TOML;
+/// `null` only comes from `inf` / `nan`.
+fn inline(value: &Value) -> String {
+    match value {
+        Value::Null => "nan".to_string(),
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(inline).collect();
+            format!("[{}]", items.join(", "))
+        }
+        Value::Object(map) if map.is_empty() => "{}".to_string(),
+        Value::Object(map) => {
+            let members: Vec<String> = map
+                .iter()
+                .map(|(k, v)| format!("{} = {}", key_text(k), inline(v)))
+                .collect();
+            format!("{{ {} }}", members.join(", "))
+        }
+        _ => value.to_string(),
+    }
+}
+
+fn is_table(value: &Value) -> bool {
+    matches!(value, Value::Object(map) if !map.is_empty())
+}
+
+/// Write the entries of a table under `header` (the keys leading to it),
+/// with an anchor comment before each.
+fn write_table(
+    out: &mut String,
+    map: &Map<String, Value>,
+    path: &str,
+    header: &[&str],
+    options: &HashOptions,
+) {
+    let anchor = |path: &str, value: &Value| compute_json_anchor_with(path, value, options);
+    let is_tables = |v: &Value| matches!(v, Value::Array(items) if is_array_of_objects(items));
+    for (key, value) in map {
+        if is_table(value) || is_tables(value) {
+            continue;
+        }
+        let child = append_key_path(path, key);
+        out.push_str(&format!("# {}\n", anchor(&child, value)));
+        out.push_str(&format!("{} = ", key_text(key)));
+        write_value(out, value, &child, "", options);
+        out.push('\n');
+    }
+    for (key, value) in map {
+        let child = append_key_path(path, key);
+        let mut nested = header.to_vec();
+        nested.push(key);
+        let name: Vec<String> = nested.iter().map(|k| key_text(k)).collect();
+        if !(is_table(value) || is_tables(value)) {
+            continue;
+        }
+        // A blank line before each table, except right after the root anchor.
+        if out.matches('\n').count() > 1 {
+            out.push('\n');
+        }
+        match value {
+            Value::Object(inner) => {
+                out.push_str(&format!(
+                    "# {}\n[{}]\n",
+                    anchor(&child, value),
+                    name.join(".")
+                ));
+                write_table(out, inner, &child, &nested, options);
+            }
+            Value::Array(items) => {
+                out.push_str(&format!("# {}\n", anchor(&child, value)));
+                for (i, item) in items.iter().enumerate() {
+                    let element = format!("{}[{}]", child, i);
+                    out.push_str(&format!(
+                        "# {}\n[[{}]]\n",
+                        anchor(&element, item),
+                        name.join(".")
+                    ));
+                    if let Value::Object(inner) = item {
+                        write_table(out, inner, &element, &nested, options);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Write a value after `key = `: arrays one element per line with anchors,
+/// everything else inline.
+fn write_value(out: &mut String, value: &Value, path: &str, indent: &str, options: &HashOptions) {
+    match value {
+        Value::Array(items) if !items.is_empty() => {
+            let inner = format!("{}  ", indent);
+            out.push_str("[\n");
+            for (i, item) in items.iter().enumerate() {
+                let element = format!("{}[{}]", path, i);
+                out.push_str(&format!(
+                    "{}# {}\n{}",
+                    inner,
+                    compute_json_anchor_with(&element, item, options),
+                    inner
+                ));
+                write_value(out, item, &element, &inner, options);
+                out.push_str(",\n");
+            }
+            out.push_str(indent);
+            out.push(']');
+        }
+        _ => out.push_str(&inline(value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const CARGO: &str = "[package]\nname = \"demo\"   # crate name\nversion = '0.1.0'\nedition = \"2021\"\n\n# Runtime dependencies\n[dependencies]\nserde = { version = \"1\", features = [\"derive\"] }\nregex = \"1\"\n\n[dependencies.clap]\nversion = \"4\"\n\n[[bin]]\nname = \"demo\"\n\n[[bin]]\nname = \"bench\"\n";
+
+    fn doc(text: &str) -> TomlDocument {
+        TomlDocument::parse(text).unwrap()
+    }
+
+    #[test]
+    fn parse_reads_tables_inline_tables_and_arrays_of_tables() {
+        assert_eq!(
+            doc(CARGO).value(),
+            json!({
+                "package": {"name": "demo", "version": "0.1.0", "edition": "2021"},
+                "dependencies": {
+                    "serde": {"version": "1", "features": ["derive"]},
+                    "regex": "1",
+                    "clap": {"version": "4"}
+                },
+                "bin": [{"name": "demo"}, {"name": "bench"}]
+            })
+        );
+        assert_eq!(
+            doc("a = 0x1F\nb = 1979-05-27T07:32:00Z\nc = inf\nd.e = 1.5\n").value(),
+            json!({"a": 31, "b": "1979-05-27T07:32:00Z", "c": null, "d": {"e": 1.5}})
+        );
+    }
+
+    #[test]
+    fn parse_reports_invalid_toml() {
+        let err = TomlDocument::parse("a = [1,\n").unwrap_err();
+        assert!(err.to_string().starts_with("Invalid TOML"), "{}", err);
+    }
+
+    #[test]
+    fn set_scalar_keeps_comments_and_string_style() {
+        let mut d = doc(CARGO);
+        d.set_path("$.package.name", &json!("demo2")).unwrap();
+        d.set_path("$.package.version", &json!("0.2.0")).unwrap();
+        d.set_path("$.dependencies.serde.version", &json!("1.0.200"))
+            .unwrap();
+        assert_eq!(
+            d.text(),
+            CARGO
+                .replace("\"demo\"   #", "\"demo2\"   #")
+                .replace("'0.1.0'", "'0.2.0'")
+                .replace("version = \"1\",", "version = \"1.0.200\",")
+        );
+    }
+
+    #[test]
+    fn set_datetime_stays_datetime() {
+        let mut d = doc("released = 2024-01-02\n");
+        d.set_path("$.released", &json!("2024-03-04")).unwrap();
+        assert_eq!(d.text(), "released = 2024-03-04\n");
+    }
+
+    #[test]
+    fn set_table_updates_only_changed_keys() {
+        let mut d = doc(CARGO);
+        d.set_path(
+            "$.package",
+            &json!({"name": "demo", "version": "0.1.0", "edition": "2024"}),
+        )
+        .unwrap();
+        assert_eq!(d.text(), CARGO.replace("\"2021\"", "\"2024\""));
+    }
+
+    #[test]
+    fn insert_dependency_into_table() {
+        let mut d = doc(CARGO);
+        d.insert_at_path("$.dependencies", Some("anyhow"), None, &json!("1"))
+            .unwrap();
+        d.insert_at_path(
+            "$.dependencies",
+            Some("tokio"),
+            None,
+            &json!({"version": "1", "features": ["full"]}),
+        )
+        .unwrap();
+        assert!(
+            d.text().contains(
+                "regex = \"1\"\nanyhow = \"1\"\ntokio = { features = [\"full\"], version = \"1\" }\n\n[dependencies.clap]"
+            ),
+            "{}",
+            d.text()
+        );
+    }
+
+    #[test]
+    fn inline_table_keys_stay_evenly_spaced() {
+        let mut d = doc(CARGO);
+        d.delete_path("$.dependencies.serde.version").unwrap();
+        d.insert_at_path("$.dependencies.serde", Some("optional"), None, &json!(true))
+            .unwrap();
+        assert!(
+            d.text()
+                .contains("serde = { features = [\"derive\"], optional = true }\n"),
+            "{}",
+            d.text()
+        );
+    }
+
+    #[test]
+    fn insert_top_level_table_gets_header() {
+        let mut d = doc("[package]\nname = \"demo\"\n");
+        d.set_path("$.profile", &json!({"release": {"lto": true}}))
+            .unwrap();
+        assert_eq!(
+            d.text(),
+            "[package]\nname = \"demo\"\n\n[profile]\nrelease = { lto = true }\n"
+        );
+    }
+
+    #[test]
+    fn array_edits_follow_existing_layout() {
+        let mut d = doc("a = [1, 2]\nb = [\n    \"x\",\n    \"y\",\n]\nc = [\n  1,\n  2\n]\n");
+        d.insert_at_path("$.a", None, None, &json!(3)).unwrap();
+        d.insert_at_path("$.a", None, Some(0), &json!(0)).unwrap();
+        d.insert_at_path("$.b", None, None, &json!("z")).unwrap();
+        d.insert_at_path("$.c", None, None, &json!(3)).unwrap();
+        assert_eq!(
+            d.text(),
+            "a = [0, 1, 2, 3]\nb = [\n    \"x\",\n    \"y\",\n    \"z\",\n]\nc = [\n  1,\n  2,\n  3\n]\n"
+        );
+        d.delete_path("$.a[0]").unwrap();
+        d.delete_path("$.c[2]").unwrap();
+        assert!(d.text().starts_with("a = [1, 2, 3]\n"), "{}", d.text());
+        assert!(d.text().ends_with("c = [\n  1,\n  2\n]\n"), "{}", d.text());
+    }
+
+    #[test]
+    fn array_of_tables_insert_and_delete() {
+        let mut d = doc(CARGO);
+        d.insert_at_path("$.bin", None, Some(1), &json!({"name": "tool"}))
+            .unwrap();
+        assert!(
+            d.text()
+                .ends_with("[[bin]]\nname = \"demo\"\n\n[[bin]]\nname = \"tool\"\n\n[[bin]]\nname = \"bench\"\n"),
+            "{}",
+            d.text()
+        );
+        d.delete_path("$.bin[0]").unwrap();
+        assert_eq!(
+            d.value()["bin"],
+            json!([{"name": "tool"}, {"name": "bench"}])
+        );
+    }
+
+    #[test]
+    fn delete_removes_comments_and_headers() {
+        let mut d = doc(CARGO);
+        d.delete_path("$.dependencies").unwrap();
+        assert_eq!(
+            d.text(),
+            "[package]\nname = \"demo\"   # crate name\nversion = '0.1.0'\nedition = \"2021\"\n\n[[bin]]\nname = \"demo\"\n\n[[bin]]\nname = \"bench\"\n"
+        );
+    }
+
+    #[test]
+    fn null_is_rejected() {
+        let mut d = doc(CARGO);
+        let err = d.set_path("$.package.name", &json!(null)).unwrap_err();
+        assert_eq!(err.to_string(), "TOML has no null value");
+        assert_eq!(d.text(), CARGO);
+    }
+
+    #[test]
+    fn format_anchors_writes_valid_toml() {
+        let d = doc(CARGO);
+        let out = format_toml_anchors(&d);
+        let anchor =
+            |path: &str, v: Value| compute_json_anchor_with(path, &v, &HashOptions::default());
+        assert!(out.starts_with(&format!("# {}\n", anchor("$", d.value()))));
+        assert!(out.contains(&format!(
+            "# {}\n[[bin]]\n# {}\nname = \"bench\"",
+            anchor("$.bin[1]", json!({"name": "bench"})),
+            anchor("$.bin[1].name", json!("bench"))
+        )));
+        assert!(out.contains(&format!(
+            "# {}\n[dependencies.serde]\n# {}\nfeatures = [\n  # {}\n  \"derive\",\n]\n",
+            anchor(
+                "$.dependencies.serde",
+                json!({"version": "1", "features": ["derive"]})
+            ),
+            anchor("$.dependencies.serde.features", json!(["derive"])),
+            anchor("$.dependencies.serde.features[0]", json!("derive"))
+        )));
+        // The anchored view is itself TOML with the same data.
+        assert_eq!(doc(&out).value(), d.value());
+    }
+}
@@ -1,20 +1,272 @@
-use std::fs;
-use std::io;
-use std::path::Path;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::diff::{diff_ops, DiffOp};
+
+const BOM: char = '\u{feff}';
+
+/// Line terminator used by a file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+/// On-disk layout of a text file that normalization throws away.
+///
+/// Recorded by [`read_text`] so that [`TextFormat::render_edit`] can write
+/// edited content back with the same line endings, final newline and BOM.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TextFormat {
+    pub line_ending: LineEnding,
+    pub trailing_newline: bool,
+    pub bom: bool,
+    /// Terminator of each original line, kept only when the file mixes LF
+    /// and CRLF.
+    pub mixed_endings: Option<Vec<LineEnding>>,
+}
+
+impl TextFormat {
+    /// Detect the layout of raw file content.
+    ///
+    /// Files with mixed endings use whichever terminator is more common for
+    /// new lines, and remember the terminator of every existing line.
+    /// Empty files are treated as LF with a final newline.
+    pub fn detect(raw: &str) -> Self {
+        let bom = raw.starts_with(BOM);
+        let crlf = raw.matches("\r\n").count();
+        let lf = raw.matches('\n').count() - crlf;
+        let mixed_endings = (crlf > 0 && lf > 0).then(|| {
+            raw.split_inclusive('\n')
+                .filter(|line| line.ends_with('\n'))
+                .map(|line| {
+                    if line.ends_with("\r\n") {
+                        LineEnding::CrLf
+                    } else {
+                        LineEnding::Lf
+                    }
+                })
+                .collect()
+        });
+        TextFormat {
+            line_ending: if crlf > lf {
+                LineEnding::CrLf
+            } else {
+                LineEnding::Lf
+            },
+            trailing_newline: raw.is_empty() || raw.ends_with('\n'),
+            bom,
+            mixed_endings,
+        }
+    }
+
+    /// Render normalized (LF, no final newline, no BOM) content in this layout.
+    pub fn render(&self, content: &str) -> String {
+        let mut out = String::with_capacity(content.len() + 8);
+        if self.bom {
+            out.push(BOM);
+        }
+        match self.line_ending {
+            LineEnding::Lf => out.push_str(content),
+            LineEnding::CrLf => out.push_str(&content.replace('\n', "\r\n")),
+        }
+        if self.trailing_newline {
+            out.push_str(self.line_ending.as_str());
+        }
+        out
+    }
+
+    /// Render `content`, an edited version of the normalized `original`, in
+    /// this layout. In a file with mixed endings, lines the edit left alone
+    /// keep their own terminator and new lines get the majority one.
+    pub fn render_edit(&self, original: &str, content: &str) -> String {
+        let Some(endings) = &self.mixed_endings else {
+            return self.render(content);
+        };
+        let old_lines: Vec<&str> = original.split('\n').collect();
+        let new_lines: Vec<&str> = content.split('\n').collect();
+        let mut kept = vec![None; new_lines.len()];
+        for op in diff_ops(&old_lines, &new_lines) {
+            if let DiffOp::Equal(old, new) = op {
+                kept[new] = endings.get(old).copied();
+            }
+        }
+
+        let mut out = String::with_capacity(content.len() + new_lines.len() + 8);
+        if self.bom {
+            out.push(BOM);
+        }
+        for (i, line) in new_lines.iter().enumerate() {
+            out.push_str(line);
+            if i + 1 < new_lines.len() || self.trailing_newline {
+                out.push_str(kept[i].unwrap_or(self.line_ending).as_str());
+            }
+        }
+        out
+    }
+}
+
+impl LineEnding {
+    fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+}
+
+/// Reads the file at `path` into normalized text plus the layout needed to write it back.
+///
+/// - Strips a leading UTF-8 BOM
+/// - Replaces CRLF (\r\n) with LF (\n)
+/// - Truncates trailing LF if present
+pub fn read_text(path: &Path) -> io::Result<(String, TextFormat)> {
+    let raw = fs::read_to_string(path)?;
+    let format = TextFormat::detect(&raw);
+    let mut content = raw.strip_prefix(BOM).unwrap_or(&raw).replace("\r\n", "\n");
+    if content.ends_with('\n') {
+        content.truncate(content.len() - 1);
+    }
+    Ok((content, format))
+}
 
 /// Reads the file at the given `path` into a string, normalizing line endings and removing trailing newline.
 ///
 /// - Replaces CRLF (\r\n) with LF (\n)
 /// - Truncates trailing LF if present
+/// - Strips a leading UTF-8 BOM
 ///
 /// Ensures platform-consistent text processing.
 pub fn read_normalized(path: &Path) -> io::Result<String> {
-    let mut content = fs::read_to_string(path)?;
-    content = content.replace("\r\n", "\n");
-    if content.ends_with('\n') {
-        content.truncate(content.len() - 1);
+    read_text(path).map(|(content, _)| content)
+}
+
+/// Writes `bytes` to `path` so that readers see either the old or the new
+/// content, never a truncated file.
+///
+/// The data goes to a temp file in the same directory, is fsynced, and is then
+/// renamed over the original. Permission bits (and, on Unix, owner and group
+/// where the process is allowed to set them) are copied from the original. If
+/// `path` is a symlink, the link target is rewritten and the link is kept.
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let target = match fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_symlink() => fs::canonicalize(path)?,
+        _ => path.to_path_buf(),
+    };
+    let original_meta = fs::metadata(&target).ok();
+    let dir = match target.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let file_name = target
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let tmp_path = dir.join(format!(
+        ".{}.hashline-{}-{}.tmp",
+        file_name.to_string_lossy(),
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let result = (|| {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&tmp_path)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+        if let Some(meta) = &original_meta {
+            fs::set_permissions(&tmp_path, meta.permissions())?;
+            copy_ownership(&tmp_path, meta);
+        }
+        fs::rename(&tmp_path, &target)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+        return result;
     }
-    Ok(content)
+    sync_dir(&dir);
+    Ok(())
+}
+
+#[cfg(unix)]
+fn copy_ownership(path: &Path, meta: &fs::Metadata) {
+    use std::os::unix::fs::MetadataExt;
+    // Only root may give a file away; fall back to keeping just the group.
+    if std::os::unix::fs::chown(path, Some(meta.uid()), Some(meta.gid())).is_err() {
+        let _ = std::os::unix::fs::chown(path, None, Some(meta.gid()));
+    }
+}
+
+#[cfg(not(unix))]
+fn copy_ownership(_path: &Path, _meta: &fs::Metadata) {}
+
+/// Flush the directory entry so the rename survives a crash.
+#[cfg(unix)]
+fn sync_dir(dir: &Path) {
+    if let Ok(d) = fs::File::open(dir) {
+        let _ = d.sync_all();
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) {}
+
+/// Failure while writing a set of files as one unit.
+#[derive(Debug)]
+pub struct WriteAllError {
+    /// File whose write failed.
+    pub path: PathBuf,
+    pub source: io::Error,
+    /// Files that could not be restored to their original content.
+    pub rollback_failures: Vec<(PathBuf, io::Error)>,
+}
+
+/// Writes every `(path, bytes)` pair, or none of them.
+///
+/// Original contents are snapshotted before the first write. If any write
+/// fails, files already written are restored (and files that did not exist
+/// before are removed) before the error is returned.
+pub fn write_all_or_rollback(writes: &[(PathBuf, Vec<u8>)]) -> Result<(), WriteAllError> {
+    let mut snapshots: Vec<Option<Vec<u8>>> = Vec::with_capacity(writes.len());
+    for (path, _) in writes {
+        match fs::read(path) {
+            Ok(bytes) => snapshots.push(Some(bytes)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => snapshots.push(None),
+            Err(e) => {
+                return Err(WriteAllError {
+                    path: path.clone(),
+                    source: e,
+                    rollback_failures: vec![],
+                })
+            }
+        }
+    }
+
+    for (i, (path, bytes)) in writes.iter().enumerate() {
+        if let Err(e) = write_atomic(path, bytes) {
+            let mut rollback_failures = Vec::new();
+            for ((done_path, _), snapshot) in writes[..i].iter().zip(&snapshots).rev() {
+                let restored = match snapshot {
+                    Some(original) => write_atomic(done_path, original),
+                    None => fs::remove_file(done_path),
+                };
+                if let Err(re) = restored {
+                    rollback_failures.push((done_path.clone(), re));
+                }
+            }
+            return Err(WriteAllError {
+                path: path.clone(),
+                source: e,
+                rollback_failures,
+            });
+        }
+    }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -38,4 +290,131 @@ mod tests {
         let content = read_normalized(temp.path()).unwrap();
         assert_eq!(content, "foo\nbar");
     }
+
+    #[test]
+    fn test_write_all_or_rollback_writes_every_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a.txt");
+        let b = dir.path().join("b.txt");
+        fs::write(&a, "old a\n").unwrap();
+        fs::write(&b, "old b\n").unwrap();
+        write_all_or_rollback(&[
+            (a.clone(), b"new a\n".to_vec()),
+            (b.clone(), b"new b\n".to_vec()),
+        ])
+        .unwrap();
+        assert_eq!(fs::read_to_string(&a).unwrap(), "new a\n");
+        assert_eq!(fs::read_to_string(&b).unwrap(), "new b\n");
+    }
+
+    #[test]
+    fn test_write_all_or_rollback_restores_on_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a.txt");
+        fs::write(&a, "old a\n").unwrap();
+        // A file in a missing directory can't be written; `a` is written first.
+        let bad = dir.path().join("missing").join("b.txt");
+        let err = write_all_or_rollback(&[
+            (a.clone(), b"new a\n".to_vec()),
+            (bad.clone(), b"x".to_vec()),
+        ])
+        .unwrap_err();
+        assert_eq!(err.path, bad);
+        assert!(err.rollback_failures.is_empty());
+        assert_eq!(fs::read_to_string(&a).unwrap(), "old a\n");
+    }
+
+    #[test]
+    fn test_read_text_records_crlf_and_round_trips() {
+        let temp = NamedTempFile::new().unwrap();
+        fs::write(temp.path(), b"line1\r\nline2\r\n").unwrap();
+        let (content, format) = read_text(temp.path()).unwrap();
+        assert_eq!(content, "line1\nline2");
+        assert_eq!(format.line_ending, LineEnding::CrLf);
+        assert!(format.trailing_newline);
+        assert_eq!(format.render(&content), "line1\r\nline2\r\n");
+    }
+
+    #[test]
+    fn test_read_text_records_missing_final_newline() {
+        let temp = NamedTempFile::new().unwrap();
+        fs::write(temp.path(), b"foo\nbar").unwrap();
+        let (content, format) = read_text(temp.path()).unwrap();
+        assert_eq!(content, "foo\nbar");
+        assert!(!format.trailing_newline);
+        assert_eq!(format.render("foo\nBAR"), "foo\nBAR");
+    }
+
+    #[test]
+    fn test_read_text_strips_and_restores_bom() {
+        let temp = NamedTempFile::new().unwrap();
+        fs::write(temp.path(), "\u{feff}foo\n").unwrap();
+        let (content, format) = read_text(temp.path()).unwrap();
+        assert_eq!(content, "foo");
+        assert!(format.bom);
+        assert_eq!(format.render(&content), "\u{feff}foo\n");
+    }
+
+    #[test]
+    fn test_detect_mixed_endings_uses_majority() {
+        let format = TextFormat::detect("a\r\nb\r\nc\n");
+        assert_eq!(format.line_ending, LineEnding::CrLf);
+        let format = TextFormat::detect("a\nb\nc\r\n");
+        assert_eq!(format.line_ending, LineEnding::Lf);
+    }
+
+    #[test]
+    fn test_render_edit_keeps_endings_of_untouched_lines() {
+        let raw = "a\nb\r\nc\n";
+        let format = TextFormat::detect(raw);
+        assert_eq!(format.render_edit("a\nb\nc", "a\nb\nc"), raw);
+        assert_eq!(format.render_edit("a\nb\nc", "A\nb\nc"), "A\nb\r\nc\n");
+        assert_eq!(
+            format.render_edit("a\nb\nc", "a\nb\nnew\nc"),
+            "a\nb\r\nnew\nc\n"
+        );
+        let uniform = TextFormat::detect("a\r\nb\r\n");
+        assert_eq!(uniform.mixed_endings, None);
+        assert_eq!(uniform.render_edit("a\nb", "a\nx"), "a\r\nx\r\n");
+    }
+
+    #[test]
+    fn test_write_atomic_replaces_content_without_leftovers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        fs::write(&path, "old\n").unwrap();
+        write_atomic(&path, b"new\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new\n");
+        let entries: Vec<_> = fs::read_dir(dir.path()).unwrap().collect();
+        assert_eq!(entries.len(), 1, "temp file left behind");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_atomic_preserves_mode() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("script.sh");
+        fs::write(&path, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        write_atomic(&path, b"#!/bin/sh\necho hi\n").unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o755);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_atomic_edits_symlink_target() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("real.txt");
+        let link = dir.path().join("link.txt");
+        fs::write(&target, "old\n").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        write_atomic(&link, b"new\n").unwrap();
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "new\n");
+    }
 }
@@ -0,0 +1,1512 @@
+//! YAML documents with path anchors.
+//!
+//! `yaml-read` and `yaml-apply` use the JSON subsystem's `PATH:HASH` anchors
+//! and its `set_path` / `insert_at_path` / `delete_path` edits. A document is
+//! parsed into a tree of spans over its source text, and an edit splices only
+//! the text it changes: comments, anchors and aliases, quoting and block or
+//! flow styles everywhere else stay as they were. A stream of several
+//! documents is addressed as an array, so `$[1].metadata.name` is a key of
+//! the second document.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+use saphyr_parser::{Event, Parser, ScalarStyle, Tag};
+use serde_json::{Map, Number, Value};
+
+use crate::hash::HashOptions;
+use crate::json::{
+    append_key_path, apply_document_edits_with, compute_json_anchor_with, parse_path_segments,
+    JsonEdit, JsonError, PathDocument, PathSegment,
+};
+
+/// Nesting deeper than this is rejected, as for JSON.
+const MAX_DEPTH: usize = 128;
+
+/// A YAML stream: the source text plus a tree of spans for each document.
+#[derive(Debug, Clone)]
+pub struct YamlDocument {
+    text: String,
+    documents: Vec<Node>,
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    span: Range<usize>,
+    kind: NodeKind,
+}
+
+#[derive(Debug, Clone)]
+enum NodeKind {
+    Scalar(Value, ScalarStyle),
+    /// An alias (`*name`): a copy of the value anchored at `target`. The
+    /// alias can be replaced, but what it holds is edited at the anchor.
+    Alias {
+        value: Value,
+        target: String,
+    },
+    Mapping {
+        entries: Vec<Entry>,
+        flow: bool,
+    },
+    Sequence {
+        entries: Vec<Entry>,
+        flow: bool,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    /// Mapping entries only: the key as text and the span of its token.
+    key: Option<(String, Range<usize>)>,
+    /// Where the entry starts: its key, or a block sequence item's `-`.
+    start: usize,
+    value: Node,
+    /// A `<<` merge key whose value is an alias, or a sequence of aliases,
+    /// of mappings. It adds their keys to its mapping and has no path itself.
+    merge: bool,
+}
+
+impl Entry {
+    /// The key this entry adds to its mapping; `None` for sequence items and
+    /// merge keys.
+    fn name(&self) -> Option<&str> {
+        self.key
+            .as_ref()
+            .filter(|_| !self.merge)
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+impl Node {
+    fn entries(&self) -> Option<&[Entry]> {
+        match &self.kind {
+            NodeKind::Mapping { entries, .. } | NodeKind::Sequence { entries, .. } => Some(entries),
+            NodeKind::Scalar(..) | NodeKind::Alias { .. } => None,
+        }
+    }
+
+    fn is_flow(&self) -> bool {
+        matches!(
+            self.kind,
+            NodeKind::Mapping { flow: true, .. } | NodeKind::Sequence { flow: true, .. }
+        )
+    }
+
+    fn is_block_collection(&self) -> bool {
+        self.entries().is_some() && !self.is_flow()
+    }
+}
+
+impl YamlDocument {
+    /// Parse a YAML stream, keeping its text verbatim.
+    pub fn parse(text: &str) -> Result<Self, JsonError> {
+        // The parser counts positions in chars; edits need byte offsets.
+        let offsets: Vec<usize> = text
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain(std::iter::once(text.len()))
+            .collect();
+        let byte = |index: usize| offsets[index.min(offsets.len() - 1)];
+        let mut events = Vec::new();
+        for event in Parser::new_from_str(text) {
+            let (event, span) =
+                event.map_err(|e| JsonError::Other(format!("Invalid YAML: {}", e)))?;
+            events.push((event, byte(span.start.index())..byte(span.end.index())));
+        }
+        let stream = events
+            .iter()
+            .filter(|(event, _)| matches!(event, Event::DocumentStart(_)))
+            .count()
+            > 1;
+        let mut builder = Builder {
+            text,
+            events,
+            pos: 0,
+            anchors: HashMap::new(),
+        };
+        let mut documents = Vec::new();
+        while builder.pos < builder.events.len() {
+            builder.pos += 1;
+            if matches!(builder.events[builder.pos - 1].0, Event::DocumentStart(_)) {
+                let root = if stream {
+                    format!("$[{}]", documents.len())
+                } else {
+                    "$".to_string()
+                };
+                documents.push(builder.node(&root, 0)?);
+            }
+        }
+        Ok(YamlDocument {
+            text: text.to_string(),
+            documents,
+        })
+    }
+
+    /// The stream text, including every edit so far.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The data of the document, or an array of documents for a stream of
+    /// several. JSON has no `.inf` or `.nan`; they read as `null`.
+    pub fn value(&self) -> Value {
+        match self.documents.as_slice() {
+            [] => Value::Null,
+            [root] => node_value(root),
+            documents => Value::Array(documents.iter().map(node_value).collect()),
+        }
+    }
+
+    /// Replace the value at `path`, adding the key if its mapping lacks it.
+    pub fn set_path(&mut self, path: &str, value: &Value) -> Result<(), JsonError> {
+        let segments = parse_path_segments(path)?;
+        let (doc, segments) = self.document(&segments)?;
+        if let Some((PathSegment::Key(key), parent_segs)) = segments.split_last() {
+            let parent = self.find_prefix(doc, segments, parent_segs.len())?;
+            // This also overrides a key merged in by `<<`.
+            if let NodeKind::Mapping { entries, .. } = &parent.kind {
+                if !entries.iter().any(|e| e.name() == Some(key)) {
+                    let parent = parent.clone();
+                    return self.add_entry(&parent, Some(key), value);
+                }
+            }
+        }
+        let (parent, node) = self.locate(doc, segments, "set_path")?;
+        self.replace_value(parent.as_ref().map(|(p, e)| (p, e)), &node, value)
+    }
+
+    /// Add `key` to the mapping at `path` (replacing an existing value), or
+    /// insert into the sequence there at `index` (appending when `None`).
+    pub fn insert_at_path(
+        &mut self,
+        path: &str,
+        key: Option<&str>,
+        index: Option<usize>,
+        value: &Value,
+    ) -> Result<(), JsonError> {
+        if key.is_some() && index.is_some() {
+            return Err("insert_at_path requires either `key` or `index`, not both".into());
+        }
+        let segments = parse_path_segments(path)?;
+        let (doc, segments) = self.document(&segments)?;
+        let target = self.find(doc, segments)?.clone();
+        self.check_shared(doc, segments, &target, &[])?;
+        if let Some(key) = key {
+            let NodeKind::Mapping { entries, .. } = &target.kind else {
+                return Err("Cannot insert key into non-object".into());
+            };
+            return match entries.iter().rev().find(|e| e.name() == Some(key)) {
+                Some(entry) => self.replace_value(Some((&target, entry)), &entry.value, value),
+                None => self.add_entry(&target, Some(key), value),
+            };
+        }
+        let NodeKind::Sequence { entries, flow } = &target.kind else {
+            return Err("Cannot insert into non-array".into());
+        };
+        match index {
+            Some(idx) if idx > entries.len() => Err(format!(
+                "Array insert index {} out of bounds (len {})",
+                idx,
+                entries.len()
+            )
+            .into()),
+            Some(idx) if idx < entries.len() && *flow => {
+                let at = entries[idx].start;
+                let item = format!("{},{}", inline(value, true), self.flow_separator(entries));
+                self.splice(vec![(at..at, item)])
+            }
+            Some(idx) if idx < entries.len() => {
+                let entry = &entries[idx];
+                let indent = self.column_indent(entry.start);
+                let item = self.block_entry(None, value, &indent);
+                if self.is_line_start(entry.start) {
+                    let at = self.leading_start(0, entry.start);
+                    let line = format!("{}{}{}", indent, item, self.newline());
+                    self.splice(vec![(at..at, line)])
+                } else {
+                    // The first item of a nested sequence shares its line (`- - a`).
+                    let at = entry.start;
+                    let line = format!("{}{}{}", item, self.newline(), indent);
+                    self.splice(vec![(at..at, line)])
+                }
+            }
+            _ => self.add_entry(&target, None, value),
+        }
+    }
+
+    /// Remove the entry at `path` with the comment lines directly above it.
+    pub fn delete_path(&mut self, path: &str) -> Result<(), JsonError> {
+        let segments = parse_path_segments(path)?;
+        let (doc, segments) = self.document(&segments)?;
+        let Some((last, parent_segs)) = segments.split_last() else {
+            return Err("Cannot delete root".into());
+        };
+        let parent = self.find_prefix(doc, segments, parent_segs.len())?.clone();
+        self.check_shared(doc, parent_segs, &parent, &segments[parent_segs.len()..])?;
+        let (entries, i) = match (last, &parent.kind) {
+            (PathSegment::Key(key), NodeKind::Mapping { entries, .. }) => {
+                match entries.iter().rposition(|e| e.name() == Some(key)) {
+                    Some(i) => (entries, i),
+                    // Deleting a missing key is not an error, as with JSON.
+                    None => return Ok(()),
+                }
+            }
+            (PathSegment::Key(_), _) => return Err("Expected object for delete_path".into()),
+            (PathSegment::Index(idx), NodeKind::Sequence { entries, .. }) => {
+                if *idx >= entries.len() {
+                    return Err(format!("Array index {} out of bounds in delete_path", idx).into());
+                }
+                (entries, *idx)
+            }
+            (PathSegment::Index(_), _) => return Err("Expected array for delete_path".into()),
+        };
+        let entry = &entries[i];
+
+        let span = if parent.is_flow() {
+            if i + 1 < entries.len() {
+                entry.start..entries[i + 1].start
+            } else if i > 0 {
+                entries[i - 1].value.span.end..entry.value.span.end
+            } else {
+                parent.span.start + 1..parent.span.end - 1
+            }
+        } else if entries.len() == 1 {
+            // A block collection cannot be empty; write `{}` or `[]` instead.
+            let empty = match parent.kind {
+                NodeKind::Mapping { .. } => Value::Object(Default::default()),
+                _ => Value::Array(Vec::new()),
+            };
+            let (grandparent, _) = self.locate(doc, parent_segs, "delete_path")?;
+            return self.replace_value(grandparent.as_ref().map(|(p, e)| (p, e)), &parent, &empty);
+        } else if !self.is_line_start(entry.start) {
+            // The first key of a compact mapping (`- name: x`): the next key
+            // moves up to take its place.
+            entry.start..entries[i + 1].start
+        } else {
+            // Comments above the first entry of a document belong to the file.
+            let prev_end = match i {
+                0 if parent_segs.is_empty() => entry.start,
+                0 => 0,
+                _ => entries[i - 1].value.span.end,
+            };
+            let start = self.leading_start(prev_end, entry.start);
+            let end = self
+                .line_end(entry.value.span.end)
+                .unwrap_or(self.text.len());
+            start..end
+        };
+        self.splice(vec![(span, String::new())])
+    }
+
+    /// The document a path addresses and the rest of the path. In a stream
+    /// of several documents, the first segment picks the document.
+    fn document<'p>(
+        &self,
+        segments: &'p [PathSegment],
+    ) -> Result<(usize, &'p [PathSegment]), JsonError> {
+        match (self.documents.len(), segments.split_first()) {
+            (0, _) => Err("The YAML stream has no documents".into()),
+            (1, _) => Ok((0, segments)),
+            (n, Some((PathSegment::Index(i), rest))) if *i < n => Ok((*i, rest)),
+            (n, _) => {
+                Err(format!("The YAML stream has {} documents; address one as $[N]", n).into())
+            }
+        }
+    }
+
+    /// The node at `segments`, with the same errors as the JSON API.
+    fn find(&self, doc: usize, segments: &[PathSegment]) -> Result<&Node, JsonError> {
+        self.find_prefix(doc, segments, segments.len())
+    }
+
+    /// The node at the first `len` of `segments`; errors name the whole path.
+    fn find_prefix(
+        &self,
+        doc: usize,
+        segments: &[PathSegment],
+        len: usize,
+    ) -> Result<&Node, JsonError> {
+        let mut current = &self.documents[doc];
+        for (i, seg) in segments[..len].iter().enumerate() {
+            self.check_shared(doc, &segments[..i], current, &segments[i..])?;
+            current = &child_entry(current, seg, i)?.value;
+        }
+        Ok(current)
+    }
+
+    /// Refuse to reach `rest` below `node` (the node at `at`) through an
+    /// alias or a key merged in with `<<`: those values are copies, edited
+    /// where they are anchored. Setting a merged key is not reaching it.
+    fn check_shared(
+        &self,
+        doc: usize,
+        at: &[PathSegment],
+        node: &Node,
+        rest: &[PathSegment],
+    ) -> Result<(), JsonError> {
+        let target = match (&node.kind, rest) {
+            (NodeKind::Alias { target, .. }, _) => join_path(target, rest),
+            (NodeKind::Mapping { entries, .. }, [PathSegment::Key(key), below @ ..])
+                if !entries.iter().any(|e| e.name() == Some(key)) =>
+            {
+                match merge_target(entries, key) {
+                    Some(target) => join_path(&target, below),
+                    None => return Ok(()),
+                }
+            }
+            _ => return Ok(()),
+        };
+        let path = self.path_string(doc, &[at, rest].concat());
+        Err(format!(
+            "{} is a copy made by a YAML alias; edit {} instead",
+            path, target
+        )
+        .into())
+    }
+
+    /// `segments` of document `doc` as a path.
+    fn path_string(&self, doc: usize, segments: &[PathSegment]) -> String {
+        let root = if self.documents.len() > 1 {
+            format!("$[{}]", doc)
+        } else {
+            "$".to_string()
+        };
+        join_path(&root, segments)
+    }
+
+    /// The node at `segments` and the collection and entry holding it, if
+    /// it is not a document root.
+    #[allow(clippy::type_complexity)]
+    fn locate(
+        &self,
+        doc: usize,
+        segments: &[PathSegment],
+        op: &str,
+    ) -> Result<(Option<(Node, Entry)>, Node), JsonError> {
+        let Some((last, parent_segs)) = segments.split_last() else {
+            return Ok((None, self.documents[doc].clone()));
+        };
+        let parent = self.find_prefix(doc, segments, parent_segs.len())?;
+        self.check_shared(doc, parent_segs, parent, &segments[parent_segs.len()..])?;
+        let entry = match (last, &parent.kind) {
+            (PathSegment::Key(_), NodeKind::Mapping { .. })
+            | (PathSegment::Index(_), NodeKind::Sequence { .. }) => {
+                child_entry(parent, last, parent_segs.len()).map_err(|e| match last {
+                    PathSegment::Index(idx) => {
+                        JsonError::Other(format!("Array index {} out of bounds in {}", idx, op))
+                    }
+                    PathSegment::Key(_) => e,
+                })?
+            }
+            (PathSegment::Key(_), _) => return Err(format!("Expected object for {}", op).into()),
+            (PathSegment::Index(_), _) => return Err(format!("Expected array for {}", op).into()),
+        };
+        Ok((Some((parent.clone(), entry.clone())), entry.value.clone()))
+    }
+
+    /// Replace `node` (held by `parent` in `entry`, or a document root) with
+    /// `value`, keeping its style: flow stays flow, block stays block and a
+    /// quoted or literal string keeps its quoting.
+    fn replace_value(
+        &mut self,
+        parent: Option<(&Node, &Entry)>,
+        node: &Node,
+        value: &Value,
+    ) -> Result<(), JsonError> {
+        let in_flow = parent.is_some_and(|(p, _)| p.is_flow());
+        let entry_start = parent.map_or(node.span.start, |(_, e)| e.start);
+        let key_span = parent.and_then(|(_, e)| e.key.as_ref().map(|(_, span)| span.clone()));
+        let edit = if in_flow || node.is_flow() {
+            (
+                node.span.clone(),
+                self.scalar_or_inline(node, value, true, ""),
+            )
+        } else if is_block_value(value) {
+            match key_span {
+                // `key: scalar` becomes `key:` with the block on the next lines.
+                Some(key) if !node.is_block_collection() => {
+                    let colon = self.after_colon(key.end);
+                    let indent = self.child_indent(entry_start);
+                    let text = format!(
+                        "{}{}{}{}",
+                        self.properties(colon..node.span.start),
+                        self.newline(),
+                        indent,
+                        self.block(value, &indent)
+                    );
+                    (colon..node.span.end, text)
+                }
+                _ => {
+                    let indent = self.column_indent(node.span.start);
+                    (node.span.clone(), self.block(value, &indent))
+                }
+            }
+        } else if node.is_block_collection() {
+            match key_span {
+                // A block collection under a key moves back onto the key's line.
+                Some(key) => {
+                    let colon = self.after_colon(key.end);
+                    let text = format!(
+                        "{} {}",
+                        self.properties(colon..node.span.start),
+                        inline(value, false)
+                    );
+                    (colon..node.span.end, text)
+                }
+                None => (node.span.clone(), inline(value, false)),
+            }
+        } else {
+            let indent = self.child_indent(entry_start);
+            (
+                node.span.clone(),
+                self.scalar_or_inline(node, value, false, &indent),
+            )
+        };
+        let (span, mut text) = edit;
+        // An empty value sits right after its `:` or `-`.
+        if span.is_empty() && self.text[..span.start].ends_with([':', '-']) {
+            text.insert(0, ' ');
+        }
+        self.splice(vec![(span, text)])
+    }
+
+    /// Append `value` (under `key` for a mapping) to `container`.
+    fn add_entry(
+        &mut self,
+        container: &Node,
+        key: Option<&str>,
+        value: &Value,
+    ) -> Result<(), JsonError> {
+        let entries = container.entries().unwrap_or_default();
+        if container.is_flow() {
+            let item = match key {
+                Some(key) => format!("{}: {}", scalar_text(key, true), inline(value, true)),
+                None => inline(value, true),
+            };
+            return match entries.last() {
+                Some(last) => {
+                    let at = last.value.span.end;
+                    let item = format!(",{}{}", self.flow_separator(entries), item);
+                    self.splice(vec![(at..at, item)])
+                }
+                None => {
+                    let interior = container.span.start + 1..container.span.end - 1;
+                    self.splice(vec![(interior, item)])
+                }
+            };
+        }
+        let (Some(first), Some(last)) = (entries.first(), entries.last()) else {
+            return Err("Cannot insert into an empty block collection".into());
+        };
+        let indent = self.column_indent(first.start);
+        let line = format!("{}{}", indent, self.block_entry(key, value, &indent));
+        let end = last.value.span.end;
+        match self.line_end(end) {
+            Some(at) => self.splice(vec![(at..at, format!("{}{}", line, self.newline()))]),
+            None => {
+                let at = self.line_tail(end);
+                self.splice(vec![(at..at, format!("{}{}", self.newline(), line))])
+            }
+        }
+    }
+
+    /// A block mapping entry (`key: value`) or sequence item (`- value`)
+    /// whose line is indented by `indent`, without that indentation.
+    fn block_entry(&self, key: Option<&str>, value: &Value, indent: &str) -> String {
+        match key {
+            Some(key) if is_block_value(value) => {
+                let child = format!("{}{}", indent, self.indent_unit());
+                format!(
+                    "{}:{}{}{}",
+                    scalar_text(key, false),
+                    self.newline(),
+                    child,
+                    self.block(value, &child)
+                )
+            }
+            Some(key) => format!("{}: {}", scalar_text(key, false), inline(value, false)),
+            None if is_block_value(value) => {
+                format!("- {}", self.block(value, &format!("{}  ", indent)))
+            }
+            None => format!("- {}", inline(value, false)),
+        }
+    }
+
+    /// Render a non-empty collection in block style. The first line goes
+    /// where the node starts; the others are indented by `indent`.
+    fn block(&self, value: &Value, indent: &str) -> String {
+        let mut out = String::new();
+        write_block(&mut out, value, indent, &self.indent_unit(), self.newline());
+        out
+    }
+
+    /// Render `value` in place of scalar `node`, keeping its quoting style;
+    /// collections are rendered inline.
+    fn scalar_or_inline(&self, node: &Node, value: &Value, flow: bool, indent: &str) -> String {
+        let (NodeKind::Scalar(_, style), Value::String(s)) = (&node.kind, value) else {
+            return inline(value, flow);
+        };
+        let single_line = !s.contains(['\n', '\r']) && !s.chars().any(char::is_control);
+        match style {
+            ScalarStyle::SingleQuoted if single_line => format!("'{}'", s.replace('\'', "''")),
+            ScalarStyle::DoubleQuoted => Value::String(s.clone()).to_string(),
+            ScalarStyle::Literal | ScalarStyle::Folded
+                if !flow && !s.trim().is_empty() && !s.starts_with([' ', '\t', '\n']) =>
+            {
+                self.literal(s, indent)
+            }
+            _ => scalar_text(s, flow),
+        }
+    }
+
+    /// A `|` block scalar with its content lines indented by `indent`.
+    fn literal(&self, s: &str, indent: &str) -> String {
+        let chomp = if s.ends_with("\n\n") {
+            "+"
+        } else if s.ends_with('\n') {
+            ""
+        } else {
+            "-"
+        };
+        let content = s.strip_suffix('\n').unwrap_or(s);
+        let lines: Vec<String> = content
+            .split('\n')
+            .map(|line| match line {
+                "" => String::new(),
+                _ => format!("{}{}", indent, line),
+            })
+            .collect();
+        format!("|{}{}{}", chomp, self.newline(), lines.join(self.newline()))
+    }
+
+    /// The anchors and tags written between `range` (a key's colon and its
+    /// value), each with a leading space.
+    fn properties(&self, range: Range<usize>) -> String {
+        self.text[range]
+            .split_whitespace()
+            .take_while(|token| !token.starts_with('#'))
+            .filter(|token| token.starts_with(['&', '!']))
+            .map(|token| format!(" {}", token))
+            .collect()
+    }
+
+    /// The position after the `:` that follows a key ending at `key_end`.
+    fn after_colon(&self, key_end: usize) -> usize {
+        self.text[key_end..]
+            .find(':')
+            .map_or(key_end, |i| key_end + i + 1)
+    }
+
+    /// The whitespace in a flow collection between a comma and the next entry.
+    fn flow_separator(&self, entries: &[Entry]) -> String {
+        match entries {
+            [first, second, ..] => {
+                let between = &self.text[first.value.span.end..second.start];
+                between[between.find(',').map_or(0, |i| i + 1)..].to_string()
+            }
+            _ => " ".to_string(),
+        }
+    }
+
+    /// Apply non-overlapping replacements and re-parse.
+    fn splice(&mut self, mut edits: Vec<(Range<usize>, String)>) -> Result<(), JsonError> {
+        edits.sort_by_key(|(span, _)| span.start);
+        let mut text = self.text.clone();
+        for (span, replacement) in edits.into_iter().rev() {
+            text.replace_range(span, &replacement);
+        }
+        *self = YamlDocument::parse(&text)?;
+        Ok(())
+    }
+
+    /// Where the comment lines directly above `start` begin (a line start),
+    /// not reaching back past `prev_end`. A blank line ends the block.
+    fn leading_start(&self, prev_end: usize, start: usize) -> usize {
+        let mut start = self.line_start(start);
+        while start > 0 {
+            let above = self.line_start(start - 1);
+            if above < prev_end || !self.text[above..start].trim_start().starts_with('#') {
+                break;
+            }
+            start = above;
+        }
+        start
+    }
+
+    fn line_start(&self, pos: usize) -> usize {
+        self.text[..pos].rfind('\n').map_or(0, |i| i + 1)
+    }
+
+    /// Whether only indentation precedes `pos` on its line.
+    fn is_line_start(&self, pos: usize) -> bool {
+        self.text[self.line_start(pos)..pos]
+            .chars()
+            .all(|c| c == ' ' || c == '\t')
+    }
+
+    /// Spaces up to the column of `pos`.
+    fn column_indent(&self, pos: usize) -> String {
+        " ".repeat(self.text[self.line_start(pos)..pos].chars().count())
+    }
+
+    /// Indentation for the children of an entry starting at `pos`.
+    fn child_indent(&self, pos: usize) -> String {
+        format!("{}{}", self.column_indent(pos), self.indent_unit())
+    }
+
+    /// If the rest of the line from `pos` holds only whitespace and a
+    /// comment, the position of its line break; otherwise `pos`.
+    fn line_tail(&self, pos: usize) -> usize {
+        let rest = &self.text[pos..];
+        let trimmed = rest.trim_start_matches([' ', '\t']);
+        let skipped = pos + rest.len() - trimmed.len();
+        if trimmed.starts_with('#') {
+            let end = trimmed.find('\n').map_or(self.text.len(), |n| skipped + n);
+            return if self.text[..end].ends_with('\r') {
+                end - 1
+            } else {
+                end
+            };
+        }
+        if trimmed.is_empty() || trimmed.starts_with(['\r', '\n']) {
+            skipped
+        } else {
+            pos
+        }
+    }
+
+    /// The start of the next line, if the rest of the line from `pos` holds
+    /// only whitespace and a comment.
+    fn line_end(&self, pos: usize) -> Option<usize> {
+        let tail = self.line_tail(pos);
+        let rest = &self.text[tail..];
+        if rest.starts_with("\r\n") {
+            Some(tail + 2)
+        } else if rest.starts_with('\n') {
+            Some(tail + 1)
+        } else {
+            None
+        }
+    }
+
+    /// One level of indentation, taken from the first indented line.
+    fn indent_unit(&self) -> String {
+        self.text
+            .lines()
+            .filter(|line| !line.trim_start().starts_with('#'))
+            .map(|line| &line[..line.len() - line.trim_start_matches(' ').len()])
+            .find(|indent| !indent.is_empty())
+            .unwrap_or("  ")
+            .to_string()
+    }
+
+    fn newline(&self) -> &'static str {
+        if self.text.contains("\r\n") {
+            "\r\n"
+        } else {
+            "\n"
+        }
+    }
+}
+
+impl PathDocument for YamlDocument {
+    fn value(&self) -> Value {
+        YamlDocument::value(self)
+    }
+
+    fn text(&self) -> &str {
+        YamlDocument::text(self)
+    }
+
+    fn set_path(&mut self, path: &str, value: &Value) -> Result<(), JsonError> {
+        YamlDocument::set_path(self, path, value)
+    }
+
+    fn insert_at_path(
+        &mut self,
+        path: &str,
+        key: Option<&str>,
+        index: Option<usize>,
+        value: &Value,
+    ) -> Result<(), JsonError> {
+        YamlDocument::insert_at_path(self, path, key, index, value)
+    }
+
+    fn delete_path(&mut self, path: &str) -> Result<(), JsonError> {
+        YamlDocument::delete_path(self, path)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Public API
+// ---------------------------------------------------------------------------
+
+/// Parse a YAML file into a `YamlDocument`.
+pub fn parse_yaml_document(file_path: &Path) -> Result<YamlDocument, JsonError> {
+    let content = fs::read_to_string(file_path)
+        .map_err(|e| JsonError::from(Box::new(e) as Box<dyn std::error::Error>))?;
+    YamlDocument::parse(&content)
+}
+
+/// Apply path-anchored edits to a YAML document atomically.
+/// Returns `JsonError::HashMismatch` if any anchor hash does not match the current value.
+#[allow(dead_code)]
+pub fn apply_yaml_edits(doc: &mut YamlDocument, edits: &[JsonEdit]) -> Result<(), JsonError> {
+    apply_yaml_edits_with(doc, edits, &HashOptions::default())
+}
+
+/// Apply YAML edits, validating anchors with explicit hash options.
+pub fn apply_yaml_edits_with(
+    doc: &mut YamlDocument,
+    edits: &[JsonEdit],
+    options: &HashOptions,
+) -> Result<(), JsonError> {
+    apply_document_edits_with(doc, edits, options)
+}
+
+/// Format a YAML document as block YAML with `# PATH:HASH` anchor comments.
+#[allow(dead_code)]
+pub fn format_yaml_anchors(doc: &YamlDocument) -> String {
+    format_yaml_anchors_with(doc, &HashOptions::default())
+}
+
+/// Format a YAML document with anchor comments using explicit hash options.
+/// Each document of a stream is printed after a `---` line.
+pub fn format_yaml_anchors_with(doc: &YamlDocument, options: &HashOptions) -> String {
+    let mut out = String::new();
+    match doc.documents.as_slice() {
+        [root] => write_anchored(&mut out, root, "$", options),
+        documents => {
+            for (i, root) in documents.iter().enumerate() {
+                out.push_str("---\n");
+                write_anchored(&mut out, root, &format!("$[{}]", i), options);
+            }
+        }
+    }
+    // Like `format_json_anchors_with`, without a final newline.
+    out.pop();
+    out
+}
+
+// ---------------------------------------------------------------------------
+// Internal helpers
+// ---------------------------------------------------------------------------
+
+/// Builds nodes from the parser's events.
+struct Builder<'a> {
+    text: &'a str,
+    events: Vec<(Event<'a>, Range<usize>)>,
+    pos: usize,
+    /// Values and paths of anchored nodes, for resolving aliases.
+    anchors: HashMap<usize, (Value, String)>,
+}
+
+impl Builder<'_> {
+    /// The node at the next event, which is at `path` in the stream.
+    fn node(&mut self, path: &str, depth: usize) -> Result<Node, JsonError> {
+        if depth > MAX_DEPTH {
+            return Err("Invalid YAML: recursion limit exceeded".into());
+        }
+        let Some((event, span)) = self.events.get(self.pos).cloned() else {
+            return Err("Invalid YAML: unexpected end of input".into());
+        };
+        self.pos += 1;
+        let (anchor, mapping) = match event {
+            Event::Scalar(raw, style, anchor, tag) => {
+                let value = scalar_value(&raw, style, tag.as_deref());
+                if anchor != 0 {
+                    self.anchors
+                        .insert(anchor, (value.clone(), path.to_string()));
+                }
+                return Ok(Node {
+                    span: self.scalar_span(span, style),
+                    kind: NodeKind::Scalar(value, style),
+                });
+            }
+            Event::Alias(id) => {
+                let (value, target) = self.anchors.get(&id).cloned().unwrap_or_default();
+                return Ok(Node {
+                    span,
+                    kind: NodeKind::Alias { value, target },
+                });
+            }
+            Event::MappingStart(anchor, _) => (anchor, true),
+            Event::SequenceStart(anchor, _) => (anchor, false),
+            _ => return Err("Invalid YAML: unexpected event".into()),
+        };
+
+        let flow = self.text[span.clone()].starts_with(['{', '[']);
+        let mut entries = Vec::new();
+        while !matches!(
+            self.events.get(self.pos),
+            Some((Event::MappingEnd | Event::SequenceEnd, _)) | None
+        ) {
+            if mapping {
+                let key = self.node(path, depth + 1)?;
+                let name = match &key.kind {
+                    NodeKind::Scalar(Value::String(s), _) => s.clone(),
+                    _ => self.text[key.span.clone()].to_string(),
+                };
+                let mut value = self.node(&append_key_path(path, &name), depth + 1)?;
+                if value.span.is_empty() {
+                    // An empty value is reported at its key; put it after the colon.
+                    let after = self.text[key.span.end..]
+                        .find(':')
+                        .map_or(key.span.end, |i| key.span.end + i + 1);
+                    value.span = after..after;
+                }
+                let merge = matches!(key.kind, NodeKind::Scalar(_, ScalarStyle::Plain))
+                    && name == "<<"
+                    && !merge_sources(&value).is_empty();
+                entries.push(Entry {
+                    start: key.span.start,
+                    key: Some((name, key.span)),
+                    value,
+                    merge,
+                });
+            } else {
+                let item_path = format!("{}[{}]", path, entries.len());
+                let value = self.node(&item_path, depth + 1)?;
+                let start = if flow {
+                    value.span.start
+                } else {
+                    dash_before(self.text, value.span.start)
+                };
+                entries.push(Entry {
+                    key: None,
+                    start,
+                    value,
+                    merge: false,
+                });
+            }
+        }
+        let end = self.events.get(self.pos).map(|(_, span)| span.clone());
+        self.pos += 1;
+
+        let span = match (flow, entries.first(), entries.last(), end) {
+            (true, _, _, Some(end)) => span.start..end.end,
+            (false, Some(first), Some(last), _) => first.start..last.value.span.end,
+            _ => span,
+        };
+        let kind = if mapping {
+            NodeKind::Mapping { entries, flow }
+        } else {
+            NodeKind::Sequence { entries, flow }
+        };
+        let node = Node { span, kind };
+        if anchor != 0 {
+            self.anchors
+                .insert(anchor, (node_value(&node), path.to_string()));
+        }
+        Ok(node)
+    }
+
+    /// Block scalars are reported from their first content line through the
+    /// indentation that follows; take the `|` / `>` header through the last
+    /// content character instead.
+    fn scalar_span(&self, span: Range<usize>, style: ScalarStyle) -> Range<usize> {
+        if !matches!(style, ScalarStyle::Literal | ScalarStyle::Folded) {
+            return span;
+        }
+        let before = self.text[..span.start].trim_end();
+        let header_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let header = &before[header_start..];
+        let header = header.find(" #").map_or(header, |i| &header[..i]);
+        let start = header
+            .rfind(['|', '>'])
+            .map_or(span.start, |i| header_start + i);
+        let end = span.start + self.text[span].trim_end().len();
+        start..end.max(start)
+    }
+}
+
+/// The `-` of the block sequence item whose value starts at `pos`, skipping
+/// any anchor or tag in between.
+fn dash_before(text: &str, pos: usize) -> usize {
+    let mut before = text[..pos].trim_end();
+    loop {
+        if before.ends_with('-') {
+            return before.len() - 1;
+        }
+        let token_start = before.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        if !before[token_start..].starts_with(['&', '!']) || token_start == 0 {
+            return pos;
+        }
+        before = before[..token_start].trim_end();
+    }
+}
+
+fn child_entry<'n>(node: &'n Node, seg: &PathSegment, i: usize) -> Result<&'n Entry, JsonError> {
+    match (seg, &node.kind) {
+        (PathSegment::Key(key), NodeKind::Mapping { entries, .. }) => entries
+            .iter()
+            .rev()
+            .find(|e| e.name() == Some(key))
+            .ok_or_else(|| JsonError::Other(format!("Key not found: {}", key))),
+        (PathSegment::Key(_), _) => {
+            Err(format!("Expected object at segment {} but got non-object", i).into())
+        }
+        (PathSegment::Index(idx), NodeKind::Sequence { entries, .. }) => entries
+            .get(*idx)
+            .ok_or_else(|| JsonError::Other(format!("Array index {} out of bounds", idx))),
+        (PathSegment::Index(_), _) => {
+            Err(format!("Expected array at segment {} but got non-array", i).into())
+        }
+    }
+}
+
+/// `segments` appended to `base`.
+fn join_path(base: &str, segments: &[PathSegment]) -> String {
+    segments
+        .iter()
+        .fold(base.to_string(), |path, seg| match seg {
+            PathSegment::Key(key) => append_key_path(&path, key),
+            PathSegment::Index(idx) => format!("{}[{}]", path, idx),
+        })
+}
+
+/// The mappings a `<<` value merges in, with the paths they are anchored
+/// at. Empty unless the value is an alias of a mapping, or a sequence of
+/// them.
+fn merge_sources(value: &Node) -> Vec<(&Map<String, Value>, &str)> {
+    fn alias(node: &Node) -> Option<(&Map<String, Value>, &str)> {
+        match &node.kind {
+            NodeKind::Alias {
+                value: Value::Object(map),
+                target,
+            } => Some((map, target.as_str())),
+            _ => None,
+        }
+    }
+    match &value.kind {
+        NodeKind::Sequence { entries, .. } => entries
+            .iter()
+            .map(|e| alias(&e.value))
+            .collect::<Option<Vec<_>>>()
+            .unwrap_or_default(),
+        _ => alias(value).into_iter().collect(),
+    }
+}
+
+/// The keys merged into a mapping by its `<<` entries. The first mapping to
+/// have a key provides it.
+fn merged_values(entries: &[Entry]) -> Map<String, Value> {
+    let mut merged = Map::new();
+    for entry in entries.iter().filter(|e| e.merge) {
+        for (map, _) in merge_sources(&entry.value) {
+            for (key, value) in map {
+                merged.entry(key.clone()).or_insert_with(|| value.clone());
+            }
+        }
+    }
+    merged
+}
+
+/// Where a key merged into a mapping by `<<` is anchored.
+fn merge_target(entries: &[Entry], key: &str) -> Option<String> {
+    entries
+        .iter()
+        .filter(|e| e.merge)
+        .flat_map(|e| merge_sources(&e.value))
+        .find(|(map, _)| map.contains_key(key))
+        .map(|(_, target)| append_key_path(target, key))
+}
+
+fn node_value(node: &Node) -> Value {
+    match &node.kind {
+        NodeKind::Scalar(value, _) | NodeKind::Alias { value, .. } => value.clone(),
+        NodeKind::Mapping { entries, .. } => {
+            // A mapping's own keys override merged ones.
+            let mut map = merged_values(entries);
+            for entry in entries {
+                if let Some(name) = entry.name() {
+                    map.insert(name.to_string(), node_value(&entry.value));
+                }
+            }
+            Value::Object(map)
+        }
+        NodeKind::Sequence { entries, .. } => {
+            Value::Array(entries.iter().map(|e| node_value(&e.value)).collect())
+        }
+    }
+}
+
+/// Resolve a scalar with the YAML 1.2 core schema. Quoted and block
+/// scalars, and anything tagged `!!str`, are strings.
+fn scalar_value(raw: &str, style: ScalarStyle, tag: Option<&Tag>) -> Value {
+    let is_str = tag.is_some_and(|t| t.is_yaml_core_schema() && t.suffix == "str");
+    if style != ScalarStyle::Plain || is_str {
+        return Value::String(raw.to_string());
+    }
+    plain_value(raw)
+}
+
+fn plain_value(raw: &str) -> Value {
+    match raw {
+        "" | "~" | "null" | "Null" | "NULL" => return Value::Null,
+        "true" | "True" | "TRUE" => return Value::Bool(true),
+        "false" | "False" | "FALSE" => return Value::Bool(false),
+        _ => {}
+    }
+    let unsigned = raw.strip_prefix(['-', '+']).unwrap_or(raw);
+    if matches!(
+        unsigned,
+        ".inf" | ".Inf" | ".INF" | ".nan" | ".NaN" | ".NAN"
+    ) {
+        return Value::Null;
+    }
+    if !unsigned.is_empty() && unsigned.bytes().all(|b| b.is_ascii_digit()) {
+        let digits = raw.strip_prefix('+').unwrap_or(raw);
+        if let Ok(n) = digits.parse::<i64>() {
+            return Value::Number(n.into());
+        }
+        if let Ok(n) = digits.parse::<u64>() {
+            return Value::Number(n.into());
+        }
+    }
+    let radix = |prefix: &str, radix: u32| {
+        raw.strip_prefix(prefix)
+            .and_then(|digits| u64::from_str_radix(digits, radix).ok())
+    };
+    if let Some(n) = radix("0x", 16).or_else(|| radix("0o", 8)) {
+        return Value::Number(n.into());
+    }
+    let is_float = unsigned.starts_with(|c: char| c.is_ascii_digit() || c == '.')
+        && unsigned.bytes().any(|b| b.is_ascii_digit())
+        && unsigned
+            .bytes()
+            .all(|b| b.is_ascii_digit() || matches!(b, b'.' | b'e' | b'E' | b'-' | b'+'));
+    if is_float {
+        if let Some(n) = raw.parse::<f64>().ok().and_then(Number::from_f64) {
+            return Value::Number(n);
+        }
+    }
+    Value::String(raw.to_string())
+}
+
+/// A non-empty mapping or sequence, written in block style.
+fn is_block_value(value: &Value) -> bool {
+    match value {
+        Value::Object(map) => !map.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        _ => false,
+    }
+}
+
+/// `s` as a plain scalar where YAML reads it back as the same string,
+/// otherwise double-quoted. Strings that YAML 1.1 readers take for booleans
+/// (`yes`, `off`) or base-60 numbers (`8080:80`) are quoted too.
+fn scalar_text(s: &str, flow: bool) -> String {
+    let yaml11 = matches!(
+        s.to_ascii_lowercase().as_str(),
+        "y" | "yes" | "n" | "no" | "on" | "off"
+    ) || s.bytes().all(|b| b.is_ascii_digit() || b == b':');
+    let indicator = s
+        .starts_with(|c: char| "-?:,[]{}#&*!|>'\"%@`".contains(c) || c.is_whitespace())
+        || s.ends_with(|c: char| c.is_whitespace() || c == ':')
+        || s.contains(": ")
+        || s.contains(" #")
+        || (flow && s.contains([',', '[', ']', '{', '}']));
+    let plain = !s.is_empty()
+        && !indicator
+        && !yaml11
+        && !s.chars().any(char::is_control)
+        && matches!(plain_value(s), Value::String(_));
+    if plain {
+        s.to_string()
+    } else {
+        Value::String(s.to_string()).to_string()
+    }
+}
+
+/// Render `value` on one line: a scalar, or a flow collection.
+fn inline(value: &Value, flow: bool) -> String {
+    match value {
+        Value::String(s) => scalar_text(s, flow),
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(|v| inline(v, true)).collect();
+            format!("[{}]", items.join(", "))
+        }
+        Value::Object(map) => {
+            let members: Vec<String> = map
+                .iter()
+                .map(|(k, v)| format!("{}: {}", scalar_text(k, true), inline(v, true)))
+                .collect();
+            format!("{{{}}}", members.join(", "))
+        }
+        _ => value.to_string(),
+    }
+}
+
+fn write_block(out: &mut String, value: &Value, indent: &str, unit: &str, newline: &str) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (i, (key, child)) in map.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(newline);
+                    out.push_str(indent);
+                }
+                out.push_str(&scalar_text(key, false));
+                out.push(':');
+                if is_block_value(child) {
+                    let inner = format!("{}{}", indent, unit);
+                    out.push_str(newline);
+                    out.push_str(&inner);
+                    write_block(out, child, &inner, unit, newline);
+                } else {
+                    out.push(' ');
+                    out.push_str(&inline(child, false));
+                }
+            }
+        }
+        Value::Array(items) if !items.is_empty() => {
+            for (i, child) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(newline);
+                    out.push_str(indent);
+                }
+                out.push_str("- ");
+                if is_block_value(child) {
+                    write_block(out, child, &format!("{}  ", indent), unit, newline);
+                } else {
+                    out.push_str(&inline(child, false));
+                }
+            }
+        }
+        _ => out.push_str(&inline(value, false)),
+    }
+}
+
+/// Write `node` in block style with an anchor comment before it and
+/// before each of its entries.
+fn write_anchored(out: &mut String, node: &Node, path: &str, options: &HashOptions) {
+    let value = node_value(node);
+    out.push_str(&format!(
+        "# {}\n",
+        compute_json_anchor_with(path, &value, options)
+    ));
+    if is_block_value(&value) {
+        write_anchored_entries(out, node, path, "", options);
+    } else {
+        out.push_str(&inline(&value, false));
+        out.push('\n');
+    }
+}
+
+/// Write the entries of `node` with their anchors. Values held by an alias
+/// or merged in with `<<` get an anchor, so they can be replaced, but none
+/// inside: those are edited where they are anchored.
+fn write_anchored_entries(
+    out: &mut String,
+    node: &Node,
+    path: &str,
+    indent: &str,
+    options: &HashOptions,
+) {
+    let children: Vec<(String, String, Value, Option<&Node>)> = match &node.kind {
+        NodeKind::Mapping { entries, .. } => {
+            let mut children: BTreeMap<String, (Value, Option<&Node>)> = merged_values(entries)
+                .into_iter()
+                .map(|(k, v)| (k, (v, None)))
+                .collect();
+            for entry in entries {
+                if let Some(name) = entry.name() {
+                    children.insert(
+                        name.to_string(),
+                        (node_value(&entry.value), Some(&entry.value)),
+                    );
+                }
+            }
+            children
+                .into_iter()
+                .map(|(k, (v, child))| {
+                    let prefix = format!("{}:", scalar_text(&k, false));
+                    (append_key_path(path, &k), prefix, v, child)
+                })
+                .collect()
+        }
+        NodeKind::Sequence { entries, .. } => entries
+            .iter()
+            .enumerate()
+            .map(|(i, e)| {
+                (
+                    format!("{}[{}]", path, i),
+                    "-".to_string(),
+                    node_value(&e.value),
+                    Some(&e.value),
+                )
+            })
+            .collect(),
+        _ => return,
+    };
+    for (child_path, prefix, value, child) in children {
+        out.push_str(&format!(
+            "{}# {}\n",
+            indent,
+            compute_json_anchor_with(&child_path, &value, options)
+        ));
+        if !is_block_value(&value) {
+            out.push_str(&format!("{}{} {}\n", indent, prefix, inline(&value, false)));
+            continue;
+        }
+        out.push_str(&format!("{}{}\n", indent, prefix));
+        let inner = format!("{}  ", indent);
+        match child.filter(|n| n.entries().is_some()) {
+            Some(child) => write_anchored_entries(out, child, &child_path, &inner, options),
+            None => {
+                out.push_str(&inner);
+                write_block(out, &value, &inner, "  ", "\n");
+                out.push('\n');
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const WORKFLOW: &str = "# CI workflow\nname: CI   # shown in the UI\non: [push, pull_request]\nenv: &env\n  RUST_LOG: \"debug\"\n  MODE: 'fast'\njobs:\n  build:\n    runs-on: ubuntu-latest\n    env: *env\n    steps:\n      - uses: actions/checkout@v4\n      # build and test\n      - run: |\n          cargo build\n          cargo test\n      - name: lint\n        with: {level: 2, fix: false}\n";
+
+    fn doc(text: &str) -> YamlDocument {
+        YamlDocument::parse(text).unwrap()
+    }
+
+    #[test]
+    fn parse_resolves_core_schema_and_aliases() {
+        assert_eq!(
+            doc(WORKFLOW).value(),
+            json!({
+                "name": "CI",
+                "on": ["push", "pull_request"],
+                "env": {"RUST_LOG": "debug", "MODE": "fast"},
+                "jobs": {"build": {
+                    "runs-on": "ubuntu-latest",
+                    "env": {"RUST_LOG": "debug", "MODE": "fast"},
+                    "steps": [
+                        {"uses": "actions/checkout@v4"},
+                        {"run": "cargo build\ncargo test\n"},
+                        {"name": "lint", "with": {"level": 2, "fix": false}}
+                    ]
+                }}
+            })
+        );
+        assert_eq!(
+            doc("a: ~\nb: 0x1F\nc: -1.5e3\nd: .inf\ne: !!str 12\nf: '12'\ng: 1.2.0\nh: True\n")
+                .value(),
+            json!({"a": null, "b": 31, "c": -1500.0, "d": null, "e": "12", "f": "12",
+                   "g": "1.2.0", "h": true})
+        );
+    }
+
+    #[test]
+    fn parse_reports_invalid_yaml() {
+        let err = YamlDocument::parse("a: [1, 2\nb: 3\n").unwrap_err();
+        assert!(err.to_string().starts_with("Invalid YAML"), "{}", err);
+    }
+
+    #[test]
+    fn set_scalar_keeps_comments_and_quoting() {
+        let mut d = doc(WORKFLOW);
+        d.set_path("$.name", &json!("Build")).unwrap();
+        d.set_path("$.env.RUST_LOG", &json!("info")).unwrap();
+        d.set_path("$.env.MODE", &json!("it's slow")).unwrap();
+        let expected = WORKFLOW
+            .replace("name: CI ", "name: Build ")
+            .replace("\"debug\"", "\"info\"")
+            .replace("'fast'", "'it''s slow'");
+        assert_eq!(d.text(), expected);
+    }
+
+    #[test]
+    fn set_literal_block_stays_literal() {
+        let mut d = doc(WORKFLOW);
+        d.set_path("$.jobs.build.steps[1].run", &json!("cargo test --all"))
+            .unwrap();
+        assert!(d
+            .text()
+            .contains("      - run: |-\n          cargo test --all\n      - name"));
+        let mut d = doc(WORKFLOW);
+        d.set_path("$.jobs.build.steps[1].run", &json!("a\nb\n"))
+            .unwrap();
+        assert!(d
+            .text()
+            .contains("      - run: |\n          a\n          b\n      - name"));
+    }
+
+    #[test]
+    fn set_flow_collection_stays_flow() {
+        let mut d = doc(WORKFLOW);
+        d.set_path("$.on", &json!(["push", "tag: v*"])).unwrap();
+        d.set_path("$.jobs.build.steps[2].with.level", &json!(3))
+            .unwrap();
+        assert!(
+            d.text().contains("on: [push, \"tag: v*\"]\n"),
+            "{}",
+            d.text()
+        );
+        assert!(d.text().contains("with: {level: 3, fix: false}"));
+    }
+
+    #[test]
+    fn set_switches_between_scalar_and_block() {
+        let mut d = doc("a: 1\nb:\n  c: 2\n  d: 3\n");
+        d.set_path("$.a", &json!({"x": [1, 2]})).unwrap();
+        assert_eq!(d.text(), "a:\n  x:\n    - 1\n    - 2\nb:\n  c: 2\n  d: 3\n");
+        d.set_path("$.b", &json!("flat")).unwrap();
+        assert_eq!(d.text(), "a:\n  x:\n    - 1\n    - 2\nb: flat\n");
+    }
+
+    #[test]
+    fn anchored_value_keeps_its_anchor() {
+        let mut d = doc("base: &b\n  x: 1\nuse: *b\n");
+        d.set_path("$.base", &json!({"k": 2})).unwrap();
+        assert_eq!(d.text(), "base: &b\n  k: 2\nuse: *b\n");
+        assert_eq!(d.value()["use"], json!({"k": 2}));
+    }
+
+    #[test]
+    fn merge_keys_take_the_first_source_and_yield_to_own_keys() {
+        let d =
+            doc("a: &a {x: 1, y: 1}\nb: &b {y: 2, z: 2}\nc:\n  <<: [*a, *b]\n  z: 3\nd: {<<: 5}\n");
+        assert_eq!(d.value()["c"], json!({"x": 1, "y": 1, "z": 3}));
+        // Only aliases of mappings merge; anything else is a plain `<<` key.
+        assert_eq!(d.value()["d"], json!({"<<": 5}));
+        let err = d.clone().delete_path("$.c.y").unwrap_err();
+        assert!(err.to_string().contains("edit $.a.y instead"), "{}", err);
+    }
+
+    #[test]
+    fn insert_into_block_collections() {
+        let mut d = doc(WORKFLOW);
+        d.insert_at_path("$.env", Some("CI"), None, &json!(true))
+            .unwrap();
+        d.insert_at_path(
+            "$.jobs.build.steps",
+            None,
+            Some(1),
+            &json!({"uses": "dtolnay/rust-toolchain@stable"}),
+        )
+        .unwrap();
+        d.insert_at_path(
+            "$.jobs.build.steps",
+            None,
+            None,
+            &json!({"run": "echo done"}),
+        )
+        .unwrap();
+        let text = d.text();
+        assert!(
+            text.contains("  MODE: 'fast'\n  CI: true\njobs:"),
+            "{}",
+            text
+        );
+        assert!(
+            text.contains(
+                "      - uses: actions/checkout@v4\n      - uses: dtolnay/rust-toolchain@stable\n      # build and test\n      - run: |"
+            ),
+            "{}",
+            text
+        );
+        assert!(
+            text.ends_with("fix: false}\n      - run: echo done\n"),
+            "{}",
+            text
+        );
+    }
+
+    #[test]
+    fn insert_into_flow_collections() {
+        let mut d = doc(WORKFLOW);
+        d.insert_at_path("$.on", None, Some(0), &json!("schedule"))
+            .unwrap();
+        d.insert_at_path("$.on", None, None, &json!("workflow_dispatch"))
+            .unwrap();
+        d.insert_at_path(
+            "$.jobs.build.steps[2].with",
+            Some("out"),
+            None,
+            &json!("a, b"),
+        )
+        .unwrap();
+        assert!(d
+            .text()
+            .contains("on: [schedule, push, pull_request, workflow_dispatch]"));
+        assert!(d
+            .text()
+            .contains("with: {level: 2, fix: false, out: \"a, b\"}"));
+        let mut d = doc("a: {}\nb: []\n");
+        d.insert_at_path("$.a", Some("k"), None, &json!(1)).unwrap();
+        d.insert_at_path("$.b", None, None, &json!("x")).unwrap();
+        assert_eq!(d.text(), "a: {k: 1}\nb: [x]\n");
+    }
+
+    #[test]
+    fn delete_takes_attached_comments() {
+        let mut d = doc(WORKFLOW);
+        d.delete_path("$.jobs.build.steps[1]").unwrap();
+        d.delete_path("$.on").unwrap();
+        assert_eq!(
+            d.text(),
+            "# CI workflow\nname: CI   # shown in the UI\nenv: &env\n  RUST_LOG: \"debug\"\n  MODE: 'fast'\njobs:\n  build:\n    runs-on: ubuntu-latest\n    env: *env\n    steps:\n      - uses: actions/checkout@v4\n      - name: lint\n        with: {level: 2, fix: false}\n"
+        );
+    }
+
+    #[test]
+    fn delete_keeps_file_header_comment() {
+        let mut d = doc(WORKFLOW);
+        d.delete_path("$.name").unwrap();
+        assert!(d.text().starts_with("# CI workflow\non: ["), "{}", d.text());
+    }
+
+    #[test]
+    fn delete_compact_and_only_entries() {
+        let mut d = doc(WORKFLOW);
+        d.delete_path("$.jobs.build.steps[2].name").unwrap();
+        d.delete_path("$.jobs.build.steps[2].with.fix").unwrap();
+        assert!(
+            d.text().ends_with("      - with: {level: 2}\n"),
+            "{}",
+            d.text()
+        );
+        let mut d = doc("a:\n  only: 1\nb: [x]\n");
+        d.delete_path("$.a.only").unwrap();
+        d.delete_path("$.b[0]").unwrap();
+        assert_eq!(d.text(), "a: {}\nb: []\n");
+    }
+
+    #[test]
+    fn streams_address_documents_by_index() {
+        let mut d = doc("kind: A\n---\nkind: B\nspec:\n  n: 1\n");
+        assert_eq!(
+            d.value(),
+            json!([{"kind": "A"}, {"kind": "B", "spec": {"n": 1}}])
+        );
+        d.set_path("$[1].spec.n", &json!(2)).unwrap();
+        assert_eq!(d.text(), "kind: A\n---\nkind: B\nspec:\n  n: 2\n");
+        assert!(d.set_path("$.kind", &json!("C")).is_err());
+    }
+
+    #[test]
+    fn format_anchors_comments_every_entry() {
+        let out = format_yaml_anchors(&doc("b: [1, {c: x}]\na: 'v'\n"));
+        let anchor =
+            |path: &str, v: Value| compute_json_anchor_with(path, &v, &HashOptions::default());
+        let expected = format!(
+            "# {}\n# {}\na: v\n# {}\nb:\n  # {}\n  - 1\n  # {}\n  -\n    # {}\n    c: x",
+            anchor("$", json!({"a": "v", "b": [1, {"c": "x"}]})),
+            anchor("$.a", json!("v")),
+            anchor("$.b", json!([1, {"c": "x"}])),
+            anchor("$.b[0]", json!(1)),
+            anchor("$.b[1]", json!({"c": "x"})),
+            anchor("$.b[1].c", json!("x")),
+        );
+        assert_eq!(out, expected);
+        // The anchored view is itself YAML with the same data.
+        assert_eq!(doc(&out).value(), json!({"a": "v", "b": [1, {"c": "x"}]}));
+    }
+}
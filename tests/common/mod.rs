@@ -0,0 +1,19 @@
+//! Helpers shared by the integration test crates. Each crate uses a subset.
+#![allow(dead_code)]
+
+use std::fs;
+use tempfile::NamedTempFile;
+
+/// Write an apply payload to a temp file for `--input`.
+pub fn write_payload(payload: &serde_json::Value) -> NamedTempFile {
+    let payload_file = NamedTempFile::new().unwrap();
+    fs::write(payload_file.path(), serde_json::to_string(payload).unwrap()).unwrap();
+    payload_file
+}
+
+/// Copy a fixture into a temp file the CLI can rewrite.
+pub fn copy_fixture(path: &str) -> NamedTempFile {
+    let tmp = NamedTempFile::new().unwrap();
+    fs::copy(path, tmp.path()).unwrap();
+    tmp
+}
@@ -0,0 +1,64 @@
+/// Memory bound for line diffs on large files.
+///
+/// Counts live heap bytes with a wrapping global allocator, so this file
+/// holds a single test: parallel tests would share the counters.
+use hashline::diff::{diff_hunks, diff_ops, DiffOp};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+struct Counting;
+
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let live = LIVE.fetch_add(layout.size(), Ordering::SeqCst) + layout.size();
+            PEAK.fetch_max(live, Ordering::SeqCst);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        LIVE.fetch_sub(layout.size(), Ordering::SeqCst);
+    }
+}
+
+#[global_allocator]
+static ALLOC: Counting = Counting;
+
+#[test]
+fn scattered_diff_of_large_file_uses_linear_memory() {
+    // 2,000 changed lines spread over 20,000: an edit script of 4,000 steps.
+    let old: String = (0..20_000).map(|i| format!("line {}\n", i)).collect();
+    let new: String = (0..20_000)
+        .map(|i| {
+            if i % 10 == 0 {
+                format!("changed {}\n", i)
+            } else {
+                format!("line {}\n", i)
+            }
+        })
+        .collect();
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+
+    let baseline = LIVE.load(Ordering::SeqCst);
+    PEAK.store(baseline, Ordering::SeqCst);
+    let ops = diff_ops(&old_lines, &new_lines);
+    let hunks = diff_hunks(&old, &new, 3);
+    let peak = PEAK.load(Ordering::SeqCst) - baseline;
+
+    let changed = ops
+        .iter()
+        .filter(|op| !matches!(op, DiffOp::Equal(..)))
+        .count();
+    assert_eq!(changed, 4_000);
+    assert_eq!(hunks.len(), 2_000);
+    // The edit script, hunk text and O(N + M) scratch rows: a few MB. A
+    // per-step trace of the search rows would need over 2 GB here.
+    assert!(peak < 32 << 20, "diff peaked at {} bytes", peak);
+}
@@ -154,6 +154,7 @@ proptest! {
             set_line: hashline::edit::SetLineOp {
                 anchor,
                 new_text,
+                verbatim: false,
             },
         }];
         // Must not panic regardless of whether it succeeds or fails
@@ -176,6 +177,7 @@ proptest! {
             set_line: hashline::edit::SetLineOp {
                 anchor,
                 new_text: new_text.clone(),
+                verbatim: false,
             },
         }];
         let result = apply_hashline_edits(&content, &edits);
@@ -183,3 +185,50 @@ proptest! {
         prop_assert!(result.is_ok(), "edit failed: {:?}", result);
     }
 }
+
+// ═══════════════════════════════════════════════════════════════════════════
+// diff_ops — a valid edit script of minimal length
+// ═══════════════════════════════════════════════════════════════════════════
+
+fn lcs_len(a: &[u8], b: &[u8]) -> usize {
+    let mut row = vec![0usize; b.len() + 1];
+    for x in a {
+        let mut diag = 0;
+        for (j, y) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if x == y { diag + 1 } else { above.max(row[j]) };
+            diag = above;
+        }
+    }
+    row[b.len()]
+}
+
+proptest! {
+    #[test]
+    fn fuzz_diff_ops_rebuilds_new_with_minimal_edits(
+        old in prop::collection::vec(0u8..4, 0..40),
+        new in prop::collection::vec(0u8..4, 0..40)
+    ) {
+        use hashline::diff::{diff_ops, DiffOp};
+        let ops = diff_ops(&old, &new);
+        let (mut next_old, mut rebuilt, mut equal) = (0, Vec::new(), 0);
+        for op in &ops {
+            match *op {
+                DiffOp::Equal(x, y) => {
+                    prop_assert_eq!((x, old[x]), (next_old, new[y]));
+                    next_old += 1;
+                    rebuilt.push(old[x]);
+                    equal += 1;
+                }
+                DiffOp::Delete(x) => {
+                    prop_assert_eq!(x, next_old);
+                    next_old += 1;
+                }
+                DiffOp::Insert(y) => rebuilt.push(new[y]),
+            }
+        }
+        prop_assert_eq!(next_old, old.len());
+        prop_assert_eq!(rebuilt, new.clone());
+        prop_assert_eq!(equal, lcs_len(&old, &new));
+    }
+}
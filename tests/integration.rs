@@ -197,6 +197,7 @@ fn edit_replace_single_line() {
         set_line: hashline::edit::SetLineOp {
             anchor: make_ref(2, "bbb"),
             new_text: "BBB".into(),
+            verbatim: false,
         },
     }];
     let result = apply_hashline_edits(content, &edits).unwrap();
@@ -212,6 +213,7 @@ fn edit_range_replace_shrink() {
             start_anchor: make_ref(2, "bbb"),
             end_anchor: Some(make_ref(3, "ccc")),
             new_text: Some("ONE".into()),
+            verbatim: false,
         },
     }];
     let result = apply_hashline_edits(content, &edits).unwrap();
@@ -226,6 +228,7 @@ fn edit_range_replace_same_count() {
             start_anchor: make_ref(2, "bbb"),
             end_anchor: Some(make_ref(3, "ccc")),
             new_text: Some("XXX\nYYY".into()),
+            verbatim: false,
         },
     }];
     let result = apply_hashline_edits(content, &edits).unwrap();
@@ -240,6 +243,7 @@ fn edit_replace_first_line() {
         set_line: hashline::edit::SetLineOp {
             anchor: make_ref(1, "first"),
             new_text: "FIRST".into(),
+            verbatim: false,
         },
     }];
     let result = apply_hashline_edits(content, &edits).unwrap();
@@ -254,6 +258,7 @@ fn edit_replace_last_line() {
         set_line: hashline::edit::SetLineOp {
             anchor: make_ref(3, "third"),
             new_text: "THIRD".into(),
+            verbatim: false,
         },
     }];
     let result = apply_hashline_edits(content, &edits).unwrap();
@@ -271,6 +276,7 @@ fn edit_delete_single_line() {
         set_line: hashline::edit::SetLineOp {
             anchor: make_ref(2, "bbb"),
             new_text: "".into(),
+            verbatim: false,
         },
     }];
     let result = apply_hashline_edits(content, &edits).unwrap();
@@ -286,6 +292,7 @@ fn edit_delete_range() {
             start_anchor: make_ref(2, "bbb"),
             end_anchor: Some(make_ref(3, "ccc")),
             new_text: Some("".into()),
+            verbatim: false,
         },
     }];
     let result = apply_hashline_edits(content, &edits).unwrap();
@@ -299,6 +306,7 @@ fn edit_delete_first_line() {
         set_line: hashline::edit::SetLineOp {
             anchor: make_ref(1, "aaa"),
             new_text: "".into(),
+            verbatim: false,
         },
     }];
     let result = apply_hashline_edits(content, &edits).unwrap();
@@ -312,12 +320,56 @@ fn edit_delete_last_line() {
         set_line: hashline::edit::SetLineOp {
             anchor: make_ref(3, "ccc"),
             new_text: "".into(),
+            verbatim: false,
         },
     }];
     let result = apply_hashline_edits(content, &edits).unwrap();
     assert_eq!(result.content, "aaa\nbbb");
 }
 
+#[test]
+fn edit_delete_lines_range() {
+    let content = "aaa\nbbb\nccc\nddd";
+    let edits = vec![HashlineEdit::DeleteLines {
+        delete_lines: hashline::edit::DeleteLinesOp {
+            start_anchor: make_ref(2, "bbb"),
+            end_anchor: Some(make_ref(3, "ccc")),
+            verbatim: false,
+        },
+    }];
+    let result = apply_hashline_edits(content, &edits).unwrap();
+    assert_eq!(result.content, "aaa\nddd");
+    assert_eq!(result.first_changed_line, Some(2));
+}
+
+#[test]
+fn edit_delete_lines_single_anchor() {
+    let content = "aaa\nbbb\nccc";
+    let edits = vec![HashlineEdit::DeleteLines {
+        delete_lines: hashline::edit::DeleteLinesOp {
+            start_anchor: make_ref(3, "ccc"),
+            end_anchor: None,
+            verbatim: false,
+        },
+    }];
+    let result = apply_hashline_edits(content, &edits).unwrap();
+    assert_eq!(result.content, "aaa\nbbb");
+}
+
+#[test]
+fn edit_delete_lines_validates_anchors() {
+    let content = "aaa\nbbb\nccc";
+    let edits = vec![HashlineEdit::DeleteLines {
+        delete_lines: hashline::edit::DeleteLinesOp {
+            start_anchor: make_ref(1, "aaa"),
+            end_anchor: Some("2:zz".into()),
+            verbatim: false,
+        },
+    }];
+    let err = apply_hashline_edits(content, &edits).unwrap_err();
+    assert!(err.downcast_ref::<HashlineMismatchError>().is_some());
+}
+
 // ═══════════════════════════════════════════════════════════════════════════
 // applyHashlineEdits — insert
 // ═══════════════════════════════════════════════════════════════════════════
@@ -330,6 +382,7 @@ fn edit_insert_after_line() {
             anchor: make_ref(1, "aaa"),
             text: Some("NEW".into()),
             content: None,
+            verbatim: false,
         },
     }];
     let result = apply_hashline_edits(content, &edits).unwrap();
@@ -345,6 +398,7 @@ fn edit_insert_multiple_lines() {
             anchor: make_ref(1, "aaa"),
             text: Some("x\ny\nz".into()),
             content: None,
+            verbatim: false,
         },
     }];
     let result = apply_hashline_edits(content, &edits).unwrap();
@@ -359,6 +413,7 @@ fn edit_insert_after_last_line() {
             anchor: make_ref(2, "bbb"),
             text: Some("NEW".into()),
             content: None,
+            verbatim: false,
         },
     }];
     let result = apply_hashline_edits(content, &edits).unwrap();
@@ -373,12 +428,466 @@ fn edit_insert_empty_dst_inserts_blank_line() {
             anchor: make_ref(1, "aaa"),
             text: Some("".into()),
             content: None,
+            verbatim: false,
         },
     }];
     let result = apply_hashline_edits(content, &edits).unwrap();
     assert_eq!(result.content, "aaa\n\nbbb");
 }
 
+#[test]
+fn edit_insert_before_line() {
+    let content = "aaa\nbbb\nccc";
+    let edits = vec![HashlineEdit::InsertBefore {
+        insert_before: hashline::edit::InsertBeforeOp {
+            anchor: make_ref(2, "bbb"),
+            text: Some("NEW".into()),
+            verbatim: false,
+        },
+    }];
+    let result = apply_hashline_edits(content, &edits).unwrap();
+    assert_eq!(result.content, "aaa\nNEW\nbbb\nccc");
+    assert_eq!(result.first_changed_line, Some(2));
+}
+
+#[test]
+fn edit_insert_before_strips_anchor_echo() {
+    let content = "aaa\nbbb";
+    let edits = vec![HashlineEdit::InsertBefore {
+        insert_before: hashline::edit::InsertBeforeOp {
+            anchor: make_ref(1, "aaa"),
+            text: Some("NEW\naaa".into()),
+            verbatim: false,
+        },
+    }];
+    let result = apply_hashline_edits(content, &edits).unwrap();
+    assert_eq!(result.content, "NEW\naaa\nbbb");
+}
+
+#[test]
+fn edit_insert_before_lands_above_replaced_line() {
+    let content = "aaa\nbbb\nccc";
+    let edits = vec![
+        HashlineEdit::InsertBefore {
+            insert_before: hashline::edit::InsertBeforeOp {
+                anchor: make_ref(2, "bbb"),
+                text: Some("above".into()),
+                verbatim: false,
+            },
+        },
+        HashlineEdit::SetLine {
+            set_line: hashline::edit::SetLineOp {
+                anchor: make_ref(2, "bbb"),
+                new_text: "B1\nB2".into(),
+                verbatim: false,
+            },
+        },
+        HashlineEdit::InsertAfter {
+            insert_after: hashline::edit::InsertAfterOp {
+                anchor: make_ref(1, "aaa"),
+                text: Some("below a".into()),
+                content: None,
+                verbatim: false,
+            },
+        },
+    ];
+    let result = apply_hashline_edits(content, &edits).unwrap();
+    assert_eq!(result.content, "aaa\nbelow a\nabove\nB1\nB2\nccc");
+}
+
+#[test]
+fn edit_insert_at_start_and_append_to_end() {
+    let content = "aaa\nbbb";
+    let edits = vec![
+        HashlineEdit::AppendToEnd {
+            append_to_end: hashline::edit::InsertTextOp {
+                text: Some("end 1".into()),
+                verbatim: false,
+            },
+        },
+        HashlineEdit::InsertAtStart {
+            insert_at_start: hashline::edit::InsertTextOp {
+                text: Some("start 1".into()),
+                verbatim: false,
+            },
+        },
+        HashlineEdit::InsertAtStart {
+            insert_at_start: hashline::edit::InsertTextOp {
+                text: Some("start 2".into()),
+                verbatim: false,
+            },
+        },
+        HashlineEdit::AppendToEnd {
+            append_to_end: hashline::edit::InsertTextOp {
+                text: Some("end 2".into()),
+                verbatim: false,
+            },
+        },
+        HashlineEdit::SetLine {
+            set_line: hashline::edit::SetLineOp {
+                anchor: make_ref(2, "bbb"),
+                new_text: "BBB".into(),
+                verbatim: false,
+            },
+        },
+    ];
+    let result = apply_hashline_edits(content, &edits).unwrap();
+    assert_eq!(result.content, "start 1\nstart 2\naaa\nBBB\nend 1\nend 2");
+    assert_eq!(result.first_changed_line, Some(1));
+}
+
+#[test]
+fn edit_insert_into_empty_file() {
+    let edits = vec![
+        HashlineEdit::AppendToEnd {
+            append_to_end: hashline::edit::InsertTextOp {
+                text: Some("body".into()),
+                verbatim: false,
+            },
+        },
+        HashlineEdit::InsertAtStart {
+            insert_at_start: hashline::edit::InsertTextOp {
+                text: Some("header".into()),
+                verbatim: false,
+            },
+        },
+    ];
+    let result = apply_hashline_edits("", &edits).unwrap();
+    assert_eq!(result.content, "header\nbody");
+}
+
+#[test]
+fn edit_single_insert_into_long_file_has_no_reformatting_warning() {
+    let content = (1..=20)
+        .map(|i| format!("line {}", i))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let edits = vec![HashlineEdit::InsertAtStart {
+        insert_at_start: hashline::edit::InsertTextOp {
+            text: Some("// header".into()),
+            verbatim: false,
+        },
+    }];
+    let result = apply_hashline_edits(&content, &edits).unwrap();
+    assert!(result.warnings.is_empty(), "{:?}", result.warnings);
+
+    // Rewriting many lines through one edit still warns.
+    let edits = vec![HashlineEdit::ReplaceLines {
+        replace_lines: hashline::edit::ReplaceLinesOp {
+            start_anchor: make_ref(1, "line 1"),
+            end_anchor: Some(make_ref(6, "line 6")),
+            new_text: Some("a\nb\nc\nd\ne\nf".into()),
+            verbatim: false,
+        },
+    }];
+    let result = apply_hashline_edits(&content, &edits).unwrap();
+    assert!(
+        result.warnings[0].contains("verify no unintended reformatting"),
+        "{:?}",
+        result.warnings
+    );
+}
+
+#[test]
+fn edit_bulk_replace_within_range_has_no_reformatting_warning() {
+    let content = (1..=40)
+        .map(|i| format!("item {}", i))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let edits = vec![HashlineEdit::Replace {
+        replace: hashline::edit::ReplaceOp {
+            old_text: "item".into(),
+            new_text: "entry".into(),
+            all: true,
+            within: Some(hashline::edit::AnchorRange {
+                start_anchor: make_ref(1, "item 1"),
+                end_anchor: Some(make_ref(40, "item 40")),
+            }),
+            ..Default::default()
+        },
+    }];
+    let result = apply_hashline_edits(&content, &edits).unwrap();
+    assert!(result.content.starts_with("entry 1\nentry 2\n"));
+    assert!(result.warnings.is_empty(), "{:?}", result.warnings);
+}
+
+// ═══════════════════════════════════════════════════════════════════════════
+// applyHashlineEdits — move and copy
+// ═══════════════════════════════════════════════════════════════════════════
+
+fn transfer_op(
+    start: (usize, &str),
+    end: Option<(usize, &str)>,
+    after: (usize, &str),
+) -> hashline::edit::TransferLinesOp {
+    hashline::edit::TransferLinesOp {
+        start_anchor: make_ref(start.0, start.1),
+        end_anchor: end.map(|(line, text)| make_ref(line, text)),
+        after_anchor: make_ref(after.0, after.1),
+        verbatim: false,
+    }
+}
+
+#[test]
+fn edit_move_lines_down() {
+    let content = "aaa\nbbb\nccc\nddd\neee";
+    let edits = vec![HashlineEdit::MoveLines {
+        move_lines: transfer_op((1, "aaa"), Some((2, "bbb")), (4, "ddd")),
+    }];
+    let result = apply_hashline_edits(content, &edits).unwrap();
+    assert_eq!(result.content, "ccc\nddd\naaa\nbbb\neee");
+    assert_eq!(result.first_changed_line, Some(1));
+    let spans: Vec<(usize, usize)> = result
+        .edit_spans
+        .iter()
+        .map(|s| (s.start_line, s.end_line))
+        .collect();
+    assert_eq!(spans, [(1, 2), (4, 4)]);
+}
+
+#[test]
+fn edit_move_lines_up() {
+    let content = "aaa\nbbb\nccc\nddd\neee";
+    let edits = vec![HashlineEdit::MoveLines {
+        move_lines: transfer_op((4, "ddd"), None, (1, "aaa")),
+    }];
+    let result = apply_hashline_edits(content, &edits).unwrap();
+    assert_eq!(result.content, "aaa\nddd\nbbb\nccc\neee");
+}
+
+#[test]
+fn edit_copy_lines_keeps_source() {
+    let content = "aaa\nbbb\nccc";
+    let edits = vec![HashlineEdit::CopyLines {
+        copy_lines: transfer_op((1, "aaa"), Some((2, "bbb")), (2, "bbb")),
+    }];
+    let result = apply_hashline_edits(content, &edits).unwrap();
+    assert_eq!(result.content, "aaa\nbbb\naaa\nbbb\nccc");
+    assert_eq!(result.first_changed_line, Some(3));
+}
+
+#[test]
+fn edit_move_lines_with_other_edits() {
+    let content = "aaa\nbbb\nccc\nddd\neee";
+    let edits = vec![
+        HashlineEdit::MoveLines {
+            move_lines: transfer_op((2, "bbb"), None, (4, "ddd")),
+        },
+        HashlineEdit::SetLine {
+            set_line: hashline::edit::SetLineOp {
+                anchor: make_ref(5, "eee"),
+                new_text: "EEE".into(),
+                verbatim: false,
+            },
+        },
+        HashlineEdit::InsertBefore {
+            insert_before: hashline::edit::InsertBeforeOp {
+                anchor: make_ref(1, "aaa"),
+                text: Some("top".into()),
+                verbatim: false,
+            },
+        },
+    ];
+    let result = apply_hashline_edits(content, &edits).unwrap();
+    assert_eq!(result.content, "top\naaa\nccc\nddd\nbbb\nEEE");
+}
+
+#[test]
+fn edit_move_lines_to_same_place_is_noop() {
+    let content = "aaa\nbbb\nccc";
+    let edits = vec![HashlineEdit::MoveLines {
+        move_lines: transfer_op((2, "bbb"), Some((3, "ccc")), (1, "aaa")),
+    }];
+    let result = apply_hashline_edits(content, &edits).unwrap();
+    assert_eq!(result.content, content);
+    assert_eq!(result.outcomes[0].status, EditStatus::Noop);
+    assert_eq!(result.noop_edits.len(), 1);
+}
+
+#[test]
+fn edit_move_lines_rejects_destination_inside_source() {
+    let content = "aaa\nbbb\nccc\nddd";
+    for edit in [
+        HashlineEdit::MoveLines {
+            move_lines: transfer_op((1, "aaa"), Some((3, "ccc")), (2, "bbb")),
+        },
+        HashlineEdit::CopyLines {
+            copy_lines: transfer_op((1, "aaa"), Some((3, "ccc")), (1, "aaa")),
+        },
+    ] {
+        let err = apply_hashline_edits(content, &[edit]).unwrap_err();
+        assert!(
+            err.to_string().contains("inside the source lines 1-3"),
+            "{}",
+            err
+        );
+    }
+}
+
+#[test]
+fn edit_move_lines_validates_after_anchor() {
+    let content = "aaa\nbbb\nccc";
+    let edits = vec![HashlineEdit::MoveLines {
+        move_lines: hashline::edit::TransferLinesOp {
+            start_anchor: make_ref(1, "aaa"),
+            end_anchor: None,
+            after_anchor: "3:zz".into(),
+            verbatim: false,
+        },
+    }];
+    let err = apply_hashline_edits(content, &edits).unwrap_err();
+    let mismatch = err.downcast_ref::<HashlineMismatchError>().unwrap();
+    assert_eq!(mismatch.mismatches[0].line, 3);
+}
+
+// ═══════════════════════════════════════════════════════════════════════════
+// applyHashlineEdits — patch
+// ═══════════════════════════════════════════════════════════════════════════
+
+fn patch_edit(diff: &str) -> HashlineEdit {
+    HashlineEdit::Patch {
+        patch: hashline::edit::PatchOp {
+            diff: diff.into(),
+            verbatim: false,
+        },
+    }
+}
+
+#[test]
+fn edit_patch_applies_every_hunk() {
+    let content = "a\nb\nc\nd\ne\nf\ng\nh";
+    let diff =
+        "--- a/f.txt\n+++ b/f.txt\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n@@ -7,2 +7,3 @@\n g\n+G\n h\n";
+    let result = apply_hashline_edits(content, &[patch_edit(diff)]).unwrap();
+    assert_eq!(result.content, "a\nB\nc\nd\ne\nf\ng\nG\nh");
+    assert_eq!(result.first_changed_line, Some(2));
+    assert_eq!(result.outcomes[0].status, EditStatus::Applied);
+}
+
+#[test]
+fn edit_patch_matches_context_ignoring_whitespace() {
+    let content = "fn main() {\n    run();\n}";
+    let diff = "@@ -1,3 +1,3 @@\n fn main(){\n-  run();\n+    go();\n }\n";
+    let result = apply_hashline_edits(content, &[patch_edit(diff)]).unwrap();
+    assert_eq!(result.content, "fn main() {\n    go();\n}");
+}
+
+#[test]
+fn edit_patch_relocates_moved_hunk() {
+    let content = "new1\nnew2\na\nb\nc";
+    let diff = "@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n";
+    let result = apply_hashline_edits(content, &[patch_edit(diff)]).unwrap();
+    assert_eq!(result.content, "new1\nnew2\na\nB\nc");
+    assert_eq!(result.outcomes[0].relocated, vec![(1, 3)]);
+}
+
+#[test]
+fn edit_patch_inserts_at_start_and_deletes() {
+    let content = "a\nb\nc";
+    let diff = "@@ -0,0 +1 @@\n+top\n@@ -2,2 +3 @@\n b\n-c\n";
+    let result = apply_hashline_edits(content, &[patch_edit(diff)]).unwrap();
+    assert_eq!(result.content, "top\na\nb");
+}
+
+#[test]
+fn edit_patch_stale_context_reports_mismatch_with_other_anchors() {
+    let content = "a\nb\nc\nd\nx\ny";
+    let edits = vec![
+        patch_edit("@@ -1,3 +1,3 @@\n a\n-B\n+b2\n c\n"),
+        HashlineEdit::SetLine {
+            set_line: hashline::edit::SetLineOp {
+                anchor: "6:zz".into(),
+                new_text: "Y".into(),
+                verbatim: false,
+            },
+        },
+    ];
+    let err = apply_hashline_edits(content, &edits).unwrap_err();
+    let mismatch = err.downcast_ref::<HashlineMismatchError>().unwrap();
+    let lines: Vec<usize> = mismatch.mismatches.iter().map(|m| m.line).collect();
+    assert_eq!(lines, vec![2, 6]);
+    assert!(mismatch
+        .to_string()
+        .contains(&format!(">>> {}|b", make_ref(2, "b"))));
+}
+
+#[test]
+fn edit_patch_ambiguous_hunk_is_not_relocated() {
+    let content = "x\na\nb\na\nb";
+    let diff = "@@ -1,2 +1,2 @@\n a\n-b\n+B\n";
+    let err = apply_hashline_edits(content, &[patch_edit(diff)]).unwrap_err();
+    assert!(err.downcast_ref::<HashlineMismatchError>().is_some());
+}
+
+#[test]
+fn edit_patch_verbatim_skips_relocation() {
+    let content = "new\na\nb";
+    let edits = vec![HashlineEdit::Patch {
+        patch: hashline::edit::PatchOp {
+            diff: "@@ -1,2 +1,2 @@\n a\n-b\n+B\n".into(),
+            verbatim: true,
+        },
+    }];
+    let err = apply_hashline_edits(content, &edits).unwrap_err();
+    assert!(err.downcast_ref::<HashlineMismatchError>().is_some());
+}
+
+#[test]
+fn edit_patch_repairs_added_text_with_heuristics() {
+    let content = "fn main() {\n    run();\n}";
+    let diff = "@@ -1,3 +1,3 @@\n fn main() {\n-run();\n+2:ab|go();\n }\n";
+    let result = apply_hashline_edits(content, &[patch_edit(diff)]).unwrap();
+    assert_eq!(result.content, "fn main() {\n    go();\n}");
+    let names: Vec<(Heuristic, Vec<usize>)> = result.outcomes[0]
+        .heuristics
+        .iter()
+        .map(|h| (h.heuristic, h.lines.clone()))
+        .collect();
+    assert_eq!(
+        names,
+        vec![
+            (Heuristic::StripHashlinePrefix, vec![1]),
+            (Heuristic::RestoreIndent, vec![1]),
+        ]
+    );
+
+    let edits = vec![HashlineEdit::Patch {
+        patch: hashline::edit::PatchOp {
+            diff: diff.into(),
+            verbatim: true,
+        },
+    }];
+    let result = apply_hashline_edits(content, &edits).unwrap();
+    assert_eq!(result.content, "fn main() {\n2:ab|go();\n}");
+    assert!(result.outcomes[0].heuristics.is_empty());
+}
+
+/// The hunk's context says where the new lines go, so an added line that
+/// repeats its neighbour is meant.
+#[test]
+fn edit_patch_keeps_added_line_repeating_context() {
+    let content = "}\nfn f() {}";
+    let diff = "@@ -1,2 +1,3 @@\n }\n+}\n fn f() {}\n";
+    let result = apply_hashline_edits(content, &[patch_edit(diff)]).unwrap();
+    assert_eq!(result.content, "}\n}\nfn f() {}");
+    assert!(result.outcomes[0].heuristics.is_empty());
+}
+
+#[test]
+fn edit_patch_rejects_multi_file_diff() {
+    let diff = "--- a/x\n+++ b/x\n@@ -1 +1 @@\n-a\n+b\n--- a/y\n+++ b/y\n@@ -1 +1 @@\n-a\n+b\n";
+    let err = apply_hashline_edits("a", &[patch_edit(diff)]).unwrap_err();
+    assert!(err.to_string().contains("2 files"), "{}", err);
+}
+
+#[test]
+fn edit_patch_json_roundtrip() {
+    let edit: HashlineEdit =
+        serde_json::from_str(r#"{"patch":{"diff":"@@ -1 +1 @@\n-a\n+b\n"}}"#).unwrap();
+    assert_eq!(edit.op_name(), "patch");
+    assert!(!edit.is_verbatim());
+}
+
 // ═══════════════════════════════════════════════════════════════════════════
 // applyHashlineEdits — heuristics
 // ═══════════════════════════════════════════════════════════════════════════
@@ -391,6 +900,7 @@ fn heuristic_strips_insert_anchor_echo() {
             anchor: make_ref(2, "bbb"),
             text: Some("bbb\nNEW".into()),
             content: None,
+            verbatim: false,
         },
     }];
     let result = apply_hashline_edits(content, &edits).unwrap();
@@ -425,6 +935,7 @@ fn heuristic_strips_range_boundary_echo() {
                 ]
                 .join("\n"),
             ),
+            verbatim: false,
         },
     }];
 
@@ -454,6 +965,7 @@ fn heuristic_restores_wrapped_line() {
                 "fourthLongIdentifier;",
             ]
             .join("\n"),
+            verbatim: false,
         },
     }];
     let result = apply_hashline_edits(&content, &edits).unwrap();
@@ -468,6 +980,7 @@ fn heuristic_merge_absorbed_next_line() {
         set_line: hashline::edit::SetLineOp {
             anchor: make_ref(1, "    typeof HOOK === 'undefined' &&"),
             new_text: "typeof HOOK === 'undefined' || typeof HOOK.checkDCE !== 'function'".into(),
+            verbatim: false,
         },
     }];
     let result = apply_hashline_edits(content, &edits).unwrap();
@@ -484,6 +997,7 @@ fn heuristic_merge_absorbed_prev_line() {
         set_line: hashline::edit::SetLineOp {
             anchor: make_ref(2, "    resolveRNStyle || hook.resolveRNStyle;"),
             new_text: "const nativeStyleResolver: ResolveNativeStyle | void = resolveRNStyle ?? hook.resolveRNStyle;".into(),
+            verbatim: false,
         },
     }];
     let result = apply_hashline_edits(content, &edits).unwrap();
@@ -501,6 +1015,7 @@ fn heuristic_polluted_anchor() {
         set_line: hashline::edit::SetLineOp {
             anchor: format!("2:{}export function foo(a, b) {{}}", src_hash),
             new_text: "BBB".into(),
+            verbatim: false,
         },
     }];
     let result = apply_hashline_edits(content, &edits).unwrap();
@@ -516,6 +1031,7 @@ fn heuristic_same_line_range_as_single() {
             start_anchor: good.clone(),
             end_anchor: Some(good),
             new_text: Some("BBB".into()),
+            verbatim: false,
         },
     }];
     let result = apply_hashline_edits(content, &edits).unwrap();
@@ -530,6 +1046,7 @@ fn heuristic_normalize_confusable_hyphens_on_noop() {
         set_line: hashline::edit::SetLineOp {
             anchor: make_ref(2, "devtools\u{2013}unsupported-bridge-protocol"),
             new_text: "devtools\u{2013}unsupported-bridge-protocol".into(),
+            verbatim: false,
         },
     }];
     let result = apply_hashline_edits(content, &edits).unwrap();
@@ -549,6 +1066,7 @@ fn heuristic_strips_hashline_prefixes_from_new_text() {
         set_line: hashline::edit::SetLineOp {
             anchor: make_ref(2, "bbb"),
             new_text,
+            verbatim: false,
         },
     }];
     let result = apply_hashline_edits(content, &edits).unwrap();
@@ -563,6 +1081,7 @@ fn heuristic_strips_diff_plus_prefix_from_new_text() {
         set_line: hashline::edit::SetLineOp {
             anchor: make_ref(2, "bbb"),
             new_text: "+BBB".into(),
+            verbatim: false,
         },
     }];
     let result = apply_hashline_edits(content, &edits).unwrap();
@@ -578,30 +1097,189 @@ fn heuristic_restores_indent_for_paired_replacement() {
             start_anchor: make_ref(1, "    foo();"),
             end_anchor: Some(make_ref(2, "    bar();")),
             new_text: Some("foo();\nbar();".into()), // indent stripped by model
+            verbatim: false,
         },
     }];
     let result = apply_hashline_edits(content, &edits).unwrap();
     assert_eq!(result.content, "    foo();\n    bar();\n    baz();");
 }
 
-// ═══════════════════════════════════════════════════════════════════════════
-// applyHashlineEdits — multiple edits
-// ═══════════════════════════════════════════════════════════════════════════
-
 #[test]
-fn edit_two_non_overlapping_replaces() {
-    let content = "aaa\nbbb\nccc\nddd\neee";
+fn heuristic_outcomes_report_rewritten_lines() {
+    let content = "    foo();\n    bar();\n    baz();";
     let edits = vec![
-        HashlineEdit::SetLine {
-            set_line: hashline::edit::SetLineOp {
-                anchor: make_ref(2, "bbb"),
-                new_text: "BBB".into(),
+        HashlineEdit::ReplaceLines {
+            replace_lines: hashline::edit::ReplaceLinesOp {
+                start_anchor: make_ref(1, "    foo();"),
+                end_anchor: Some(make_ref(2, "    bar();")),
+                new_text: Some("foo();\n    bar2();".into()),
+                verbatim: false,
             },
         },
         HashlineEdit::SetLine {
             set_line: hashline::edit::SetLineOp {
-                anchor: make_ref(4, "ddd"),
+                anchor: make_ref(3, "    baz();"),
+                new_text: "    baz();".into(),
+                verbatim: false,
+            },
+        },
+    ];
+    let result = apply_hashline_edits(content, &edits).unwrap();
+    assert_eq!(result.content, "    foo();\n    bar2();\n    baz();");
+    assert_eq!(result.outcomes[0].status, EditStatus::Applied);
+    assert_eq!(
+        result.outcomes[0].heuristics,
+        vec![HeuristicEvent {
+            heuristic: Heuristic::RestoreIndent,
+            lines: vec![1],
+            detail: None,
+        }]
+    );
+    assert_eq!(result.outcomes[1].status, EditStatus::Noop);
+    assert!(result.outcomes[1].heuristics.is_empty());
+}
+
+#[test]
+fn heuristic_outcomes_track_prefix_and_echo_lines() {
+    let content = "aaa\nbbb\nccc";
+    let bbb = make_ref(2, "bbb");
+    let edits = vec![HashlineEdit::InsertAfter {
+        insert_after: hashline::edit::InsertAfterOp {
+            anchor: bbb.clone(),
+            text: Some(format!("{}|bbb\n3:00|NEW", bbb)),
+            content: None,
+            verbatim: false,
+        },
+    }];
+    let result = apply_hashline_edits(content, &edits).unwrap();
+    assert_eq!(result.content, "aaa\nbbb\nNEW\nccc");
+    let names: Vec<(Heuristic, Vec<usize>)> = result.outcomes[0]
+        .heuristics
+        .iter()
+        .map(|h| (h.heuristic, h.lines.clone()))
+        .collect();
+    assert_eq!(
+        names,
+        vec![
+            (Heuristic::StripHashlinePrefix, vec![1, 2]),
+            (Heuristic::StripAnchorEcho, vec![1]),
+        ]
+    );
+}
+
+#[test]
+fn outcomes_mark_duplicates_and_relocations() {
+    let content = "aaa\nbbb\nccc";
+    let edit = HashlineEdit::SetLine {
+        set_line: hashline::edit::SetLineOp {
+            // Stale line number; the hash is unique, so the anchor relocates.
+            anchor: format!("1:{}", compute_line_hash(3, "ccc")),
+            new_text: "CCC".into(),
+            verbatim: false,
+        },
+    };
+    let result = apply_hashline_edits(content, &[edit.clone(), edit]).unwrap();
+    assert_eq!(result.content, "aaa\nbbb\nCCC");
+    assert_eq!(result.outcomes[0].relocated, vec![(1, 3)]);
+    assert_eq!(result.outcomes[1].status, EditStatus::Duplicate);
+}
+
+#[test]
+fn verbatim_edit_skips_heuristics() {
+    let content = "    foo();\n    bar();\n    baz();";
+    let edits = vec![HashlineEdit::ReplaceLines {
+        replace_lines: hashline::edit::ReplaceLinesOp {
+            start_anchor: make_ref(1, "    foo();"),
+            end_anchor: Some(make_ref(2, "    bar();")),
+            // Deliberately dedented, and the second line really starts with `+`.
+            new_text: Some("foo();\n+bar();".into()),
+            verbatim: true,
+        },
+    }];
+    let result = apply_hashline_edits(content, &edits).unwrap();
+    assert_eq!(result.content, "foo();\n+bar();\n    baz();");
+    assert!(result.outcomes[0].heuristics.is_empty());
+}
+
+#[test]
+fn verbatim_edit_does_not_relocate() {
+    let content = "aaa\nbbb\nccc";
+    let edits = vec![HashlineEdit::SetLine {
+        set_line: hashline::edit::SetLineOp {
+            anchor: format!("1:{}", compute_line_hash(3, "ccc")),
+            new_text: "CCC".into(),
+            verbatim: true,
+        },
+    }];
+    let err = apply_hashline_edits(content, &edits).unwrap_err();
+    assert!(err.downcast_ref::<HashlineMismatchError>().is_some());
+}
+
+#[test]
+fn verbatim_option_applies_to_every_edit() {
+    let content = "aaa\nbbb\nccc";
+    let edits = vec![HashlineEdit::InsertAfter {
+        insert_after: hashline::edit::InsertAfterOp {
+            anchor: make_ref(2, "bbb"),
+            text: Some("bbb\nNEW".into()),
+            content: None,
+            verbatim: false,
+        },
+    }];
+    let options = ApplyOptions {
+        relocate: false,
+        verbatim: true,
+        ..Default::default()
+    };
+    let result = apply_hashline_edits_with_options(content, &edits, &options).unwrap();
+    assert_eq!(result.content, "aaa\nbbb\nbbb\nNEW\nccc");
+}
+
+#[test]
+fn disabled_heuristic_does_not_run() {
+    // A Markdown list whose new items really start with `+`.
+    let content = "# List\n+ a\nend";
+    let edits = vec![HashlineEdit::SetLine {
+        set_line: hashline::edit::SetLineOp {
+            anchor: make_ref(2, "+ a"),
+            new_text: "+ a\n+ b".into(),
+            verbatim: false,
+        },
+    }];
+    let result = apply_hashline_edits(content, &edits).unwrap();
+    assert_eq!(result.content, "# List\n a\n b\nend");
+
+    let mut heuristics = HeuristicSet::all();
+    heuristics.set(Heuristic::StripDiffPlus, false);
+    let options = ApplyOptions {
+        heuristics,
+        ..Default::default()
+    };
+    let result = apply_hashline_edits_with_options(content, &edits, &options).unwrap();
+    assert_eq!(result.content, "# List\n+ a\n+ b\nend");
+    assert!(result.outcomes[0].heuristics.is_empty());
+}
+
+// ═══════════════════════════════════════════════════════════════════════════
+// applyHashlineEdits — multiple edits
+// ═══════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn edit_two_non_overlapping_replaces() {
+    let content = "aaa\nbbb\nccc\nddd\neee";
+    let edits = vec![
+        HashlineEdit::SetLine {
+            set_line: hashline::edit::SetLineOp {
+                anchor: make_ref(2, "bbb"),
+                new_text: "BBB".into(),
+                verbatim: false,
+            },
+        },
+        HashlineEdit::SetLine {
+            set_line: hashline::edit::SetLineOp {
+                anchor: make_ref(4, "ddd"),
                 new_text: "DDD".into(),
+                verbatim: false,
             },
         },
     ];
@@ -618,12 +1296,14 @@ fn edit_replace_plus_delete() {
             set_line: hashline::edit::SetLineOp {
                 anchor: make_ref(2, "bbb"),
                 new_text: "BBB".into(),
+                verbatim: false,
             },
         },
         HashlineEdit::SetLine {
             set_line: hashline::edit::SetLineOp {
                 anchor: make_ref(4, "ddd"),
                 new_text: "".into(),
+                verbatim: false,
             },
         },
     ];
@@ -639,6 +1319,7 @@ fn edit_replace_plus_insert() {
             set_line: hashline::edit::SetLineOp {
                 anchor: make_ref(3, "ccc"),
                 new_text: "CCC".into(),
+                verbatim: false,
             },
         },
         HashlineEdit::InsertAfter {
@@ -646,6 +1327,7 @@ fn edit_replace_plus_insert() {
                 anchor: make_ref(1, "aaa"),
                 text: Some("INSERTED".into()),
                 content: None,
+                verbatim: false,
             },
         },
     ];
@@ -672,6 +1354,7 @@ fn error_stale_hash() {
         set_line: hashline::edit::SetLineOp {
             anchor: "2:zz".into(),
             new_text: "BBB".into(),
+            verbatim: false,
         },
     }];
     let err = apply_hashline_edits(content, &edits).unwrap_err();
@@ -685,6 +1368,7 @@ fn error_stale_hash_shows_markers() {
         set_line: hashline::edit::SetLineOp {
             anchor: "2:zz".into(),
             new_text: "BBB".into(),
+            verbatim: false,
         },
     }];
     let err = apply_hashline_edits(content, &edits).unwrap_err();
@@ -703,12 +1387,14 @@ fn error_collects_all_mismatches() {
             set_line: hashline::edit::SetLineOp {
                 anchor: "2:zz".into(),
                 new_text: "BBB".into(),
+                verbatim: false,
             },
         },
         HashlineEdit::SetLine {
             set_line: hashline::edit::SetLineOp {
                 anchor: "4:zz".into(),
                 new_text: "DDD".into(),
+                verbatim: false,
             },
         },
     ];
@@ -729,6 +1415,7 @@ fn error_relocates_unique_hash() {
         set_line: hashline::edit::SetLineOp {
             anchor: stale,
             new_text: "CCC".into(),
+            verbatim: false,
         },
     }];
     let result = apply_hashline_edits(content, &edits).unwrap();
@@ -743,6 +1430,7 @@ fn error_no_relocate_duplicate_hash() {
         set_line: hashline::edit::SetLineOp {
             anchor: stale,
             new_text: "DUP".into(),
+            verbatim: false,
         },
     }];
     assert!(apply_hashline_edits(content, &edits).is_err());
@@ -755,6 +1443,7 @@ fn error_out_of_range_line() {
         set_line: hashline::edit::SetLineOp {
             anchor: "10:aa".into(),
             new_text: "X".into(),
+            verbatim: false,
         },
     }];
     let err = apply_hashline_edits(content, &edits).unwrap_err();
@@ -769,6 +1458,7 @@ fn error_range_start_gt_end() {
             start_anchor: make_ref(5, "eee"),
             end_anchor: Some(make_ref(2, "bbb")),
             new_text: Some("X".into()),
+            verbatim: false,
         },
     }];
     assert!(apply_hashline_edits(content, &edits).is_err());
@@ -782,12 +1472,177 @@ fn insert_empty_dst_inserts_blank_line_error_section() {
             anchor: make_ref(1, "aaa"),
             text: Some("".into()),
             content: None,
+            verbatim: false,
         },
     }];
     let result = apply_hashline_edits(content, &edits).unwrap();
     assert_eq!(result.content, "aaa\n\nbbb");
 }
 
+#[test]
+fn error_overlapping_ranges_name_both_edits() {
+    let content = "aaa\nbbb\nccc\nddd\neee";
+    let edits = vec![
+        HashlineEdit::InsertAfter {
+            insert_after: hashline::edit::InsertAfterOp {
+                anchor: make_ref(5, "eee"),
+                text: Some("end".into()),
+                content: None,
+                verbatim: false,
+            },
+        },
+        HashlineEdit::ReplaceLines {
+            replace_lines: hashline::edit::ReplaceLinesOp {
+                start_anchor: make_ref(2, "bbb"),
+                end_anchor: Some(make_ref(4, "ddd")),
+                new_text: Some("X".into()),
+                verbatim: false,
+            },
+        },
+        HashlineEdit::SetLine {
+            set_line: hashline::edit::SetLineOp {
+                anchor: make_ref(3, "ccc"),
+                new_text: "Y".into(),
+                verbatim: false,
+            },
+        },
+    ];
+    let err = apply_hashline_edits(content, &edits).unwrap_err();
+    let conflict = err.downcast_ref::<EditConflictError>().unwrap();
+    assert_eq!(
+        *conflict,
+        EditConflictError {
+            first: ConflictingEdit {
+                edit_index: 1,
+                op: "replace_lines",
+                start_line: 2,
+                end_line: 4,
+            },
+            second: ConflictingEdit {
+                edit_index: 2,
+                op: "set_line",
+                start_line: 3,
+                end_line: 3,
+            },
+        }
+    );
+    assert_eq!(
+        conflict.to_string(),
+        "edit 1 (replace_lines, lines 2-4) and edit 2 (set_line, line 3) overlap. \
+         Combine them into one edit or target separate lines."
+    );
+}
+
+#[test]
+fn error_same_line_set_twice() {
+    let content = "aaa\nbbb";
+    let set = |text: &str| HashlineEdit::SetLine {
+        set_line: hashline::edit::SetLineOp {
+            anchor: make_ref(2, "bbb"),
+            new_text: text.into(),
+            verbatim: false,
+        },
+    };
+    // Identical edits are deduplicated, different ones conflict.
+    assert!(apply_hashline_edits(content, &[set("B"), set("B")]).is_ok());
+    let err = apply_hashline_edits(content, &[set("B"), set("C")]).unwrap_err();
+    assert!(err.downcast_ref::<EditConflictError>().is_some());
+}
+
+#[test]
+fn error_insert_inside_replaced_range() {
+    let content = "aaa\nbbb\nccc\nddd";
+    let replace = HashlineEdit::ReplaceLines {
+        replace_lines: hashline::edit::ReplaceLinesOp {
+            start_anchor: make_ref(2, "bbb"),
+            end_anchor: Some(make_ref(3, "ccc")),
+            new_text: Some("X".into()),
+            verbatim: false,
+        },
+    };
+    let after = |line: usize, text: &str| HashlineEdit::InsertAfter {
+        insert_after: hashline::edit::InsertAfterOp {
+            anchor: make_ref(line, text),
+            text: Some("new".into()),
+            content: None,
+            verbatim: false,
+        },
+    };
+    let err = apply_hashline_edits(content, &[replace.clone(), after(2, "bbb")]).unwrap_err();
+    assert!(err.downcast_ref::<EditConflictError>().is_some());
+    // Inserting at either edge of the range is fine.
+    let result = apply_hashline_edits(content, &[replace.clone(), after(3, "ccc")]).unwrap();
+    assert_eq!(result.content, "aaa\nX\nnew\nddd");
+    let result = apply_hashline_edits(content, &[replace, after(1, "aaa")]).unwrap();
+    assert_eq!(result.content, "aaa\nnew\nX\nddd");
+}
+
+#[test]
+fn error_move_source_overlaps_other_edit() {
+    let content = "aaa\nbbb\nccc\nddd";
+    let edits = vec![
+        HashlineEdit::DeleteLines {
+            delete_lines: hashline::edit::DeleteLinesOp {
+                start_anchor: make_ref(1, "aaa"),
+                end_anchor: None,
+                verbatim: false,
+            },
+        },
+        HashlineEdit::MoveLines {
+            move_lines: hashline::edit::TransferLinesOp {
+                start_anchor: make_ref(1, "aaa"),
+                end_anchor: Some(make_ref(2, "bbb")),
+                after_anchor: make_ref(4, "ddd"),
+                verbatim: false,
+            },
+        },
+    ];
+    let err = apply_hashline_edits(content, &edits).unwrap_err();
+    let conflict = err.downcast_ref::<EditConflictError>().unwrap();
+    assert_eq!(
+        (conflict.first.edit_index, conflict.second.edit_index),
+        (0, 1)
+    );
+    assert_eq!(conflict.second.op, "move_lines");
+}
+
+#[test]
+fn error_copy_source_overlaps_other_edit() {
+    let content = "aaa\nbbb\nccc\nddd\neee";
+    let copy = HashlineEdit::CopyLines {
+        copy_lines: transfer_op((2, "bbb"), Some((4, "ddd")), (5, "eee")),
+    };
+    let set_line = HashlineEdit::SetLine {
+        set_line: hashline::edit::SetLineOp {
+            anchor: make_ref(3, "ccc"),
+            new_text: "CCC".into(),
+            verbatim: false,
+        },
+    };
+    let err = apply_hashline_edits(content, &[copy.clone(), set_line]).unwrap_err();
+    let conflict = err.downcast_ref::<EditConflictError>().unwrap();
+    assert_eq!(
+        (conflict.first.op, conflict.second.op),
+        ("copy_lines", "set_line")
+    );
+
+    // An insert inside the copied range conflicts too; one next to it does not.
+    let insert = |line: usize, text: &str| HashlineEdit::InsertAfter {
+        insert_after: hashline::edit::InsertAfterOp {
+            anchor: make_ref(line, text),
+            text: Some("new".into()),
+            content: None,
+            verbatim: false,
+        },
+    };
+    assert!(apply_hashline_edits(content, &[copy.clone(), insert(2, "bbb")]).is_err());
+    let result = apply_hashline_edits(content, &[copy, insert(4, "ddd")]).unwrap();
+    assert_eq!(
+        result.content,
+        "aaa\nbbb\nccc\nddd\nnew\neee\nbbb\nccc\nddd"
+    );
+}
+
 #[test]
 fn error_reject_replace_edit() {
     let content = "aaa\nbbb";
@@ -795,6 +1650,7 @@ fn error_reject_replace_edit() {
         replace: hashline::edit::ReplaceOp {
             old_text: "aaa".into(),
             new_text: "AAA".into(),
+            ..Default::default()
         },
     }];
     let err = apply_hashline_edits(content, &edits).unwrap_err();
@@ -814,6 +1670,7 @@ fn replace_basic_substitution() {
         replace: hashline::edit::ReplaceOp {
             old_text: "hello world".into(),
             new_text: "hi world".into(),
+            ..Default::default()
         },
     }];
     let result = apply_replace_edits(content, &edits).unwrap();
@@ -829,6 +1686,7 @@ fn replace_multiline_old_text() {
         replace: hashline::edit::ReplaceOp {
             old_text: "let x = 1;".into(),
             new_text: "let x = 42;".into(),
+            ..Default::default()
         },
     }];
     let result = apply_replace_edits(content, &edits).unwrap();
@@ -844,6 +1702,7 @@ fn replace_errors_on_not_found() {
         replace: hashline::edit::ReplaceOp {
             old_text: "zzz".into(),
             new_text: "ZZZ".into(),
+            ..Default::default()
         },
     }];
     let err = apply_replace_edits(content, &edits).unwrap_err();
@@ -857,6 +1716,7 @@ fn replace_errors_on_ambiguous_match() {
         replace: hashline::edit::ReplaceOp {
             old_text: "foo".into(),
             new_text: "FOO".into(),
+            ..Default::default()
         },
     }];
     let err = apply_replace_edits(content, &edits).unwrap_err();
@@ -870,6 +1730,7 @@ fn replace_errors_on_empty_old_text() {
         replace: hashline::edit::ReplaceOp {
             old_text: "".into(),
             new_text: "x".into(),
+            ..Default::default()
         },
     }];
     assert!(apply_replace_edits(content, &edits).is_err());
@@ -883,6 +1744,7 @@ fn replace_skips_anchor_edits() {
         set_line: hashline::edit::SetLineOp {
             anchor: make_ref(1, "aaa"),
             new_text: "AAA".into(),
+            verbatim: false,
         },
     }];
     let result = apply_replace_edits(content, &edits).unwrap();
@@ -899,12 +1761,14 @@ fn replace_multiple_ops_sequential() {
             replace: hashline::edit::ReplaceOp {
                 old_text: "alpha".into(),
                 new_text: "ALPHA".into(),
+                ..Default::default()
             },
         },
         HashlineEdit::Replace {
             replace: hashline::edit::ReplaceOp {
                 old_text: "gamma".into(),
                 new_text: "GAMMA".into(),
+                ..Default::default()
             },
         },
     ];
@@ -915,78 +1779,462 @@ fn replace_multiple_ops_sequential() {
 }
 
 #[test]
-fn replace_json_roundtrip() {
-    let json = r#"{"replace":{"old_text":"foo","new_text":"bar"}}"#;
-    let edit: HashlineEdit = serde_json::from_str(json).unwrap();
-    match &edit {
-        HashlineEdit::Replace { replace } => {
-            assert_eq!(replace.old_text, "foo");
-            assert_eq!(replace.new_text, "bar");
-        }
-        _ => panic!("expected Replace"),
-    }
+fn replace_all_occurrences() {
+    let content = "foo();\nbar(foo);\nfoo();";
+    let edits = vec![HashlineEdit::Replace {
+        replace: hashline::edit::ReplaceOp {
+            old_text: "foo".into(),
+            new_text: "renamed".into(),
+            all: true,
+            ..Default::default()
+        },
+    }];
+    let result = apply_replace_edits(content, &edits).unwrap();
+    assert_eq!(result.content, "renamed();\nbar(renamed);\nrenamed();");
+    assert_eq!(result.replacements, 3);
+    assert_eq!(result.first_changed_line, Some(1));
+    let mut lines: Vec<usize> = result.spans.iter().map(|s| s.start_line).collect();
+    lines.sort_unstable();
+    assert_eq!(lines, [1, 2, 3]);
 }
 
-// ═══════════════════════════════════════════════════════════════════════════
-// JSON deserialization
-// ═══════════════════════════════════════════════════════════════════════════
-
 #[test]
-fn json_deserialize_set_line() {
-    let json = r#"{"set_line":{"anchor":"2:ab","new_text":"hello"}}"#;
-    let edit: HashlineEdit = serde_json::from_str(json).unwrap();
-    match &edit {
-        HashlineEdit::SetLine { set_line } => {
-            assert_eq!(set_line.anchor, "2:ab");
-            assert_eq!(set_line.new_text, "hello");
-        }
-        _ => panic!("expected SetLine"),
-    }
+fn replace_nth_occurrence() {
+    let content = "x = 1\nx = 1\nx = 1";
+    let op = |occurrence: usize| {
+        vec![HashlineEdit::Replace {
+            replace: hashline::edit::ReplaceOp {
+                old_text: "x = 1".into(),
+                new_text: "x = 2".into(),
+                occurrence: Some(occurrence),
+                ..Default::default()
+            },
+        }]
+    };
+    let result = apply_replace_edits(content, &op(2)).unwrap();
+    assert_eq!(result.content, "x = 1\nx = 2\nx = 1");
+    assert_eq!(result.first_changed_line, Some(2));
+    let err = apply_replace_edits(content, &op(4)).unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("occurrence 4 requested but old_text matches 3 locations"),
+        "err: {}",
+        err
+    );
 }
 
 #[test]
-fn json_deserialize_params() {
-    let json =
-        r#"{"path":"/tmp/test.rs","edits":[{"set_line":{"anchor":"1:ab","new_text":"hi"}}]}"#;
-    let params: hashline::HashlineParams = serde_json::from_str(json).unwrap();
-    assert_eq!(params.path, "/tmp/test.rs");
-    assert_eq!(params.edits.len(), 1);
+fn replace_regex_with_capture_groups() {
+    let content = "let a = get(1);\nlet b = get(22);";
+    let edits = vec![HashlineEdit::Replace {
+        replace: hashline::edit::ReplaceOp {
+            old_text: r"get\((?P<n>\d+)\)".into(),
+            new_text: "fetch(${n}, $0)".into(),
+            regex: true,
+            all: true,
+            ..Default::default()
+        },
+    }];
+    let result = apply_replace_edits(content, &edits).unwrap();
+    assert_eq!(
+        result.content,
+        "let a = fetch(1, get(1));\nlet b = fetch(22, get(22));"
+    );
 }
 
-// ═══════════════════════════════════════════════════════════════════════════
-// CLI — argument validation
-// ═══════════════════════════════════════════════════════════════════════════
-
 #[test]
-fn cli_rejects_start_line_zero() {
-    let output = hashline_bin()
-        .args(["read", "--start-line", "0", "src/cli.rs"])
-        .output()
-        .unwrap();
-    assert_eq!(output.status.code(), Some(2));
-    let stderr = String::from_utf8_lossy(&output.stderr);
-    assert!(stderr.contains("invalid value '0'"), "stderr: {}", stderr);
+fn replace_rejects_invalid_regex_and_conflicting_options() {
+    let content = "aaa";
+    let op = |old_text: &str, all: bool, occurrence: Option<usize>| {
+        vec![HashlineEdit::Replace {
+            replace: hashline::edit::ReplaceOp {
+                old_text: old_text.into(),
+                new_text: "x".into(),
+                regex: true,
+                all,
+                occurrence,
+                ..Default::default()
+            },
+        }]
+    };
+    let err = apply_replace_edits(content, &op("a(", false, None)).unwrap_err();
+    assert!(err.to_string().contains("invalid regex"), "err: {}", err);
+    let err = apply_replace_edits(content, &op("a", true, Some(1))).unwrap_err();
+    assert!(err.to_string().contains("not both"), "err: {}", err);
 }
 
 #[test]
-fn cli_rejects_negative_start_line() {
-    let output = hashline_bin()
-        .args(["read", "--start-line", "-1", "src/cli.rs"])
-        .output()
-        .unwrap();
-    assert_eq!(output.status.code(), Some(2));
+fn replace_within_limits_search_to_lines() {
+    let content = "foo\nfoo\nfoo\nfoo";
+    let edits = vec![HashlineEdit::Replace {
+        replace: hashline::edit::ReplaceOp {
+            old_text: "foo".into(),
+            new_text: "bar".into(),
+            all: true,
+            within: Some(hashline::edit::AnchorRange {
+                start_anchor: make_ref(2, "foo"),
+                end_anchor: Some(make_ref(3, "foo")),
+            }),
+            ..Default::default()
+        },
+    }];
+    let result = apply_replace_edits(content, &edits).unwrap();
+    assert_eq!(result.content, "foo\nbar\nbar\nfoo");
+    assert_eq!(result.replacements, 2);
 }
 
 #[test]
-fn cli_rejects_lines_zero() {
-    let output = hashline_bin()
-        .args(["read", "--lines", "0", "src/cli.rs"])
-        .output()
-        .unwrap();
-    assert_eq!(output.status.code(), Some(2));
-    let stderr = String::from_utf8_lossy(&output.stderr);
-    assert!(stderr.contains("invalid value '0'"), "stderr: {}", stderr);
-}
+fn replace_within_validates_anchors() {
+    let content = "foo\nbar";
+    let edits = vec![HashlineEdit::Replace {
+        replace: hashline::edit::ReplaceOp {
+            old_text: "bar".into(),
+            new_text: "baz".into(),
+            within: Some(hashline::edit::AnchorRange {
+                start_anchor: "2:zz".into(),
+                end_anchor: None,
+            }),
+            ..Default::default()
+        },
+    }];
+    let err = apply_replace_edits(content, &edits).unwrap_err();
+    assert!(err.downcast_ref::<HashlineMismatchError>().is_some());
+
+    let edits = vec![HashlineEdit::Replace {
+        replace: hashline::edit::ReplaceOp {
+            old_text: "bar".into(),
+            new_text: "baz".into(),
+            within: Some(hashline::edit::AnchorRange {
+                start_anchor: make_ref(1, "foo"),
+                end_anchor: None,
+            }),
+            ..Default::default()
+        },
+    }];
+    let err = apply_replace_edits(content, &edits).unwrap_err();
+    assert!(
+        err.to_string().contains("not found in lines 1-1"),
+        "err: {}",
+        err
+    );
+}
+
+#[test]
+fn cli_replace_all_through_apply() {
+    let tmp = NamedTempFile::new().unwrap();
+    fs::write(tmp.path(), "old_name();\nold_name();\n").unwrap();
+    let payload = json!({
+        "path": tmp.path().to_str().unwrap(),
+        "edits": [{"replace": {"old_text": "old_name", "new_text": "new_name", "all": true}}]
+    });
+    let output = run_apply_payload(&payload, &[]);
+    assert!(output.status.success());
+    assert_eq!(
+        fs::read_to_string(tmp.path()).unwrap(),
+        "new_name();\nnew_name();\n"
+    );
+}
+
+#[test]
+fn replace_fuzzy_ignores_whitespace_differences() {
+    let content = "fn main() {\n    let x  =  1;\n    let y = 2;  \n}";
+    let edits = vec![HashlineEdit::Replace {
+        replace: hashline::edit::ReplaceOp {
+            old_text: "let x = 1;\nlet y = 2;".into(),
+            new_text: "    let xy = 3;".into(),
+            fuzzy: true,
+            ..Default::default()
+        },
+    }];
+    let result = apply_replace_edits(content, &edits).unwrap();
+    assert_eq!(result.content, "fn main() {\n    let xy = 3;\n}");
+    assert_eq!(result.fuzzy_matches.len(), 1);
+    assert_eq!(
+        (
+            result.fuzzy_matches[0].start_line,
+            result.fuzzy_matches[0].end_line
+        ),
+        (2, 3)
+    );
+}
+
+#[test]
+fn replace_fuzzy_matches_whole_lines_only_and_stays_unique() {
+    let content = "a = 1\nb = 1\na=1";
+    let op = |old_text: &str| {
+        vec![HashlineEdit::Replace {
+            replace: hashline::edit::ReplaceOp {
+                old_text: old_text.into(),
+                new_text: "z".into(),
+                fuzzy: true,
+                ..Default::default()
+            },
+        }]
+    };
+    let err = apply_replace_edits(content, &op("= 1")).unwrap_err();
+    assert!(
+        err.to_string().contains("not found in file (fuzzy)"),
+        "{}",
+        err
+    );
+    let err = apply_replace_edits(content, &op("a =1")).unwrap_err();
+    assert!(err.to_string().contains("matches 2 locations"), "{}", err);
+}
+
+#[test]
+fn replace_fuzzy_keeps_trailing_newline_semantics() {
+    let content = "one\n  two\nthree";
+    let edits = vec![HashlineEdit::Replace {
+        replace: hashline::edit::ReplaceOp {
+            old_text: "two\n".into(),
+            new_text: "".into(),
+            fuzzy: true,
+            ..Default::default()
+        },
+    }];
+    let result = apply_replace_edits(content, &edits).unwrap();
+    assert_eq!(result.content, "one\nthree");
+}
+
+#[test]
+fn cli_format_json_reports_fuzzy_match() {
+    let tmp = NamedTempFile::new().unwrap();
+    fs::write(tmp.path(), "if (ok) {\n\treturn  1;\n}\n").unwrap();
+    let payload = json!({
+        "path": tmp.path().to_str().unwrap(),
+        "edits": [{"replace": {"old_text": "return 1;", "new_text": "\treturn 2;", "fuzzy": true}}]
+    });
+    let output = run_apply_payload(&payload, &["--format", "json"]);
+    assert!(output.status.success());
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        result["files"][0]["edits"][0]["fuzzy_matches"],
+        json!([{"start_line": 2, "end_line": 2}])
+    );
+    assert_eq!(
+        fs::read_to_string(tmp.path()).unwrap(),
+        "if (ok) {\n\treturn 2;\n}\n"
+    );
+}
+
+#[test]
+fn replace_anchor_validated_with_other_anchors() {
+    let content = "let a = 1;\nlet b = 1;\nlet c = 1;";
+    let edits = vec![
+        HashlineEdit::SetLine {
+            set_line: hashline::edit::SetLineOp {
+                anchor: "1:zz".into(),
+                new_text: "let a = 2;".into(),
+                verbatim: false,
+            },
+        },
+        HashlineEdit::Replace {
+            replace: hashline::edit::ReplaceOp {
+                old_text: "1".into(),
+                new_text: "2".into(),
+                anchor: Some("3:zz".into()),
+                ..Default::default()
+            },
+        },
+    ];
+    let err = apply_hashline_edits(content, &edits).unwrap_err();
+    let mismatch = err.downcast_ref::<HashlineMismatchError>().unwrap();
+    let lines: Vec<usize> = mismatch.mismatches.iter().map(|m| m.line).collect();
+    assert_eq!(lines, [1, 3]);
+}
+
+#[test]
+fn replace_anchor_applies_in_anchor_pipeline() {
+    let content = "x = 1\nx = 1\nx = 1";
+    let edits = vec![
+        HashlineEdit::InsertAfter {
+            insert_after: hashline::edit::InsertAfterOp {
+                anchor: make_ref(1, "x = 1"),
+                text: Some("inserted".into()),
+                content: None,
+                verbatim: false,
+            },
+        },
+        HashlineEdit::Replace {
+            replace: hashline::edit::ReplaceOp {
+                old_text: "1".into(),
+                new_text: "2".into(),
+                anchor: Some(make_ref(2, "x = 1")),
+                ..Default::default()
+            },
+        },
+    ];
+    let result = apply_hashline_edits(content, &edits).unwrap();
+    assert_eq!(result.content, "x = 1\ninserted\nx = 2\nx = 1");
+}
+
+#[test]
+fn replace_anchor_conflicts_with_set_line() {
+    let content = "aaa\nbbb";
+    let edits = vec![
+        HashlineEdit::Replace {
+            replace: hashline::edit::ReplaceOp {
+                old_text: "b".into(),
+                new_text: "c".into(),
+                all: true,
+                anchor: Some(make_ref(2, "bbb")),
+                ..Default::default()
+            },
+        },
+        HashlineEdit::SetLine {
+            set_line: hashline::edit::SetLineOp {
+                anchor: make_ref(2, "bbb"),
+                new_text: "BBB".into(),
+                verbatim: false,
+            },
+        },
+    ];
+    let err = apply_hashline_edits(content, &edits).unwrap_err();
+    let conflict = err.downcast_ref::<EditConflictError>().unwrap();
+    assert_eq!(conflict.first.op, "replace");
+}
+
+#[test]
+fn cli_replace_range_uses_lines_as_read() {
+    let tmp = NamedTempFile::new().unwrap();
+    fs::write(
+        tmp.path(),
+        "fn a() {\n    old();\n}\nfn b() {\n    old();\n}\n",
+    )
+    .unwrap();
+    let payload = json!({
+        "path": tmp.path().to_str().unwrap(),
+        "edits": [
+            {"insert_before": {"anchor": make_ref(1, "fn a() {"), "text": "// header"}},
+            {"replace": {
+                "old_text": "old()",
+                "new_text": "new()",
+                "range": {"start_anchor": make_ref(4, "fn b() {"), "end_anchor": make_ref(6, "}")}
+            }}
+        ]
+    });
+    let output = run_apply_payload(&payload, &[]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        fs::read_to_string(tmp.path()).unwrap(),
+        "// header\nfn a() {\n    old();\n}\nfn b() {\n    new();\n}\n"
+    );
+}
+
+#[test]
+fn cli_replace_stale_anchor_exits_1() {
+    let tmp = NamedTempFile::new().unwrap();
+    fs::write(tmp.path(), "aaa\nbbb\n").unwrap();
+    let payload = json!({
+        "path": tmp.path().to_str().unwrap(),
+        "edits": [{"replace": {"old_text": "bbb", "new_text": "BBB", "anchor": "2:zz"}}]
+    });
+    let output = run_apply_payload(&payload, &[]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("changed since last read"), "{}", stderr);
+    assert_eq!(fs::read_to_string(tmp.path()).unwrap(), "aaa\nbbb\n");
+}
+
+#[test]
+fn replace_json_roundtrip() {
+    let json = r#"{"replace":{"old_text":"foo","new_text":"bar"}}"#;
+    let edit: HashlineEdit = serde_json::from_str(json).unwrap();
+    match &edit {
+        HashlineEdit::Replace { replace } => {
+            assert_eq!(replace.old_text, "foo");
+            assert_eq!(replace.new_text, "bar");
+        }
+        _ => panic!("expected Replace"),
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════════════════
+// JSON deserialization
+// ═══════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn json_deserialize_set_line() {
+    let json = r#"{"set_line":{"anchor":"2:ab","new_text":"hello"}}"#;
+    let edit: HashlineEdit = serde_json::from_str(json).unwrap();
+    match &edit {
+        HashlineEdit::SetLine { set_line } => {
+            assert_eq!(set_line.anchor, "2:ab");
+            assert_eq!(set_line.new_text, "hello");
+        }
+        _ => panic!("expected SetLine"),
+    }
+}
+
+#[test]
+fn json_deserialize_params() {
+    let json =
+        r#"{"path":"/tmp/test.rs","edits":[{"set_line":{"anchor":"1:ab","new_text":"hi"}}]}"#;
+    let params: hashline::HashlineParams = serde_json::from_str(json).unwrap();
+    assert_eq!(params.path, "/tmp/test.rs");
+    assert_eq!(params.edits.len(), 1);
+}
+
+#[test]
+fn json_deserialize_delete_and_anchorless_inserts() {
+    let json = r#"{"path":"a.rs","edits":[
+        {"delete_lines":{"start_anchor":"2:ab","end_anchor":"4:cd"}},
+        {"insert_before":{"anchor":"1:ef","text":"x"}},
+        {"insert_at_start":{"text":"top"}},
+        {"append_to_end":{"text":"bottom","verbatim":true}}
+    ]}"#;
+    let params: hashline::HashlineParams = serde_json::from_str(json).unwrap();
+    let ops: Vec<&str> = params.edits.iter().map(HashlineEdit::op_name).collect();
+    assert_eq!(
+        ops,
+        [
+            "delete_lines",
+            "insert_before",
+            "insert_at_start",
+            "append_to_end"
+        ]
+    );
+    assert!(params.edits[3].is_verbatim());
+}
+
+// ═══════════════════════════════════════════════════════════════════════════
+// CLI — argument validation
+// ═══════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn cli_rejects_start_line_zero() {
+    let output = hashline_bin()
+        .args(["read", "--start-line", "0", "src/cli.rs"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("invalid value '0'"), "stderr: {}", stderr);
+}
+
+#[test]
+fn cli_rejects_negative_start_line() {
+    let output = hashline_bin()
+        .args(["read", "--start-line", "-1", "src/cli.rs"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn cli_rejects_lines_zero() {
+    let output = hashline_bin()
+        .args(["read", "--lines", "0", "src/cli.rs"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("invalid value '0'"), "stderr: {}", stderr);
+}
 
 #[test]
 fn cli_rejects_negative_lines() {
@@ -1112,3 +2360,1318 @@ fn cli_hash_outputs_each_line_once() {
     assert!(lines[0].starts_with("1:"));
     assert!(lines[1].starts_with("2:"));
 }
+
+// ═══════════════════════════════════════════════════════════════════════════
+// CLI — multi-file transactions
+// ═══════════════════════════════════════════════════════════════════════════
+
+fn run_apply_payload(payload: &serde_json::Value, extra_args: &[&str]) -> std::process::Output {
+    let payload_file = NamedTempFile::new().unwrap();
+    fs::write(payload_file.path(), serde_json::to_string(payload).unwrap()).unwrap();
+    let mut args = vec!["apply"];
+    args.extend_from_slice(extra_args);
+    args.extend_from_slice(&["--input", payload_file.path().to_str().unwrap()]);
+    hashline_bin().args(&args).output().unwrap()
+}
+
+#[test]
+fn parse_apply_input_accepts_single_and_transaction() {
+    let (files, is_tx) =
+        parse_apply_input(r#"{"path":"a.rs","edits":[]}"#).expect("single payload");
+    assert!(!is_tx);
+    assert_eq!(files.len(), 1);
+
+    let (files, is_tx) =
+        parse_apply_input(r#"{"files":[{"path":"a.rs","edits":[]},{"path":"b.rs","edits":[]}]}"#)
+            .expect("transaction payload");
+    assert!(is_tx);
+    assert_eq!(files.len(), 2);
+    assert_eq!(files[1].path, "b.rs");
+}
+
+#[test]
+fn cli_transaction_writes_all_files() {
+    let a = NamedTempFile::new().unwrap();
+    let b = NamedTempFile::new().unwrap();
+    fs::write(a.path(), "aaa\nbbb\n").unwrap();
+    fs::write(b.path(), "ccc\nddd\n").unwrap();
+
+    let payload = json!({
+        "files": [
+            {
+                "path": a.path().to_str().unwrap(),
+                "edits": [{"set_line": {"anchor": make_ref(1, "aaa"), "new_text": "AAA"}}]
+            },
+            {
+                "path": b.path().to_str().unwrap(),
+                "edits": [{"set_line": {"anchor": make_ref(2, "ddd"), "new_text": "DDD"}}]
+            }
+        ]
+    });
+    let output = run_apply_payload(&payload, &["--emit-updated"]);
+
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(fs::read_to_string(a.path()).unwrap(), "AAA\nbbb\n");
+    assert_eq!(fs::read_to_string(b.path()).unwrap(), "ccc\nDDD\n");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains(&format!("--- {}", a.path().display())));
+    assert!(stdout.contains(&format!("--- {}", b.path().display())));
+}
+
+#[test]
+fn cli_transaction_mismatch_writes_nothing() {
+    let a = NamedTempFile::new().unwrap();
+    let b = NamedTempFile::new().unwrap();
+    fs::write(a.path(), "aaa\nbbb\n").unwrap();
+    fs::write(b.path(), "ccc\nddd\n").unwrap();
+
+    let payload = json!({
+        "files": [
+            {
+                "path": a.path().to_str().unwrap(),
+                "edits": [{"set_line": {"anchor": make_ref(1, "aaa"), "new_text": "AAA"}}]
+            },
+            {
+                "path": b.path().to_str().unwrap(),
+                "edits": [{"set_line": {"anchor": "2:zz", "new_text": "DDD"}}]
+            }
+        ]
+    });
+    let output = run_apply_payload(&payload, &[]);
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains(b.path().to_str().unwrap()),
+        "stderr: {}",
+        stderr
+    );
+    assert!(stderr.contains(">>>"), "stderr: {}", stderr);
+    assert_eq!(fs::read_to_string(a.path()).unwrap(), "aaa\nbbb\n");
+    assert_eq!(fs::read_to_string(b.path()).unwrap(), "ccc\nddd\n");
+}
+
+#[test]
+fn cli_transaction_rejects_duplicate_paths() {
+    let a = NamedTempFile::new().unwrap();
+    fs::write(a.path(), "aaa\n").unwrap();
+    let path = a.path().to_str().unwrap();
+
+    let payload = json!({
+        "files": [
+            {"path": path, "edits": [{"set_line": {"anchor": make_ref(1, "aaa"), "new_text": "X"}}]},
+            {"path": path, "edits": [{"set_line": {"anchor": make_ref(1, "aaa"), "new_text": "Y"}}]}
+        ]
+    });
+    let output = run_apply_payload(&payload, &[]);
+
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("more than once"), "stderr: {}", stderr);
+    assert_eq!(fs::read_to_string(a.path()).unwrap(), "aaa\n");
+}
+
+// ═══════════════════════════════════════════════════════════════════════════
+// CLI — file layout preservation
+// ═══════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn cli_apply_preserves_crlf_line_endings() {
+    let tmp = NamedTempFile::new().unwrap();
+    fs::write(tmp.path(), "aaa\r\nbbb\r\nccc\r\n").unwrap();
+
+    let payload = json!({
+        "path": tmp.path().to_str().unwrap(),
+        "edits": [{"set_line": {"anchor": make_ref(2, "bbb"), "new_text": "BBB"}}]
+    });
+    let output = run_apply_payload(&payload, &[]);
+
+    assert!(output.status.success());
+    assert_eq!(fs::read(tmp.path()).unwrap(), b"aaa\r\nBBB\r\nccc\r\n");
+}
+
+#[test]
+fn cli_apply_keeps_each_line_ending_in_mixed_file() {
+    let tmp = NamedTempFile::new().unwrap();
+    fs::write(tmp.path(), "a\nb\r\nc\n").unwrap();
+
+    let payload = json!({
+        "path": tmp.path().to_str().unwrap(),
+        "edits": [
+            {"set_line": {"anchor": make_ref(1, "a"), "new_text": "A"}},
+            {"insert_after": {"anchor": make_ref(3, "c"), "text": "d"}}
+        ]
+    });
+    let output = run_apply_payload(&payload, &[]);
+
+    assert!(output.status.success());
+    assert_eq!(fs::read(tmp.path()).unwrap(), b"A\nb\r\nc\nd\n");
+}
+
+#[test]
+fn cli_apply_preserves_missing_final_newline_and_bom() {
+    let tmp = NamedTempFile::new().unwrap();
+    fs::write(tmp.path(), "\u{feff}aaa\nbbb").unwrap();
+
+    let payload = json!({
+        "path": tmp.path().to_str().unwrap(),
+        "edits": [{"set_line": {"anchor": make_ref(1, "aaa"), "new_text": "AAA"}}]
+    });
+    let output = run_apply_payload(&payload, &[]);
+
+    assert!(output.status.success());
+    assert_eq!(fs::read_to_string(tmp.path()).unwrap(), "\u{feff}AAA\nbbb");
+}
+
+#[test]
+fn cli_append_to_end_keeps_final_newline() {
+    let tmp = NamedTempFile::new().unwrap();
+    fs::write(tmp.path(), "aaa\nbbb\n").unwrap();
+
+    let payload = json!({
+        "path": tmp.path().to_str().unwrap(),
+        "edits": [
+            {"insert_at_start": {"text": "// header"}},
+            {"append_to_end": {"text": "ccc"}}
+        ]
+    });
+    let output = run_apply_payload(&payload, &[]);
+
+    assert!(output.status.success());
+    assert_eq!(
+        fs::read_to_string(tmp.path()).unwrap(),
+        "// header\naaa\nbbb\nccc\n"
+    );
+}
+
+#[test]
+fn cli_apply_noop_leaves_file_bytes_untouched() {
+    let tmp = NamedTempFile::new().unwrap();
+    fs::write(tmp.path(), "aaa\r\nbbb").unwrap();
+
+    let payload = json!({
+        "path": tmp.path().to_str().unwrap(),
+        "edits": [{"set_line": {"anchor": make_ref(1, "aaa"), "new_text": "aaa"}}]
+    });
+    let output = run_apply_payload(&payload, &[]);
+
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("No changes applied."));
+    assert_eq!(fs::read(tmp.path()).unwrap(), b"aaa\r\nbbb");
+}
+
+// ═══════════════════════════════════════════════════════════════════════════
+// Whole-file fingerprint (expect_file_hash)
+// ═══════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn edit_no_relocation_when_disabled() {
+    let content = "aaa\nbbb\nccc";
+    let stale = format!("2:{}", compute_line_hash(3, "ccc"));
+    let edits = vec![HashlineEdit::SetLine {
+        set_line: hashline::edit::SetLineOp {
+            anchor: stale,
+            new_text: "CCC".into(),
+            verbatim: false,
+        },
+    }];
+    let options = ApplyOptions {
+        relocate: false,
+        ..Default::default()
+    };
+    let err = apply_hashline_edits_with_options(content, &edits, &options).unwrap_err();
+    assert!(err.downcast_ref::<HashlineMismatchError>().is_some());
+}
+
+#[test]
+fn cli_read_fingerprint_header() {
+    let tmp = NamedTempFile::new().unwrap();
+    fs::write(tmp.path(), "one\ntwo\n").unwrap();
+
+    let output = hashline_bin()
+        .args(["read", "--fingerprint", tmp.path().to_str().unwrap()])
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        lines[0],
+        format!(
+            "# hashline file_hash={}",
+            compute_file_fingerprint("one\ntwo")
+        )
+    );
+    assert!(lines[1].starts_with("1:"));
+    assert_eq!(lines.len(), 3);
+}
+
+#[test]
+fn cli_expect_file_hash_refuses_changed_file() {
+    let tmp = NamedTempFile::new().unwrap();
+    fs::write(tmp.path(), "aaa\nbbb\nccc\n").unwrap();
+    let fingerprint = compute_file_fingerprint("aaa\nbbb\nccc");
+    // Someone else edits a different line after our read.
+    fs::write(tmp.path(), "aaa\nbbb\nCCC\n").unwrap();
+
+    let payload = json!({
+        "path": tmp.path().to_str().unwrap(),
+        "expect_file_hash": fingerprint,
+        "edits": [{"set_line": {"anchor": make_ref(1, "aaa"), "new_text": "AAA"}}]
+    });
+    let output = run_apply_payload(&payload, &[]);
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("File changed since last read"),
+        "stderr: {}",
+        stderr
+    );
+    assert_eq!(fs::read_to_string(tmp.path()).unwrap(), "aaa\nbbb\nCCC\n");
+}
+
+#[test]
+fn cli_expect_file_hash_strict_applies_exact_anchors() {
+    let tmp = NamedTempFile::new().unwrap();
+    fs::write(tmp.path(), "aaa\nbbb\nCCC\n").unwrap();
+    let fingerprint = compute_file_fingerprint("aaa\nbbb\nccc");
+
+    let payload = json!({
+        "path": tmp.path().to_str().unwrap(),
+        "expect_file_hash": fingerprint,
+        "on_file_change": "strict",
+        "edits": [{"set_line": {"anchor": make_ref(1, "aaa"), "new_text": "AAA"}}]
+    });
+    let output = run_apply_payload(&payload, &[]);
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("without relocation"), "stderr: {}", stderr);
+    assert_eq!(fs::read_to_string(tmp.path()).unwrap(), "AAA\nbbb\nCCC\n");
+}
+
+#[test]
+fn cli_expect_file_hash_strict_rejects_relocation() {
+    let tmp = NamedTempFile::new().unwrap();
+    // A line was inserted above, so "ccc" moved from line 2 to line 3.
+    fs::write(tmp.path(), "aaa\nnew\nccc\n").unwrap();
+    let fingerprint = compute_file_fingerprint("aaa\nccc");
+
+    let payload = json!({
+        "path": tmp.path().to_str().unwrap(),
+        "expect_file_hash": fingerprint,
+        "on_file_change": "strict",
+        "edits": [{"set_line": {"anchor": make_ref(2, "ccc"), "new_text": "CCC"}}]
+    });
+    let output = run_apply_payload(&payload, &[]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(fs::read_to_string(tmp.path()).unwrap(), "aaa\nnew\nccc\n");
+}
+
+// ═══════════════════════════════════════════════════════════════════════════
+// Configurable hash width
+// ═══════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn edit_wide_hash_relocates_past_narrow_collision() {
+    // Find two distinct lines whose 2-char hashes collide.
+    let mut by_hash = std::collections::HashMap::new();
+    let (a, b) = (0..)
+        .map(|i| format!("let value_{} = {};", i, i))
+        .find_map(|line| {
+            let h = compute_line_hash(1, &line);
+            by_hash.insert(h, line.clone()).map(|prev| (prev, line))
+        })
+        .unwrap();
+    // The target line `a` has moved down by one since the anchor was taken.
+    let content = format!("header\ninserted\n{}\n{}", a, b);
+    let wide = HashOptions {
+        width: 4,
+        ..Default::default()
+    };
+    let edit = |hash: String| {
+        vec![HashlineEdit::SetLine {
+            set_line: hashline::edit::SetLineOp {
+                anchor: format!("2:{}", hash),
+                new_text: "moved".into(),
+                verbatim: false,
+            },
+        }]
+    };
+
+    let narrow_err = apply_hashline_edits(&content, &edit(compute_line_hash(2, &a))).unwrap_err();
+    assert!(narrow_err.downcast_ref::<HashlineMismatchError>().is_some());
+
+    let options = ApplyOptions {
+        hash: wide,
+        ..Default::default()
+    };
+    let result = apply_hashline_edits_with_options(
+        &content,
+        &edit(compute_line_hash_with(2, &a, &wide)),
+        &options,
+    )
+    .unwrap();
+    assert_eq!(result.content, format!("header\ninserted\nmoved\n{}", b));
+}
+
+#[test]
+fn edit_mismatch_error_uses_configured_width() {
+    let options = ApplyOptions {
+        hash: HashOptions {
+            width: 6,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let edits = vec![HashlineEdit::SetLine {
+        set_line: hashline::edit::SetLineOp {
+            anchor: "1:000000".into(),
+            new_text: "x".into(),
+            verbatim: false,
+        },
+    }];
+    let err = apply_hashline_edits_with_options("aaa\nbbb", &edits, &options).unwrap_err();
+    let msg = err.to_string();
+    let expected = compute_line_hash_with(1, "aaa", &options.hash);
+    assert!(msg.contains(&format!(">>> 1:{}|aaa", expected)), "{}", msg);
+}
+
+#[test]
+fn cli_read_and_apply_with_hash_width_flag() {
+    let tmp = NamedTempFile::new().unwrap();
+    fs::write(tmp.path(), "alpha\nbeta\n").unwrap();
+    let path = tmp.path().to_str().unwrap();
+
+    let output = hashline_bin()
+        .args(["read", "--hash-width", "4", path])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines[0], "# hashline hash_width=4");
+    let wide = HashOptions {
+        width: 4,
+        ..Default::default()
+    };
+    let anchor = format!("2:{}", compute_line_hash_with(2, "beta", &wide));
+    assert_eq!(lines[2], format!("{}|beta", anchor));
+
+    let payload = json!({
+        "path": path,
+        "edits": [{"set_line": {"anchor": anchor, "new_text": "BETA"}}]
+    });
+    let output = run_apply_payload(&payload, &["--hash-width", "4"]);
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(fs::read_to_string(tmp.path()).unwrap(), "alpha\nBETA\n");
+}
+
+#[test]
+fn cli_apply_mismatch_hints_at_hash_width_of_anchor() {
+    let tmp = NamedTempFile::new().unwrap();
+    fs::write(tmp.path(), "alpha\nbeta\n").unwrap();
+    let wide = HashOptions {
+        width: 4,
+        ..Default::default()
+    };
+    let anchor = format!("2:{}", compute_line_hash_with(2, "beta", &wide));
+
+    // Read with --hash-width 4, applied without it.
+    let payload = json!({
+        "path": tmp.path().to_str().unwrap(),
+        "edits": [{"set_line": {"anchor": anchor, "new_text": "BETA"}}]
+    });
+    let output = run_apply_payload(&payload, &[]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("anchor hash is 4 chars but hash_width is 2"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("--hash-width 4"), "{}", stderr);
+
+    let output = run_apply_payload(&payload, &["--format", "json"]);
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert!(result.to_string().contains("hash_width is 2"), "{}", result);
+    assert_eq!(fs::read_to_string(tmp.path()).unwrap(), "alpha\nbeta\n");
+}
+
+#[test]
+fn cli_hash_width_from_env_and_config() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let file = dir.path().join("f.txt");
+    fs::write(&file, "one\n").unwrap();
+    fs::write(dir.path().join(".hashline.json"), r#"{"hash_width": 3}"#).unwrap();
+
+    let hash_of = |output: std::process::Output| {
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        stdout.trim().split(':').nth(1).unwrap().to_string()
+    };
+
+    let from_config = hashline_bin()
+        .current_dir(dir.path())
+        .env_remove("HASHLINE_HASH_WIDTH")
+        .args(["hash", "f.txt"])
+        .output()
+        .unwrap();
+    assert_eq!(hash_of(from_config).len(), 3);
+
+    let from_env = hashline_bin()
+        .current_dir(dir.path())
+        .env("HASHLINE_HASH_WIDTH", "6")
+        .args(["hash", "f.txt"])
+        .output()
+        .unwrap();
+    assert_eq!(hash_of(from_env).len(), 6);
+
+    let from_flag = hashline_bin()
+        .current_dir(dir.path())
+        .env("HASHLINE_HASH_WIDTH", "6")
+        .args(["hash", "--hash-width", "2", "f.txt"])
+        .output()
+        .unwrap();
+    assert_eq!(hash_of(from_flag).len(), 2);
+}
+
+#[test]
+fn cli_rejects_unsupported_hash_width() {
+    let tmp = NamedTempFile::new().unwrap();
+    fs::write(tmp.path(), "x\n").unwrap();
+    let output = hashline_bin()
+        .args(["read", "--hash-width", "5", tmp.path().to_str().unwrap()])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+}
+
+// ═══════════════════════════════════════════════════════════════════════════
+// Context-aware hashing
+// ═══════════════════════════════════════════════════════════════════════════
+
+fn neighbors_options() -> HashOptions {
+    HashOptions {
+        context: hashline::hash::HashContext::Neighbors,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn edit_context_hash_relocates_repeated_line() {
+    let before = ["fn a() {", "    1", "}", "fn b() {", "    2", "}"];
+    let after = format!("// header\n{}", before.join("\n"));
+    let set_close = |anchor: String| {
+        vec![HashlineEdit::SetLine {
+            set_line: hashline::edit::SetLineOp {
+                anchor,
+                new_text: "} // b".into(),
+                verbatim: false,
+            },
+        }]
+    };
+
+    // Plain hashes: every `}` shares an anchor, so the shifted line can't be found.
+    let plain = format!("6:{}", compute_line_hash(6, "}"));
+    assert!(apply_hashline_edits(&after, &set_close(plain)).is_err());
+
+    // Context hashes include the neighbours, so the second `}` is unique.
+    let ctx = neighbors_options();
+    let hashes = hashline::hash::compute_line_hashes(&before, &ctx);
+    let options = ApplyOptions {
+        hash: ctx,
+        ..Default::default()
+    };
+    let result =
+        apply_hashline_edits_with_options(&after, &set_close(format!("6:{}", hashes[5])), &options)
+            .unwrap();
+    assert!(result.content.ends_with("    2\n} // b"));
+    assert!(result.content.contains("    1\n}\n"));
+}
+
+#[test]
+fn cli_context_partial_read_matches_full_read() {
+    let tmp = NamedTempFile::new().unwrap();
+    fs::write(tmp.path(), "a {\n}\n\nb {\n}\n").unwrap();
+    let path = tmp.path().to_str().unwrap();
+
+    let read = |extra: &[&str]| {
+        let mut args = vec!["read", "--hash-context", "neighbors"];
+        args.extend_from_slice(extra);
+        args.push(path);
+        let output = hashline_bin().args(&args).output().unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    let full = read(&[]);
+    let full_lines: Vec<&str> = full.lines().collect();
+    assert_eq!(full_lines[0], "# hashline context=neighbors");
+    assert_ne!(
+        full_lines[2].split('|').next().unwrap()[2..],
+        full_lines[5].split('|').next().unwrap()[2..]
+    );
+
+    let part = read(&["--start-line", "2", "--lines", "2"]);
+    let part_lines: Vec<&str> = part.lines().collect();
+    assert_eq!(part_lines[1..], full_lines[2..4]);
+}
+
+#[test]
+fn cli_apply_with_context_hash() {
+    let tmp = NamedTempFile::new().unwrap();
+    fs::write(tmp.path(), "a {\n}\nb {\n}\n").unwrap();
+    let hashes =
+        hashline::hash::compute_line_hashes(&["a {", "}", "b {", "}"], &neighbors_options());
+
+    let payload = json!({
+        "path": tmp.path().to_str().unwrap(),
+        "edits": [{"set_line": {"anchor": format!("4:{}", hashes[3]), "new_text": "} // b"}}]
+    });
+    let output = run_apply_payload(&payload, &["--hash-context", "neighbors"]);
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        fs::read_to_string(tmp.path()).unwrap(),
+        "a {\n}\nb {\n} // b\n"
+    );
+}
+
+// ═══════════════════════════════════════════════════════════════════════════
+// Whitespace-sensitive hashing
+// ═══════════════════════════════════════════════════════════════════════════
+
+fn indent_options() -> HashOptions {
+    HashOptions {
+        whitespace: hashline::hash::HashWhitespace::Indent,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn cli_read_python_reports_indent_mode() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let file = dir.path().join("app.py");
+    fs::write(&file, "if x:\n    y = 1\n").unwrap();
+
+    let output = hashline_bin()
+        .env_remove("HASHLINE_HASH_WHITESPACE")
+        .args(["read", file.to_str().unwrap()])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines[0], "# hashline whitespace=indent");
+    assert_eq!(
+        lines[2],
+        format!(
+            "2:{}|    y = 1",
+            compute_line_hash_with(2, "    y = 1", &indent_options())
+        )
+    );
+}
+
+#[test]
+fn cli_apply_python_rejects_reindented_anchor() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let file = dir.path().join("app.py");
+    // The anchor was taken while `y = 1` was inside the `if` block...
+    let anchor = format!(
+        "2:{}",
+        compute_line_hash_with(2, "    y = 1", &indent_options())
+    );
+    // ...but someone has since dedented it out of the block.
+    fs::write(&file, "if x:\ny = 1\n").unwrap();
+
+    let payload = json!({
+        "path": file.to_str().unwrap(),
+        "edits": [{"set_line": {"anchor": anchor, "new_text": "    y = 2"}}]
+    });
+    let output = run_apply_payload(&payload, &[]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(fs::read_to_string(&file).unwrap(), "if x:\ny = 1\n");
+
+    // With whitespace ignored, an anchor from before the dedent still validates.
+    let payload = json!({
+        "path": file.to_str().unwrap(),
+        "edits": [{"set_line": {"anchor": make_ref(2, "    y = 1"), "new_text": "    y = 2"}}]
+    });
+    let output = run_apply_payload(&payload, &["--hash-whitespace", "ignore"]);
+    assert!(output.status.success());
+    assert_eq!(fs::read_to_string(&file).unwrap(), "if x:\n    y = 2\n");
+}
+
+#[test]
+fn cli_hash_whitespace_flag_on_other_files() {
+    let tmp = NamedTempFile::new().unwrap();
+    fs::write(tmp.path(), "  a\n").unwrap();
+    let output = hashline_bin()
+        .args([
+            "hash",
+            "--hash-whitespace",
+            "indent",
+            tmp.path().to_str().unwrap(),
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap().trim(),
+        format!("1:{}", compute_line_hash_with(1, "  a", &indent_options()))
+    );
+}
+
+// ═══════════════════════════════════════════════════════════════════════════
+// apply --dry-run
+// ═══════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn cli_dry_run_prints_diff_and_leaves_file() {
+    let tmp = NamedTempFile::new().unwrap();
+    fs::write(tmp.path(), "a\nb\nc\n").unwrap();
+    let path = tmp.path().to_str().unwrap();
+    let payload = json!({
+        "path": path,
+        "edits": [{"set_line": {"anchor": make_ref(2, "b"), "new_text": "B"}}]
+    });
+    let output = run_apply_payload(&payload, &["--dry-run"]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!("--- {path}\n+++ {path}\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n")
+    );
+    assert_eq!(fs::read_to_string(tmp.path()).unwrap(), "a\nb\nc\n");
+}
+
+#[test]
+fn cli_dry_run_json_attributes_hunks_to_edits() {
+    let tmp = NamedTempFile::new().unwrap();
+    let lines: Vec<String> = (1..=20).map(|i| format!("line {i}")).collect();
+    fs::write(tmp.path(), lines.join("\n") + "\n").unwrap();
+    let payload = json!({
+        "path": tmp.path().to_str().unwrap(),
+        "edits": [
+            {"replace": {"old_text": "line 18", "new_text": "eighteen"}},
+            {"set_line": {"anchor": make_ref(2, "line 2"), "new_text": "two"}},
+            {"insert_after": {"anchor": make_ref(10, "line 10"), "text": "ten and a half"}}
+        ]
+    });
+    let output = run_apply_payload(&payload, &["--dry-run=json"]);
+    assert!(output.status.success());
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(result["dry_run"], true);
+    let file = &result["files"][0];
+    assert_eq!(file["changed"], true);
+    let hunks = file["hunks"].as_array().unwrap();
+    assert_eq!(hunks.len(), 3);
+    assert_eq!(hunks[0]["edits"], json!([1]));
+    assert_eq!(hunks[1]["edits"], json!([2]));
+    assert_eq!(hunks[1]["header"], "@@ -8,6 +8,7 @@");
+    assert_eq!(hunks[2]["edits"], json!([0]));
+    assert!(hunks[2]["lines"]
+        .as_array()
+        .unwrap()
+        .contains(&json!("+eighteen")));
+    assert_eq!(
+        fs::read_to_string(tmp.path()).unwrap(),
+        lines.join("\n") + "\n"
+    );
+}
+
+#[test]
+fn cli_dry_run_json_attributes_move_hunks() {
+    let tmp = NamedTempFile::new().unwrap();
+    let lines: Vec<String> = (1..=20).map(|i| format!("line {}", i)).collect();
+    fs::write(tmp.path(), lines.join("\n") + "\n").unwrap();
+    let payload = json!({
+        "path": tmp.path().to_str().unwrap(),
+        "edits": [
+            {"move_lines": {
+                "start_anchor": make_ref(2, "line 2"),
+                "end_anchor": make_ref(3, "line 3"),
+                "after_anchor": make_ref(18, "line 18")
+            }}
+        ]
+    });
+    let output = run_apply_payload(&payload, &["--dry-run=json"]);
+    assert!(output.status.success());
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let hunks = result["files"][0]["hunks"].as_array().unwrap();
+    assert_eq!(hunks.len(), 2);
+    assert_eq!(hunks[0]["edits"], json!([0]));
+    assert_eq!(hunks[1]["edits"], json!([0]));
+}
+
+#[test]
+fn cli_dry_run_mismatch_exits_1() {
+    let tmp = NamedTempFile::new().unwrap();
+    fs::write(tmp.path(), "a\nb\n").unwrap();
+    let payload = json!({
+        "path": tmp.path().to_str().unwrap(),
+        "edits": [{"set_line": {"anchor": make_ref(2, "stale"), "new_text": "B"}}]
+    });
+    let output = run_apply_payload(&payload, &["--dry-run"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    assert_eq!(fs::read_to_string(tmp.path()).unwrap(), "a\nb\n");
+}
+
+// ═══════════════════════════════════════════════════════════════════════════
+// apply --format json
+// ═══════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn cli_format_json_reports_edits_and_updated_anchors() {
+    let tmp = NamedTempFile::new().unwrap();
+    fs::write(tmp.path(), "a\nb\nc\n").unwrap();
+    let payload = json!({
+        "path": tmp.path().to_str().unwrap(),
+        "edits": [
+            {"set_line": {"anchor": make_ref(1, "a"), "new_text": "a"}},
+            {"set_line": {"anchor": make_ref(2, "b"), "new_text": "B"}}
+        ]
+    });
+    let output = run_apply_payload(&payload, &["--format", "json"]);
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(result["status"], "ok");
+    assert_eq!(result["changed"], true);
+    let file = &result["files"][0];
+    assert_eq!(file["first_changed_line"], 2);
+    assert_eq!(file["edits"][0]["status"], "noop");
+    assert_eq!(file["edits"][1]["status"], "applied");
+    assert_eq!(file["edits"][1]["op"], "set_line");
+    assert_eq!(file["noop_edits"][0]["edit_index"], 0);
+    assert_eq!(file["noop_edits"][0]["anchor"], make_ref(1, "a"));
+    let lines = file["updated"]["lines"].as_array().unwrap();
+    assert_eq!(lines[1]["anchor"], make_ref(2, "B"));
+    assert_eq!(lines[1]["content"], "B");
+    assert_eq!(fs::read_to_string(tmp.path()).unwrap(), "a\nB\nc\n");
+}
+
+#[test]
+fn cli_format_json_mismatch_includes_remaps() {
+    let tmp = NamedTempFile::new().unwrap();
+    fs::write(tmp.path(), "a\nb\n").unwrap();
+    let stale = make_ref(2, "stale");
+    let payload = json!({
+        "path": tmp.path().to_str().unwrap(),
+        "edits": [{"set_line": {"anchor": stale, "new_text": "B"}}]
+    });
+    let output = run_apply_payload(&payload, &["--format", "json"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stderr.is_empty());
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(result["status"], "mismatch");
+    assert_eq!(result["kind"], "anchors");
+    assert_eq!(result["mismatches"][0]["line"], 2);
+    assert_eq!(result["remaps"][&stale], make_ref(2, "b"));
+}
+
+#[test]
+fn cli_format_json_reports_errors() {
+    let payload_file = NamedTempFile::new().unwrap();
+    fs::write(payload_file.path(), "{not json").unwrap();
+    let output = hashline_bin()
+        .args([
+            "apply",
+            "--format",
+            "json",
+            "--input",
+            payload_file.path().to_str().unwrap(),
+        ])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(result["status"], "error");
+    assert!(result["message"]
+        .as_str()
+        .unwrap()
+        .starts_with("Invalid JSON input"));
+}
+
+#[test]
+fn cli_format_json_reports_conflicts() {
+    let tmp = NamedTempFile::new().unwrap();
+    fs::write(tmp.path(), "aaa\nbbb\nccc\n").unwrap();
+    let payload = json!({
+        "path": tmp.path().to_str().unwrap(),
+        "edits": [
+            {"replace": {"old_text": "ccc", "new_text": "CCC"}},
+            {"set_line": {"anchor": make_ref(2, "bbb"), "new_text": "B"}},
+            {"delete_lines": {"start_anchor": make_ref(1, "aaa"), "end_anchor": make_ref(2, "bbb")}}
+        ]
+    });
+    let output = run_apply_payload(&payload, &["--format", "json"]);
+    assert_eq!(output.status.code(), Some(2));
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(result["status"], "error");
+    assert_eq!(result["kind"], "conflict");
+    assert_eq!(result["edits"][0]["index"], 1);
+    assert_eq!(result["edits"][1]["index"], 2);
+    assert_eq!(result["edits"][1]["op"], "delete_lines");
+    assert_eq!(result["edits"][1]["start_line"], 1);
+    assert_eq!(result["edits"][1]["end_line"], 2);
+    assert_eq!(fs::read_to_string(tmp.path()).unwrap(), "aaa\nbbb\nccc\n");
+}
+
+#[test]
+fn cli_format_json_reports_heuristics() {
+    let tmp = NamedTempFile::new().unwrap();
+    fs::write(tmp.path(), "fn main() {\n    run();\n}\n").unwrap();
+    let payload = json!({
+        "path": tmp.path().to_str().unwrap(),
+        "edits": [{"set_line": {"anchor": make_ref(2, "    run();"), "new_text": "go();"}}]
+    });
+    let output = run_apply_payload(&payload, &["--format", "json"]);
+    assert!(output.status.success());
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let edit = &result["files"][0]["edits"][0];
+    assert_eq!(edit["status"], "applied");
+    assert_eq!(
+        edit["heuristics"],
+        json!([{"name": "restore_indent", "lines": [1]}])
+    );
+
+    // Text mode reports the same on stderr.
+    fs::write(tmp.path(), "fn main() {\n    run();\n}\n").unwrap();
+    let output = run_apply_payload(&payload, &[]);
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("Note: edit 0 (set_line): restore_indent on text line 1"),
+        "stderr: {}",
+        stderr
+    );
+}
+
+// ═══════════════════════════════════════════════════════════════════════════
+// apply --strict / verbatim
+// ═══════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn cli_strict_writes_text_exactly() {
+    let tmp = NamedTempFile::new().unwrap();
+    fs::write(tmp.path(), "fn main() {\n    run();\n}\n").unwrap();
+    let payload = json!({
+        "path": tmp.path().to_str().unwrap(),
+        "edits": [{"set_line": {"anchor": make_ref(2, "    run();"), "new_text": "run();"}}]
+    });
+    let output = run_apply_payload(&payload, &["--strict"]);
+    assert!(output.status.success());
+    assert_eq!(
+        fs::read_to_string(tmp.path()).unwrap(),
+        "fn main() {\nrun();\n}\n"
+    );
+}
+
+#[test]
+fn cli_verbatim_field_in_payload() {
+    let tmp = NamedTempFile::new().unwrap();
+    fs::write(tmp.path(), "fn main() {\n    run();\n}\n").unwrap();
+    let payload = json!({
+        "path": tmp.path().to_str().unwrap(),
+        "edits": [{"set_line": {
+            "anchor": make_ref(2, "    run();"),
+            "new_text": "run();",
+            "verbatim": true
+        }}]
+    });
+    let output = run_apply_payload(&payload, &[]);
+    assert!(output.status.success());
+    assert_eq!(
+        fs::read_to_string(tmp.path()).unwrap(),
+        "fn main() {\nrun();\n}\n"
+    );
+}
+
+#[test]
+fn cli_disable_heuristic_flag_and_config() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let file = dir.path().join("main.rs");
+    let original = "fn main() {\n    run();\n}\n";
+    let payload = json!({
+        "path": file.to_str().unwrap(),
+        "edits": [{"set_line": {"anchor": make_ref(2, "    run();"), "new_text": "go();"}}]
+    });
+
+    fs::write(&file, original).unwrap();
+    let output = run_apply_payload(&payload, &["--disable-heuristic", "restore_indent"]);
+    assert!(output.status.success());
+    assert_eq!(
+        fs::read_to_string(&file).unwrap(),
+        "fn main() {\ngo();\n}\n"
+    );
+
+    // Same via the config file; --enable-heuristic turns it back on.
+    let config = dir.path().join(".hashline.json");
+    fs::write(&config, r#"{"heuristics": {"restore_indent": false}}"#).unwrap();
+    let payload_file = NamedTempFile::new().unwrap();
+    fs::write(payload_file.path(), payload.to_string()).unwrap();
+    let apply = |extra: &[&str]| {
+        fs::write(&file, original).unwrap();
+        let mut cmd = hashline_bin();
+        cmd.env("HASHLINE_CONFIG", &config)
+            .arg("apply")
+            .args(extra)
+            .args(["--input", payload_file.path().to_str().unwrap()]);
+        assert!(cmd.output().unwrap().status.success());
+        fs::read_to_string(&file).unwrap()
+    };
+    assert_eq!(apply(&[]), "fn main() {\ngo();\n}\n");
+    assert_eq!(
+        apply(&["--enable-heuristic", "restore_indent"]),
+        "fn main() {\n    go();\n}\n"
+    );
+}
+
+#[test]
+fn cli_rejects_unknown_heuristic() {
+    let output = hashline_bin()
+        .args(["apply", "--disable-heuristic", "bogus"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn cli_apply_format_json_reports_config_errors_as_json() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let config = dir.path().join(".hashline.json");
+    let payload_file = NamedTempFile::new().unwrap();
+    fs::write(payload_file.path(), r#"{"path": "a.rs", "edits": []}"#).unwrap();
+    let apply = |config_text: &str, width_env: Option<&str>| {
+        fs::write(&config, config_text).unwrap();
+        let mut cmd = hashline_bin();
+        cmd.env("HASHLINE_CONFIG", &config)
+            .env_remove("HASHLINE_HASH_WIDTH")
+            .args(["apply", "--format", "json", "--input"])
+            .arg(payload_file.path());
+        if let Some(width) = width_env {
+            cmd.env("HASHLINE_HASH_WIDTH", width);
+        }
+        cmd.output().unwrap()
+    };
+
+    for output in [
+        apply(r#"{"hash_width": 8}"#, None),
+        apply(r#"{"heuristics": {"bogus": false}}"#, None),
+        apply("{}", Some("9")),
+    ] {
+        assert_eq!(output.status.code(), Some(2));
+        assert!(output.stderr.is_empty(), "{:?}", output);
+        let result: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(result["status"], "error");
+        assert!(
+            result["message"].as_str().unwrap().starts_with("Error: "),
+            "{}",
+            result
+        );
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════════════════
+// apply --patch
+// ═══════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn cli_patch_applies_git_diff_to_every_file() {
+    let dir = tempfile::TempDir::new().unwrap();
+    fs::create_dir(dir.path().join("src")).unwrap();
+    fs::write(dir.path().join("src/a.rs"), "fn a() {\n    one();\n}\n").unwrap();
+    fs::write(dir.path().join("b.txt"), "x\ny\n").unwrap();
+    let diff = "diff --git a/src/a.rs b/src/a.rs\n--- a/src/a.rs\n+++ b/src/a.rs\n@@ -1,3 +1,3 @@\n fn a() {\n-    one();\n+    two();\n }\ndiff --git a/b.txt b/b.txt\n--- a/b.txt\n+++ b/b.txt\n@@ -2 +2,2 @@\n y\n+z\n";
+    fs::write(dir.path().join("fix.diff"), diff).unwrap();
+    let output = hashline_bin()
+        .current_dir(dir.path())
+        .args(["apply", "--patch", "fix.diff"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        fs::read_to_string(dir.path().join("src/a.rs")).unwrap(),
+        "fn a() {\n    two();\n}\n"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.path().join("b.txt")).unwrap(),
+        "x\ny\nz\n"
+    );
+}
+
+#[test]
+fn cli_patch_stale_hunk_exits_1_and_writes_nothing() {
+    let dir = tempfile::TempDir::new().unwrap();
+    fs::write(dir.path().join("a.txt"), "one\ntwo\n").unwrap();
+    fs::write(dir.path().join("b.txt"), "x\nchanged\n").unwrap();
+    let diff = "--- a.txt\n+++ a.txt\n@@ -1 +1 @@\n-one\n+ONE\n--- b.txt\n+++ b.txt\n@@ -1,2 +1,2 @@\n x\n-y\n+Y\n";
+    let mut child = hashline_bin()
+        .current_dir(dir.path())
+        .args(["apply", "--patch", "-"])
+        .stdin(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    use std::io::Write;
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(diff.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("b.txt: 1 line has changed since last read"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains(&format!(">>> {}|changed", make_ref(2, "changed"))));
+    assert_eq!(
+        fs::read_to_string(dir.path().join("a.txt")).unwrap(),
+        "one\ntwo\n"
+    );
+}
+
+#[test]
+fn cli_patch_dry_run_attributes_hunks_to_patch_edit() {
+    let tmp = NamedTempFile::new().unwrap();
+    fs::write(tmp.path(), "a\nb\n").unwrap();
+    let diff_file = NamedTempFile::new().unwrap();
+    let path = tmp.path().to_str().unwrap();
+    fs::write(
+        diff_file.path(),
+        format!("--- {0}\n+++ {0}\n@@ -2 +2 @@\n-b\n+B\n", path),
+    )
+    .unwrap();
+    let output = hashline_bin()
+        .args(["apply", "--dry-run=json", "--patch"])
+        .arg(diff_file.path())
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let v: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(v["files"][0]["edits"][0]["op"], "patch");
+    assert_eq!(fs::read_to_string(tmp.path()).unwrap(), "a\nb\n");
+}
+
+#[test]
+fn cli_patch_conflicts_with_input() {
+    let output = hashline_bin()
+        .args(["apply", "--patch", "x.diff", "--input", "x.json"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn cli_patch_rejects_file_creation() {
+    let diff_file = NamedTempFile::new().unwrap();
+    fs::write(
+        diff_file.path(),
+        "--- /dev/null\n+++ b/new.rs\n@@ -0,0 +1 @@\n+x\n",
+    )
+    .unwrap();
+    let output = hashline_bin()
+        .args(["apply", "--patch"])
+        .arg(diff_file.path())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("creating files is not supported"),
+        "{}",
+        stderr
+    );
+}
+
+// ═══════════════════════════════════════════════════════════════════════════
+// apply --emit-patch
+// ═══════════════════════════════════════════════════════════════════════════
+
+#[test]
+fn cli_emit_patch_names_edits_in_hunk_headers() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let file = dir.path().join("f.txt");
+    let original = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n";
+    fs::write(&file, original).unwrap();
+    let payload = json!({
+        "path": "f.txt",
+        "edits": [
+            {"set_line": {"anchor": make_ref(2, "b"), "new_text": "B"}},
+            {"append_to_end": {"text": "k"}},
+            {"insert_after": {"anchor": make_ref(3, "c"), "text": "c2"}}
+        ]
+    });
+    fs::write(dir.path().join("e.json"), payload.to_string()).unwrap();
+    let output = hashline_bin()
+        .current_dir(dir.path())
+        .args(["apply", "--emit-patch", "out.diff", "--input", "e.json"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let patch = fs::read_to_string(dir.path().join("out.diff")).unwrap();
+    assert_eq!(
+        patch,
+        "--- a/f.txt\n+++ b/f.txt\n@@ -1,6 +1,7 @@ edits 0,2\n a\n-b\n+B\n c\n+c2\n d\n e\n f\n@@ -8,3 +9,4 @@ edit 1\n h\n i\n j\n+k\n"
+    );
+
+    // The emitted patch reproduces the change on the original file.
+    let updated = fs::read_to_string(&file).unwrap();
+    fs::write(&file, original).unwrap();
+    let output = hashline_bin()
+        .current_dir(dir.path())
+        .args(["apply", "--patch", "out.diff"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(fs::read_to_string(&file).unwrap(), updated);
+}
+
+#[test]
+fn cli_emit_patch_with_dry_run_writes_only_the_patch() {
+    let tmp = NamedTempFile::new().unwrap();
+    fs::write(tmp.path(), "aaa\nbbb\n").unwrap();
+    let patch_file = NamedTempFile::new().unwrap();
+    let payload = json!({
+        "path": tmp.path().to_str().unwrap(),
+        "edits": [{"set_line": {"anchor": make_ref(2, "bbb"), "new_text": "BBB"}}]
+    });
+    let output = run_apply_payload(
+        &payload,
+        &[
+            "--dry-run",
+            "--emit-patch",
+            patch_file.path().to_str().unwrap(),
+        ],
+    );
+    assert!(output.status.success());
+    let patch = fs::read_to_string(patch_file.path()).unwrap();
+    assert!(
+        patch.contains("@@ -1,2 +1,2 @@ edit 0\n aaa\n-bbb\n+BBB\n"),
+        "{}",
+        patch
+    );
+    assert_eq!(fs::read_to_string(tmp.path()).unwrap(), "aaa\nbbb\n");
+}
+
+#[test]
+fn cli_emit_patch_unwritable_target_leaves_files_alone() {
+    let tmp = NamedTempFile::new().unwrap();
+    fs::write(tmp.path(), "aaa\n").unwrap();
+    let payload = json!({
+        "path": tmp.path().to_str().unwrap(),
+        "edits": [{"set_line": {"anchor": make_ref(1, "aaa"), "new_text": "AAA"}}]
+    });
+    let output = run_apply_payload(&payload, &["--emit-patch", "/nonexistent-dir/out.diff"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Error writing patch"));
+    assert_eq!(fs::read_to_string(tmp.path()).unwrap(), "aaa\n");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn cli_emit_patch_is_removed_when_writes_roll_back() {
+    let tmp = NamedTempFile::new().unwrap();
+    fs::write(tmp.path(), "aaa\n").unwrap();
+    let patch_file = NamedTempFile::new().unwrap();
+    // /proc/version reads fine but cannot be rewritten, even by root.
+    let version = fs::read_to_string("/proc/version").unwrap();
+    let version = version.trim_end_matches('\n');
+    let payload = json!({
+        "files": [
+            {"path": tmp.path().to_str().unwrap(), "edits": [{"set_line": {"anchor": make_ref(1, "aaa"), "new_text": "AAA"}}]},
+            {"path": "/proc/version", "edits": [{"set_line": {"anchor": make_ref(1, version), "new_text": "x"}}]}
+        ]
+    });
+    let output = run_apply_payload(
+        &payload,
+        &["--emit-patch", patch_file.path().to_str().unwrap()],
+    );
+    assert_eq!(output.status.code(), Some(2), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Error writing /proc/version"));
+    assert_eq!(fs::read_to_string(tmp.path()).unwrap(), "aaa\n");
+    assert!(!patch_file.path().exists());
+}
+
+#[test]
+fn cli_emit_patch_stdout_rejects_format_json() {
+    let output = hashline_bin()
+        .args(["apply", "--emit-patch", "-", "--format", "json"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let v: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(v["status"], "error");
+}
+
+/// With `--dry-run`, `--emit-patch -` prints the patch once, with the absolute
+/// target path made relative to the working directory as git expects.
+#[test]
+fn cli_emit_patch_stdout_with_dry_run_prints_one_relative_patch() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let file = dir.path().join("s.txt");
+    fs::write(&file, "aaa\nbbb\n").unwrap();
+    let payload = json!({
+        "path": file.to_str().unwrap(),
+        "edits": [{"set_line": {"anchor": make_ref(2, "bbb"), "new_text": "BBB"}}]
+    });
+    fs::write(dir.path().join("e.json"), payload.to_string()).unwrap();
+    let output = hashline_bin()
+        .current_dir(dir.path())
+        .args([
+            "apply",
+            "--dry-run",
+            "--emit-patch",
+            "-",
+            "--input",
+            "e.json",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let patch = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(
+        patch,
+        "--- a/s.txt\n+++ b/s.txt\n@@ -1,2 +1,2 @@ edit 0\n aaa\n-bbb\n+BBB\n"
+    );
+    assert_eq!(fs::read_to_string(&file).unwrap(), "aaa\nbbb\n");
+
+    fs::write(dir.path().join("out.diff"), &patch).unwrap();
+    let output = hashline_bin()
+        .current_dir(dir.path())
+        .args(["apply", "--patch", "out.diff"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(fs::read_to_string(&file).unwrap(), "aaa\nBBB\n");
+}
+
+#[test]
+fn cli_emit_patch_stdout_rejects_dry_run_json() {
+    let output = hashline_bin()
+        .args(["apply", "--emit-patch", "-", "--dry-run=json"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--dry-run=json"));
+}
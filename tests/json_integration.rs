@@ -1,6 +1,9 @@
+mod common;
+
+use common::write_payload;
 use hashline::json::{
-    apply_json_edits, compute_json_anchor, format_json_anchors, parse_json_ast, DeletePathOp,
-    InsertAtPathOp, JsonEdit, JsonError, SetPathOp,
+    apply_json_document_edits, apply_json_edits, compute_json_anchor, format_json_anchors,
+    parse_json_ast, DeletePathOp, InsertAtPathOp, JsonDocument, JsonEdit, JsonError, SetPathOp,
 };
 use serde_json::{json, Value};
 use std::fs;
@@ -417,12 +420,7 @@ fn cli_json_roundtrip_special_keys() {
             {"set_path": {"anchor": anchor, "value": 2}}
         ]
     });
-    let payload_file = NamedTempFile::new().unwrap();
-    fs::write(
-        payload_file.path(),
-        serde_json::to_string(&payload).unwrap(),
-    )
-    .unwrap();
+    let payload_file = write_payload(&payload);
 
     let apply_output = Command::new(env!("CARGO_BIN_EXE_hashline"))
         .args([
@@ -449,12 +447,7 @@ fn cli_json_apply_mismatch_reports_error() {
             {"set_path": {"anchor": "$.version:ff", "value": "2.0"}}
         ]
     });
-    let payload_file = NamedTempFile::new().unwrap();
-    fs::write(
-        payload_file.path(),
-        serde_json::to_string(&payload).unwrap(),
-    )
-    .unwrap();
+    let payload_file = write_payload(&payload);
 
     let output = Command::new(env!("CARGO_BIN_EXE_hashline"))
         .args([
@@ -470,3 +463,281 @@ fn cli_json_apply_mismatch_reports_error() {
     assert!(stderr.contains("current hash"));
     assert!(stderr.contains("updated anchor"));
 }
+
+// ---------------------------------------------------------------------------
+// Format preservation
+// ---------------------------------------------------------------------------
+
+const TABBED: &str = "{\n\t\"version\": \"1.0.0\",\n\t\"ratio\": 1.0,\n\t\"big\": 1e3,\n\t\"zeta\": true,\n\t\"alpha\": [1, 2]\n}";
+
+#[test]
+fn json_document_set_keeps_indent_order_and_numbers() {
+    let mut doc = JsonDocument::parse(TABBED).unwrap();
+    let anchor = compute_json_anchor("$.version", &doc.value()["version"]);
+    apply_json_document_edits(
+        &mut doc,
+        &[JsonEdit::SetPath {
+            set_path: SetPathOp {
+                anchor,
+                value: json!({"major": 2}),
+            },
+        }],
+    )
+    .unwrap();
+    assert_eq!(
+        doc.text(),
+        TABBED.replace("\"1.0.0\"", "{\n\t\t\"major\": 2\n\t}")
+    );
+}
+
+#[test]
+fn json_document_insert_and_delete_touch_only_their_members() {
+    let mut doc = JsonDocument::parse(TABBED).unwrap();
+    let value = doc.value();
+    apply_json_document_edits(
+        &mut doc,
+        &[
+            JsonEdit::InsertAtPath {
+                insert_at_path: InsertAtPathOp {
+                    anchor: compute_json_anchor("$", &value),
+                    key: Some("added".to_string()),
+                    index: None,
+                    value: json!(null),
+                },
+            },
+            JsonEdit::InsertAtPath {
+                insert_at_path: InsertAtPathOp {
+                    anchor: compute_json_anchor("$.alpha", &value["alpha"]),
+                    key: None,
+                    index: Some(1),
+                    value: json!(9),
+                },
+            },
+            JsonEdit::DeletePath {
+                delete_path: DeletePathOp {
+                    anchor: compute_json_anchor("$.zeta", &value["zeta"]),
+                },
+            },
+        ],
+    )
+    .unwrap();
+    assert_eq!(
+        doc.text(),
+        "{\n\t\"version\": \"1.0.0\",\n\t\"ratio\": 1.0,\n\t\"big\": 1e3,\n\t\"alpha\": [1, 9, 2],\n\t\"added\": null\n}"
+    );
+}
+
+#[test]
+fn json_document_stale_anchor_leaves_text_unchanged() {
+    let mut doc = JsonDocument::parse(TABBED).unwrap();
+    let anchor = compute_json_anchor("$.ratio", &doc.value()["ratio"]);
+    let result = apply_json_document_edits(
+        &mut doc,
+        &[
+            JsonEdit::SetPath {
+                set_path: SetPathOp {
+                    anchor,
+                    value: json!(2),
+                },
+            },
+            JsonEdit::DeletePath {
+                delete_path: DeletePathOp {
+                    anchor: "$.zeta:ff".to_string(),
+                },
+            },
+        ],
+    );
+    assert!(matches!(result, Err(JsonError::HashMismatch { .. })));
+    assert_eq!(doc.text(), TABBED);
+}
+
+#[test]
+fn cli_json_apply_rewrites_only_edited_value() {
+    let original = "{\n    \"name\": \"demo\",\n    \"version\": \"1.0.0\",\n    \"weights\": [1.50, 2e2],\n    \"a\": {}\n}";
+    let tmp = NamedTempFile::new().unwrap();
+    fs::write(tmp.path(), original).unwrap();
+
+    let ast = parse_json_ast(tmp.path()).unwrap();
+    let payload = json!({
+        "path": tmp.path().to_str().unwrap(),
+        "edits": [
+            {"set_path": {"anchor": compute_json_anchor("$.version", &ast["version"]), "value": "1.1.0"}}
+        ]
+    });
+    let payload_file = write_payload(&payload);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_hashline"))
+        .args([
+            "json-apply",
+            "--input",
+            payload_file.path().to_str().unwrap(),
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        fs::read_to_string(tmp.path()).unwrap(),
+        original.replace("1.0.0", "1.1.0")
+    );
+}
+
+#[test]
+fn cli_json_read_and_apply_jsonc_keep_comments() {
+    let original = "{\n  // Compiler settings\n  \"compilerOptions\": {\n    \"target\": \"es2020\", // keep in sync with node\n    \"strict\": true,\n  },\n}\n";
+    let tmp = NamedTempFile::new().unwrap();
+    fs::write(tmp.path(), original).unwrap();
+
+    let read_output = Command::new(env!("CARGO_BIN_EXE_hashline"))
+        .args(["json-read", tmp.path().to_str().unwrap()])
+        .output()
+        .unwrap();
+    assert!(read_output.status.success());
+    let stdout = String::from_utf8(read_output.stdout).unwrap();
+    assert!(
+        stdout.contains("// $.compilerOptions.target:"),
+        "{}",
+        stdout
+    );
+
+    let ast = parse_json_ast(tmp.path()).unwrap();
+    let options = &ast["compilerOptions"];
+    let payload = json!({
+        "path": tmp.path().to_str().unwrap(),
+        "edits": [
+            {"set_path": {"anchor": compute_json_anchor("$.compilerOptions.target", &options["target"]), "value": "es2022"}},
+            {"delete_path": {"anchor": compute_json_anchor("$.compilerOptions.strict", &options["strict"])}}
+        ]
+    });
+    let payload_file = write_payload(&payload);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_hashline"))
+        .args([
+            "json-apply",
+            "--input",
+            payload_file.path().to_str().unwrap(),
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        fs::read_to_string(tmp.path()).unwrap(),
+        "{\n  // Compiler settings\n  \"compilerOptions\": {\n    \"target\": \"es2022\", // keep in sync with node\n  },\n}\n"
+    );
+}
+
+// ---------------------------------------------------------------------------
+// JSONPath selectors
+// ---------------------------------------------------------------------------
+
+const WORKSPACE: &str = "{\n  \"packages\": [\n    {\"name\": \"core\", \"version\": \"1.0.0\"},\n    {\"name\": \"cli\", \"version\": \"1.0.0\"},\n    {\"name\": \"docs\", \"private\": true}\n  ]\n}\n";
+
+#[test]
+fn cli_json_read_select_prints_matches_under_selector_anchor() {
+    let tmp = NamedTempFile::new().unwrap();
+    fs::write(tmp.path(), WORKSPACE).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_hashline"))
+        .args([
+            "json-read",
+            tmp.path().to_str().unwrap(),
+            "--select",
+            "$.packages[*].version",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let all = json!(["1.0.0", "1.0.0"]);
+    let expected = format!(
+        "// {}\n// {}\n\"1.0.0\"\n// {}\n\"1.0.0\"\n",
+        compute_json_anchor("$.packages[*].version", &all),
+        compute_json_anchor("$.packages[0].version", &all[0]),
+        compute_json_anchor("$.packages[1].version", &all[1]),
+    );
+    assert!(stdout.starts_with(&expected), "{}", stdout);
+    assert!(!stdout.contains("docs"), "{}", stdout);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_hashline"))
+        .args([
+            "json-read",
+            tmp.path().to_str().unwrap(),
+            "--select",
+            "$.missing[*]",
+        ])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("No values match path"), "{}", stderr);
+}
+
+#[test]
+fn cli_json_apply_selector_edits_every_match() {
+    let tmp = NamedTempFile::new().unwrap();
+    fs::write(tmp.path(), WORKSPACE).unwrap();
+
+    let ast = parse_json_ast(tmp.path()).unwrap();
+    let payload = json!({
+        "path": tmp.path().to_str().unwrap(),
+        "edits": [
+            {"set_path": {"anchor": compute_json_anchor("$.packages[*].version", &json!(["1.0.0", "1.0.0"])), "value": "1.1.0"}},
+            {"delete_path": {"anchor": compute_json_anchor("$.packages[?(@.private==true)]", &json!([ast["packages"][2]]))}},
+            {"insert_at_path": {"anchor": compute_json_anchor("$.packages[-2]", &ast["packages"][1]), "key": "bin", "value": "hl"}}
+        ]
+    });
+    let payload_file = write_payload(&payload);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_hashline"))
+        .args([
+            "json-apply",
+            "--input",
+            payload_file.path().to_str().unwrap(),
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let updated = parse_json_ast(tmp.path()).unwrap();
+    assert_eq!(
+        updated,
+        json!({"packages": [
+            {"name": "core", "version": "1.1.0"},
+            {"name": "cli", "version": "1.1.0", "bin": "hl"}
+        ]})
+    );
+}
+
+#[test]
+fn cli_json_apply_selector_mismatch_reports_selector_anchor() {
+    let tmp = NamedTempFile::new().unwrap();
+    fs::write(tmp.path(), WORKSPACE).unwrap();
+
+    // Hashed before the second package existed: the match set changed.
+    let stale = compute_json_anchor("$.packages[*].version", &json!(["1.0.0"]));
+    let payload = json!({
+        "path": tmp.path().to_str().unwrap(),
+        "edits": [{"set_path": {"anchor": stale, "value": "2.0.0"}}]
+    });
+    let payload_file = write_payload(&payload);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_hashline"))
+        .args([
+            "json-apply",
+            "--input",
+            payload_file.path().to_str().unwrap(),
+        ])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("Hash mismatch for $.packages[*].version."),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains("hashline json-read --select '$.packages[*].version' "),
+        "{}",
+        stderr
+    );
+    assert_eq!(fs::read_to_string(tmp.path()).unwrap(), WORKSPACE);
+}
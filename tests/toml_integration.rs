@@ -0,0 +1,168 @@
+mod common;
+
+use common::{copy_fixture, write_payload};
+use hashline::json::compute_json_anchor;
+use hashline::toml::{apply_toml_edits, format_toml_anchors, TomlDocument};
+use hashline::{InsertAtPathOp, JsonEdit, JsonError};
+use serde_json::json;
+use std::fs;
+use std::process::Command;
+use tempfile::NamedTempFile;
+
+const PYPROJECT: &str = "tests/fixtures/toml/pyproject.toml";
+
+fn load_pyproject() -> TomlDocument {
+    TomlDocument::parse(&fs::read_to_string(PYPROJECT).unwrap()).unwrap()
+}
+
+// ---------------------------------------------------------------------------
+// pyproject.toml tests
+// ---------------------------------------------------------------------------
+
+/// Dashed keys need bracket quoting in the path, exactly as JSON anchors do.
+#[test]
+fn toml_read_pyproject_has_quoted_key_anchors() {
+    let doc = load_pyproject();
+    let value = doc.value();
+    let output = format_toml_anchors(&doc);
+    for (path, v) in [
+        ("$", &value),
+        (
+            "$.project[\"requires-python\"]",
+            &value["project"]["requires-python"],
+        ),
+        (
+            "$.project.dependencies[1]",
+            &value["project"]["dependencies"][1],
+        ),
+        ("$.tool.ruff", &value["tool"]["ruff"]),
+    ] {
+        let anchor = compute_json_anchor(path, v);
+        assert!(
+            output.contains(&format!("# {}\n", anchor)),
+            "{}\n{}",
+            anchor,
+            output
+        );
+    }
+}
+
+#[test]
+fn toml_insert_into_changed_array_is_rejected() {
+    let mut doc = load_pyproject();
+    let original = doc.text().to_string();
+    // Hashed before "rich" was added.
+    let edits = vec![JsonEdit::InsertAtPath {
+        insert_at_path: InsertAtPathOp {
+            anchor: compute_json_anchor("$.project.dependencies", &json!(["requests>=2"])),
+            key: None,
+            index: None,
+            value: json!("httpx"),
+        },
+    }];
+    let err = apply_toml_edits(&mut doc, &edits).unwrap_err();
+    assert!(matches!(err, JsonError::HashMismatch { .. }));
+    assert_eq!(doc.text(), original);
+}
+
+#[test]
+fn cli_toml_read_keeps_table_headers() {
+    let output = Command::new(env!("CARGO_BIN_EXE_hashline"))
+        .args(["toml-read", PYPROJECT])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("\n[tool.ruff]\n"), "{}", stdout);
+    assert!(
+        stdout.contains("# $.tool.ruff[\"line-length\"]:"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn cli_toml_apply_mismatch_reports_error() {
+    let tmp = copy_fixture(PYPROJECT);
+    let payload = json!({
+        "path": tmp.path().to_str().unwrap(),
+        "edits": [
+            {"delete_path": {"anchor": compute_json_anchor("$.tool.ruff", &json!(null))}}
+        ]
+    });
+    let payload_file = write_payload(&payload);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_hashline"))
+        .args([
+            "toml-apply",
+            "--input",
+            payload_file.path().to_str().unwrap(),
+        ])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("hashline toml-read"), "{}", stderr);
+    assert_eq!(
+        fs::read_to_string(tmp.path()).unwrap(),
+        fs::read_to_string(PYPROJECT).unwrap()
+    );
+}
+
+#[test]
+fn cli_toml_read_rejects_unclosed_table_header() {
+    let tmp = NamedTempFile::new().unwrap();
+    fs::write(tmp.path(), "[package\nname = 1\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_hashline"))
+        .args(["toml-read", tmp.path().to_str().unwrap()])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Error parsing TOML"), "{}", stderr);
+}
+
+// ---------------------------------------------------------------------------
+// Format preservation
+// ---------------------------------------------------------------------------
+
+/// A multi-line array grows by one line in its own indent, and trailing
+/// comments stay on the values they annotate.
+#[test]
+fn cli_toml_apply_appends_to_multiline_array_in_place() {
+    let tmp = copy_fixture(PYPROJECT);
+    let value = load_pyproject().value();
+    let project = &value["project"];
+    let payload = json!({
+        "path": tmp.path().to_str().unwrap(),
+        "edits": [
+            {"set_path": {"anchor": compute_json_anchor("$.project.version", &project["version"]), "value": "0.2.0"}},
+            {"insert_at_path": {"anchor": compute_json_anchor("$.project.dependencies", &project["dependencies"]), "value": "httpx"}},
+            {"set_path": {"anchor": compute_json_anchor("$.tool.ruff.line-length", &value["tool"]["ruff"]["line-length"]), "value": 120}}
+        ]
+    });
+    let payload_file = write_payload(&payload);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_hashline"))
+        .args([
+            "toml-apply",
+            "--emit-updated",
+            "--input",
+            payload_file.path().to_str().unwrap(),
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        fs::read_to_string(tmp.path()).unwrap(),
+        fs::read_to_string(PYPROJECT)
+            .unwrap()
+            .replace("0.1.0", "0.2.0")
+            .replace("\"rich\",\n", "\"rich\",\n    \"httpx\",\n")
+            .replace("100  #", "120  #")
+    );
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let anchor = compute_json_anchor("$.project.version", &json!("0.2.0"));
+    assert!(stdout.contains(&anchor), "{}", stdout);
+}
@@ -0,0 +1,327 @@
+mod common;
+
+use common::{copy_fixture, write_payload};
+use hashline::json::compute_json_anchor;
+use hashline::yaml::{apply_yaml_edits, format_yaml_anchors, YamlDocument};
+use hashline::{JsonEdit, JsonError, SetPathOp};
+use serde_json::json;
+use std::fs;
+use std::process::Command;
+use tempfile::NamedTempFile;
+
+const COMPOSE: &str = "tests/fixtures/yaml/compose.yaml";
+const STREAM: &str = "tests/fixtures/yaml/stream.yaml";
+
+fn load_compose() -> YamlDocument {
+    YamlDocument::parse(&fs::read_to_string(COMPOSE).unwrap()).unwrap()
+}
+
+// ---------------------------------------------------------------------------
+// compose.yaml tests
+// ---------------------------------------------------------------------------
+
+#[test]
+fn yaml_read_compose_has_json_anchors() {
+    let doc = load_compose();
+    let value = doc.value();
+    let output = format_yaml_anchors(&doc);
+    for (path, v) in [
+        ("$", &value),
+        ("$.services.web.image", &value["services"]["web"]["image"]),
+        (
+            "$.services.web.ports[0]",
+            &value["services"]["web"]["ports"][0],
+        ),
+        (
+            "$.services.worker.environment",
+            &value["services"]["worker"]["environment"],
+        ),
+    ] {
+        let anchor = compute_json_anchor(path, v);
+        assert!(
+            output.contains(&format!("# {}\n", anchor)),
+            "{}\n{}",
+            anchor,
+            output
+        );
+    }
+}
+
+#[test]
+fn yaml_hash_mismatch_leaves_text_unchanged() {
+    let mut doc = load_compose();
+    let original = doc.text().to_string();
+    let edits = vec![JsonEdit::SetPath {
+        set_path: SetPathOp {
+            anchor: compute_json_anchor("$.services.web.image", &json!("nginx:1.24")),
+            value: json!("nginx:1.26"),
+        },
+    }];
+    let err = apply_yaml_edits(&mut doc, &edits).unwrap_err();
+    assert!(matches!(err, JsonError::HashMismatch { .. }));
+    assert_eq!(doc.text(), original);
+}
+
+#[test]
+fn cli_yaml_read_interleaves_anchors_with_source_lines() {
+    let output = Command::new(env!("CARGO_BIN_EXE_hashline"))
+        .args(["yaml-read", COMPOSE])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("# $.services.web.image:"), "{}", stdout);
+    assert!(stdout.contains("      - \"8080:80\"\n"), "{}", stdout);
+    assert!(stdout.contains("    image: nginx:1.25\n"), "{}", stdout);
+}
+
+#[test]
+fn cli_yaml_apply_mismatch_reports_error() {
+    let tmp = copy_fixture(COMPOSE);
+    let payload = json!({
+        "path": tmp.path().to_str().unwrap(),
+        "edits": [
+            {"delete_path": {"anchor": compute_json_anchor("$.services.worker", &json!(null))}}
+        ]
+    });
+    let payload_file = write_payload(&payload);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_hashline"))
+        .args([
+            "yaml-apply",
+            "--input",
+            payload_file.path().to_str().unwrap(),
+        ])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("hashline yaml-read"), "{}", stderr);
+    assert_eq!(
+        fs::read_to_string(tmp.path()).unwrap(),
+        fs::read_to_string(COMPOSE).unwrap()
+    );
+}
+
+// ---------------------------------------------------------------------------
+// Format preservation
+// ---------------------------------------------------------------------------
+
+/// Comments, quoting and the `&env` / `*env` alias pair survive an edit; the
+/// alias reads the new value through its anchor, and its anchor covers it
+/// whole.
+#[test]
+fn cli_yaml_apply_keeps_comments_and_aliases() {
+    let tmp = copy_fixture(COMPOSE);
+    let value = load_compose().value();
+    let web = &value["services"]["web"];
+    let payload = json!({
+        "path": tmp.path().to_str().unwrap(),
+        "edits": [
+            {"set_path": {"anchor": compute_json_anchor("$.services.web.image", &web["image"]), "value": "nginx:1.27"}},
+            {"insert_at_path": {"anchor": compute_json_anchor("$.services.web.ports", &web["ports"]), "value": "8443:443"}},
+            {"set_path": {"anchor": compute_json_anchor("$.services.web.environment.LOG", &web["environment"]["LOG"]), "value": "debug"}}
+        ]
+    });
+    let payload_file = write_payload(&payload);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_hashline"))
+        .args([
+            "yaml-apply",
+            "--emit-updated",
+            "--input",
+            payload_file.path().to_str().unwrap(),
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let text = fs::read_to_string(tmp.path()).unwrap();
+    assert_eq!(
+        text,
+        fs::read_to_string(COMPOSE)
+            .unwrap()
+            .replace("nginx:1.25", "nginx:1.27")
+            .replace("\"8080:80\"\n", "\"8080:80\"\n      - \"8443:443\"\n")
+            .replace("LOG: info", "LOG: debug")
+    );
+    let updated = YamlDocument::parse(&text).unwrap().value();
+    assert_eq!(updated["services"]["worker"]["environment"]["LOG"], "debug");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let anchor = compute_json_anchor(
+        "$.services.worker.environment",
+        &updated["services"]["worker"]["environment"],
+    );
+    assert!(stdout.contains(&anchor), "{}", stdout);
+    assert!(
+        !stdout.contains("# $.services.worker.environment.LOG:"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn cli_yaml_read_reports_invalid_yaml() {
+    let tmp = NamedTempFile::new().unwrap();
+    fs::write(tmp.path(), "a: [1, 2\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_hashline"))
+        .args(["yaml-read", tmp.path().to_str().unwrap()])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Error parsing YAML"), "{}", stderr);
+}
+
+// ---------------------------------------------------------------------------
+// Aliases and merge keys
+// ---------------------------------------------------------------------------
+
+#[test]
+fn yaml_merge_key_adds_keys_its_mapping_lacks() {
+    let value = load_compose().value();
+    let web = &value["services"]["web"];
+    assert_eq!(web["restart"], "unless-stopped");
+    assert_eq!(web["logging"], json!({"driver": "json-file"}));
+    assert_eq!(web["image"], "nginx:1.25");
+    assert!(web.get("<<").is_none(), "{}", web);
+}
+
+/// Merged and aliased values are anchored as a whole, never inside.
+#[test]
+fn cli_yaml_read_anchors_no_path_inside_shared_values() {
+    let output = Command::new(env!("CARGO_BIN_EXE_hashline"))
+        .args(["yaml-read", COMPOSE])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("# $.services.web.restart:"), "{}", stdout);
+    assert!(stdout.contains("# $.services.web.logging:"), "{}", stdout);
+    assert!(
+        stdout.contains("# $[\"x-defaults\"].logging.driver:"),
+        "{}",
+        stdout
+    );
+    for inside in [
+        "$.services.web.logging.driver",
+        "$.services.worker.environment.LOG",
+        "\"<<\"",
+    ] {
+        assert!(!stdout.contains(inside), "{}\n{}", inside, stdout);
+    }
+}
+
+/// Setting a merged key gives the mapping a key of its own, which takes
+/// precedence; the anchored defaults and the other service keep theirs.
+#[test]
+fn cli_yaml_apply_set_merged_key_overrides_it() {
+    let tmp = copy_fixture(COMPOSE);
+    let payload = json!({
+        "path": tmp.path().to_str().unwrap(),
+        "edits": [
+            {"set_path": {"anchor": compute_json_anchor("$.services.web.restart", &json!("unless-stopped")), "value": "always"}}
+        ]
+    });
+    let payload_file = write_payload(&payload);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_hashline"))
+        .args([
+            "yaml-apply",
+            "--input",
+            payload_file.path().to_str().unwrap(),
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let text = fs::read_to_string(tmp.path()).unwrap();
+    assert_eq!(
+        text,
+        fs::read_to_string(COMPOSE).unwrap().replace(
+            "      LOG: info\n",
+            "      LOG: info\n    restart: always\n"
+        )
+    );
+    let updated = YamlDocument::parse(&text).unwrap().value();
+    assert_eq!(updated["services"]["web"]["restart"], "always");
+    assert_eq!(updated["services"]["worker"]["restart"], "unless-stopped");
+}
+
+#[test]
+fn cli_yaml_apply_inside_shared_value_names_its_anchor() {
+    let value = load_compose().value();
+    let worker = &value["services"]["worker"];
+    for (edit, anchored_at) in [
+        (
+            json!({"set_path": {"anchor": compute_json_anchor("$.services.worker.environment.LOG", &worker["environment"]["LOG"]), "value": "warn"}}),
+            "edit $.services.web.environment.LOG instead",
+        ),
+        (
+            json!({"delete_path": {"anchor": compute_json_anchor("$.services.worker.logging.driver", &worker["logging"]["driver"])}}),
+            "edit $[\"x-defaults\"].logging.driver instead",
+        ),
+        (
+            json!({"delete_path": {"anchor": compute_json_anchor("$.services.worker.restart", &worker["restart"])}}),
+            "edit $[\"x-defaults\"].restart instead",
+        ),
+    ] {
+        let tmp = copy_fixture(COMPOSE);
+        let payload = json!({"path": tmp.path().to_str().unwrap(), "edits": [edit]});
+        let payload_file = write_payload(&payload);
+
+        let output = Command::new(env!("CARGO_BIN_EXE_hashline"))
+            .args([
+                "yaml-apply",
+                "--input",
+                payload_file.path().to_str().unwrap(),
+            ])
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(2));
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains(anchored_at), "{}", stderr);
+        assert_eq!(
+            fs::read_to_string(tmp.path()).unwrap(),
+            fs::read_to_string(COMPOSE).unwrap()
+        );
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Multi-document streams
+// ---------------------------------------------------------------------------
+
+#[test]
+fn cli_yaml_apply_edits_one_document_of_a_stream() {
+    let tmp = copy_fixture(STREAM);
+
+    let read_output = Command::new(env!("CARGO_BIN_EXE_hashline"))
+        .args(["yaml-read", tmp.path().to_str().unwrap()])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(read_output.stdout).unwrap();
+    assert!(stdout.contains("# $[1].spec.replicas:"), "{}", stdout);
+
+    let payload = json!({
+        "path": tmp.path().to_str().unwrap(),
+        "edits": [
+            {"set_path": {"anchor": compute_json_anchor("$[1].spec.replicas", &json!(2)), "value": 3}}
+        ]
+    });
+    let payload_file = write_payload(&payload);
+    let output = Command::new(env!("CARGO_BIN_EXE_hashline"))
+        .args([
+            "yaml-apply",
+            "--input",
+            payload_file.path().to_str().unwrap(),
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        fs::read_to_string(tmp.path()).unwrap(),
+        fs::read_to_string(STREAM)
+            .unwrap()
+            .replace("replicas: 2", "replicas: 3")
+    );
+}